@@ -1,6 +1,17 @@
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use prost::Message;
+use prost_types::{DescriptorProto, FileDescriptorSet};
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Well-known types compiled and registered alongside `proto/`, from the include directory
+/// that ships with protoc
+const WELL_KNOWN_TYPES: &[&str] = &[
+    "google/protobuf/duration.proto",
+    "google/protobuf/struct.proto",
+    "google/protobuf/timestamp.proto",
+];
 
 fn main() {
     // Tell cargo to rerun this build script if the proto files change
@@ -14,19 +25,52 @@ fn main() {
 
     // Find all proto files
     let proto_files = find_proto_files(&proto_dir);
+    let mut inputs = proto_files.clone();
+    inputs.extend(WELL_KNOWN_TYPES.iter().map(PathBuf::from));
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
 
     // Configure protobuf compilation
     let mut config = prost_build::Config::new();
     config.type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]");
+    // Generate the well-known types instead of using prost-types, and give them their canonical
+    // JSON form (see src/well_known.rs) rather than raw seconds/nanos pairs
+    config.compile_well_known_types();
+    // Their comments contain C++ and Java snippets that rustdoc would run as doctests
+    config.disable_comments([".google.protobuf"]);
+    config.type_attribute(
+        ".google.protobuf.Duration",
+        "#[serde(into = \"String\", try_from = \"String\")]",
+    );
+    config.type_attribute(
+        ".google.protobuf.Timestamp",
+        "#[serde(into = \"String\", try_from = \"String\")]",
+    );
+    for name in ["Struct", "Value", "ListValue"] {
+        config.message_attribute(
+            format!(".google.protobuf.{}", name),
+            "#[serde(into = \"serde_json::Value\", try_from = \"serde_json::Value\")]",
+        );
+    }
+    // Embedded by the message registry to render schemas
+    let descriptor_path = out_dir.join("file_descriptor_set.bin");
+    config.file_descriptor_set_path(&descriptor_path);
+    // One nested module per package, included from main.rs
+    config.include_file("protos.rs");
 
     // Compile proto files
     config
-        .compile_protos(&proto_files, &[proto_dir])
+        .compile_protos(&inputs, &[&proto_dir])
         .expect("Failed to compile proto files");
 
     // Generate registry file
-    let registry_content = generate_registry_file(&proto_files);
-    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let descriptors = FileDescriptorSet::decode(
+        fs::read(&descriptor_path)
+            .expect("Failed to read descriptor set")
+            .as_slice(),
+    )
+    .expect("Failed to decode descriptor set");
+    let registry_content = generate_registry_file(&proto_dir, &proto_files, &descriptors);
     fs::write(out_dir.join("registry.rs"), registry_content)
         .expect("Failed to write registry file");
 }
@@ -37,7 +81,7 @@ fn find_proto_files(dir: &PathBuf) -> Vec<PathBuf> {
         for entry in fs::read_dir(dir).expect("Failed to read directory") {
             let entry = entry.expect("Failed to read directory entry");
             let path = entry.path();
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "proto") {
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "proto") {
                 proto_files.push(path);
             }
         }
@@ -45,13 +89,105 @@ fn find_proto_files(dir: &PathBuf) -> Vec<PathBuf> {
     proto_files
 }
 
-fn generate_registry_file(_proto_files: &[PathBuf]) -> String {
+/// Emits a `register_messages` function registering every message defined in `proto_files`,
+/// including nested messages, under its fully qualified protobuf name.
+fn generate_registry_file(
+    proto_dir: &Path,
+    proto_files: &[PathBuf],
+    descriptors: &FileDescriptorSet,
+) -> String {
+    // Descriptor file names are relative to the include path; imported dependencies other than
+    // the well-known types are not generated here and are skipped
+    let mut compiled: Vec<String> = proto_files
+        .iter()
+        .filter_map(|p| p.strip_prefix(proto_dir).ok())
+        .map(|p| p.to_string_lossy().replace('\\', "/"))
+        .collect();
+    compiled.extend(WELL_KNOWN_TYPES.iter().map(|name| name.to_string()));
+
+    let mut registrations = Vec::new();
+    for file in descriptors
+        .file
+        .iter()
+        .filter(|f| compiled.contains(&f.name().to_string()))
+    {
+        let package = file.package();
+        let module: Vec<String> = package
+            .split('.')
+            .filter(|s| !s.is_empty())
+            .map(module_ident)
+            .collect();
+        for message in &file.message_type {
+            collect_messages(message, package, &module, &mut registrations);
+        }
+    }
+    registrations.sort();
+
     let mut content = String::new();
-    content.push_str("use crate::message_registry::MessageRegistry;\n");
-    content.push_str("use crate::{Vector3, ImuMessage};\n\n");
+    content.push_str("use crate::message_registry::MessageRegistry;\n\n");
     content.push_str("pub fn register_messages(registry: &mut MessageRegistry) {\n");
-    content.push_str("    registry.register::<Vector3>(\"zspy.Vector3\");\n");
-    content.push_str("    registry.register::<ImuMessage>(\"zspy.ImuMessage\");\n");
+    for (name, path) in registrations {
+        content.push_str(&format!(
+            "    registry.register::<{}>(\"{}\");\n",
+            path, name
+        ));
+    }
     content.push_str("}\n");
     content
 }
+
+fn collect_messages(
+    message: &DescriptorProto,
+    scope: &str,
+    module: &[String],
+    registrations: &mut Vec<(String, String)>,
+) {
+    // Map fields are represented as synthetic nested entry messages that prost doesn't generate
+    if message.options.as_ref().is_some_and(|o| o.map_entry()) {
+        return;
+    }
+    let name = if scope.is_empty() {
+        message.name().to_string()
+    } else {
+        format!("{}.{}", scope, message.name())
+    };
+    let mut path = vec!["crate".to_string(), "proto".to_string()];
+    path.extend(module.iter().cloned());
+    path.push(type_ident(message.name()));
+    registrations.push((name.clone(), path.join("::")));
+
+    // prost generates nested types in a module named after the parent message
+    let mut nested_module = module.to_vec();
+    nested_module.push(module_ident(message.name()));
+    for nested in &message.nested_type {
+        collect_messages(nested, &name, &nested_module, registrations);
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "abstract", "become", "box", "do", "final", "macro", "override", "priv", "try", "typeof",
+    "unsized", "virtual", "yield",
+];
+
+/// Module name prost-build generates for a package segment or parent message.
+fn module_ident(name: &str) -> String {
+    let ident = name.to_snake_case();
+    match ident.as_str() {
+        "crate" | "self" | "super" => format!("{}_", ident),
+        _ if KEYWORDS.contains(&ident.as_str()) => format!("r#{}", ident),
+        _ => ident,
+    }
+}
+
+/// Type name prost-build generates for a message.
+fn type_ident(name: &str) -> String {
+    let ident = name.to_upper_camel_case();
+    if ident == "Self" {
+        "Self_".to_string()
+    } else {
+        ident
+    }
+}
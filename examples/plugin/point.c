@@ -0,0 +1,67 @@
+/*
+ * Example zspy plugin handling `example.Point`: two little-endian float32
+ * values, x then y.
+ *
+ *   cc -shared -fPIC -o libpoint.so examples/plugin/point.c
+ */
+#include "zspy_plugin.h"
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static const char *const TYPES[] = {"example.Point", NULL};
+
+static int32_t reply(zspy_buffer *out, int32_t status, const char *text) {
+    out->len = strlen(text);
+    out->data = malloc(out->len);
+    if (out->data == NULL) {
+        out->len = 0;
+        return 1;
+    }
+    memcpy(out->data, text, out->len);
+    return status;
+}
+
+uint32_t zspy_plugin_abi_version(void) { return ZSPY_PLUGIN_ABI_VERSION; }
+
+const char *const *zspy_plugin_types(void) { return TYPES; }
+
+int32_t zspy_plugin_decode(const char *type_name, const uint8_t *data,
+                           size_t len, zspy_buffer *out) {
+    float point[2];
+    char json[64];
+    (void)type_name;
+    if (len != sizeof(point)) {
+        return reply(out, 1, "expected 8 bytes");
+    }
+    memcpy(point, data, sizeof(point));
+    snprintf(json, sizeof(json), "{\"x\": %g, \"y\": %g}", point[0], point[1]);
+    return reply(out, 0, json);
+}
+
+int32_t zspy_plugin_encode(const char *type_name, const uint8_t *json,
+                           size_t len, zspy_buffer *out) {
+    float point[2];
+    char text[128];
+    (void)type_name;
+    if (len >= sizeof(text)) {
+        return reply(out, 1, "document too long");
+    }
+    memcpy(text, json, len);
+    text[len] = '\0';
+    /* zspy passes compact JSON with sorted keys */
+    if (sscanf(text, "{\"x\":%f,\"y\":%f}", &point[0], &point[1]) != 2) {
+        return reply(out, 1, "expected {\"x\": <number>, \"y\": <number>}");
+    }
+    out->len = sizeof(point);
+    out->data = malloc(out->len);
+    if (out->data == NULL) {
+        out->len = 0;
+        return 1;
+    }
+    memcpy(out->data, point, sizeof(point));
+    return 0;
+}
+
+void zspy_plugin_free(zspy_buffer buffer) { free(buffer.data); }
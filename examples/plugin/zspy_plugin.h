@@ -0,0 +1,47 @@
+/*
+ * C ABI for zspy decoder plugins, loaded with `--plugin-dir` or
+ * MessageRegistry::register_plugin.
+ *
+ * A plugin is a shared library exporting the functions below. Every type it
+ * lists becomes usable with `--type` like a protobuf message. The functions
+ * may be called from several threads at once.
+ */
+#ifndef ZSPY_PLUGIN_H
+#define ZSPY_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define ZSPY_PLUGIN_ABI_VERSION 1
+
+/* A buffer allocated by the plugin and released with zspy_plugin_free. */
+typedef struct {
+    uint8_t *data;
+    size_t len;
+} zspy_buffer;
+
+/* Must return ZSPY_PLUGIN_ABI_VERSION. */
+uint32_t zspy_plugin_abi_version(void);
+
+/* NULL-terminated array of the type names the plugin handles. */
+const char *const *zspy_plugin_types(void);
+
+/*
+ * Decodes `len` bytes at `data` as `type_name`. On success returns 0 and sets
+ * `out` to UTF-8 JSON; on failure returns non-zero and may set `out` to a
+ * UTF-8 error message.
+ */
+int32_t zspy_plugin_decode(const char *type_name, const uint8_t *data,
+                           size_t len, zspy_buffer *out);
+
+/*
+ * Optional. Encodes the UTF-8 JSON document at `json` as `type_name`, with the
+ * same conventions as zspy_plugin_decode.
+ */
+int32_t zspy_plugin_encode(const char *type_name, const uint8_t *json,
+                           size_t len, zspy_buffer *out);
+
+/* Releases a buffer returned through `out`. */
+void zspy_plugin_free(zspy_buffer buffer);
+
+#endif
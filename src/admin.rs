@@ -0,0 +1,167 @@
+//! `zspy admin`: query the admin space of routers and peers (`@/**`) and render the replies
+//! as one tree, descending into their JSON payloads, or as pretty JSON.
+
+use crate::terminal;
+use clap::Args;
+use colored::Colorize;
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fmt::Write;
+use std::time::Duration;
+use zenoh::Session;
+
+#[derive(Args)]
+pub struct AdminArgs {
+    /// Admin space path to show, e.g. `<zid>/router` (default: everything)
+    pub path: Option<String>,
+    /// How long to wait for replies
+    #[arg(long, default_value = "1s", value_parser = humantime::parse_duration)]
+    pub timeout: Duration,
+    /// Print one JSON object mapping each admin key to its payload instead of a tree
+    #[arg(long)]
+    pub json: bool,
+    /// Only show this many levels of the tree, summarizing what lies below
+    #[arg(long, conflicts_with = "json")]
+    pub depth: Option<usize>,
+}
+
+/// A tree node: a key chunk, JSON field or array element, with a scalar value at leaves.
+#[derive(Default)]
+struct Node {
+    value: Option<String>,
+    /// Children in insertion order, which keeps array elements in order
+    children: Vec<(String, Node)>,
+}
+
+impl Node {
+    fn child(&mut self, name: &str) -> &mut Node {
+        let index = match self.children.iter().position(|(n, _)| n == name) {
+            Some(index) => index,
+            None => {
+                self.children.push((name.to_string(), Node::default()));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index].1
+    }
+
+    fn insert_json(&mut self, value: &Value) {
+        match value {
+            Value::Object(fields) => {
+                for (name, field) in fields {
+                    self.child(name).insert_json(field);
+                }
+            }
+            // Short lists of scalars, like locators, read better on one line
+            Value::Array(items) if items.iter().all(|i| !i.is_object() && !i.is_array()) => {
+                self.value = Some(value.to_string());
+            }
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.child(&format!("[{}]", i)).insert_json(item);
+                }
+            }
+            Value::String(s) => self.value = Some(s.clone()),
+            other => self.value = Some(other.to_string()),
+        }
+    }
+
+    /// Number of nodes below this one.
+    fn size(&self) -> usize {
+        self.children.iter().map(|(_, c)| 1 + c.size()).sum()
+    }
+
+    fn render(&self, out: &mut String, prefix: &str, depth: Option<usize>) {
+        let last = self.children.len().saturating_sub(1);
+        for (i, (name, child)) in self.children.iter().enumerate() {
+            let (branch, indent) = match (i == last, terminal::plain()) {
+                (true, false) => ("└── ", "    "),
+                (false, false) => ("├── ", "│   "),
+                (true, true) => ("`-- ", "    "),
+                (false, true) => ("|-- ", "|   "),
+            };
+            let _ = write!(out, "{}{}{}", prefix, branch, name.cyan());
+            match &child.value {
+                // Multi-line text, like the linkstate graphs, continues below its name
+                Some(value) if value.contains('\n') => {
+                    let _ = write!(out, ":");
+                    for line in value.trim_end().lines() {
+                        let _ = write!(out, "\n{}{}  {}", prefix, indent, line.yellow());
+                    }
+                }
+                Some(value) => {
+                    let _ = write!(out, ": {}", value.yellow());
+                }
+                None => {}
+            }
+            match depth {
+                Some(0) if !child.children.is_empty() => {
+                    let _ = writeln!(out, " {}", format!("({} more)", child.size()).dimmed());
+                }
+                _ => {
+                    let _ = writeln!(out);
+                    child.render(
+                        out,
+                        &format!("{}{}", prefix, indent),
+                        depth.map(|d| d.saturating_sub(1)),
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub async fn run(session: &Session, args: &AdminArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let path = args
+        .path
+        .as_deref()
+        .unwrap_or_default()
+        .trim_start_matches("@/")
+        .trim_matches('/');
+    let selector = if path.is_empty() {
+        "@/**".to_string()
+    } else {
+        format!("@/{}/**", path)
+    };
+    let replies = session.get(selector.as_str()).timeout(args.timeout).await?;
+    let mut samples = Vec::new();
+    while let Ok(reply) = replies.recv_async().await {
+        if let Ok(sample) = reply.result() {
+            let payload = sample.payload().to_bytes();
+            let value = serde_json::from_slice(&payload).unwrap_or_else(|_| {
+                Value::String(match std::str::from_utf8(&payload) {
+                    Ok(text) => text.to_string(),
+                    Err(_) => format!("<{} bytes of binary data>", payload.len()),
+                })
+            });
+            samples.push((sample.key_expr().to_string(), value));
+        }
+    }
+    samples.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    if args.json {
+        let object: Map<String, Value> = samples.into_iter().collect();
+        println!("{}", serde_json::to_string_pretty(&object)?);
+        return Ok(());
+    }
+    if samples.is_empty() {
+        println!(
+            "{}",
+            format!("No admin space replies for {}", selector).yellow()
+        );
+        return Ok(());
+    }
+    let mut root = Node::default();
+    for (key, value) in &samples {
+        let mut node = &mut root;
+        for chunk in key.split('/').skip(1) {
+            node = node.child(chunk);
+        }
+        node.insert_json(value);
+    }
+    let mut out = String::new();
+    let _ = writeln!(out, "{} (this session: {})", selector, session.zid());
+    root.render(&mut out, "", args.depth);
+    print!("{}", out);
+    Ok(())
+}
@@ -0,0 +1,48 @@
+//! Sample attachments as `key=value` metadata.
+//!
+//! Attachments are serialized with the standard Zenoh serialization of a list of string pairs
+//! (the same wire format as a map), so they interoperate with other Zenoh applications.
+
+use serde_json::{Map, Value};
+use std::error::Error;
+use zenoh::bytes::ZBytes;
+use zenoh_ext::{z_deserialize, z_serialize};
+
+/// Parses a `key=value` command-line argument.
+pub fn parse_pair(arg: &str) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid argument '{}': expected key=value", arg))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+pub fn encode(pairs: &[(String, String)]) -> ZBytes {
+    z_serialize(pairs)
+}
+
+/// Renders an attachment as a JSON object of its pairs, or as a string when it isn't a
+/// serialized list of string pairs.
+pub fn decode(attachment: &ZBytes) -> Value {
+    match z_deserialize::<Vec<(String, String)>>(attachment) {
+        Ok(pairs) => Value::Object(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (k, Value::String(v)))
+                .collect::<Map<_, _>>(),
+        ),
+        Err(_) => Value::String(String::from_utf8_lossy(&attachment.to_bytes()).to_string()),
+    }
+}
+
+/// Formats a decoded attachment as `k=v, k=v`.
+pub fn display(attachment: &Value) -> String {
+    match attachment {
+        Value::Object(pairs) => pairs
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v.as_str().unwrap_or_default()))
+            .collect::<Vec<_>>()
+            .join(", "),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
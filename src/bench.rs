@@ -0,0 +1,319 @@
+//! Throughput benchmarking for `zspy bench pub` / `zspy bench sub`.
+//!
+//! Every benchmark payload starts with a little-endian u64 sequence number, padded to the
+//! requested size. The subscriber counts gaps in the sequence as drops; a sequence number of
+//! 0 marks a restarted publisher.
+
+use crate::publish::CongestionControlArg;
+use crate::stats::format_bandwidth;
+use clap::Subcommand;
+use colored::Colorize;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::time;
+use zenoh::{bytes::ZBytes, Session};
+#[cfg(feature = "shm")]
+use zenoh::{
+    shm::{BlockOn, GarbageCollect, PosixShmProviderBackend, ShmProvider, ShmProviderBuilder},
+    Wait,
+};
+
+/// Size of the sequence number at the start of each payload
+const HEADER_LEN: usize = 8;
+
+#[derive(Subcommand)]
+pub enum BenchCommands {
+    /// Publish fixed-size payloads as fast as possible, or at a target rate
+    Pub {
+        /// The key to publish to
+        key: String,
+        /// Payload size in bytes (at least 8)
+        #[arg(long, default_value = "1024")]
+        size: usize,
+        /// Target rate in messages per second (default: unlimited)
+        #[arg(long, value_parser = parse_rate)]
+        rate: Option<f64>,
+        /// How long to publish for
+        #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+        duration: Duration,
+        /// What to do when the transmission queue is full
+        #[arg(long, value_enum, default_value = "block")]
+        congestion_control: CongestionControlArg,
+        /// Publish from shared-memory buffers to measure zero-copy throughput (requires a build
+        /// with the `shm` feature)
+        #[arg(long)]
+        shm: bool,
+    },
+    /// Receive benchmark payloads and report throughput and drops
+    Sub {
+        /// The key expression to subscribe to
+        key: String,
+        /// Stop after this long (default: until Ctrl-C)
+        #[arg(long, value_parser = humantime::parse_duration)]
+        duration: Option<Duration>,
+    },
+}
+
+/// Parses a target rate, which must be positive with a representable period between messages.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(rate) if rate > 0.0 && Duration::try_from_secs_f64(1.0 / rate).is_ok() => Ok(rate),
+        _ => Err(format!(
+            "'{}' is not a positive rate in messages per second",
+            s
+        )),
+    }
+}
+
+/// Message and byte counts accumulated between reports.
+#[derive(Default)]
+struct Counter {
+    messages: u64,
+    bytes: u64,
+}
+
+impl Counter {
+    fn add(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+    }
+
+    fn report(&self, elapsed: Duration) -> String {
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        format!(
+            "{:.0} msg/s, {}",
+            self.messages as f64 / secs,
+            format_bandwidth(self.bytes as f64 / secs)
+        )
+    }
+}
+
+pub async fn run(
+    session: &Session,
+    command: &BenchCommands,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match command {
+        BenchCommands::Pub {
+            key,
+            size,
+            rate,
+            duration,
+            congestion_control,
+            shm,
+        } => {
+            run_pub(
+                session,
+                key,
+                *size,
+                *rate,
+                *duration,
+                *congestion_control,
+                *shm,
+            )
+            .await
+        }
+        BenchCommands::Sub { key, duration } => run_sub(session, key, *duration).await,
+    }
+}
+
+async fn run_pub(
+    session: &Session,
+    key: &str,
+    size: usize,
+    rate: Option<f64>,
+    duration: Duration,
+    congestion_control: CongestionControlArg,
+    shm: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let size = size.max(HEADER_LEN);
+    let payloads = PayloadSource::new(size, shm)?;
+    let publisher = session
+        .declare_publisher(key.to_string())
+        .congestion_control(congestion_control.into())
+        .await?;
+    println!(
+        "Publishing {} byte {}payloads to {} for {}",
+        size,
+        if shm { "shared-memory " } else { "" },
+        key.cyan(),
+        humantime::format_duration(duration)
+    );
+
+    let start = Instant::now();
+    let mut next_report = start + Duration::from_secs(1);
+    let mut last_report = start;
+    let mut interval = Counter::default();
+    let mut total = Counter::default();
+    let mut seq = 0u64;
+
+    tokio::select! {
+        result = async {
+            while start.elapsed() < duration {
+                if let Some(rate) = rate {
+                    // Schedule against the start time so timer granularity doesn't skew the rate
+                    let Some(due) = Duration::try_from_secs_f64(seq as f64 / rate)
+                        .ok()
+                        .and_then(|offset| start.checked_add(offset))
+                    else {
+                        break;
+                    };
+                    if due > Instant::now() {
+                        time::sleep_until(due.into()).await;
+                    }
+                }
+                publisher.put(payloads.next(seq).await?).await?;
+                seq += 1;
+                interval.add(size);
+                total.add(size);
+
+                let now = Instant::now();
+                if now >= next_report {
+                    println!("{}", interval.report(now - last_report));
+                    interval = Counter::default();
+                    last_report = now;
+                    next_report = now + Duration::from_secs(1);
+                }
+            }
+            Ok::<_, Box<dyn Error + Send + Sync>>(())
+        } => result?,
+        _ = tokio::signal::ctrl_c() => {}
+    }
+
+    println!(
+        "{}",
+        format!(
+            "Published {} messages: {}",
+            total.messages,
+            total.report(start.elapsed())
+        )
+        .green()
+    );
+    Ok(())
+}
+
+/// Builds benchmark payloads, in shared memory with `--shm`.
+enum PayloadSource {
+    Heap(usize),
+    #[cfg(feature = "shm")]
+    Shm(usize, ShmProvider<PosixShmProviderBackend>),
+}
+
+/// Payloads the shared-memory pool can hold at once, so publishing doesn't stall on
+/// buffers still held by the transport
+#[cfg(feature = "shm")]
+const SHM_POOL_PAYLOADS: usize = 64;
+
+impl PayloadSource {
+    #[cfg(feature = "shm")]
+    fn new(size: usize, shm: bool) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if !shm {
+            return Ok(PayloadSource::Heap(size));
+        }
+        let provider = ShmProviderBuilder::default_backend(size * SHM_POOL_PAYLOADS).wait()?;
+        Ok(PayloadSource::Shm(size, provider))
+    }
+
+    #[cfg(not(feature = "shm"))]
+    fn new(size: usize, shm: bool) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if shm {
+            return Err(
+                "This zspy was built without shared memory support; rebuild with `--features shm`"
+                    .into(),
+            );
+        }
+        Ok(PayloadSource::Heap(size))
+    }
+
+    /// A payload of the configured size starting with `seq`.
+    async fn next(&self, seq: u64) -> Result<ZBytes, Box<dyn Error + Send + Sync>> {
+        match self {
+            PayloadSource::Heap(size) => {
+                let mut payload = vec![0u8; *size];
+                payload[..HEADER_LEN].copy_from_slice(&seq.to_le_bytes());
+                Ok(payload.into())
+            }
+            #[cfg(feature = "shm")]
+            PayloadSource::Shm(size, provider) => {
+                let mut buf = provider
+                    .alloc(*size)
+                    .with_policy::<BlockOn<GarbageCollect>>()
+                    .await
+                    .map_err(|e| format!("Failed to allocate shared memory: {:?}", e))?;
+                // Pool buffers are reused, so clear what a previous payload left behind
+                buf[..HEADER_LEN].copy_from_slice(&seq.to_le_bytes());
+                buf[HEADER_LEN..].fill(0);
+                Ok(buf.into())
+            }
+        }
+    }
+}
+
+async fn run_sub(
+    session: &Session,
+    key: &str,
+    duration: Option<Duration>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let subscriber = session.declare_subscriber(key).await?;
+    println!("Measuring throughput on {}", key.cyan());
+
+    let mut report = time::interval(Duration::from_secs(1));
+    report.tick().await;
+    let deadline = duration.map(|d| Instant::now() + d);
+    let mut start = None;
+    let mut last_report = Instant::now();
+    let mut interval = Counter::default();
+    let mut total = Counter::default();
+    let (mut interval_dropped, mut dropped) = (0u64, 0u64);
+    let mut expected: Option<u64> = None;
+
+    loop {
+        tokio::select! {
+            sample = subscriber.recv_async() => {
+                let sample = sample?;
+                let payload = sample.payload().to_bytes();
+                start.get_or_insert_with(Instant::now);
+                interval.add(payload.len());
+                total.add(payload.len());
+                let Some(header) = payload.get(..HEADER_LEN) else {
+                    continue;
+                };
+                let seq = u64::from_le_bytes(header.try_into()?);
+                if let Some(expected) = expected.filter(|&e| seq > e) {
+                    interval_dropped += seq - expected;
+                    dropped += seq - expected;
+                }
+                expected = Some(seq + 1);
+            }
+            _ = report.tick() => {
+                let now = Instant::now();
+                if interval.messages > 0 || interval_dropped > 0 {
+                    println!(
+                        "{}, {} dropped",
+                        interval.report(now - last_report),
+                        interval_dropped
+                    );
+                }
+                interval = Counter::default();
+                interval_dropped = 0;
+                last_report = now;
+            }
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => break,
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    let elapsed = start.map_or(Duration::ZERO, |s| s.elapsed());
+    let loss = 100.0 * dropped as f64 / (total.messages + dropped).max(1) as f64;
+    println!(
+        "{}",
+        format!(
+            "Received {} messages: {}, {} dropped ({:.2}%)",
+            total.messages,
+            total.report(elapsed),
+            dropped,
+            loss
+        )
+        .green()
+    );
+    Ok(())
+}
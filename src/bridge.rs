@@ -0,0 +1,229 @@
+//! `zspy bridge`: republish samples from one key expression under another prefix, optionally
+//! transforming their payloads on the way.
+
+use crate::attachment;
+use crate::codec::PayloadEncoding;
+use crate::message_registry::MessageRegistry;
+use crate::metrics;
+use crate::publish::Progress;
+use crate::script::Script;
+use crate::stats::format_summary;
+use crate::transform::{Step, Transform};
+use clap::Args;
+use colored::Colorize;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime};
+use zenoh::{
+    key_expr::KeyExpr,
+    sample::{Sample, SampleKind},
+    Session,
+};
+
+#[derive(Args)]
+pub struct BridgeArgs {
+    /// The key expression to subscribe to, e.g. `robot/**`
+    pub source: String,
+    /// Prefix to republish under. The part of each key after the wildcard-free start of
+    /// `source` is appended, so `robot/arm/joint` bridged from `robot/**` to `sim` becomes
+    /// `sim/arm/joint`
+    pub destination: String,
+    /// Protobuf type of the incoming payloads, needed to transform them
+    #[arg(long)]
+    pub r#type: Option<String>,
+    /// Schemaless format of the incoming payloads (default: JSON)
+    #[arg(long, value_enum, conflicts_with = "type")]
+    pub encoding: Option<PayloadEncoding>,
+    /// Rename a field of the decoded message, e.g. `angularVelocity=gyro` (repeatable)
+    #[arg(long, value_parser = attachment::parse_pair)]
+    pub rename: Vec<(String, String)>,
+    /// Remove a field from the decoded message (repeatable)
+    #[arg(long)]
+    pub drop: Vec<String>,
+    /// Re-encode payloads as this protobuf type (default: the incoming format)
+    #[arg(long)]
+    pub output_type: Option<String>,
+    /// Re-encode payloads in this schemaless format, e.g. `json` to turn protobuf into JSON
+    #[arg(long, value_enum, conflicts_with = "output_type")]
+    pub output_encoding: Option<PayloadEncoding>,
+    /// Transform pipeline (YAML) to apply to each payload, after `--drop` and `--rename`
+    #[arg(long, value_name = "FILE")]
+    pub transform: Option<PathBuf>,
+    /// Rhai script run on each transformed message, as for `echo --script`: the message it ends
+    /// with is republished in its place and `publish(key, value)` publishes derived messages
+    #[arg(long, value_name = "FILE")]
+    pub script: Option<PathBuf>,
+}
+
+impl BridgeArgs {
+    /// The pipeline to apply: `--transform`, with the flags overriding its formats and their
+    /// `--drop` and `--rename` steps running before its own.
+    fn transform(&self) -> Result<Transform, Box<dyn Error + Send + Sync>> {
+        let mut transform = match &self.transform {
+            Some(path) => Transform::load(path)?,
+            None => Transform::default(),
+        };
+        if self.r#type.is_some() || self.encoding.is_some() {
+            transform.r#type = self.r#type.clone();
+            transform.encoding = self.encoding;
+        }
+        if self.output_type.is_some() || self.output_encoding.is_some() {
+            transform.output_type = self.output_type.clone();
+            transform.output_encoding = self.output_encoding;
+        }
+        let flags = self
+            .drop
+            .iter()
+            .map(|field| Step::Drop(field.clone()))
+            .chain(self.rename.iter().map(|(from, to)| Step::Rename {
+                from: from.clone(),
+                to: to.clone(),
+            }));
+        transform.steps.splice(0..0, flags);
+        Ok(transform)
+    }
+}
+
+/// The leading chunks of `key_expr` that contain no wildcards.
+fn literal_prefix(key_expr: &str) -> Vec<&str> {
+    key_expr
+        .split('/')
+        .take_while(|chunk| !chunk.contains('*'))
+        .collect()
+}
+
+/// Maps `key` received on a subscription to `source` to the same key under `destination`.
+fn destination_key(key: &str, literal: &[&str], destination: &str) -> String {
+    let rest: Vec<&str> = key.split('/').skip(literal.len()).collect();
+    if rest.is_empty() {
+        destination.to_string()
+    } else {
+        format!("{}/{}", destination, rest.join("/"))
+    }
+}
+
+/// Republishes `sample` under `destination`, transforming its payload and running `script` on
+/// it if requested.
+async fn republish(
+    session: &Session,
+    registry: &MessageRegistry,
+    transform: &Transform,
+    script: Option<&Script>,
+    literal: &[&str],
+    destination: &str,
+    sample: Sample,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    metrics::received(sample.key_expr().as_str(), sample.payload().len());
+    let key = destination_key(sample.key_expr().as_str(), literal, destination);
+    if sample.kind() == SampleKind::Delete {
+        session
+            .delete(key)
+            .attachment(sample.attachment().cloned())
+            .priority(sample.priority())
+            .congestion_control(sample.congestion_control())
+            .express(sample.express())
+            .await?;
+        return Ok(());
+    }
+    let skip = |e: Box<dyn Error + Send + Sync>| {
+        metrics::decode_error(sample.key_expr().as_str());
+        format!("Skipping sample on {}: {}", sample.key_expr(), e)
+    };
+    let payloads = if let Some(script) = script {
+        let value = transform
+            .rewrite(registry, &sample.payload().to_bytes())
+            .map_err(skip)?;
+        let timestamp = sample
+            .timestamp()
+            .map(|t| t.get_time().to_system_time())
+            .unwrap_or_else(SystemTime::now);
+        let output = script
+            .run(sample.key_expr().as_str(), timestamp, value)
+            .map_err(|e| skip(e.into()))?;
+        output.publish(session).await?;
+        output
+            .messages
+            .iter()
+            .map(|value| transform.encode(registry, value))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(skip)?
+    } else if !transform.is_identity() {
+        vec![transform
+            .apply(registry, &sample.payload().to_bytes())
+            .map_err(skip)?]
+    } else {
+        vec![(
+            sample.payload().to_bytes().to_vec(),
+            sample.encoding().clone(),
+        )]
+    };
+    for (payload, encoding) in payloads {
+        let len = payload.len();
+        session
+            .put(&key, payload)
+            .encoding(encoding)
+            .attachment(sample.attachment().cloned())
+            .priority(sample.priority())
+            .congestion_control(sample.congestion_control())
+            .express(sample.express())
+            .await?;
+        metrics::published(&key, len);
+    }
+    Ok(())
+}
+
+pub async fn run(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &BridgeArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let transform = args.transform()?;
+    transform.check_types(registry)?;
+    let script = args.script.as_deref().map(Script::load).transpose()?;
+    let source = KeyExpr::try_from(args.source.as_str())?;
+    let destination = args.destination.trim_end_matches('/');
+    let destination_space = KeyExpr::try_from(format!("{}/**", destination))?;
+    if destination_space.intersects(&source) {
+        return Err(format!(
+            "Destination {} overlaps source {}, so the bridge would receive its own output",
+            destination, source
+        )
+        .into());
+    }
+    let literal = literal_prefix(source.as_str());
+
+    let subscriber = session.declare_subscriber(&source).await?;
+    println!(
+        "Bridging {} to {}",
+        source.as_str().cyan(),
+        format!("{}/...", destination).cyan()
+    );
+
+    let start = Instant::now();
+    let progress = Progress::new("Bridged");
+    let (mut count, mut failed) = (0u64, 0u64);
+    tokio::select! {
+        result = async {
+            while let Ok(sample) = subscriber.recv_async().await {
+                match republish(session, registry, &transform, script.as_ref(), &literal, destination, sample).await {
+                    Ok(()) => count += 1,
+                    Err(err) => {
+                        failed += 1;
+                        progress.suspend(|| eprintln!("{}", err.to_string().yellow()));
+                    }
+                }
+                progress.update(count);
+            }
+            Ok::<_, Box<dyn Error + Send + Sync>>(())
+        } => result?,
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    progress.finish();
+
+    let mut summary = format!("Bridged {}", format_summary(count, start.elapsed()));
+    if failed > 0 {
+        summary.push_str(&format!(", {} failed", failed));
+    }
+    println!("{}", summary.green());
+    Ok(())
+}
@@ -0,0 +1,93 @@
+//! `zspy cache`: keep the latest sample of every key under a key expression and answer
+//! queries with them, a lightweight stand-in for the storage plugin in tests.
+
+use clap::Args;
+use colored::Colorize;
+use std::collections::HashMap;
+use std::error::Error;
+use zenoh::{
+    sample::{Sample, SampleKind},
+    Session,
+};
+
+#[derive(Args)]
+pub struct CacheArgs {
+    /// The key expression to cache and serve, e.g. `robot/**`
+    pub key_expr: String,
+    /// Print each sample as it updates the cache
+    #[arg(short, long)]
+    pub verbose: bool,
+}
+
+pub async fn run(session: &Session, args: &CacheArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let subscriber = session.declare_subscriber(args.key_expr.as_str()).await?;
+    let queryable = session.declare_queryable(args.key_expr.as_str()).await?;
+    println!("Caching key: {}", args.key_expr.cyan());
+
+    // Latest sample per concrete key; deletes remove the key
+    let mut cache: HashMap<String, Sample> = HashMap::new();
+    let mut queries = 0u64;
+    loop {
+        tokio::select! {
+            sample = subscriber.recv_async() => {
+                let sample = sample?;
+                let key = sample.key_expr().to_string();
+                if args.verbose {
+                    let kind = match sample.kind() {
+                        SampleKind::Put => "Put".green(),
+                        SampleKind::Delete => "Delete".red(),
+                    };
+                    println!(
+                        ">> [{}] '{}' ({} bytes)",
+                        kind,
+                        key.cyan(),
+                        sample.payload().len()
+                    );
+                }
+                match sample.kind() {
+                    SampleKind::Put => {
+                        cache.insert(key, sample);
+                    }
+                    SampleKind::Delete => {
+                        cache.remove(&key);
+                    }
+                }
+            }
+            query = queryable.recv_async() => {
+                let query = match query {
+                    Ok(query) => query,
+                    Err(_) => break,
+                };
+                let mut replies = 0;
+                for sample in cache.values() {
+                    if !query.key_expr().intersects(sample.key_expr()) {
+                        continue;
+                    }
+                    let mut reply = query
+                        .reply(sample.key_expr().clone(), sample.payload().clone())
+                        .encoding(sample.encoding().clone())
+                        .attachment(sample.attachment().cloned());
+                    if let Some(timestamp) = sample.timestamp() {
+                        reply = reply.timestamp(*timestamp);
+                    }
+                    reply.await?;
+                    replies += 1;
+                }
+                queries += 1;
+                println!(
+                    ">> [{}] '{}' answered with {} of {} cached keys",
+                    "Query".green(),
+                    query.selector().to_string().cyan(),
+                    replies,
+                    cache.len()
+                );
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    println!(
+        "{}",
+        format!("Answered {} queries, {} keys cached", queries, cache.len()).green()
+    );
+    Ok(())
+}
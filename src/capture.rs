@@ -0,0 +1,179 @@
+//! `zspy info` and `zspy filter`: inspect and slice capture files without replaying them.
+//!
+//! Indexed zbag captures are summarized from their index alone, and filtering only reads the
+//! chunks that hold a selected key within the selected time range. MCAP files, version 1
+//! captures and interrupted recordings are read in full instead.
+
+use crate::recording::{self, CaptureFormat, CaptureIndex};
+use crate::stats::{format_bytes, format_duration};
+use clap::Args;
+use colored::Colorize;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+use zenoh::key_expr::KeyExpr;
+
+#[derive(Args)]
+pub struct InfoArgs {
+    /// Capture file to inspect
+    pub input: PathBuf,
+}
+
+#[derive(Args)]
+pub struct FilterArgs {
+    /// Capture file to read
+    pub input: PathBuf,
+    /// Only keep samples on keys matching this key expression (repeatable)
+    #[arg(long = "key", value_name = "KEYEXPR")]
+    pub keys: Vec<String>,
+    /// Drop samples received less than this long after the start of the capture, e.g. `10s`
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub start: Option<Duration>,
+    /// Drop samples received more than this long after the start of the capture
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub end: Option<Duration>,
+    /// Capture file to write
+    #[arg(short, long)]
+    pub output: PathBuf,
+    /// Capture format (default: from the output extension, `.mcap` for MCAP)
+    #[arg(long, value_enum)]
+    pub format: Option<CaptureFormat>,
+}
+
+fn format_time(ns: u64) -> String {
+    humantime::format_rfc3339_millis(UNIX_EPOCH + Duration::from_nanos(ns)).to_string()
+}
+
+pub fn info(args: &InfoArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let index = match recording::read_index(&args.input)? {
+        Some(index) => {
+            println!(
+                "{}: indexed, {} chunks",
+                args.input.display().to_string().cyan(),
+                index.chunks.len()
+            );
+            index
+        }
+        None => {
+            println!(
+                "{}: no index, read in full",
+                args.input.display().to_string().cyan()
+            );
+            CaptureIndex::scan(&mut *recording::open(&args.input)?)?
+        }
+    };
+    let (Some(start), Some(end)) = (index.start_ns(), index.end_ns()) else {
+        println!("{}", "The capture holds no samples".yellow());
+        return Ok(());
+    };
+    let duration = Duration::from_nanos(end - start);
+    println!("Start:    {}", format_time(start));
+    println!("End:      {}", format_time(end));
+    println!("Duration: {}", format_duration(duration));
+    println!(
+        "Samples:  {} ({})",
+        index.samples(),
+        format_bytes(index.bytes() as f64)
+    );
+    println!("Keys:     {}", index.keys.len());
+
+    let mut keys: Vec<_> = index.keys.iter().collect();
+    keys.sort_by(|a, b| a.key.cmp(&b.key));
+    let width = keys.iter().map(|k| k.key.len()).max().unwrap_or(0).max(3);
+    println!(
+        "\n  {:<width$}  {:>9}  {:>10}  {:>10}",
+        "Key",
+        "Samples",
+        "Size",
+        "Rate",
+        width = width
+    );
+    for key in keys {
+        let span = Duration::from_nanos(key.last_ns - key.first_ns).as_secs_f64();
+        let rate = if span > 0.0 {
+            format!("{:.1} Hz", (key.samples - 1) as f64 / span)
+        } else {
+            "-".to_string()
+        };
+        println!(
+            "  {:<width$}  {:>9}  {:>10}  {:>10}",
+            key.key,
+            key.samples,
+            format_bytes(key.bytes as f64),
+            rate,
+            width = width
+        );
+    }
+    Ok(())
+}
+
+pub fn filter(args: &FilterArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if fs::canonicalize(&args.output)
+        .is_ok_and(|output| fs::canonicalize(&args.input).is_ok_and(|input| input == output))
+    {
+        return Err("The filtered capture must be written to another file".into());
+    }
+    let key_exprs = args
+        .keys
+        .iter()
+        .map(|key| KeyExpr::try_from(key.as_str()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let matches_key = |key: &str| {
+        key_exprs.is_empty()
+            || KeyExpr::try_from(key)
+                .is_ok_and(|key| key_exprs.iter().any(|expr| expr.intersects(&key)))
+    };
+    // Times are relative to the first sample of the whole capture
+    let after_start = |ns: u64, origin: u64| {
+        args.start
+            .is_none_or(|start| Duration::from_nanos(ns.saturating_sub(origin)) >= start)
+    };
+    let before_end = |ns: u64, origin: u64| {
+        args.end
+            .is_none_or(|end| Duration::from_nanos(ns.saturating_sub(origin)) <= end)
+    };
+
+    let index = recording::read_index(&args.input)?;
+    let mut origin = index.as_ref().and_then(CaptureIndex::start_ns);
+    let mut reader = match (&index, origin) {
+        (Some(index), Some(origin)) => {
+            let selected: Vec<bool> = index.keys.iter().map(|k| matches_key(&k.key)).collect();
+            recording::open_chunks(&args.input, index, |chunk| {
+                chunk.keys.iter().any(|&key| selected[key])
+                    && after_start(chunk.last_ns, origin)
+                    && before_end(chunk.first_ns, origin)
+            })?
+        }
+        _ => recording::open(&args.input)?,
+    };
+
+    let format = args
+        .format
+        .unwrap_or_else(|| CaptureFormat::from_path(&args.output));
+    let mut writer = recording::create(&args.output, format, None)?;
+    let (mut read, mut written) = (0u64, 0u64);
+    while let Some(sample) = reader.read()? {
+        read += 1;
+        let origin = *origin.get_or_insert(sample.timestamp_ns);
+        if matches_key(&sample.key)
+            && after_start(sample.timestamp_ns, origin)
+            && before_end(sample.timestamp_ns, origin)
+        {
+            writer.write(&sample)?;
+            written += 1;
+        }
+    }
+    writer.finish()?;
+    println!(
+        "{}",
+        format!(
+            "Wrote {} of {} samples read to {}",
+            written,
+            read,
+            args.output.display()
+        )
+        .green()
+    );
+    Ok(())
+}
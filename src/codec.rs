@@ -0,0 +1,109 @@
+//! Conversion between JSON text and payload bytes.
+//!
+//! Protobuf message types implement [`Codec`] through the message registry; schemaless
+//! formats are selected with `--encoding`.
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+use zenoh::bytes::Encoding;
+
+pub trait Codec: Send + Sync {
+    /// Decodes `bytes` into pretty-printed JSON.
+    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>>;
+    /// Encodes a JSON document into payload bytes.
+    fn encode(&self, json: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+}
+
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let value: Value = serde_json::from_slice(bytes)?;
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    fn encode(&self, json: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let value: Value = serde_json::from_str(json)?;
+        Ok(serde_json::to_vec(&value)?)
+    }
+}
+
+pub struct CborCodec;
+
+impl Codec for CborCodec {
+    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut cursor = std::io::Cursor::new(bytes);
+        let value: Value = ciborium::from_reader(&mut cursor)?;
+        if cursor.position() as usize != bytes.len() {
+            return Err("Trailing bytes after CBOR value".into());
+        }
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    fn encode(&self, json: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let value: Value = serde_json::from_str(json)?;
+        let mut buf = Vec::new();
+        ciborium::into_writer(&value, &mut buf)?;
+        Ok(buf)
+    }
+}
+
+pub struct MsgpackCodec;
+
+impl Codec for MsgpackCodec {
+    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut cursor = std::io::Cursor::new(bytes);
+        let value: Value = rmp_serde::from_read(&mut cursor)?;
+        if cursor.position() as usize != bytes.len() {
+            return Err("Trailing bytes after MessagePack value".into());
+        }
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    fn encode(&self, json: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let value: Value = serde_json::from_str(json)?;
+        Ok(rmp_serde::to_vec_named(&value)?)
+    }
+}
+
+/// Normalizes a hand-written JSON, JSON5 or YAML document into JSON text for [`Codec::encode`].
+pub fn to_json(input: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+    // JSON is valid JSON5, so only fall back to YAML when JSON5 parsing fails
+    let value: Value = match json5::from_str(input) {
+        Ok(value) => value,
+        // json5's errors span several lines, so report the YAML error alone
+        Err(_) => serde_yaml::from_str(input)
+            .map_err(|e| format!("Value is not valid JSON, JSON5 or YAML: {}", e))?,
+    };
+    Ok(serde_json::to_string(&value)?)
+}
+
+/// Schemaless payload encodings selectable with `--encoding`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadEncoding {
+    Json,
+    Cbor,
+    Msgpack,
+}
+
+impl PayloadEncoding {
+    pub fn codec(self) -> &'static dyn Codec {
+        match self {
+            PayloadEncoding::Json => &JsonCodec,
+            PayloadEncoding::Cbor => &CborCodec,
+            PayloadEncoding::Msgpack => &MsgpackCodec,
+        }
+    }
+
+    /// Zenoh encoding set on published samples.
+    pub fn zenoh_encoding(self) -> Encoding {
+        match self {
+            PayloadEncoding::Json => Encoding::APPLICATION_JSON,
+            PayloadEncoding::Cbor => Encoding::APPLICATION_CBOR,
+            PayloadEncoding::Msgpack => Encoding::from("application/msgpack"),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! `zspy compare`: subscribe to the same key expression on two separate sessions, e.g. on either
+//! side of a bridge between routers or networks, and report messages that only show up on one of
+//! them and how much later they arrive on one than the other.
+//!
+//! Messages are matched by key and payload; a message seen on one session is reported missing on
+//! the other once `--window` passes without a match.
+
+use crate::error::ZspyError;
+use crate::stats::format_duration;
+use clap::Args;
+use colored::Colorize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::time;
+use zenoh::Config;
+
+#[derive(Args)]
+pub struct CompareArgs {
+    /// The key expression to compare
+    pub key_expr: String,
+    /// Zenoh configuration file (JSON5) of the first session, or an endpoint for it to connect
+    /// to as a client, e.g. `tcp/10.0.0.1:7447`
+    #[arg(long, value_name = "CONFIG")]
+    pub session_a: String,
+    /// Configuration file or endpoint of the second session
+    #[arg(long, value_name = "CONFIG")]
+    pub session_b: String,
+    /// How long a message may take to show up on the other session before it is reported missing
+    #[arg(long, default_value = "1s", value_parser = humantime::parse_duration)]
+    pub window: Duration,
+    /// Stop after this long, e.g. `1m` (default: until Ctrl-C)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Side {
+    A,
+    B,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::A => "A",
+            Side::B => "B",
+        })
+    }
+}
+
+/// Loads `spec` as a config file if it names one, and otherwise connects to it as an endpoint.
+fn session_config(spec: &str) -> Result<Config, Box<dyn Error + Send + Sync>> {
+    if Path::new(spec).is_file() {
+        return Config::from_file(spec);
+    }
+    let mut config = Config::default();
+    config.insert_json5("mode", "\"client\"")?;
+    config.insert_json5("connect/endpoints", &serde_json::to_string(&[spec])?)?;
+    Ok(config)
+}
+
+/// A message seen on one session and not yet on the other.
+struct Unmatched {
+    side: Side,
+    arrived: Instant,
+    len: usize,
+}
+
+#[derive(Default)]
+struct Comparison {
+    /// Unmatched messages by key and payload hash, oldest first; all from the same side, since
+    /// a message from the other side would have matched one
+    unmatched: HashMap<(String, u64), VecDeque<Unmatched>>,
+    matched: u64,
+    /// Messages missing from the other side, by side and key
+    missing: BTreeMap<(Side, String), u64>,
+    /// Arrival on B minus arrival on A of each matched message, in milliseconds
+    skews: Vec<f64>,
+}
+
+impl Comparison {
+    fn observe(&mut self, side: Side, key: &str, payload: &[u8], now: Instant) {
+        let mut hasher = DefaultHasher::new();
+        payload.hash(&mut hasher);
+        let queue = self
+            .unmatched
+            .entry((key.to_string(), hasher.finish()))
+            .or_default();
+        // Identical payloads repeat, e.g. a constant status; the latest one is most likely the
+        // same message, as the earlier ones would have matched already had they arrived
+        match queue.back() {
+            Some(other) if other.side != side => {
+                let delay = now.duration_since(other.arrived).as_secs_f64() * 1e3;
+                self.skews
+                    .push(if side == Side::B { delay } else { -delay });
+                self.matched += 1;
+                queue.pop_back();
+            }
+            _ => queue.push_back(Unmatched {
+                side,
+                arrived: now,
+                len: payload.len(),
+            }),
+        }
+    }
+
+    /// Removes the messages that have waited for a match since before `cutoff`, printing each.
+    fn expire(&mut self, cutoff: Option<Instant>) {
+        let mut expired = Vec::new();
+        self.unmatched.retain(|(key, _), queue| {
+            while let Some(message) = queue.front() {
+                if cutoff.is_some_and(|cutoff| message.arrived >= cutoff) {
+                    break;
+                }
+                expired.push((message.arrived, message.side, key.clone(), message.len));
+                queue.pop_front();
+            }
+            !queue.is_empty()
+        });
+        expired.sort_by_key(|(arrived, ..)| *arrived);
+        for (_, side, key, len) in expired {
+            println!(
+                "{} '{}' ({} bytes)",
+                format!("Only on {}:", side).yellow(),
+                key.cyan(),
+                len
+            );
+            *self.missing.entry((side, key)).or_default() += 1;
+        }
+    }
+}
+
+pub async fn run(args: &CompareArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let session_a = zenoh::open(session_config(&args.session_a)?)
+        .await
+        .map_err(|e| ZspyError::Connection(format!("session A: {}", e)))?;
+    let session_b = zenoh::open(session_config(&args.session_b)?)
+        .await
+        .map_err(|e| ZspyError::Connection(format!("session B: {}", e)))?;
+    let subscriber_a = session_a.declare_subscriber(args.key_expr.as_str()).await?;
+    let subscriber_b = session_b.declare_subscriber(args.key_expr.as_str()).await?;
+    println!(
+        "Comparing {} on session A ({}) and session B ({})",
+        args.key_expr.cyan(),
+        session_a.zid(),
+        session_b.zid()
+    );
+
+    let start = Instant::now();
+    let mut comparison = Comparison::default();
+    let mut ticker = time::interval((args.window / 4).max(Duration::from_millis(10)));
+    let deadline = async {
+        match args.duration {
+            Some(duration) => time::sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            sample = subscriber_a.recv_async() => {
+                let sample = sample?;
+                let payload = sample.payload().to_bytes();
+                comparison.observe(Side::A, sample.key_expr().as_str(), &payload, Instant::now());
+            }
+            sample = subscriber_b.recv_async() => {
+                let sample = sample?;
+                let payload = sample.payload().to_bytes();
+                comparison.observe(Side::B, sample.key_expr().as_str(), &payload, Instant::now());
+            }
+            _ = ticker.tick() => {
+                comparison.expire(Instant::now().checked_sub(args.window));
+            }
+            _ = &mut deadline => break,
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    // Whatever is still waiting for a match when we stop counts as missing
+    comparison.expire(None);
+    session_a.close().await?;
+    session_b.close().await?;
+
+    let only = |side: Side| -> u64 {
+        comparison
+            .missing
+            .iter()
+            .filter(|((s, _), _)| *s == side)
+            .map(|(_, count)| count)
+            .sum()
+    };
+    let summary = format!(
+        "Compared {} in {}: {} matched, {} only on A, {} only on B",
+        args.key_expr,
+        format_duration(start.elapsed()),
+        comparison.matched,
+        only(Side::A),
+        only(Side::B)
+    );
+    println!("\n{}", summary.green());
+    if !comparison.skews.is_empty() {
+        let skews = &mut comparison.skews;
+        skews.sort_by(f64::total_cmp);
+        let mean = skews.iter().sum::<f64>() / skews.len() as f64;
+        let p99 = skews[(0.99 * (skews.len() - 1) as f64).round() as usize];
+        println!(
+            "Arrival on B relative to A: mean {:+.3}ms, min {:+.3}ms, max {:+.3}ms, p99 {:+.3}ms",
+            mean,
+            skews[0],
+            skews[skews.len() - 1],
+            p99
+        );
+    }
+    for ((side, key), count) in &comparison.missing {
+        println!("  only on {}: {} ({} messages)", side, key.cyan(), count);
+    }
+    Ok(())
+}
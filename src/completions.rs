@@ -0,0 +1,34 @@
+//! Shell completion scripts for `zspy completions`.
+
+use clap::builder::PossibleValuesParser;
+use clap::Command;
+use clap_complete::Shell;
+use std::io;
+
+/// Writes a completion script for `shell` to stdout.
+///
+/// The names in `types` are baked into the script as the values of every `--type` option and
+/// of `types show`, so types loaded with `--proto-path` complete too when passed here.
+pub fn generate(mut cmd: Command, shell: Shell, types: &[String]) {
+    add_type_values(&mut cmd, types);
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, &mut io::stdout());
+}
+
+fn add_type_values(cmd: &mut Command, types: &[String]) {
+    let mut owned = std::mem::take(cmd);
+    let id = if owned.get_name() == "show" {
+        "name"
+    } else {
+        "type"
+    };
+    if owned.get_arguments().any(|arg| arg.get_id() == id) {
+        owned = owned.mut_arg(id, |arg| {
+            arg.value_parser(PossibleValuesParser::new(types.iter().cloned()))
+        });
+    }
+    for sub in owned.get_subcommands_mut() {
+        add_type_values(sub, types);
+    }
+    *cmd = owned;
+}
@@ -0,0 +1,89 @@
+//! Payload compression for `zspy pub --compress` and `zspy echo --decompress`.
+//!
+//! LZ4 payloads use the LZ4 frame format, so both algorithms can be recognized by the magic
+//! number their frames start with.
+
+use crate::error::ZspyError;
+use clap::ValueEnum;
+use std::error::Error;
+use std::io::{Read, Write};
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    Zstd,
+    Lz4,
+}
+
+/// How `echo --decompress` picks the algorithm.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Decompress {
+    /// Decompress payloads starting with a zstd or LZ4 frame, leaving others alone
+    Auto,
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+            Compression::Lz4 => "lz4",
+        }
+    }
+
+    /// The algorithm whose frame `data` starts with, if any.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&ZSTD_MAGIC) {
+            Some(Compression::Zstd)
+        } else if data.starts_with(&LZ4_MAGIC) {
+            Some(Compression::Lz4)
+        } else {
+            None
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        match self {
+            Compression::Zstd => Ok(zstd::encode_all(data, 0)?),
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let result = match self {
+            Compression::Zstd => zstd::decode_all(data),
+            Compression::Lz4 => {
+                let mut out = Vec::new();
+                lz4_flex::frame::FrameDecoder::new(data)
+                    .read_to_end(&mut out)
+                    .map(|_| out)
+            }
+        };
+        result.map_err(|e| {
+            ZspyError::Decode(format!(
+                "Failed to decompress {} payload: {}",
+                self.name(),
+                e
+            ))
+            .into()
+        })
+    }
+}
+
+impl Decompress {
+    /// The algorithm to decompress `data` with, or `None` to leave it as is.
+    pub fn algorithm(self, data: &[u8]) -> Option<Compression> {
+        match self {
+            Decompress::Auto => Compression::detect(data),
+            Decompress::Zstd => Some(Compression::Zstd),
+            Decompress::Lz4 => Some(Compression::Lz4),
+        }
+    }
+}
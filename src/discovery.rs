@@ -0,0 +1,186 @@
+//! Network discovery for `zspy list`.
+//!
+//! Combines three sources of information: the session's own transports, multicast scouting,
+//! and the admin space (`@/**`) of every node that exposes it.
+
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::time::Duration;
+use tokio::time;
+use zenoh::{
+    config::{Config, WhatAmI},
+    Session,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EntityKind {
+    Publisher,
+    Subscriber,
+    Queryable,
+}
+
+impl EntityKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Publisher => "publisher",
+            Self::Subscriber => "subscriber",
+            Self::Queryable => "queryable",
+        }
+    }
+
+    fn from_admin_segment(segment: &str) -> Option<Self> {
+        match segment {
+            "publisher" => Some(Self::Publisher),
+            "subscriber" => Some(Self::Subscriber),
+            "queryable" => Some(Self::Queryable),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct NodeInfo {
+    pub whatami: String,
+    pub locators: BTreeSet<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Discovery {
+    /// Known nodes keyed by ZID
+    pub nodes: BTreeMap<String, NodeInfo>,
+    /// Declared entities: kind -> key expression -> ZIDs of the nodes reporting it
+    pub entities: BTreeMap<EntityKind, BTreeMap<String, BTreeSet<String>>>,
+}
+
+impl Discovery {
+    fn add_node(&mut self, zid: String, whatami: &str) -> &mut NodeInfo {
+        let node = self.nodes.entry(zid).or_default();
+        if node.whatami.is_empty() {
+            node.whatami = whatami.to_string();
+        }
+        node
+    }
+
+    /// Every node and entity as one array of objects, each with a `kind` of `router`, `peer`,
+    /// `client`, `publisher`, `subscriber` or `queryable`.
+    pub fn to_json(&self) -> Value {
+        let nodes = self.nodes.iter().map(|(zid, node)| {
+            json!({
+                "kind": node.whatami,
+                "zid": zid,
+                "locators": node.locators,
+            })
+        });
+        let entities = self.entities.iter().flat_map(|(kind, entities)| {
+            entities.iter().map(|(key_expr, zids)| {
+                json!({
+                    "kind": kind.name(),
+                    "key_expr": key_expr,
+                    "zids": zids,
+                })
+            })
+        });
+        Value::Array(nodes.chain(entities).collect())
+    }
+
+    pub fn nodes_of(&self, whatami: &str) -> Vec<(&String, &NodeInfo)> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.whatami == whatami)
+            .collect()
+    }
+
+    fn add_admin_reply(&mut self, key: &str, payload: &[u8]) {
+        let segments: Vec<&str> = key.split('/').collect();
+        let [_, zid, whatami, rest @ ..] = segments.as_slice() else {
+            return;
+        };
+        self.add_node(zid.to_string(), whatami);
+
+        match rest {
+            [] => {
+                let Ok(info) = serde_json::from_slice::<Value>(payload) else {
+                    return;
+                };
+                if let Some(locators) = info["locators"].as_array() {
+                    let node = self.add_node(zid.to_string(), whatami);
+                    node.locators
+                        .extend(locators.iter().filter_map(|l| l.as_str()).map(String::from));
+                }
+                for session in info["sessions"].as_array().into_iter().flatten() {
+                    if let (Some(peer), Some(kind)) =
+                        (session["peer"].as_str(), session["whatami"].as_str())
+                    {
+                        self.add_node(peer.to_string(), kind);
+                    }
+                }
+            }
+            [segment, key_expr @ ..] if !key_expr.is_empty() => {
+                if let Some(kind) = EntityKind::from_admin_segment(segment) {
+                    self.entities
+                        .entry(kind)
+                        .or_default()
+                        .entry(key_expr.join("/"))
+                        .or_default()
+                        .insert(zid.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Gathers everything that can be learned about the network within `timeout`.
+pub async fn discover(
+    session: &Session,
+    config: &Config,
+    timeout: Duration,
+) -> Result<Discovery, Box<dyn Error + Send + Sync>> {
+    let mut discovery = Discovery::default();
+
+    for zid in session.info().routers_zid().await {
+        discovery.add_node(zid.to_string(), "router");
+    }
+    for zid in session.info().peers_zid().await {
+        discovery.add_node(zid.to_string(), "peer");
+    }
+
+    let scout = zenoh::scout(WhatAmI::Router | WhatAmI::Peer, config.clone()).await?;
+    let replies = session.get("@/**").timeout(timeout).await?;
+
+    let scouting = async {
+        let mut hellos = Vec::new();
+        let _ = time::timeout(timeout, async {
+            while let Ok(hello) = scout.recv_async().await {
+                hellos.push(hello);
+            }
+        })
+        .await;
+        hellos
+    };
+    let admin = async {
+        let mut samples = Vec::new();
+        while let Ok(reply) = replies.recv_async().await {
+            if let Ok(sample) = reply.result() {
+                samples.push((
+                    sample.key_expr().as_str().to_string(),
+                    sample.payload().to_bytes().into_owned(),
+                ));
+            }
+        }
+        samples
+    };
+    let (hellos, samples) = tokio::join!(scouting, admin);
+
+    for hello in hellos {
+        let node = discovery.add_node(hello.zid().to_string(), hello.whatami().to_str());
+        node.locators
+            .extend(hello.locators().iter().map(|l| l.to_string()));
+    }
+    for (key, payload) in samples {
+        discovery.add_admin_reply(&key, &payload);
+    }
+
+    Ok(discovery)
+}
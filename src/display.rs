@@ -0,0 +1,242 @@
+//! Display options for decoded messages: enums by name or number, bytes fields as base64, hex
+//! or just their length, and floats rounded to a precision.
+//!
+//! Enums and bytes fields are found through the message descriptor, so they are only rewritten
+//! for protobuf types; rounding applies to any JSON.
+//!
+//! Also syntax highlighting and single-line rendering of JSON text for `--compact`.
+
+use crate::terminal;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::ValueEnum;
+use colored::Colorize;
+use prost_reflect::{Kind, MessageDescriptor};
+use serde_json::{Number, Value};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BytesFormat {
+    Base64,
+    Hex,
+    /// Only the length, e.g. `<1024 bytes>`
+    Len,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EnumFormat {
+    Name,
+    Number,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct DisplayOptions {
+    pub bytes: Option<BytesFormat>,
+    pub enums: Option<EnumFormat>,
+    /// Decimal places floats are rounded to
+    pub precision: Option<u32>,
+}
+
+impl DisplayOptions {
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_none() && self.enums.is_none() && self.precision.is_none()
+    }
+
+    /// Rewrites `value`, a message of type `descriptor` if it has one, for display.
+    pub fn apply(&self, descriptor: Option<&MessageDescriptor>, value: &mut Value) {
+        if let Some(descriptor) =
+            descriptor.filter(|_| self.bytes.is_some() || self.enums.is_some())
+        {
+            self.message(descriptor, value);
+        }
+        if let Some(precision) = self.precision {
+            round(value, 10f64.powi(precision as i32));
+        }
+    }
+
+    fn message(&self, descriptor: &MessageDescriptor, value: &mut Value) {
+        let Value::Object(fields) = value else {
+            return;
+        };
+        for field in descriptor.fields() {
+            // Either name, depending on the JSON style the message was decoded with
+            let name = if fields.contains_key(field.json_name()) {
+                field.json_name()
+            } else {
+                field.name()
+            };
+            let Some(value) = fields.get_mut(name) else {
+                continue;
+            };
+            match (value, field.kind()) {
+                (Value::Object(entries), Kind::Message(entry)) if field.is_map() => {
+                    let kind = entry.map_entry_value_field().kind();
+                    for value in entries.values_mut() {
+                        self.field(&kind, value);
+                    }
+                }
+                (Value::Array(items), kind) if field.is_list() => {
+                    for item in items {
+                        self.field(&kind, item);
+                    }
+                }
+                (value, kind) => self.field(&kind, value),
+            }
+        }
+    }
+
+    fn field(&self, kind: &Kind, value: &mut Value) {
+        match kind {
+            Kind::Bytes => {
+                let Some(format) = self.bytes else {
+                    return;
+                };
+                let bytes: Option<Vec<u8>> = match value {
+                    Value::String(text) => BASE64_STANDARD.decode(text.as_str()).ok(),
+                    Value::Array(items) => items
+                        .iter()
+                        .map(|item| item.as_u64().and_then(|b| u8::try_from(b).ok()))
+                        .collect(),
+                    _ => None,
+                };
+                if let Some(bytes) = bytes {
+                    *value = Value::String(match format {
+                        BytesFormat::Base64 => BASE64_STANDARD.encode(&bytes),
+                        BytesFormat::Hex => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+                        BytesFormat::Len => format!("<{} bytes>", bytes.len()),
+                    });
+                }
+            }
+            Kind::Enum(enum_type) => {
+                let renamed = match (self.enums, &*value) {
+                    (Some(EnumFormat::Name), Value::Number(n)) => n
+                        .as_i64()
+                        .and_then(|n| enum_type.get_value(i32::try_from(n).ok()?))
+                        .map(|v| Value::String(v.name().to_string())),
+                    (Some(EnumFormat::Number), Value::String(name)) => enum_type
+                        .get_value_by_name(name)
+                        .map(|v| Value::from(v.number())),
+                    _ => None,
+                };
+                if let Some(renamed) = renamed {
+                    *value = renamed;
+                }
+            }
+            // Well-known types have JSON forms of their own
+            Kind::Message(message) if !message.full_name().starts_with("google.protobuf.") => {
+                self.message(message, value)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Rounds every non-integer number in `value` to a multiple of `1 / scale`.
+fn round(value: &mut Value, scale: f64) {
+    match value {
+        Value::Number(n) if n.is_f64() => {
+            if let Some(rounded) = n
+                .as_f64()
+                .and_then(|f| Number::from_f64((f * scale).round() / scale))
+            {
+                *n = rounded;
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| round(item, scale)),
+        Value::Object(fields) => fields.values_mut().for_each(|field| round(field, scale)),
+        _ => {}
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Key,
+    String,
+    Number,
+    /// `true`, `false` or `null`
+    Literal,
+    Whitespace,
+    Punctuation,
+}
+
+/// Splits `text`, which must be valid JSON, into tokens.
+fn tokens(text: &str) -> Vec<(Token, &str)> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let run = |pred: fn(u8) -> bool| {
+            bytes[start..]
+                .iter()
+                .position(|&b| !pred(b))
+                .map_or(bytes.len(), |n| start + n)
+        };
+        let (token, end) = match bytes[start] {
+            b'"' => {
+                let mut end = start + 1;
+                while end < bytes.len() && bytes[end] != b'"' {
+                    end += if bytes[end] == b'\\' { 2 } else { 1 };
+                }
+                let end = (end + 1).min(bytes.len());
+                // A string followed by a colon is an object key
+                let next = bytes[end..].iter().find(|b| !b.is_ascii_whitespace());
+                let token = if next == Some(&b':') {
+                    Token::Key
+                } else {
+                    Token::String
+                };
+                (token, end)
+            }
+            b if b.is_ascii_whitespace() => (Token::Whitespace, run(|b| b.is_ascii_whitespace())),
+            b'-' | b'0'..=b'9' => (
+                Token::Number,
+                run(|b| b.is_ascii_digit() || matches!(b, b'-' | b'+' | b'.' | b'e' | b'E')),
+            ),
+            b if b.is_ascii_alphabetic() => (Token::Literal, run(|b| b.is_ascii_alphabetic())),
+            _ => (Token::Punctuation, start + 1),
+        };
+        tokens.push((token, &text[start..end]));
+        start = end;
+    }
+    tokens
+}
+
+/// Whether `text` is a JSON object or array, as opposed to a scalar or plain text.
+fn is_json_document(text: &str) -> bool {
+    text.trim_start().starts_with(['{', '['])
+        && serde_json::from_str::<serde::de::IgnoredAny>(text).is_ok()
+}
+
+/// Colors the keys, strings, numbers and literals of JSON `text` distinctly; anything else is
+/// colored as a whole. Without colors the text is returned as is, without being parsed.
+pub fn highlight(text: &str) -> String {
+    if !terminal::stdout_colors() {
+        return text.to_string();
+    }
+    if !is_json_document(text) {
+        return text.yellow().to_string();
+    }
+    tokens(text)
+        .into_iter()
+        .map(|(token, text)| match token {
+            Token::Key => text.blue().to_string(),
+            Token::String => text.green().to_string(),
+            Token::Number => text.yellow().to_string(),
+            Token::Literal => text.magenta().to_string(),
+            Token::Whitespace | Token::Punctuation => text.to_string(),
+        })
+        .collect()
+}
+
+/// JSON `text` on a single line, keeping its field order, or `None` if it isn't a JSON object
+/// or array.
+pub fn compact(text: &str) -> Option<String> {
+    if !is_json_document(text) {
+        return None;
+    }
+    Some(
+        tokens(text)
+            .into_iter()
+            .filter(|(token, _)| *token != Token::Whitespace)
+            .map(|(_, text)| text)
+            .collect(),
+    )
+}
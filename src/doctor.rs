@@ -0,0 +1,331 @@
+//! `zspy doctor`: run the checks that usually explain why nothing shows up — scouting, the
+//! configured endpoints, multicast, the admin space and a publish/subscribe round trip between
+//! two sessions — and print what passed, what failed and what to try next.
+
+use crate::stats::format_duration;
+use crate::terminal;
+use clap::Args;
+use colored::Colorize;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::time;
+use zenoh::config::{Config, WhatAmI};
+use zenoh::sample::Locality;
+use zenoh::Session;
+
+#[derive(Args)]
+pub struct DoctorArgs {
+    /// How long each check may take
+    #[arg(long, default_value = "3s", value_parser = humantime::parse_duration)]
+    pub timeout: Duration,
+}
+
+enum Outcome {
+    Pass(String),
+    /// Not necessarily a problem, but worth knowing about
+    Warn(String, &'static str),
+    Fail(String, &'static str),
+    Skip(String),
+}
+
+#[derive(Default)]
+struct Report {
+    checks: usize,
+    failed: usize,
+}
+
+impl Report {
+    fn print(&mut self, name: &str, outcome: Outcome) {
+        if !matches!(outcome, Outcome::Skip(_)) {
+            self.checks += 1;
+        }
+        let (mark, detail, hint) = match outcome {
+            Outcome::Pass(detail) => (terminal::symbol("✔", "ok").green(), detail, None),
+            Outcome::Warn(detail, hint) => ("!".yellow(), detail, Some(hint)),
+            Outcome::Fail(detail, hint) => {
+                self.failed += 1;
+                (terminal::symbol("✘", "x").red(), detail, Some(hint))
+            }
+            Outcome::Skip(detail) => ("-".dimmed(), detail, None),
+        };
+        println!("{} {:<16} {}", mark, name.bold(), detail);
+        if let Some(hint) = hint {
+            println!(
+                "  {} {}",
+                terminal::symbol("→", "->").dimmed(),
+                hint.dimmed()
+            );
+        }
+    }
+}
+
+/// A config value, or `None` when it isn't set.
+fn get<T: serde::de::DeserializeOwned>(config: &Config, key: &str) -> Option<T> {
+    serde_json::from_str(&config.get_json(key).ok()?).ok()
+}
+
+async fn check_scouting(config: &Config, timeout: Duration) -> Outcome {
+    if get(config, "scouting/multicast/enabled") == Some(false) {
+        return Outcome::Skip("multicast scouting is disabled in the configuration".to_string());
+    }
+    let scout = match zenoh::scout(WhatAmI::Router | WhatAmI::Peer, config.clone()).await {
+        Ok(scout) => scout,
+        Err(e) => {
+            return Outcome::Fail(
+                format!("could not scout: {}", e),
+                "Check the scouting/multicast/interface setting of the configuration",
+            )
+        }
+    };
+    let mut found = BTreeSet::new();
+    let _ = time::timeout(timeout, async {
+        while let Ok(hello) = scout.recv_async().await {
+            found.insert((hello.whatami().to_str(), hello.zid().to_string()));
+        }
+    })
+    .await;
+    if found.is_empty() {
+        return Outcome::Warn(
+            "no routers or peers answered".to_string(),
+            "Start a router (`zenohd`) on this network, or connect to one with --connect",
+        );
+    }
+    let routers = found.iter().filter(|(kind, _)| *kind == "router").count();
+    Outcome::Pass(format!(
+        "{} routers and {} peers answered",
+        routers,
+        found.len() - routers
+    ))
+}
+
+/// Tries to reach `endpoint`, e.g. `tcp/192.168.1.1:7447`, without a Zenoh handshake.
+async fn check_endpoint(endpoint: &str, timeout: Duration) -> Outcome {
+    // Drop the endpoint's `?metadata` and `#config`
+    let locator = endpoint.split(['?', '#']).next().unwrap_or_default();
+    let Some((protocol, address)) = locator.split_once('/') else {
+        return Outcome::Fail(
+            "not a valid endpoint".to_string(),
+            "Endpoints look like <protocol>/<address>, e.g. tcp/192.168.1.1:7447",
+        );
+    };
+    match protocol {
+        "tcp" | "tls" | "ws" => match time::timeout(timeout, TcpStream::connect(address)).await {
+            Ok(Ok(_)) => Outcome::Pass("accepts TCP connections".to_string()),
+            Ok(Err(e)) => Outcome::Fail(
+                e.to_string(),
+                "Check the router is running there and the address, port and firewall",
+            ),
+            Err(_) => Outcome::Fail(
+                format!("no answer within {}", format_duration(timeout)),
+                "A firewall is probably dropping the connection, or the host is down",
+            ),
+        },
+        "unixsock-stream" if Path::new(address).exists() => {
+            Outcome::Pass("socket exists".to_string())
+        }
+        "unixsock-stream" => Outcome::Fail(
+            "socket does not exist".to_string(),
+            "Check the router listening on it is running",
+        ),
+        _ => Outcome::Skip(format!("{} endpoints can't be probed", protocol)),
+    }
+}
+
+fn check_multicast(config: &Config) -> Outcome {
+    if get(config, "scouting/multicast/enabled") == Some(false) {
+        return Outcome::Skip("multicast scouting is disabled in the configuration".to_string());
+    }
+    let group: SocketAddr = get::<String>(config, "scouting/multicast/address")
+        .and_then(|address| address.parse().ok())
+        .unwrap_or_else(|| SocketAddr::from(([224, 0, 0, 224], 7446)));
+    let probe = || -> std::io::Result<()> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        if let SocketAddr::V4(v4) = group {
+            socket.join_multicast_v4(v4.ip(), &Ipv4Addr::UNSPECIFIED)?;
+        }
+        // No route to the group fails here, which is how multicast usually turns out missing
+        socket.send_to(b"", group)?;
+        Ok(())
+    };
+    match probe() {
+        Ok(()) => Outcome::Pass(format!("can join and send to {}", group)),
+        Err(e) => Outcome::Fail(
+            format!("{}: {}", group, e),
+            "Multicast is often unavailable in containers, VMs and on VPNs or Wi-Fi; \
+             connect to a router explicitly with --connect instead",
+        ),
+    }
+}
+
+async fn check_admin_space(session: &Session, timeout: Duration) -> Outcome {
+    let replies = match session.get("@/**").timeout(timeout).await {
+        Ok(replies) => replies,
+        Err(e) => return Outcome::Fail(e.to_string(), "The session refused the query"),
+    };
+    let mut nodes = BTreeSet::new();
+    while let Ok(reply) = replies.recv_async().await {
+        if let Ok(sample) = reply.result() {
+            // Admin keys start with @/<zid>/<kind>
+            if let Some(zid) = sample.key_expr().as_str().split('/').nth(1) {
+                nodes.insert(zid.to_string());
+            }
+        }
+    }
+    nodes.remove(&session.zid().to_string());
+    if nodes.is_empty() {
+        return Outcome::Warn(
+            "no node answered `@/**`".to_string(),
+            "Routers answer by default; peers only with adminspace/enabled set. \
+             `list` and `admin` won't show entities without it",
+        );
+    }
+    Outcome::Pass(format!("{} nodes answered", nodes.len()))
+}
+
+/// Publishes from `session` until a second session, opened with the same configuration,
+/// receives it through the network.
+async fn check_loopback(session: &Session, config: &Config, timeout: Duration) -> Outcome {
+    let mut config = config.clone();
+    // The first session already holds the listening endpoints
+    if let Err(e) = config.insert_json5("listen/endpoints", "[]") {
+        return Outcome::Fail(e.to_string(), "The configuration could not be adjusted");
+    }
+    let receiver = match time::timeout(timeout, zenoh::open(config)).await {
+        Ok(Ok(receiver)) => receiver,
+        Ok(Err(e)) => {
+            return Outcome::Fail(
+                format!("could not open a second session: {}", e),
+                "Check the configuration, e.g. listen endpoints pinned to a port",
+            )
+        }
+        Err(_) => {
+            return Outcome::Fail(
+                "the second session did not open in time".to_string(),
+                "The router may be overloaded or refusing connections",
+            )
+        }
+    };
+    let key = format!("zspy/doctor/{}", session.zid());
+    let result = async {
+        let subscriber = receiver
+            .declare_subscriber(key.as_str())
+            .allowed_origin(Locality::Remote)
+            .await?;
+        let start = Instant::now();
+        // Routes take a moment to propagate, so keep publishing until the first one arrives
+        let mut ticker = time::interval(Duration::from_millis(100));
+        let received = time::timeout(timeout, async {
+            loop {
+                tokio::select! {
+                    sample = subscriber.recv_async() => break sample.map(|_| ()),
+                    _ = ticker.tick() => session.put(key.as_str(), "ping").await?,
+                }
+            }
+        })
+        .await;
+        Ok::<_, Box<dyn Error + Send + Sync>>(received.map(|r| r.map(|_| start.elapsed())))
+    }
+    .await;
+    let _ = receiver.close().await;
+    match result {
+        Ok(Ok(Ok(elapsed))) => Outcome::Pass(format!(
+            "a second session received a sample after {}",
+            format_duration(elapsed)
+        )),
+        Ok(Ok(Err(e))) | Err(e) => Outcome::Fail(e.to_string(), "Publishing failed"),
+        Ok(Err(_)) => Outcome::Fail(
+            format!("nothing arrived within {}", format_duration(timeout)),
+            "In client mode, check the router routes between its clients; in peer mode, peers \
+             find each other by multicast scouting, or need --listen and --connect endpoints",
+        ),
+    }
+}
+
+pub async fn run(config: &Config, args: &DoctorArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mode: String = get(config, "mode").unwrap_or_else(|| "peer".to_string());
+    let connect: Vec<String> = get(config, "connect/endpoints").unwrap_or_default();
+    let listen: Vec<String> = get(config, "listen/endpoints").unwrap_or_default();
+    let list = |endpoints: &[String]| match endpoints {
+        [] => "none".to_string(),
+        _ => endpoints.join(", "),
+    };
+    println!(
+        "Mode {}, connecting to {}, listening on {}\n",
+        mode.cyan(),
+        list(&connect),
+        list(&listen)
+    );
+
+    let mut report = Report::default();
+    report.print("Scouting", check_scouting(config, args.timeout).await);
+    if connect.is_empty() {
+        report.print(
+            "Endpoints",
+            Outcome::Skip("none configured; routers are found by scouting".to_string()),
+        );
+    }
+    for endpoint in &connect {
+        report.print(endpoint, check_endpoint(endpoint, args.timeout).await);
+    }
+    report.print("Multicast", check_multicast(config));
+
+    let session = match time::timeout(args.timeout, zenoh::open(config.clone())).await {
+        Ok(Ok(session)) => {
+            report.print(
+                "Session",
+                Outcome::Pass(format!("opened as {}", session.zid())),
+            );
+            Some(session)
+        }
+        Ok(Err(e)) => {
+            report.print(
+                "Session",
+                Outcome::Fail(
+                    e.to_string(),
+                    "Fix the failing checks above; in client mode a router must be reachable",
+                ),
+            );
+            None
+        }
+        Err(_) => {
+            report.print(
+                "Session",
+                Outcome::Fail(
+                    format!("did not open within {}", format_duration(args.timeout)),
+                    "In client mode a router must be reachable; try --mode peer",
+                ),
+            );
+            None
+        }
+    };
+    match &session {
+        Some(session) => {
+            report.print(
+                "Admin space",
+                check_admin_space(session, args.timeout).await,
+            );
+            report.print(
+                "Loopback",
+                check_loopback(session, config, args.timeout).await,
+            );
+        }
+        None => {
+            report.print("Admin space", Outcome::Skip("no session".to_string()));
+            report.print("Loopback", Outcome::Skip("no session".to_string()));
+        }
+    }
+    if let Some(session) = session {
+        let _ = session.close().await;
+    }
+
+    println!();
+    if report.failed > 0 {
+        return Err(format!("{} of {} checks failed", report.failed, report.checks).into());
+    }
+    println!("{}", "All checks passed".green());
+    Ok(())
+}
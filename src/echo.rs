@@ -0,0 +1,1429 @@
+//! `zspy echo`: subscribe to one or more key expressions and print every sample.
+
+use crate::attachment;
+use crate::codec::PayloadEncoding;
+use crate::compression::{Compression, Decompress};
+use crate::display::{self, BytesFormat, DisplayOptions, EnumFormat};
+use crate::error::ZspyError;
+use crate::expr::{self, Filter};
+use crate::jq::JqFilter;
+use crate::latency;
+use crate::message_registry::MessageRegistry;
+use crate::metrics;
+use crate::output::{
+    self, Console, EchoRecord, KeyTable, OnDecodeError, OutputFormat, Printer, RawEscape,
+    SplitOutput,
+};
+use crate::presence::PresenceOptions;
+use crate::publish::ReliabilityArg;
+use crate::reorder::Reorder;
+use crate::ros2;
+use crate::script::Script;
+use crate::stats::{format_bandwidth, format_summary};
+use crate::terminal;
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc as std_mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::time;
+use zenoh::{
+    handlers::{RingChannel, RingChannelHandler},
+    key_expr::KeyExpr,
+    pubsub::Subscriber,
+    sample::{Sample, SampleKind},
+    time::Timestamp,
+    Session,
+};
+
+/// Options for [`run`]. `Default` matches the CLI defaults apart from `keys`, which must be set.
+#[derive(Args, Default)]
+pub struct EchoArgs {
+    /// The key expressions to subscribe to
+    #[arg(required_unless_present = "key", value_delimiter = ',')]
+    pub keys: Vec<String>,
+    /// Additional key expression to subscribe to (repeatable, comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    pub key: Vec<String>,
+    /// Optional protobuf message type
+    #[arg(long)]
+    pub r#type: Option<String>,
+    /// Decode payloads in a schemaless format instead of a protobuf type
+    #[arg(long, value_enum, conflicts_with_all = ["type", "auto_decode"])]
+    pub encoding: Option<PayloadEncoding>,
+    /// Decode CDR payloads published by rmw_zenoh as this ROS 2 type, e.g.
+    /// `std_msgs/msg/String`. Keys starting with `/` are treated as ROS topics
+    #[arg(long, conflicts_with_all = ["type", "auto_decode", "encoding"])]
+    pub ros2_type: Option<String>,
+    /// Directory searched for `<package>/msg/<Type>.msg` definitions (repeatable)
+    #[arg(long, requires = "ros2_type")]
+    pub ros2_msg_path: Vec<PathBuf>,
+    /// ROS domain ID used to map topics to key expressions
+    #[arg(long, default_value = "0", requires = "ros2_type")]
+    pub ros_domain_id: u32,
+    /// Guess the payload format (JSON, text, CBOR or a registered protobuf type) when no
+    /// type is given
+    #[arg(long)]
+    pub auto_decode: bool,
+    /// Output format
+    #[arg(long, value_enum, default_value = "human")]
+    pub output: OutputFormat,
+    /// How to show binary payloads when no type is given (default: a hex dump of their start)
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub raw_escape: Option<RawEscape>,
+    /// Show the bytes fields of protobuf messages as base64, hex or just their length
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub bytes: Option<BytesFormat>,
+    /// Show the enum fields of protobuf messages by name or by number
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub enums: Option<EnumFormat>,
+    /// Round floats to this many decimal places
+    #[arg(long, value_name = "DIGITS")]
+    pub precision: Option<u32>,
+    /// Only print this field of the decoded message, e.g. `orientation.x` (repeatable)
+    #[arg(long)]
+    pub field: Vec<String>,
+    /// Transform each decoded message with a jq filter, e.g. `{x: .angularVelocity.x}`.
+    /// Messages for which it outputs nothing are skipped
+    #[arg(long, value_name = "FILTER", conflicts_with = "field")]
+    pub jq: Option<String>,
+    /// Run each decoded message through this Rhai script, which sees `key`, `timestamp` and
+    /// `value`, keeps a `state` map between messages, ends with the message to show (`()` drops
+    /// it) and may `publish(key, value)` derived messages as JSON
+    #[arg(long, value_name = "FILE")]
+    pub script: Option<PathBuf>,
+    /// Drop messages not matching this predicate, e.g. `angularVelocity.z > 0.5`
+    #[arg(long)]
+    pub filter: Option<String>,
+    /// Decompress zstd or LZ4 payloads before decoding: those starting with a frame of either
+    /// (`auto`, the default without a value), or all of them with the given algorithm
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ALGORITHM",
+        num_args = 0..=1,
+        default_missing_value = "auto"
+    )]
+    pub decompress: Option<Decompress>,
+    /// Only show samples of this kind
+    #[arg(long, value_enum)]
+    pub kind: Option<Kind>,
+    /// Only show samples published with this reliability, e.g. `best-effort` to watch the
+    /// streams that may lose samples on a lossy link. Zenoh lets publishers, not subscribers,
+    /// choose reliability, so this selects samples rather than changing delivery
+    #[arg(long, value_enum)]
+    pub reliability: Option<ReliabilityArg>,
+    /// Exit after printing this many messages
+    #[arg(long)]
+    pub count: Option<u64>,
+    /// Exit after this long, e.g. `30s`
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
+    /// Also show the priority, congestion control, reliability and express flag of each sample
+    #[arg(long, short)]
+    pub verbose: bool,
+    /// Before printing live samples, query the key expressions and print the stored samples
+    /// returned by storages and queryables
+    #[arg(long)]
+    pub history: bool,
+    /// Instead of printing each message, print a summary line every second
+    #[arg(long)]
+    pub stats: bool,
+    /// Only print the fields that changed since the previous message on the same key
+    #[arg(long)]
+    pub diff: bool,
+    /// Also write each payload to its own file in this directory, named by key, timestamp and
+    /// sequence number
+    #[arg(long)]
+    pub save_dir: Option<PathBuf>,
+    /// Write the decoded message to a `.json` file next to each saved payload
+    #[arg(long, requires = "save_dir")]
+    pub save_json: bool,
+    /// Instead of printing each message, keep redrawing a table of the last value on each key
+    /// (printed once at the end when stdout isn't a terminal, or with --plain)
+    #[arg(long, conflicts_with_all = ["stats", "output"])]
+    pub group_by_key: bool,
+    /// Instead of printing each message, write each key's messages as JSON lines to its own
+    /// file under this directory, e.g. `robot/imu.jsonl`
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["stats", "output", "group_by_key"])]
+    pub split_output: Option<PathBuf>,
+    /// Skip messages whose payload is identical to the previous one on the same key (unlike
+    /// --keep-overlaps, this compares payloads, not subscriptions)
+    #[arg(long)]
+    pub dedup: bool,
+    /// Print a sample once for every overlapping key expression it matches, instead of once;
+    /// unrelated to --dedup, which skips repeated payloads
+    #[arg(long)]
+    pub keep_overlaps: bool,
+    /// Warn when two or more sources publish to the same key within this window (default: 5s)
+    #[arg(
+        long,
+        value_name = "WINDOW",
+        num_args = 0..=1,
+        default_missing_value = "5s",
+        value_parser = humantime::parse_duration
+    )]
+    pub detect_conflicts: Option<Duration>,
+    /// Field holding a per-key sequence number, e.g. `header.seq`, used to report missed and
+    /// out-of-order messages
+    #[arg(long)]
+    pub seq_field: Option<String>,
+    /// Also show the rate of change per second of this numeric field since the previous message
+    /// on the same key, e.g. `odometry.ticks` (repeatable)
+    #[arg(long, value_name = "FIELD")]
+    pub derive: Vec<String>,
+    /// Decode the message embedded in a bytes field, e.g. `payload:my.pkg.Inner`. Repeatable;
+    /// applied in order, so later ones can reach into messages decoded by earlier ones
+    #[arg(long, value_name = "PATH:TYPE", value_parser = parse_decode_field)]
+    pub decode_field: Vec<(String, String)>,
+    /// What to do with a payload that fails to decode: show the error with a hex dump (default)
+    /// or the raw bytes, skip the message, or stop
+    #[arg(long, value_enum, default_value = "hex")]
+    pub on_decode_error: OnDecodeError,
+    /// Print at most this many messages per second on each key, e.g. `2hz`, dropping the rest
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    pub throttle: Option<f64>,
+    /// How many received samples may wait to be processed before zspy drops new ones, and how
+    /// many rendered messages may wait to be written (default: 10000)
+    #[arg(long, value_name = "SAMPLES")]
+    pub buffer_size: Option<usize>,
+    /// Instead of streaming every sample, keep only the latest this many on each key expression
+    /// and print them each time Enter is pressed, or every --pull-interval
+    #[arg(long, value_name = "SAMPLES")]
+    pub pull: Option<usize>,
+    /// Print the samples kept by --pull on this interval, e.g. `5s`, instead of on Enter
+    #[arg(long, requires = "pull", value_parser = latency::parse_interval)]
+    pub pull_interval: Option<Duration>,
+    /// Print each JSON message on a single line instead of pretty-printed
+    #[arg(long)]
+    pub compact: bool,
+    /// Shorten printed messages longer than this, e.g. `1KiB`, to their start and end and the
+    /// number of bytes left out
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_payload_display: Option<usize>,
+    /// Don't decode payloads larger than this, e.g. `4MiB`; they are shown as their size only
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_payload_size: Option<usize>,
+    /// Show which protobuf fields are set: unset `optional` fields and submessages print as
+    /// `null` and each oneof names its active member
+    #[arg(long, requires = "type")]
+    pub presence: bool,
+    /// Leave unset protobuf fields and fields at their default value out of decoded messages
+    #[arg(long, requires = "type")]
+    pub skip_defaults: bool,
+    /// Hold samples for this long, e.g. `200ms`, and print them sorted by their Zenoh
+    /// timestamps, so streams from several publishers show in causal order
+    #[arg(long, value_name = "WINDOW", value_parser = humantime::parse_duration, conflicts_with = "pull")]
+    pub order_by_timestamp: Option<Duration>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Kind {
+    Put,
+    Delete,
+}
+
+impl From<Kind> for SampleKind {
+    fn from(kind: Kind) -> Self {
+        match kind {
+            Kind::Put => SampleKind::Put,
+            Kind::Delete => SampleKind::Delete,
+        }
+    }
+}
+
+/// Parses a rate such as `2hz`, `0.5Hz` or `10`.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let number = s
+        .strip_suffix("hz")
+        .or_else(|| s.strip_suffix("Hz"))
+        .unwrap_or(s);
+    let rate = match number.trim().parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => rate,
+        _ => return Err(format!("'{}' is not a positive rate, e.g. `2hz`", s)),
+    };
+    // The throttle waits 1/rate between messages, which has to fit in a Duration
+    match Duration::try_from_secs_f64(1.0 / rate) {
+        Ok(_) => Ok(rate),
+        Err(_) => Err(format!("'{}' is too low a rate", s)),
+    }
+}
+
+/// Parses a size such as `512`, `4KiB`, `1.5MB` or `2M`, with all units powers of 1024.
+fn parse_size(s: &str) -> Result<usize, String> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(format!("'{}' is not a size, e.g. `1KiB`", s)),
+    };
+    match number.parse::<f64>() {
+        Ok(number) if number >= 0.0 => Ok((number * multiplier as f64) as usize),
+        _ => Err(format!("'{}' is not a size, e.g. `1KiB`", s)),
+    }
+}
+
+/// Shortens `text` to about `max` bytes for `--max-payload-display`, keeping its start and end
+/// around a note of how much was left out. Returns `None` if it already fits.
+fn truncate_display(text: &str, max: usize) -> Option<String> {
+    if text.len() <= max {
+        return None;
+    }
+    let mut head = max / 2;
+    while !text.is_char_boundary(head) {
+        head -= 1;
+    }
+    let mut tail = text.len() - max / 2;
+    while !text.is_char_boundary(tail) {
+        tail += 1;
+    }
+    let ellipsis = terminal::symbol("…", "...");
+    Some(format!(
+        "{} {} [{} of {} bytes not shown] {} {}",
+        &text[..head],
+        ellipsis,
+        tail - head,
+        text.len(),
+        ellipsis,
+        &text[tail..]
+    ))
+}
+
+fn parse_decode_field(s: &str) -> Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((path, msg_type)) if !path.is_empty() && !msg_type.is_empty() => {
+            Ok((path.to_string(), msg_type.to_string()))
+        }
+        _ => Err(format!("Invalid argument '{}': expected path:type", s)),
+    }
+}
+
+const DEFAULT_BUFFER_SIZE: usize = 10_000;
+
+/// Writes to the output on a thread of its own, so a slow terminal or pipe holds up rendering
+/// rather than the subscriber. Each flush hands everything written since to the thread;
+/// dropping the writer waits for the thread to write it all.
+struct BackgroundWriter {
+    buffer: Vec<u8>,
+    tx: Option<std_mpsc::SyncSender<Vec<u8>>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl BackgroundWriter {
+    fn new(mut out: Box<dyn Write + Send>, capacity: usize) -> Self {
+        let (tx, rx) = std_mpsc::sync_channel::<Vec<u8>>(capacity);
+        let thread = std::thread::spawn(move || {
+            for chunk in rx {
+                out.write_all(&chunk)?;
+                out.flush()?;
+            }
+            Ok(())
+        });
+        Self {
+            buffer: Vec::new(),
+            tx: Some(tx),
+            thread: Some(thread),
+        }
+    }
+
+    /// The error that stopped the thread, e.g. a closed pipe.
+    fn error(&mut self) -> io::Error {
+        self.tx = None;
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(Err(e))) => e,
+            _ => io::ErrorKind::BrokenPipe.into(),
+        }
+    }
+}
+
+impl Drop for BackgroundWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        self.tx = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Write for BackgroundWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::take(&mut self.buffer);
+        match &self.tx {
+            // Blocks while the thread is `capacity` chunks behind
+            Some(tx) if tx.send(chunk).is_ok() => Ok(()),
+            _ => Err(self.error()),
+        }
+    }
+}
+
+/// How often `--group-by-key` redraws the table while it changes
+const TABLE_REDRAW: Duration = Duration::from_millis(100);
+
+/// Counts accumulated between `--stats` reports.
+#[derive(Default)]
+struct IntervalStats {
+    messages: u64,
+    bytes: u64,
+    decode_failures: u64,
+}
+
+impl IntervalStats {
+    fn report(&self, elapsed: Duration) -> String {
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        format!(
+            "{} msgs | {:.2} Hz | {} | {} decode failures",
+            self.messages,
+            self.messages as f64 / secs,
+            format_bandwidth(self.bytes as f64 / secs),
+            self.decode_failures
+        )
+    }
+}
+
+/// How far a sequence number may fall behind the highest one seen on its key and still count
+/// as reordered rather than a restarted publisher
+const REORDER_WINDOW: u64 = 100;
+
+/// Sequence numbers seen per key for `--seq-field`.
+#[derive(Default)]
+struct SequenceTracker {
+    /// Highest sequence number seen per key
+    last: HashMap<String, u64>,
+    missed: u64,
+    out_of_order: u64,
+    duplicates: u64,
+}
+
+impl SequenceTracker {
+    /// Records `seq` on `key`, returning a warning if it isn't the next expected number.
+    fn observe(&mut self, key: &str, seq: u64) -> Option<String> {
+        let Some(last) = self.last.get_mut(key) else {
+            self.last.insert(key.to_string(), seq);
+            return None;
+        };
+        let previous = *last;
+        if seq > previous {
+            *last = seq;
+            let gap = seq - previous - 1;
+            self.missed += gap;
+            (gap > 0).then(|| {
+                format!(
+                    "Missed {} messages on {} ({} {} {})",
+                    gap,
+                    key,
+                    previous,
+                    terminal::symbol("→", "->"),
+                    seq
+                )
+            })
+        } else if seq == previous {
+            self.duplicates += 1;
+            Some(format!("Duplicate sequence {} on {}", seq, key))
+        } else if previous - seq > REORDER_WINDOW {
+            *last = seq;
+            Some(format!("Sequence on {} restarted at {}", key, seq))
+        } else {
+            // A late message was already counted as missed when the gap opened
+            self.out_of_order += 1;
+            self.missed = self.missed.saturating_sub(1);
+            Some(format!(
+                "Out-of-order sequence {} on {} (after {})",
+                seq, key, previous
+            ))
+        }
+    }
+}
+
+/// Per-key rate limit for `--throttle`.
+struct Throttle {
+    interval: Duration,
+    /// When a message was last let through on each key
+    last: HashMap<String, Instant>,
+    dropped: u64,
+}
+
+impl Throttle {
+    fn new(rate: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / rate),
+            last: HashMap::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Whether a message on `key` may be shown now.
+    fn allow(&mut self, key: &str, now: Instant) -> bool {
+        match self.last.get_mut(key) {
+            Some(last) if now.duration_since(*last) < self.interval => {
+                self.dropped += 1;
+                false
+            }
+            Some(last) => {
+                *last = now;
+                true
+            }
+            None => {
+                self.last.insert(key.to_string(), now);
+                true
+            }
+        }
+    }
+}
+
+/// Values of the `--derive` fields at the previous message on each key.
+#[derive(Default)]
+struct Derivatives {
+    previous: HashMap<String, (SystemTime, Vec<Option<f64>>)>,
+}
+
+impl Derivatives {
+    /// Records the `fields` of `value`, published at `time` on `key`, and returns the rate of
+    /// change of each that has a previous value to compare with.
+    fn observe(
+        &mut self,
+        fields: &[String],
+        key: &str,
+        time: SystemTime,
+        value: &Value,
+    ) -> Vec<(String, f64)> {
+        let values: Vec<Option<f64>> = fields
+            .iter()
+            .map(|field| match expr::get_path(value, field)? {
+                Value::Number(n) => n.as_f64(),
+                // 64-bit integers are strings in the protobuf JSON mapping
+                Value::String(s) => s.parse().ok(),
+                _ => None,
+            })
+            .collect();
+        let Some((last_time, last_values)) = self
+            .previous
+            .insert(key.to_string(), (time, values.clone()))
+        else {
+            return Vec::new();
+        };
+        let elapsed = match time.duration_since(last_time) {
+            Ok(elapsed) if !elapsed.is_zero() => elapsed.as_secs_f64(),
+            _ => return Vec::new(),
+        };
+        fields
+            .iter()
+            .zip(values.iter().zip(&last_values))
+            .filter_map(|(field, pair)| match pair {
+                (Some(value), Some(last)) => Some((field.clone(), (value - last) / elapsed)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Tracks which sources recently published on each key for `--detect-conflicts`.
+struct ConflictDetector {
+    window: Duration,
+    /// Last time each source was seen, per key
+    sources: HashMap<String, HashMap<String, Instant>>,
+    /// Samples that carried no source to attribute them to
+    unattributed: u64,
+    conflicting_keys: HashSet<String>,
+}
+
+impl ConflictDetector {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            sources: HashMap::new(),
+            unattributed: 0,
+            conflicting_keys: HashSet::new(),
+        }
+    }
+
+    /// Records a sample on `key` and returns the other sources active within the window if
+    /// `source` just joined them.
+    fn observe(&mut self, key: &str, source: Option<&str>, now: Instant) -> Option<Vec<String>> {
+        let Some(source) = source else {
+            self.unattributed += 1;
+            return None;
+        };
+        let window = self.window;
+        let sources = self.sources.entry(key.to_string()).or_default();
+        sources.retain(|_, seen| now.duration_since(*seen) <= window);
+        let joined = sources.insert(source.to_string(), now).is_none();
+        if !joined || sources.len() < 2 {
+            return None;
+        }
+        self.conflicting_keys.insert(key.to_string());
+        let mut others: Vec<String> = sources
+            .keys()
+            .filter(|other| *other != source)
+            .cloned()
+            .collect();
+        others.sort();
+        Some(others)
+    }
+}
+
+/// How many shown samples [`OverlapFilter`] remembers; copies arrive right after each other
+const OVERLAP_HISTORY: usize = 1024;
+
+/// Drops the copies of a sample delivered to each of several overlapping subscriptions.
+struct OverlapFilter {
+    subscriptions: Vec<KeyExpr<'static>>,
+    /// Recently shown samples by key and timestamp, whose ID identifies the source
+    seen: HashSet<(String, Timestamp)>,
+    order: VecDeque<(String, Timestamp)>,
+    dropped: u64,
+}
+
+impl OverlapFilter {
+    fn new(subscriptions: Vec<KeyExpr<'static>>) -> Self {
+        Self {
+            subscriptions,
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Whether `sample`, received through subscription `index`, was already shown.
+    fn is_duplicate(&mut self, index: usize, sample: &Sample) -> bool {
+        let duplicate = match sample.timestamp() {
+            Some(timestamp) => {
+                let id = (sample.key_expr().to_string(), *timestamp);
+                let duplicate = !self.seen.insert(id.clone());
+                if !duplicate {
+                    self.order.push_back(id);
+                    if self.order.len() > OVERLAP_HISTORY {
+                        if let Some(oldest) = self.order.pop_front() {
+                            self.seen.remove(&oldest);
+                        }
+                    }
+                }
+                duplicate
+            }
+            // Without a timestamp a copy can't be told from a repeated message, but every
+            // subscription matching the key gets one, so only keep the first subscription's
+            None => self.subscriptions[..index]
+                .iter()
+                .any(|subscription| subscription.intersects(sample.key_expr())),
+        };
+        if duplicate {
+            self.dropped += 1;
+        }
+        duplicate
+    }
+}
+
+/// The type to decode samples on `key` as: `--type`, or else the one in the type map unless
+/// another way of decoding was asked for.
+fn message_type<'a>(
+    registry: &'a MessageRegistry,
+    args: &'a EchoArgs,
+    key: &str,
+) -> Option<&'a str> {
+    args.r#type.as_deref().or_else(|| {
+        (args.encoding.is_none() && !args.auto_decode && args.ros2_type.is_none())
+            .then(|| registry.type_for_key(key))
+            .flatten()
+    })
+}
+
+fn to_record<'a>(
+    registry: &MessageRegistry,
+    args: &EchoArgs,
+    ros2: Option<&ros2::Decoder>,
+    subscription: Option<&'a str>,
+    sample: &'a Sample,
+    payload: &'a [u8],
+    history: bool,
+) -> EchoRecord<'a> {
+    // Samples are only timestamped when the publisher or a router has timestamping enabled;
+    // otherwise fall back to the local receive time
+    let timestamp = sample
+        .timestamp()
+        .map(|t| t.get_time().to_system_time())
+        .unwrap_or_else(SystemTime::now);
+    let source = sample.timestamp().map(|t| t.get_id().to_string());
+
+    let oversized = args.max_payload_size.is_some_and(|max| payload.len() > max);
+    let msg_type = message_type(registry, args, sample.key_expr().as_str());
+    // Binary payloads without a type are rendered per --raw-escape
+    let mut binary = None;
+    let (display, detected) = match (msg_type, args.encoding) {
+        // Deletes carry no payload to decode
+        _ if sample.kind() == SampleKind::Delete => (Ok(String::new()), None),
+        _ if oversized => (
+            Ok(format!(
+                "<{} byte payload not decoded: over --max-payload-size>",
+                payload.len()
+            )),
+            None,
+        ),
+        _ if ros2.is_some() => {
+            let display = ros2
+                .map(|decoder| decoder.decode(payload))
+                .transpose()
+                .and_then(|value| Ok(serde_json::to_string_pretty(&value)?));
+            (display, None)
+        }
+        (None, _) if args.auto_decode => {
+            let decoded = registry.auto_decode(payload);
+            if decoded.format != "binary" {
+                (Ok(decoded.text), Some(decoded.format))
+            } else {
+                let (text, dump, value) = output::render_binary(args.raw_escape, payload);
+                binary = Some((dump, value));
+                (Ok(text), Some(decoded.format))
+            }
+        }
+        (None, Some(encoding)) => (encoding.codec().decode(payload), None),
+        (Some(msg_type), _) if args.presence || args.skip_defaults => {
+            let options = PresenceOptions {
+                mark: args.presence,
+                skip_defaults: args.skip_defaults,
+                ..Default::default()
+            };
+            (registry.decode_presence(msg_type, payload, options), None)
+        }
+        (Some(msg_type), _) => (registry.decode(msg_type, payload), None),
+        (None, None) if output::is_binary(payload) => {
+            let (text, dump, value) = output::render_binary(args.raw_escape, payload);
+            binary = Some((dump, value));
+            (Ok(text), None)
+        }
+        (None, None) => (Ok(String::from_utf8_lossy(payload).to_string()), None),
+    };
+    let display =
+        if args.decode_field.is_empty() || sample.kind() == SampleKind::Delete || oversized {
+            display
+        } else {
+            display.and_then(|text| decode_fields(registry, &args.decode_field, &text))
+        };
+    let decode_failed = display.is_err();
+    let display = display.unwrap_or_else(|e| format!("Error decoding message: {}", e));
+    // Auto-detected text stays a string even if it happens to parse as a JSON scalar
+    let decoded = if sample.kind() == SampleKind::Delete {
+        Value::Null
+    } else if decode_failed {
+        args.on_decode_error.fallback(payload)
+    } else if let Some((_, value)) = &binary {
+        value.clone()
+    } else if !matches!(detected.as_deref(), Some("text" | "binary")) {
+        serde_json::from_str(&display).unwrap_or_else(|_| Value::String(display.clone()))
+    } else {
+        Value::String(display.clone())
+    };
+    // Text format is rendered from the payload; filters keep working on the JSON
+    let text_format_type = msg_type.or_else(|| {
+        detected
+            .as_deref()
+            .and_then(|format| format.strip_prefix("protobuf "))
+    });
+    let display = match text_format_type {
+        Some(msg_type)
+            if args.output == OutputFormat::Textproto
+                && !decode_failed
+                && !oversized
+                && args.decode_field.is_empty()
+                && sample.kind() == SampleKind::Put =>
+        {
+            registry
+                .decode_text_format(msg_type, payload)
+                .unwrap_or(display)
+        }
+        _ => display,
+    };
+
+    EchoRecord {
+        key: sample.key_expr().as_str(),
+        subscription,
+        timestamp,
+        source,
+        kind: sample.kind(),
+        priority: sample.priority(),
+        reliability: sample.reliability(),
+        congestion_control: sample.congestion_control(),
+        express: sample.express(),
+        encoding: sample.encoding().to_string(),
+        payload,
+        display,
+        decoded,
+        detected,
+        decode_failed,
+        attachment: sample.attachment().map(attachment::decode),
+        history,
+        shm: is_shm(sample),
+        compression: None,
+        rates: Vec::new(),
+        dump: binary.and_then(|(dump, _)| dump),
+    }
+}
+
+/// Applies `--decode-field` to a decoded message.
+fn decode_fields(
+    registry: &MessageRegistry,
+    fields: &[(String, String)],
+    text: &str,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let mut value: Value = serde_json::from_str(text)?;
+    for (path, msg_type) in fields {
+        registry.decode_embedded(&mut value, path, msg_type)?;
+    }
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
+#[cfg(feature = "shm")]
+fn is_shm(sample: &Sample) -> bool {
+    sample.payload().as_shm().is_some()
+}
+
+#[cfg(not(feature = "shm"))]
+fn is_shm(_sample: &Sample) -> bool {
+    false
+}
+
+/// Writes the record's payload (and with `json`, its JSON record) to `dir`, naming the files
+/// `<key>_<unix ms>_<seq>.bin` / `.json` with `/` and other unsafe characters in the key
+/// replaced by `_`.
+fn save_record(
+    dir: &Path,
+    seq: u64,
+    record: &EchoRecord,
+    json: bool,
+    verbose: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let key: String = record
+        .key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let millis = record
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    // Keys may contain '.', so the extension is appended rather than set with `with_extension`
+    let stem = format!("{}_{}_{:06}", key, millis, seq);
+    std::fs::write(dir.join(format!("{}.bin", stem)), record.payload)?;
+    if json {
+        let json = serde_json::to_string_pretty(&record.to_json(verbose))?;
+        std::fs::write(dir.join(format!("{}.json", stem)), json)?;
+    }
+    Ok(())
+}
+
+/// Replaces the record's payload with the fields that changed since the previous message on
+/// its key, returning false if nothing did. The first message on a key is shown in full.
+fn apply_diff(previous: &mut HashMap<String, Value>, record: &mut EchoRecord) -> bool {
+    if record.kind == SampleKind::Delete {
+        previous.remove(record.key);
+        return true;
+    }
+    let Some(old) = previous.insert(record.key.to_string(), record.decoded.clone()) else {
+        return true;
+    };
+    let changes = expr::diff(&old, &record.decoded);
+    if changes.is_empty() {
+        return false;
+    }
+    let label = |path: &str| {
+        if path.is_empty() {
+            "value".to_string()
+        } else {
+            path.to_string()
+        }
+    };
+    record.display = changes
+        .iter()
+        .map(|(path, old, new)| {
+            let arrow = terminal::symbol("→", "->");
+            format!("{}: {} {} {}", label(path), old, arrow, new)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    record.decoded = Value::Object(
+        changes
+            .into_iter()
+            .map(|(path, old, new)| (label(&path), json!({ "old": old, "new": new })))
+            .collect(),
+    );
+    true
+}
+
+/// Hands the samples kept by the `--pull` subscribers, tagged with their index, to the echo
+/// loop every `interval`, or each time a line is read from stdin without one.
+async fn pull(
+    subscribers: Vec<(usize, Subscriber<RingChannelHandler<Sample>>)>,
+    interval: Option<Duration>,
+    tx: mpsc::Sender<(usize, Sample)>,
+) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut ticks = interval.map(time::interval);
+    if let Some(ticks) = ticks.as_mut() {
+        ticks.tick().await;
+    }
+    loop {
+        match ticks.as_mut() {
+            Some(ticks) => {
+                ticks.tick().await;
+            }
+            None => match lines.next_line().await {
+                Ok(Some(_)) => {}
+                // Without a terminal there is nothing left to wait for
+                Ok(None) | Err(_) => return,
+            },
+        }
+        for (index, subscriber) in &subscribers {
+            while let Ok(Some(sample)) = subscriber.try_recv() {
+                if tx.send((*index, sample)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+pub async fn run(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &EchoArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    run_to(session, registry, args, &Console::stdio()).await
+}
+
+/// Like [`run`], but prints the samples, status lines, warnings and summary to `console`.
+pub async fn run_to(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &EchoArgs,
+    console: &Console,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(type_name) = &args.r#type {
+        registry.check_type(type_name)?;
+    }
+    for (_, type_name) in &args.decode_field {
+        registry.check_type(type_name)?;
+    }
+    let filter = args.filter.as_deref().map(Filter::parse).transpose()?;
+    let jq = args.jq.as_deref().map(JqFilter::parse).transpose()?;
+    let script = args.script.as_deref().map(Script::load).transpose()?;
+    let display = DisplayOptions {
+        bytes: args.bytes,
+        enums: args.enums,
+        precision: args.precision,
+    };
+    let ros2 = args
+        .ros2_type
+        .as_deref()
+        .map(|msg_type| ros2::Decoder::new(msg_type, &args.ros2_msg_path))
+        .transpose()?;
+    let keys: Vec<String> = args
+        .keys
+        .iter()
+        .chain(&args.key)
+        .map(|key| match args.ros2_type.as_deref() {
+            Some(msg_type) if key.starts_with('/') => {
+                ros2::topic_key_expr(key, msg_type, args.ros_domain_id)
+                    .ok_or_else(|| format!("Invalid ROS 2 type name: {}", msg_type))
+            }
+            _ => Ok(key.clone()),
+        })
+        .collect::<Result<_, _>>()?;
+    if let Some(dir) = &args.save_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut split = args
+        .split_output
+        .as_deref()
+        .map(SplitOutput::new)
+        .transpose()?;
+    let buffer_size = args.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE).max(1);
+    let (tx, mut rx) = mpsc::channel(buffer_size);
+    // Samples that arrive while the channel is full, counted rather than stalling Zenoh
+    let dropped = Arc::new(AtomicU64::new(0));
+
+    // Every subscriber feeds one channel so samples are printed in arrival order
+    let mut subscribers = Vec::new();
+    let mut pulled = Vec::new();
+    let mut subscriptions = Vec::new();
+    for (index, key) in keys.iter().enumerate() {
+        if args.output == OutputFormat::Human {
+            console.println(format!("Subscribing to key: {}", key.cyan()));
+        }
+        tracing::debug!(key = key.as_str(), "Declaring subscriber");
+        let builder = session.declare_subscriber(key.as_str());
+        if let Some(capacity) = args.pull {
+            // Zenoh keeps the latest samples, dropping older ones, until they are pulled
+            let subscriber = builder.with(RingChannel::new(capacity.max(1))).await?;
+            subscriptions.push(subscriber.key_expr().clone());
+            pulled.push((index, subscriber));
+            continue;
+        }
+        let tx = tx.clone();
+        let dropped = dropped.clone();
+        let subscriber = builder
+            .callback(move |sample| {
+                if let Err(mpsc::error::TrySendError::Full(_)) = tx.try_send((index, sample)) {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .await?;
+        subscriptions.push(subscriber.key_expr().clone());
+        subscribers.push(subscriber);
+    }
+    let puller = args.pull.map(|capacity| {
+        if args.output == OutputFormat::Human {
+            let trigger = match args.pull_interval {
+                Some(interval) => format!("every {}", humantime::format_duration(interval)),
+                None => "when Enter is pressed".to_string(),
+            };
+            console.println(format!(
+                "Keeping the latest {} samples per key expression, printed {}",
+                capacity.max(1),
+                trigger
+            ));
+        }
+        tokio::spawn(pull(pulled, args.pull_interval, tx.clone()))
+    });
+    drop(tx);
+    let mut overlaps =
+        (keys.len() > 1 && !args.keep_overlaps).then(|| OverlapFilter::new(subscriptions));
+
+    // Subscribers are declared first so no live sample is missed while querying
+    let mut history = VecDeque::new();
+    if args.history {
+        for (index, key) in keys.iter().enumerate() {
+            let replies = session.get(key.as_str()).await?;
+            while let Ok(reply) = replies.recv_async().await {
+                match reply.into_result() {
+                    Ok(sample) => history.push_back((index, sample)),
+                    Err(err) => tracing::warn!(
+                        key = key.as_str(),
+                        "History query error: {}",
+                        String::from_utf8_lossy(&err.payload().to_bytes())
+                    ),
+                }
+            }
+        }
+    }
+    let mut printer = Printer::with_writer(
+        args.output,
+        args.verbose,
+        args.on_decode_error,
+        Box::new(BackgroundWriter::new(console.out(), buffer_size)),
+    );
+
+    let start = Instant::now();
+    let deadline = args.duration.map(|d| start + d);
+    let mut report = time::interval(Duration::from_secs(1));
+    report.tick().await;
+    let mut interval = IntervalStats::default();
+    let mut last_report = start;
+    let (mut received, mut shown) = (0u64, 0u64);
+    let mut previous: HashMap<String, Value> = HashMap::new();
+    let mut saved = 0u64;
+    let mut last_payloads: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut table = KeyTable::default();
+    let live_table = terminal::live_updates();
+    // Redrawn on a timer rather than per sample, so a burst draws once and its last rows show
+    let mut redraw = time::interval(TABLE_REDRAW);
+    redraw.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+    let mut table_changed = false;
+    let mut conflicts = args.detect_conflicts.map(ConflictDetector::new);
+    let mut sequences = SequenceTracker::default();
+    let mut derivatives = Derivatives::default();
+    let mut throttle = args.throttle.map(Throttle::new);
+    let mut reported_drops = 0;
+    let mut oversized = 0u64;
+    let mut reorder = args.order_by_timestamp.map(Reorder::new);
+    // Samples released by --order-by-timestamp, and whether no more will arrive
+    let mut released = VecDeque::new();
+    let mut stopping = false;
+    while args.count.is_none_or(|count| shown < count) {
+        let release_at = reorder.as_ref().and_then(Reorder::next_deadline);
+        let (index, sample, is_history) = match history.pop_front() {
+            Some((index, sample)) => (index, sample, true),
+            None => match released.pop_front() {
+                Some((index, sample)) => (index, sample, false),
+                None if stopping => match reorder.as_mut().filter(|r| !r.is_empty()) {
+                    Some(reorder) => {
+                        released.extend(reorder.drain());
+                        continue;
+                    }
+                    None => break,
+                },
+                None => tokio::select! {
+                    sample = rx.recv() => match sample {
+                        Some((index, sample)) => match reorder.as_mut() {
+                            Some(reorder) => {
+                                let now = Instant::now();
+                                reorder.push(sample.timestamp().copied(), (index, sample), now);
+                                released.extend(reorder.release(now));
+                                continue;
+                            }
+                            None => (index, sample, false),
+                        },
+                        None => {
+                            stopping = true;
+                            continue;
+                        }
+                },
+                _ = time::sleep_until(release_at.unwrap_or(start).into()), if release_at.is_some() => {
+                    if let Some(reorder) = reorder.as_mut() {
+                        released.extend(reorder.release(Instant::now()));
+                    }
+                    continue;
+                }
+                _ = redraw.tick(), if table_changed => {
+                    table.draw(console);
+                    table_changed = false;
+                    continue;
+                }
+                _ = report.tick() => {
+                    let total = dropped.load(Ordering::Relaxed);
+                    if total > reported_drops {
+                        console.eprintln(format!(
+                                "Dropped {} samples: zspy can't keep up; raise --buffer-size, \
+                                 or do less per sample with --stats or --throttle",
+                                total - reported_drops
+                            )
+                            .yellow()
+                        );
+                        reported_drops = total;
+                    }
+                    if args.stats {
+                        let now = Instant::now();
+                        console.println(interval.report(now - last_report));
+                        interval = IntervalStats::default();
+                        last_report = now;
+                    }
+                    continue;
+                }
+                _ = time::sleep_until(deadline.unwrap_or(start).into()), if deadline.is_some() => {
+                    stopping = true;
+                    continue;
+                }
+                _ = tokio::signal::ctrl_c() => {
+                    stopping = true;
+                    continue;
+                }
+                },
+            },
+        };
+        if let Some(overlaps) = overlaps.as_mut() {
+            if overlaps.is_duplicate(index, &sample) {
+                continue;
+            }
+        }
+        received += 1;
+        let payload = sample.payload().to_bytes();
+        metrics::received(sample.key_expr().as_str(), payload.len());
+        if args.dedup {
+            let key = sample.key_expr().as_str();
+            if last_payloads
+                .get(key)
+                .is_some_and(|last| **last == *payload)
+            {
+                continue;
+            }
+            last_payloads.insert(key.to_string(), payload.to_vec());
+        }
+        if args.kind.is_some_and(|kind| sample.kind() != kind.into()) {
+            continue;
+        }
+        if args
+            .reliability
+            .is_some_and(|reliability| sample.reliability() != reliability.into())
+        {
+            continue;
+        }
+        let decompressed = match args.decompress.and_then(|d| d.algorithm(&payload)) {
+            Some(compression) => match compression.decompress(&payload) {
+                Ok(bytes) => Some((compression, bytes)),
+                Err(err) => {
+                    console.eprintln(format!("{} on {}", err, sample.key_expr()).yellow());
+                    None
+                }
+            },
+            None => None,
+        };
+        let bytes: &[u8] = match &decompressed {
+            Some((_, bytes)) => bytes,
+            None => &payload,
+        };
+        if args.max_payload_size.is_some_and(|max| bytes.len() > max) {
+            oversized += 1;
+        }
+        // Only tag samples with their subscription when there is more than one
+        let subscription = (keys.len() > 1).then(|| keys[index].as_str());
+        let mut record = to_record(
+            registry,
+            args,
+            ros2.as_ref(),
+            subscription,
+            &sample,
+            bytes,
+            is_history,
+        );
+        record.compression = decompressed
+            .as_ref()
+            .map(|(compression, _)| (compression.name(), payload.len()));
+        if let Some(compression) = Compression::detect(&payload)
+            .filter(|_| record.decode_failed && args.decompress.is_none())
+        {
+            record.display.push_str(&format!(
+                " (the payload looks {}-compressed; try --decompress)",
+                compression.name()
+            ));
+        }
+        if record.decode_failed {
+            interval.decode_failures += 1;
+            metrics::decode_error(record.key);
+            match args.on_decode_error {
+                OnDecodeError::Skip => continue,
+                OnDecodeError::Fail => {
+                    return Err(
+                        ZspyError::Decode(format!("{} on {}", record.display, record.key)).into(),
+                    )
+                }
+                OnDecodeError::Raw | OnDecodeError::Hex => {}
+            }
+        }
+        if let Some(detector) = conflicts.as_mut().filter(|_| !is_history) {
+            let source = record.source.as_deref();
+            if let Some(others) = detector.observe(record.key, source, Instant::now()) {
+                console.eprintln(
+                    format!(
+                        "Conflict on {}: {} publishes alongside {} (within {})",
+                        record.key,
+                        source.unwrap_or_default(),
+                        others.join(", "),
+                        humantime::format_duration(detector.window)
+                    )
+                    .yellow(),
+                );
+            }
+        }
+        if let Some(field) = args.seq_field.as_deref().filter(|_| !is_history) {
+            let seq = expr::get_path(&record.decoded, field).and_then(|value| match value {
+                Value::Number(n) => n.as_u64(),
+                Value::String(s) => s.parse().ok(),
+                _ => None,
+            });
+            if let Some(warning) = seq.and_then(|seq| sequences.observe(record.key, seq)) {
+                console.eprintln(warning.yellow());
+            }
+        }
+        if let Some(script) = script
+            .as_ref()
+            .filter(|_| record.kind == SampleKind::Put && !record.decode_failed)
+        {
+            let mut output = match script.run(record.key, record.timestamp, record.decoded.clone())
+            {
+                Ok(output) => output,
+                Err(err) => {
+                    console.eprintln(format!("Script error on {}: {}", record.key, err).yellow());
+                    continue;
+                }
+            };
+            if let Err(err) = output.publish(session).await {
+                console.eprintln(format!("Can't publish script output: {}", err).yellow());
+            }
+            record.decoded = match output.messages.pop() {
+                Some(message) => message,
+                None => continue,
+            };
+            record.render_decoded()?;
+        }
+        if filter.as_ref().is_some_and(|f| !f.matches(&record.decoded)) {
+            continue;
+        }
+        if !args.derive.is_empty() && record.kind == SampleKind::Put && !record.decode_failed {
+            record.rates =
+                derivatives.observe(&args.derive, record.key, record.timestamp, &record.decoded);
+        }
+        if let Some(dir) = args
+            .save_dir
+            .as_deref()
+            .filter(|_| record.kind == SampleKind::Put)
+        {
+            saved += 1;
+            save_record(dir, saved, &record, args.save_json, args.verbose)?;
+        }
+        if let Some(throttle) = throttle.as_mut() {
+            if !throttle.allow(record.key, Instant::now()) {
+                continue;
+            }
+        }
+        if !display.is_empty() && record.kind == SampleKind::Put && !record.decode_failed {
+            let msg_type = message_type(registry, args, record.key).or_else(|| {
+                record
+                    .detected
+                    .as_deref()
+                    .and_then(|format| format.strip_prefix("protobuf "))
+            });
+            let descriptor = msg_type.and_then(|msg_type| registry.descriptor(msg_type));
+            display.apply(descriptor.as_ref(), &mut record.decoded);
+            record.render_decoded()?;
+        }
+        // Keep the error of an undecodable message rather than projecting its raw payload
+        if !args.field.is_empty() && !record.decode_failed {
+            record.decoded = expr::project(&record.decoded, &args.field);
+            record.render_decoded()?;
+        }
+        if let Some(jq) = jq
+            .as_ref()
+            .filter(|_| record.kind == SampleKind::Put && !record.decode_failed)
+        {
+            let mut outputs = match jq.run(record.decoded.clone()) {
+                Ok(outputs) => outputs,
+                Err(err) => {
+                    console.eprintln(format!("jq error on {}: {}", record.key, err).yellow());
+                    continue;
+                }
+            };
+            // Several outputs, e.g. from `.[]`, are printed together as one array
+            record.decoded = match outputs.len() {
+                0 => continue,
+                1 => outputs.remove(0),
+                _ => Value::Array(outputs),
+            };
+            record.render_decoded()?;
+        }
+        if args.diff && !apply_diff(&mut previous, &mut record) {
+            continue;
+        }
+        if let Some(compact) = display::compact(&record.display).filter(|_| args.compact) {
+            record.display = compact;
+        }
+        if let Some(truncated) = args
+            .max_payload_display
+            .and_then(|max| truncate_display(&record.display, max))
+        {
+            record.display = truncated;
+        }
+        if args.stats {
+            interval.messages += 1;
+            interval.bytes += payload.len() as u64;
+        } else if args.group_by_key {
+            table.update(&record);
+            // Without a terminal to redraw on, the table is printed once at the end
+            table_changed = live_table;
+        } else if let Some(split) = split.as_mut() {
+            split.write(record.key, &record.to_json(args.verbose))?;
+        } else {
+            printer.print(&record)?;
+        }
+        shown += 1;
+    }
+
+    if let Some(puller) = puller {
+        puller.abort();
+    }
+    if args.group_by_key && received > 0 {
+        table.draw(console);
+    }
+    // Print every rendered message before the summary
+    drop(printer);
+    let mut summary = format!("Received {}", format_summary(received, start.elapsed()));
+    if shown != received {
+        summary.push_str(&format!(", {} shown", shown));
+    }
+    if let Some(dir) = &args.save_dir {
+        summary.push_str(&format!(", {} saved to {}", saved, dir.display()));
+    }
+    if let Some(split) = split.as_mut() {
+        split.flush()?;
+        summary.push_str(&format!(
+            ", {} keys written to {}",
+            split.keys(),
+            split.dir().display()
+        ));
+    }
+    if let Some(throttle) = &throttle {
+        summary.push_str(&format!(", {} throttled", throttle.dropped));
+    }
+    if let Some(reorder) = reorder.as_ref().filter(|r| r.late > 0) {
+        summary.push_str(&format!(
+            ", {} arrived too late to put in timestamp order",
+            reorder.late
+        ));
+    }
+    if let Some(reorder) = reorder.as_ref().filter(|r| r.untimestamped > 0) {
+        summary.push_str(&format!(
+            ", {} without a timestamp to order by (enable timestamping on publishers)",
+            reorder.untimestamped
+        ));
+    }
+    if oversized > 0 {
+        summary.push_str(&format!(
+            ", {} over --max-payload-size not decoded",
+            oversized
+        ));
+    }
+    let dropped = dropped.load(Ordering::Relaxed);
+    if dropped > 0 {
+        summary.push_str(&format!(
+            ", {} dropped by zspy while its buffer was full",
+            dropped
+        ));
+    }
+    if let Some(overlaps) = overlaps.as_ref().filter(|o| o.dropped > 0) {
+        summary.push_str(&format!(
+            ", {} copies from overlapping subscriptions dropped",
+            overlaps.dropped
+        ));
+    }
+    if args.seq_field.is_some() {
+        summary.push_str(&format!(
+            ", {} missed, {} out of order, {} duplicates",
+            sequences.missed, sequences.out_of_order, sequences.duplicates
+        ));
+    }
+    if let Some(detector) = &conflicts {
+        summary.push_str(&format!(
+            ", {} keys with conflicting sources",
+            detector.conflicting_keys.len()
+        ));
+        if detector.unattributed > 0 {
+            summary.push_str(&format!(
+                " ({} samples had no source; enable timestamping on publishers to identify them)",
+                detector.unattributed
+            ));
+        }
+    }
+    // Keep machine-readable output on stdout clean
+    if args.output == OutputFormat::Human {
+        console.println(summary.green());
+    } else {
+        console.eprintln(summary);
+    }
+
+    Ok(())
+}
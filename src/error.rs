@@ -0,0 +1,104 @@
+//! Error categories with distinct process exit codes, so scripts can tell failures apart.
+//!
+//! Functions keep returning `Box<dyn Error + Send + Sync>`; errors that fall into one of the
+//! categories below are a boxed [`ZspyError`], which [`classify`] recovers.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum ZspyError {
+    /// A message type name that isn't registered, and registered names it may be a typo of
+    UnknownType(String, Vec<String>),
+    /// A short or differently cased type name that matches several registered types
+    AmbiguousType(String, Vec<String>),
+    /// The Zenoh session could not be opened
+    Connection(String),
+    /// A payload or file could not be decoded
+    Decode(String),
+    /// A value could not be encoded as the requested type or format
+    Encode(String),
+    /// A wait or query gave up before the expected sample arrived
+    Timeout(String),
+    /// Messages checked by `zspy validate` broke its rules or couldn't be decoded
+    Validation(String),
+}
+
+impl ZspyError {
+    /// Wraps any error as a decode error, leaving errors that are already categorized alone.
+    pub fn decode(err: Box<dyn Error + Send + Sync>) -> Box<dyn Error + Send + Sync> {
+        if err.is::<ZspyError>() {
+            return err;
+        }
+        Box::new(ZspyError::Decode(err.to_string()))
+    }
+
+    /// Wraps any error as an encode error, leaving errors that are already categorized alone.
+    pub fn encode(err: Box<dyn Error + Send + Sync>) -> Box<dyn Error + Send + Sync> {
+        if err.is::<ZspyError>() {
+            return err;
+        }
+        Box::new(ZspyError::Encode(err.to_string()))
+    }
+}
+
+impl fmt::Display for ZspyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZspyError::UnknownType(name, suggestions) => {
+                write!(f, "Unknown message type: {}", name)?;
+                if !suggestions.is_empty() {
+                    write!(f, " (did you mean {}?)", suggestions.join(" or "))?;
+                }
+                Ok(())
+            }
+            ZspyError::AmbiguousType(name, candidates) => write!(
+                f,
+                "Ambiguous message type {}, could be: {}",
+                name,
+                candidates.join(", ")
+            ),
+            ZspyError::Connection(message) => {
+                write!(f, "Failed to open Zenoh session: {}", message)
+            }
+            ZspyError::Decode(message)
+            | ZspyError::Encode(message)
+            | ZspyError::Timeout(message)
+            | ZspyError::Validation(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for ZspyError {}
+
+/// Category name and exit code of an error returned by zspy.
+///
+/// | code | kind             |                                         |
+/// |------|------------------|-----------------------------------------|
+/// | 1    | `error`          | anything not listed below               |
+/// | 2    | `unknown_type`   | also used by clap for invalid arguments |
+/// | 2    | `ambiguous_type` | a short name matching several types     |
+/// | 3    | `connection`     |                                         |
+/// | 4    | `decode`         |                                         |
+/// | 5    | `encode`         |                                         |
+/// | 6    | `io`             | e.g. a file that can't be read          |
+/// | 7    | `timeout`        | e.g. `zspy wait` saw no matching sample |
+/// | 8    | `validation`     | `zspy validate` found invalid messages  |
+pub fn classify(err: &(dyn Error + 'static)) -> (&'static str, u8) {
+    if let Some(err) = err.downcast_ref::<ZspyError>() {
+        return match err {
+            ZspyError::UnknownType(..) => ("unknown_type", 2),
+            ZspyError::AmbiguousType(..) => ("ambiguous_type", 2),
+            ZspyError::Connection(_) => ("connection", 3),
+            ZspyError::Decode(_) => ("decode", 4),
+            ZspyError::Encode(_) => ("encode", 5),
+            ZspyError::Timeout(_) => ("timeout", 7),
+            ZspyError::Validation(_) => ("validation", 8),
+        };
+    }
+    if err.is::<io::Error>() {
+        return ("io", 6);
+    }
+    ("error", 1)
+}
@@ -0,0 +1,181 @@
+//! `zspy export`: write selected numeric fields of decoded messages to CSV for plotting.
+//!
+//! Each row holds the sample time in seconds since the UNIX epoch, the key, and one column per
+//! field path. Booleans become `1`/`0`, numeric strings (e.g. 64-bit integers of dynamic
+//! protobuf types) are kept, and missing or non-numeric values leave the cell empty.
+
+use crate::codec::PayloadEncoding;
+use crate::expr;
+use crate::message_registry::MessageRegistry;
+use crate::output::csv_field;
+use crate::recording::{self, RecordedSample};
+use clap::Args;
+use colored::Colorize;
+use serde_json::Value;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zenoh::{key_expr::KeyExpr, sample::SampleKind, Session};
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// The key expression to export, e.g. `robot/**`
+    pub key_expr: String,
+    /// Protobuf type of the payloads
+    #[arg(long)]
+    pub r#type: Option<String>,
+    /// Schemaless format of the payloads (default: JSON)
+    #[arg(long, value_enum, conflicts_with = "type")]
+    pub encoding: Option<PayloadEncoding>,
+    /// Comma-separated field paths to export, e.g. `angularVelocity.x,timestamp`
+    #[arg(long, required = true, value_delimiter = ',')]
+    pub fields: Vec<String>,
+    /// CSV file to write (default: stdout)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Read samples from this capture file instead of subscribing
+    #[arg(long)]
+    pub input: Option<PathBuf>,
+}
+
+impl ExportArgs {
+    fn decode(
+        &self,
+        registry: &MessageRegistry,
+        payload: &[u8],
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        registry.decode_with(self.r#type.as_deref(), self.encoding, payload)
+    }
+}
+
+/// The CSV cell for a field value: numbers, booleans as `1`/`0`, or empty.
+fn numeric_cell(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::Number(n)) => n.to_string(),
+        Some(Value::Bool(b)) => u8::from(*b).to_string(),
+        Some(Value::String(s)) if s.parse::<f64>().is_ok() => s.clone(),
+        _ => String::new(),
+    }
+}
+
+struct CsvWriter {
+    out: Box<dyn Write>,
+    rows: u64,
+    failed: u64,
+}
+
+impl CsvWriter {
+    fn new(mut out: Box<dyn Write>, fields: &[String]) -> io::Result<Self> {
+        let header: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        writeln!(out, "time,key,{}", header.join(","))?;
+        Ok(Self {
+            out,
+            rows: 0,
+            failed: 0,
+        })
+    }
+
+    /// Writes a row for `sample`, or skips it with a warning if it doesn't decode.
+    fn write(
+        &mut self,
+        registry: &MessageRegistry,
+        args: &ExportArgs,
+        sample: &RecordedSample,
+    ) -> io::Result<()> {
+        if sample.kind == SampleKind::Delete {
+            return Ok(());
+        }
+        let value = match args.decode(registry, &sample.payload) {
+            Ok(value) => value,
+            Err(err) => {
+                self.failed += 1;
+                eprintln!(
+                    "{}",
+                    format!("Skipping sample on {}: {}", sample.key, err).yellow()
+                );
+                return Ok(());
+            }
+        };
+        let cells: Vec<String> = args
+            .fields
+            .iter()
+            .map(|field| numeric_cell(expr::get_path(&value, field)))
+            .collect();
+        writeln!(
+            self.out,
+            "{}.{:09},{},{}",
+            sample.timestamp_ns / 1_000_000_000,
+            sample.timestamp_ns % 1_000_000_000,
+            csv_field(&sample.key),
+            cells.join(",")
+        )?;
+        self.rows += 1;
+        Ok(())
+    }
+}
+
+pub async fn run(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &ExportArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(msg_type) = &args.r#type {
+        registry.check_type(msg_type)?;
+    }
+    let key_expr = KeyExpr::try_from(args.key_expr.as_str())?;
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let mut csv = CsvWriter::new(out, &args.fields)?;
+
+    match &args.input {
+        Some(input) => {
+            let mut reader = recording::open(input)?;
+            while let Some(sample) = reader.read()? {
+                let matches = KeyExpr::try_from(sample.key.as_str())
+                    .is_ok_and(|key| key.intersects(&key_expr));
+                if matches {
+                    csv.write(registry, args, &sample)?;
+                }
+            }
+        }
+        None => {
+            let subscriber = session.declare_subscriber(&key_expr).await?;
+            eprintln!(
+                "Exporting {} (press Ctrl-C to stop)",
+                key_expr.as_str().cyan()
+            );
+            tokio::select! {
+                result = async {
+                    while let Ok(sample) = subscriber.recv_async().await {
+                        let timestamp = sample
+                            .timestamp()
+                            .map(|t| t.get_time().to_system_time())
+                            .unwrap_or_else(SystemTime::now);
+                        let timestamp_ns = timestamp
+                            .duration_since(UNIX_EPOCH)
+                            .map_or(0, |d| d.as_nanos() as u64);
+                        let sample = RecordedSample::from_sample(&sample, timestamp_ns);
+                        csv.write(registry, args, &sample)?;
+                    }
+                    Ok::<_, Box<dyn Error + Send + Sync>>(())
+                } => result?,
+                _ = tokio::signal::ctrl_c() => {}
+            }
+        }
+    }
+    csv.out.flush()?;
+
+    let mut summary = format!("Exported {} rows", csv.rows);
+    if let Some(path) = &args.output {
+        summary.push_str(&format!(" to {}", path.display()));
+    }
+    if csv.failed > 0 {
+        summary.push_str(&format!(", {} failed to decode", csv.failed));
+    }
+    eprintln!("{}", summary.green());
+    Ok(())
+}
@@ -0,0 +1,434 @@
+//! Field paths and filter expressions evaluated over decoded JSON messages.
+//!
+//! A field path is a dot-separated list of object keys and array indices, e.g.
+//! `angular_velocity.z` or `points.0.x` (`points[0].x` is accepted too).
+//!
+//! A filter is a boolean expression over field paths:
+//!
+//! ```text
+//! expr       := or
+//! or         := and ("||" and)*
+//! and        := unary ("&&" unary)*
+//! unary      := "!" unary | "(" expr ")" | comparison
+//! comparison := operand (("==" | "!=" | ">" | ">=" | "<" | "<=") operand)?
+//! operand    := number | "string" | 'string' | true | false | null | path
+//! ```
+//!
+//! A bare operand is true when it resolves to a value other than `null`, `false`, `0` or `""`.
+
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::fmt;
+
+fn segments(path: &str) -> Vec<String> {
+    path.replace('[', ".")
+        .replace(']', "")
+        .split('.')
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Resolves `path` inside `value`, returning `None` if any segment is missing.
+pub fn get_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut current = value;
+    for segment in segments(path) {
+        current = match current {
+            Value::Object(fields) => fields.get(&segment)?,
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+fn get_path_mut<'a>(value: &'a mut Value, segments: &[String]) -> Option<&'a mut Value> {
+    let mut current = value;
+    for segment in segments {
+        current = match current {
+            Value::Object(fields) => fields.get_mut(segment)?,
+            Value::Array(items) => items.get_mut(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+/// Removes the field at `path` from `value` and returns it, if it exists.
+pub fn remove_path(value: &mut Value, path: &str) -> Option<Value> {
+    let segments = segments(path);
+    let (last, parents) = segments.split_last()?;
+    match get_path_mut(value, parents)? {
+        Value::Object(fields) => fields.remove(last),
+        Value::Array(items) => {
+            let index = last.parse::<usize>().ok().filter(|&i| i < items.len())?;
+            Some(items.remove(index))
+        }
+        _ => None,
+    }
+}
+
+/// Sets the field at `path` inside `value`, creating or replacing objects along the way.
+/// Array elements can be replaced but not appended.
+pub fn set_path(value: &mut Value, path: &str, new_value: Value) {
+    let mut current = value;
+    for segment in segments(path) {
+        if let Value::Array(items) = current {
+            match segment.parse::<usize>().ok().filter(|&i| i < items.len()) {
+                Some(index) => {
+                    current = &mut items[index];
+                    continue;
+                }
+                None => return,
+            }
+        }
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        current = current
+            .as_object_mut()
+            .expect("just made an object")
+            .entry(segment)
+            .or_insert(Value::Null);
+    }
+    *current = new_value;
+}
+
+/// Builds the projection of `value` onto `paths`: the bare value for a single path, otherwise
+/// an object keyed by path. Missing fields become `null`.
+pub fn project(value: &Value, paths: &[String]) -> Value {
+    match paths {
+        [path] => get_path(value, path).cloned().unwrap_or(Value::Null),
+        _ => Value::Object(
+            paths
+                .iter()
+                .map(|p| {
+                    (
+                        p.clone(),
+                        get_path(value, p).cloned().unwrap_or(Value::Null),
+                    )
+                })
+                .collect::<Map<_, _>>(),
+        ),
+    }
+}
+
+/// Lists the leaf fields that differ between `old` and `new` as `(path, old, new)`, descending
+/// into objects and arrays. Fields missing on one side are `null` there.
+pub fn diff(old: &Value, new: &Value) -> Vec<(String, Value, Value)> {
+    let mut changes = Vec::new();
+    diff_at("", old, new, &mut changes);
+    changes
+}
+
+fn diff_at(path: &str, old: &Value, new: &Value, changes: &mut Vec<(String, Value, Value)>) {
+    let child = |segment: &str| {
+        if path.is_empty() {
+            segment.to_string()
+        } else {
+            format!("{}.{}", path, segment)
+        }
+    };
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            for (name, old_value) in old_fields {
+                let new_value = new_fields.get(name).unwrap_or(&Value::Null);
+                diff_at(&child(name), old_value, new_value, changes);
+            }
+            for (name, new_value) in new_fields {
+                if !old_fields.contains_key(name) {
+                    diff_at(&child(name), &Value::Null, new_value, changes);
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            for i in 0..old_items.len().max(new_items.len()) {
+                let old_value = old_items.get(i).unwrap_or(&Value::Null);
+                let new_value = new_items.get(i).unwrap_or(&Value::Null);
+                diff_at(&child(&i.to_string()), old_value, new_value, changes);
+            }
+        }
+        _ if old != new => changes.push((path.to_string(), old.clone(), new.clone())),
+        _ => {}
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid filter expression: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Path(String),
+    Literal(Value),
+    Op(CompareOp),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Path(String),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Truthy(Operand),
+    Compare(Operand, CompareOp, Operand),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// A parsed filter expression.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(ParseError(format!(
+                "unexpected {:?}",
+                parser.tokens[parser.pos]
+            )));
+        }
+        Ok(Self { expr })
+    }
+
+    pub fn matches(&self, value: &Value) -> bool {
+        eval(&self.expr, value)
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            '&' if next == Some('&') => {
+                tokens.push(Token::And);
+                i += 2;
+            }
+            '|' if next == Some('|') => {
+                tokens.push(Token::Or);
+                i += 2;
+            }
+            '=' if next == Some('=') => {
+                tokens.push(Token::Op(CompareOp::Eq));
+                i += 2;
+            }
+            '!' if next == Some('=') => {
+                tokens.push(Token::Op(CompareOp::Ne));
+                i += 2;
+            }
+            '!' => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            '>' | '<' => {
+                let op = match (c, next == Some('=')) {
+                    ('>', true) => CompareOp::Ge,
+                    ('>', false) => CompareOp::Gt,
+                    ('<', true) => CompareOp::Le,
+                    _ => CompareOp::Lt,
+                };
+                tokens.push(Token::Op(op));
+                i += if next == Some('=') { 2 } else { 1 };
+            }
+            '"' | '\'' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&ch| ch == c)
+                    .ok_or_else(|| ParseError("unterminated string".to_string()))?;
+                let text: String = chars[i + 1..i + 1 + end].iter().collect();
+                tokens.push(Token::Literal(Value::String(text)));
+                i += end + 2;
+            }
+            _ => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric()
+                        || matches!(chars[i], '_' | '.' | '-' | '[' | ']'))
+                {
+                    i += 1;
+                }
+                if start == i {
+                    return Err(ParseError(format!("unexpected character '{}'", c)));
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.as_str() {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    _ => match word.parse::<f64>() {
+                        Ok(n) => Token::Literal(serde_json::json!(n)),
+                        Err(_) => Token::Path(word),
+                    },
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            lhs = Expr::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(ParseError("expected ')'".to_string())),
+                }
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        let lhs = self.operand()?;
+        if let Some(Token::Op(op)) = self.peek().cloned() {
+            self.pos += 1;
+            let rhs = self.operand()?;
+            return Ok(Expr::Compare(lhs, op, rhs));
+        }
+        Ok(Expr::Truthy(lhs))
+    }
+
+    fn operand(&mut self) -> Result<Operand, ParseError> {
+        match self.next() {
+            Some(Token::Path(path)) => Ok(Operand::Path(path)),
+            Some(Token::Literal(value)) => Ok(Operand::Literal(value)),
+            Some(token) => Err(ParseError(format!("unexpected {:?}", token))),
+            None => Err(ParseError("unexpected end of expression".to_string())),
+        }
+    }
+}
+
+fn resolve<'a>(operand: &'a Operand, value: &'a Value) -> Option<&'a Value> {
+    match operand {
+        Operand::Path(path) => get_path(value, path),
+        Operand::Literal(literal) => Some(literal),
+    }
+}
+
+fn truthy(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(b)) => *b,
+        Some(Value::Number(n)) => n.as_f64() != Some(0.0),
+        Some(Value::String(s)) => !s.is_empty(),
+        Some(_) => true,
+    }
+}
+
+fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        // Enums and 64-bit integers may be rendered as strings; compare them numerically
+        (Value::String(a), Value::Number(b)) => a.parse::<f64>().ok()?.partial_cmp(&b.as_f64()?),
+        (Value::Number(a), Value::String(b)) => a.as_f64()?.partial_cmp(&b.parse::<f64>().ok()?),
+        _ => None,
+    }
+}
+
+fn eval(expr: &Expr, value: &Value) -> bool {
+    match expr {
+        Expr::Truthy(operand) => truthy(resolve(operand, value)),
+        Expr::Compare(lhs, op, rhs) => {
+            let lhs = resolve(lhs, value).unwrap_or(&Value::Null);
+            let rhs = resolve(rhs, value).unwrap_or(&Value::Null);
+            let ordering = compare(lhs, rhs);
+            match op {
+                CompareOp::Eq => ordering == Some(Ordering::Equal),
+                CompareOp::Ne => ordering != Some(Ordering::Equal),
+                CompareOp::Gt => ordering == Some(Ordering::Greater),
+                CompareOp::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                CompareOp::Lt => ordering == Some(Ordering::Less),
+                CompareOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            }
+        }
+        Expr::Not(inner) => !eval(inner, value),
+        Expr::And(lhs, rhs) => eval(lhs, value) && eval(rhs, value),
+        Expr::Or(lhs, rhs) => eval(lhs, value) || eval(rhs, value),
+    }
+}
@@ -0,0 +1,287 @@
+//! `zspy gateway`: an HTTP bridge for browser dashboards and scripts.
+//!
+//! - `GET /subscribe/<keyexpr>` streams matching samples as Server-Sent Events, one JSON object
+//!   per `data:` line.
+//! - `POST /publish/<key>` publishes the request body.
+//!
+//! Both accept `?type=<protobuf type>` or `?encoding=json|cbor|msgpack` to decode samples to
+//! JSON, or encode a JSON (or JSON5/YAML) body, through the message registry.
+
+use crate::codec::{self, PayloadEncoding};
+use crate::http::{self, Request};
+use crate::message_registry::MessageRegistry;
+use crate::metrics;
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+use zenoh::{key_expr::KeyExpr, sample::Sample, Session};
+
+/// How often idle event streams get a comment line, so dead clients are noticed
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+const USAGE: &str = "zspy gateway\n\n\
+    GET  /subscribe/<keyexpr>[?type=<T>|?encoding=<E>]  Server-Sent Events of matching samples\n\
+    POST /publish/<key>[?type=<T>|?encoding=<E>]        Publish the request body\n";
+
+#[derive(Args)]
+pub struct GatewayArgs {
+    /// Port to serve HTTP on
+    #[arg(long, default_value = "8080")]
+    pub http_port: u16,
+    /// Address to listen on; use `0.0.0.0` to accept connections from other hosts
+    #[arg(long, default_value = "127.0.0.1")]
+    pub bind: IpAddr,
+    /// Let pages served from this origin, e.g. `http://localhost:3000` or `*` for any, call the
+    /// gateway from a browser
+    #[arg(long)]
+    pub cors_origin: Option<String>,
+}
+
+/// How payloads are converted, from the `type` and `encoding` query parameters.
+enum Format {
+    Type(String),
+    Encoding(PayloadEncoding),
+    Raw,
+}
+
+impl Format {
+    fn from_query(registry: &MessageRegistry, request: &Request) -> Result<Self, String> {
+        match (request.query.get("type"), request.query.get("encoding")) {
+            (Some(_), Some(_)) => Err("Use either type or encoding, not both".to_string()),
+            (Some(msg_type), None) => {
+                registry.check_type(msg_type).map_err(|e| e.to_string())?;
+                Ok(Format::Type(msg_type.clone()))
+            }
+            (None, Some(encoding)) => PayloadEncoding::from_str(encoding, true)
+                .map(Format::Encoding)
+                .map_err(|_| format!("Unknown encoding: {}", encoding)),
+            (None, None) => Ok(Format::Raw),
+        }
+    }
+
+    fn decode(
+        &self,
+        registry: &MessageRegistry,
+        payload: &[u8],
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let text = match self {
+            Format::Type(msg_type) => registry.decode(msg_type, payload)?,
+            Format::Encoding(encoding) => encoding.codec().decode(payload)?,
+            Format::Raw => {
+                let text = String::from_utf8_lossy(payload).into_owned();
+                // Untyped payloads stay strings unless they hold JSON
+                return Ok(serde_json::from_str(&text).unwrap_or(Value::String(text)));
+            }
+        };
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    fn encode(
+        &self,
+        registry: &MessageRegistry,
+        body: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let text = || std::str::from_utf8(body);
+        match self {
+            Format::Type(msg_type) => registry.encode(msg_type, &codec::to_json(text()?)?),
+            Format::Encoding(encoding) => encoding.codec().encode(&codec::to_json(text()?)?),
+            Format::Raw => Ok(body.to_vec()),
+        }
+    }
+}
+
+fn sample_json(registry: &MessageRegistry, format: &Format, sample: &Sample) -> Value {
+    let payload = sample.payload().to_bytes();
+    let timestamp = sample
+        .timestamp()
+        .map(|t| t.get_time().to_system_time())
+        .unwrap_or_else(SystemTime::now);
+    let mut value = json!({
+        "key": sample.key_expr().as_str(),
+        "kind": sample.kind().to_string(),
+        "timestamp": humantime::format_rfc3339_nanos(timestamp).to_string(),
+        "encoding": sample.encoding().to_string(),
+    });
+    match format.decode(registry, &payload) {
+        Ok(decoded) => value["payload"] = decoded,
+        Err(err) => {
+            metrics::decode_error(sample.key_expr().as_str());
+            value["error"] = json!(err.to_string());
+        }
+    }
+    value
+}
+
+pub async fn run(
+    session: Session,
+    registry: Arc<MessageRegistry>,
+    args: &GatewayArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let listener = TcpListener::bind(SocketAddr::new(args.bind, args.http_port)).await?;
+    println!(
+        "Serving gateway on {}",
+        format!("http://{}", listener.local_addr()?).cyan()
+    );
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let (session, registry) = (session.clone(), registry.clone());
+        let cors = args.cors_origin.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle(stream, peer, &session, &registry, cors.as_deref()).await {
+                tracing::debug!(%peer, "Gateway request failed: {}", err);
+            }
+        });
+    }
+    println!("\n{}", "Gateway stopped".green());
+    Ok(())
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    session: &Session,
+    registry: &MessageRegistry,
+    cors: Option<&str>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let Some(request) = http::read_request(&mut stream).await? else {
+        return Ok(());
+    };
+    let format = match Format::from_query(registry, &request) {
+        Ok(format) => format,
+        Err(message) => return bad_request(&mut stream, cors, &message).await,
+    };
+    let route = (
+        request.method.as_str(),
+        request.path.strip_prefix("/subscribe/"),
+        request.path.strip_prefix("/publish/"),
+    );
+
+    match route {
+        ("GET", Some(key), _) => {
+            let Ok(key_expr) = KeyExpr::try_from(key.to_string()) else {
+                return bad_request(
+                    &mut stream,
+                    cors,
+                    &format!("Invalid key expression: {}", key),
+                )
+                .await;
+            };
+            println!("{} subscribed to {}", peer, key_expr.as_str().cyan());
+            stream_samples(&mut stream, session, registry, &key_expr, &format, cors).await?;
+            println!("{} unsubscribed from {}", peer, key_expr.as_str().cyan());
+            Ok(())
+        }
+        ("POST", _, Some(key)) => {
+            let Ok(key_expr) = KeyExpr::try_from(key.to_string()) else {
+                return bad_request(&mut stream, cors, &format!("Invalid key: {}", key)).await;
+            };
+            let payload = match format.encode(registry, &request.body) {
+                Ok(payload) => payload,
+                Err(err) => return bad_request(&mut stream, cors, &err.to_string()).await,
+            };
+            let len = payload.len();
+            let mut put = session.put(&key_expr, payload);
+            if let Format::Encoding(encoding) = format {
+                put = put.encoding(encoding.zenoh_encoding());
+            }
+            if let Err(err) = put.await {
+                return bad_request(&mut stream, cors, &err.to_string()).await;
+            }
+            metrics::published(key_expr.as_str(), len);
+            let body = json!({ "key": key_expr.as_str(), "bytes": len }).to_string();
+            http::respond(
+                &mut stream,
+                "200 OK",
+                "application/json",
+                cors,
+                body.as_bytes(),
+            )
+            .await?;
+            Ok(())
+        }
+        ("OPTIONS", _, _) => {
+            Ok(http::respond(&mut stream, "204 No Content", "text/plain", cors, b"").await?)
+        }
+        ("GET", _, _) if request.path == "/" => {
+            Ok(http::respond(&mut stream, "200 OK", "text/plain", cors, USAGE.as_bytes()).await?)
+        }
+        ("GET" | "POST", _, _) => Ok(http::respond(
+            &mut stream,
+            "404 Not Found",
+            "text/plain",
+            cors,
+            USAGE.as_bytes(),
+        )
+        .await?),
+        _ => Ok(http::respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            cors,
+            b"",
+        )
+        .await?),
+    }
+}
+
+async fn bad_request(
+    stream: &mut TcpStream,
+    cors: Option<&str>,
+    message: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let body = json!({ "error": message }).to_string();
+    http::respond(
+        stream,
+        "400 Bad Request",
+        "application/json",
+        cors,
+        body.as_bytes(),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Sends samples on `key_expr` as Server-Sent Events until the client disconnects.
+async fn stream_samples(
+    stream: &mut TcpStream,
+    session: &Session,
+    registry: &MessageRegistry,
+    key_expr: &KeyExpr<'_>,
+    format: &Format,
+    cors: Option<&str>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let subscriber = session.declare_subscriber(key_expr).await?;
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n{}Connection: close\r\n\r\n",
+        http::cors_headers(cors)
+    );
+    stream.write_all(head.as_bytes()).await?;
+
+    let mut keepalive = time::interval(KEEPALIVE);
+    keepalive.tick().await;
+    loop {
+        let event = tokio::select! {
+            sample = subscriber.recv_async() => {
+                let sample = sample?;
+                metrics::received(sample.key_expr().as_str(), sample.payload().len());
+                format!("data: {}\n\n", sample_json(registry, format, &sample))
+            }
+            _ = keepalive.tick() => ": keepalive\n\n".to_string(),
+        };
+        // A failed write means the client went away
+        if stream.write_all(event.as_bytes()).await.is_err() {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! `zspy stats`: collect the payload sizes and inter-arrival times of a key expression for a
+//! while and print their percentiles and histograms, to profile a topic before consuming it.
+//!
+//! Inter-arrival times are measured per key, so a wildcard over several keys reports how often
+//! each of them is published rather than how closely their samples interleave.
+
+use crate::stats::{format_duration, format_summary};
+use crate::terminal;
+use clap::Args;
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::time;
+use zenoh::Session;
+
+/// Width of the longest histogram bar, in characters
+const BAR_WIDTH: usize = 40;
+
+#[derive(Args)]
+pub struct StatsArgs {
+    /// The key expression to profile
+    pub key_expr: String,
+    /// How long to collect samples for (Ctrl-C stops early)
+    #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+    pub duration: Duration,
+    /// Number of histogram buckets
+    #[arg(long, default_value = "10", value_parser = clap::value_parser!(u16).range(1..))]
+    pub buckets: u16,
+    /// Also write the percentiles and histograms to this file as JSON
+    #[arg(long, value_name = "FILE")]
+    pub json: Option<PathBuf>,
+}
+
+struct Bucket {
+    low: f64,
+    high: f64,
+    count: u64,
+}
+
+/// Percentiles and histogram of a set of values.
+struct Distribution {
+    min: f64,
+    max: f64,
+    mean: f64,
+    /// The 50th, 90th, 99th and 99.9th percentiles
+    percentiles: [(&'static str, f64); 4],
+    buckets: Vec<Bucket>,
+}
+
+impl Distribution {
+    /// Summarizes `values` into `buckets` equally wide buckets, which are whole numbers wide
+    /// when `integral`. Returns `None` when there are no values.
+    fn new(values: &mut [f64], buckets: usize, integral: bool) -> Option<Self> {
+        values.sort_by(f64::total_cmp);
+        let (min, max) = (*values.first()?, *values.last()?);
+        let percentile = |p: f64| values[(p / 100.0 * (values.len() - 1) as f64).round() as usize];
+        let mut width = (max - min) / buckets as f64;
+        if integral {
+            width = width.ceil().max(1.0);
+        }
+        let count = if width > 0.0 {
+            (((max - min) / width).floor() as usize + 1).min(buckets)
+        } else {
+            1
+        };
+        let mut histogram: Vec<Bucket> = (0..count)
+            .map(|i| {
+                let low = min + i as f64 * width;
+                let high = if i + 1 == count {
+                    max
+                } else if integral {
+                    low + width - 1.0
+                } else {
+                    low + width
+                };
+                Bucket {
+                    low,
+                    high,
+                    count: 0,
+                }
+            })
+            .collect();
+        for value in values.iter() {
+            let index = if width > 0.0 {
+                ((value - min) / width) as usize
+            } else {
+                0
+            };
+            histogram[index.min(count - 1)].count += 1;
+        }
+        Some(Self {
+            min,
+            max,
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            percentiles: [
+                ("p50", percentile(50.0)),
+                ("p90", percentile(90.0)),
+                ("p99", percentile(99.0)),
+                ("p99.9", percentile(99.9)),
+            ],
+            buckets: histogram,
+        })
+    }
+
+    fn print(&self, title: &str, format: impl Fn(f64) -> String) {
+        let mut line = format!(
+            "min {}, mean {}, max {}",
+            format(self.min),
+            format(self.mean),
+            format(self.max)
+        );
+        for (name, value) in &self.percentiles {
+            line.push_str(&format!(", {} {}", name, format(*value)));
+        }
+        println!("\n{}: {}", title.green(), line);
+        let labels: Vec<(String, String)> = self
+            .buckets
+            .iter()
+            .map(|b| (format(b.low), format(b.high)))
+            .collect();
+        let low_width = labels.iter().map(|(l, _)| l.len()).max().unwrap_or(0);
+        let high_width = labels.iter().map(|(_, h)| h.len()).max().unwrap_or(0);
+        let most = self.buckets.iter().map(|b| b.count).max().unwrap_or(1);
+        for (bucket, (low, high)) in self.buckets.iter().zip(&labels) {
+            let bar = (bucket.count as f64 / most as f64 * BAR_WIDTH as f64).round() as usize;
+            // Keep a sliver of bar for buckets that aren't empty but round down to nothing
+            let bar = if bucket.count > 0 { bar.max(1) } else { 0 };
+            println!(
+                "  {:>lw$} {} {:>hw$} {}{} {}",
+                low,
+                terminal::symbol("–", "-"),
+                high,
+                terminal::symbol("│", "|"),
+                terminal::symbol("█", "#").repeat(bar).cyan(),
+                bucket.count,
+                lw = low_width,
+                hw = high_width
+            );
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut object = json!({
+            "min": self.min,
+            "mean": self.mean,
+            "max": self.max,
+            "histogram": self.buckets.iter().map(|b| json!({
+                "low": b.low,
+                "high": b.high,
+                "count": b.count,
+            })).collect::<Vec<_>>(),
+        });
+        for (name, value) in &self.percentiles {
+            object[name] = json!(value);
+        }
+        object
+    }
+}
+
+pub async fn run(session: &Session, args: &StatsArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let subscriber = session.declare_subscriber(args.key_expr.as_str()).await?;
+    println!(
+        "Collecting statistics on {} for {}",
+        args.key_expr.cyan(),
+        humantime::format_duration(args.duration)
+    );
+
+    let start = Instant::now();
+    let mut sizes = Vec::new();
+    // Inter-arrival times in seconds, measured between samples on the same key
+    let mut intervals = Vec::new();
+    let mut last_arrival: HashMap<String, Instant> = HashMap::new();
+    let deadline = time::sleep(args.duration);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            sample = subscriber.recv_async() => {
+                let sample = sample?;
+                let now = Instant::now();
+                sizes.push(sample.payload().len() as f64);
+                let key = sample.key_expr().as_str();
+                if let Some(last) = last_arrival.insert(key.to_string(), now) {
+                    intervals.push(now.duration_since(last).as_secs_f64());
+                }
+            }
+            _ = &mut deadline => break,
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    let elapsed = start.elapsed();
+    let messages = sizes.len() as u64;
+    println!(
+        "\n{} on {} keys",
+        format!("Received {}", format_summary(messages, elapsed)).green(),
+        last_arrival.len()
+    );
+
+    let buckets = args.buckets as usize;
+    let sizes = Distribution::new(&mut sizes, buckets, true);
+    let intervals = Distribution::new(&mut intervals, buckets, false);
+    if let Some(sizes) = &sizes {
+        sizes.print("Payload size", |bytes| format!("{:.0} B", bytes));
+    }
+    match &intervals {
+        Some(intervals) => intervals.print("Inter-arrival time", |secs| {
+            format_duration(Duration::from_secs_f64(secs))
+        }),
+        None if messages > 0 => {
+            println!("\nNo key received more than one sample; no inter-arrival times to show")
+        }
+        None => {}
+    }
+
+    if let Some(path) = &args.json {
+        let report = json!({
+            "key_expr": args.key_expr,
+            "duration_secs": elapsed.as_secs_f64(),
+            "messages": messages,
+            "keys": last_arrival.len(),
+            "payload_size_bytes": sizes.as_ref().map(Distribution::to_json),
+            "inter_arrival_secs": intervals.as_ref().map(Distribution::to_json),
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&report)? + "\n")?;
+        println!("\nWrote statistics to {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Just enough HTTP/1.1 for the metrics endpoint and `zspy gateway`: one request per
+//! connection, bodies sized by `Content-Length`.
+
+use std::collections::HashMap;
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Request heads larger than this are rejected rather than buffered
+const MAX_HEAD_LEN: usize = 8192;
+/// Largest accepted request body
+const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+
+/// Headers that let pages served from `origin`, e.g. a browser dashboard, call the endpoints.
+/// Without an origin, browsers keep other sites' pages from reading the responses.
+pub fn cors_headers(origin: Option<&str>) -> String {
+    origin
+        .map(|origin| {
+            format!(
+                "Access-Control-Allow-Origin: {}\r\nAccess-Control-Allow-Methods: GET, POST, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type\r\n",
+                origin
+            )
+        })
+        .unwrap_or_default()
+}
+
+pub struct Request {
+    pub method: String,
+    /// Percent-decoded path, without the query string
+    pub path: String,
+    /// Percent-decoded query parameters
+    pub query: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// Reads one request from `stream`, or `None` if the client closed the connection or sent
+/// something too large or malformed.
+pub async fn read_request(stream: &mut TcpStream) -> io::Result<Option<Request>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_len = loop {
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 || buf.len() + n > MAX_HEAD_LEN + chunk.len() {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_len]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Ok(None);
+    };
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if content_length > MAX_BODY_LEN {
+        return Ok(None);
+    }
+
+    let mut body = buf[head_len..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    Ok(Some(Request {
+        method: method.to_string(),
+        path: percent_decode(path, false),
+        query: query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(name, true), percent_decode(value, true))
+            })
+            .collect(),
+        body,
+    }))
+}
+
+/// Writes a complete response and closes the connection. `status` is e.g. `200 OK`; `cors` is
+/// the origin allowed to read it, if any.
+pub async fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    cors: Option<&str>,
+    body: &[u8],
+) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len(),
+        cors_headers(cors)
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await
+}
+
+/// Decodes `%XX` escapes in `input`, and with `query`, `+` as a space.
+fn percent_decode(input: &str, query: bool) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = input.get(i + 1..i + 3);
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b'+' if query => out.push(b' '),
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,41 @@
+//! jq filters for `zspy echo --jq`, evaluated in-process with jaq.
+
+use jaq_interpret::{Ctx, FilterT, ParseCtx, RcIter, Val};
+use serde_json::Value;
+use std::error::Error;
+
+/// A compiled jq filter, with jq's standard library available.
+pub struct JqFilter {
+    filter: jaq_interpret::Filter,
+}
+
+impl JqFilter {
+    pub fn parse(expr: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let (main, errors) = jaq_parse::parse(expr, jaq_parse::main());
+        let main = match main {
+            Some(main) if errors.is_empty() => main,
+            _ => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                return Err(format!("Invalid jq filter '{}': {}", expr, errors.join("; ")).into());
+            }
+        };
+        let mut defs = ParseCtx::new(Vec::new());
+        defs.insert_natives(jaq_core::core());
+        defs.insert_defs(jaq_std::std());
+        let filter = defs.compile(main);
+        if !defs.errs.is_empty() {
+            let errors: Vec<String> = defs.errs.iter().map(|(e, _)| e.to_string()).collect();
+            return Err(format!("Invalid jq filter '{}': {}", expr, errors.join("; ")).into());
+        }
+        Ok(Self { filter })
+    }
+
+    /// Runs the filter on `input`, returning all of its outputs, or the first error.
+    pub fn run(&self, input: Value) -> Result<Vec<Value>, String> {
+        let inputs = RcIter::new(std::iter::empty());
+        self.filter
+            .run((Ctx::new([], &inputs), Val::from(input)))
+            .map(|output| output.map(Value::from).map_err(|e| e.to_string()))
+            .collect()
+    }
+}
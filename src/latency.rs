@@ -0,0 +1,131 @@
+//! Round-trip latency measurement for `zspy ping` / `zspy pong`.
+//!
+//! `ping` publishes on `<key>/ping` and listens on `<key>/pong`; `pong` republishes every
+//! ping payload verbatim. Each ping payload starts with the sequence number and the send time
+//! (nanoseconds since the ping process started, both little-endian u64), padded to the
+//! requested size, so the RTT is measured on a single monotonic clock.
+
+use colored::Colorize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::time;
+use zenoh::Session;
+
+/// Size of the sequence number and timestamp header at the start of each ping
+pub const HEADER_LEN: usize = 16;
+
+pub struct PingOptions {
+    pub count: u64,
+    pub interval: Duration,
+    pub size: usize,
+    pub timeout: Duration,
+}
+
+fn ping_key(key: &str) -> String {
+    format!("{}/ping", key)
+}
+
+fn pong_key(key: &str) -> String {
+    format!("{}/pong", key)
+}
+
+/// Parses the delay between pings or test messages, which must be non-zero.
+pub fn parse_interval(s: &str) -> Result<Duration, String> {
+    match humantime::parse_duration(s) {
+        Ok(interval) if interval.is_zero() => Err("the interval must be more than 0s".to_string()),
+        Ok(interval) => Ok(interval),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Returns the `p`th percentile (0-100) of already sorted samples.
+pub fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p / 100.0 * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+pub async fn run_ping(
+    session: &Session,
+    key: &str,
+    options: &PingOptions,
+) -> Result<Vec<Duration>, Box<dyn Error + Send + Sync>> {
+    if options.count == 0 {
+        return Ok(Vec::new());
+    }
+    let size = options.size.max(HEADER_LEN);
+    let publisher = session.declare_publisher(ping_key(key)).await?;
+    let subscriber = session.declare_subscriber(pong_key(key)).await?;
+    let start = Instant::now();
+
+    let mut pending = HashMap::new();
+    let mut rtts = Vec::new();
+    let mut ticker = time::interval(options.interval);
+    let mut seq = 0u64;
+    let mut deadline = None;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick(), if seq < options.count => {
+                let sent_ns = start.elapsed().as_nanos() as u64;
+                let mut payload = vec![0u8; size];
+                payload[..8].copy_from_slice(&seq.to_le_bytes());
+                payload[8..HEADER_LEN].copy_from_slice(&sent_ns.to_le_bytes());
+                publisher.put(payload).await?;
+                pending.insert(seq, sent_ns);
+                seq += 1;
+                if seq == options.count {
+                    deadline = Some(Instant::now() + options.timeout);
+                }
+            }
+            sample = subscriber.recv_async() => {
+                let sample = sample?;
+                let payload = sample.payload().to_bytes();
+                if payload.len() < HEADER_LEN {
+                    continue;
+                }
+                let reply_seq = u64::from_le_bytes(payload[..8].try_into()?);
+                let sent_ns = u64::from_le_bytes(payload[8..HEADER_LEN].try_into()?);
+                if pending.remove(&reply_seq).is_none() {
+                    continue;
+                }
+                let rtt = Duration::from_nanos(
+                    (start.elapsed().as_nanos() as u64).saturating_sub(sent_ns),
+                );
+                println!(
+                    "{} bytes from {}: seq={} rtt={:.3}ms",
+                    payload.len(),
+                    pong_key(key).cyan(),
+                    reply_seq,
+                    rtt.as_secs_f64() * 1e3
+                );
+                rtts.push(rtt);
+            }
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => break,
+        }
+        if seq == options.count && pending.is_empty() {
+            break;
+        }
+    }
+
+    Ok(rtts)
+}
+
+pub async fn run_pong(session: &Session, key: &str) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let publisher = session.declare_publisher(pong_key(key)).await?;
+    let subscriber = session.declare_subscriber(ping_key(key)).await?;
+    let mut count = 0;
+    loop {
+        tokio::select! {
+            sample = subscriber.recv_async() => {
+                publisher.put(sample?.payload().clone()).await?;
+                count += 1;
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    Ok(count)
+}
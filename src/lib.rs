@@ -0,0 +1,86 @@
+//! zspy's decoding, encoding and pub/sub machinery for Zenoh, usable without the CLI.
+//!
+//! [`MessageRegistry`] maps message type names to protobuf codecs, with the types compiled
+//! from `proto/` registered by [`MessageRegistry::with_builtin_types`]. Schemaless formats
+//! implement [`Codec`] in [`codec`], and ROS 2 CDR payloads are decoded by [`ros2::Decoder`].
+//! The `echo`, `publish` and `recording` modules hold the engines behind the matching
+//! subcommands.
+//!
+//! ```
+//! use zspy::MessageRegistry;
+//!
+//! let registry = MessageRegistry::with_builtin_types();
+//! let json = r#"{"angular_velocity": {"x": 1.0, "y": 0.0, "z": 0.0},
+//!     "linear_acceleration": null, "timestamp": 42}"#;
+//! let bytes = registry.encode("zspy.ImuMessage", json)?;
+//! println!("{}", registry.decode("zspy.ImuMessage", &bytes)?);
+//! # Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+//! ```
+
+pub mod admin;
+pub mod attachment;
+pub mod bench;
+pub mod bridge;
+pub mod cache;
+pub mod capture;
+pub mod codec;
+pub mod compare;
+pub mod compression;
+pub mod discovery;
+pub mod display;
+pub mod doctor;
+pub mod echo;
+pub mod error;
+pub mod export;
+pub mod expr;
+pub mod gateway;
+pub mod histogram;
+pub mod http;
+pub mod jq;
+pub mod latency;
+pub mod logging;
+pub mod message_registry;
+pub mod metrics;
+pub mod monitor;
+pub mod output;
+pub mod pager;
+pub mod playback;
+pub mod plot;
+pub mod plugin;
+pub mod presence;
+pub mod profile;
+pub mod publish;
+pub mod random;
+pub mod recording;
+pub mod reorder;
+pub mod ros2;
+pub mod scenario;
+pub mod schema;
+pub mod script;
+pub mod selftest;
+pub mod session_config;
+pub mod snapshot;
+pub mod stats;
+pub mod template;
+pub mod terminal;
+pub mod trace;
+pub mod transform;
+pub mod tree;
+pub mod type_map;
+pub mod validate;
+pub mod wait;
+pub mod well_known;
+
+pub use codec::Codec;
+pub use message_registry::MessageRegistry;
+
+// Include the generated proto code
+pub use self::proto::*;
+pub mod proto {
+    include!(concat!(env!("OUT_DIR"), "/protos.rs"));
+}
+
+// Include the auto-generated registry
+mod registry {
+    include!(concat!(env!("OUT_DIR"), "/registry.rs"));
+}
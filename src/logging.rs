@@ -0,0 +1,38 @@
+//! Global command-line options controlling diagnostic logging.
+//!
+//! zspy and Zenoh both log through `tracing`, so one subscriber shows why e.g. a session fails
+//! to connect. Logs go to stderr to keep command output on stdout clean.
+
+use crate::terminal;
+use clap::Args;
+use std::error::Error;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Args)]
+pub struct LogArgs {
+    /// Log filter, e.g. `debug` or `zenoh=debug,zspy=trace` (default: $RUST_LOG, else `error`)
+    #[arg(long, global = true)]
+    pub log_level: Option<String>,
+    /// Emit logs as one JSON object per line
+    #[arg(long, global = true)]
+    pub log_json: bool,
+}
+
+impl LogArgs {
+    /// Installs the global `tracing` subscriber.
+    pub fn init(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let filter = match &self.log_level {
+            Some(level) => EnvFilter::try_new(level)?,
+            None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("error")),
+        };
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_ansi(terminal::stderr_colors())
+            .with_writer(std::io::stderr);
+        if self.log_json {
+            builder.json().try_init()
+        } else {
+            builder.try_init()
+        }
+    }
+}
@@ -1,27 +1,120 @@
-use clap::{Parser, Subcommand};
+use clap::{
+    parser::ValueSource, ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand,
+    ValueEnum,
+};
 use colored::Colorize;
+use prost_reflect::DescriptorPool;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::time::Duration;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::AsyncWriteExt;
 use tokio::time;
-use zenoh::{config::Config, Session};
+use zenoh::{
+    config::{Config, WhatAmI, WhatAmIMatcher},
+    key_expr::KeyExpr,
+    query::{Query, QueryTarget, Selector},
+    sample::SampleKind,
+    Session,
+};
 
-mod message_registry;
-use message_registry::MessageRegistry;
+mod completions;
+mod repl;
 
-// Include the generated proto code
-pub use self::proto::*;
-pub mod proto {
-    include!(concat!(env!("OUT_DIR"), "/zspy.rs"));
-}
-
-// Include the auto-generated registry
-mod registry {
-    include!(concat!(env!("OUT_DIR"), "/registry.rs"));
-}
+use zspy::admin::{self, AdminArgs};
+use zspy::attachment;
+use zspy::bench::{self, BenchCommands};
+use zspy::bridge::{self, BridgeArgs};
+use zspy::cache::{self, CacheArgs};
+use zspy::capture::{self, FilterArgs, InfoArgs};
+use zspy::codec;
+use zspy::compare::{self, CompareArgs};
+use zspy::discovery::{self, EntityKind};
+use zspy::display;
+use zspy::doctor::{self, DoctorArgs};
+use zspy::echo::{self, EchoArgs};
+use zspy::error::{self, ZspyError};
+use zspy::export::{self, ExportArgs};
+use zspy::gateway::{self, GatewayArgs};
+use zspy::histogram::{self, StatsArgs};
+use zspy::latency::{self, PingOptions};
+use zspy::logging::LogArgs;
+use zspy::message_registry::{JsonStyle, MessageRegistry};
+use zspy::metrics;
+use zspy::monitor;
+use zspy::output::{display_payload, OutputFormat, SplitOutput};
+use zspy::pager::Pager;
+use zspy::playback::{self, PlaybackOptions};
+use zspy::plot::{self, PlotArgs};
+use zspy::profile;
+use zspy::publish::{self, PubArgs};
+use zspy::recording::{self, CaptureFormat, KeyTypes, SampleWriter};
+use zspy::reorder::Reorder;
+use zspy::scenario::{self, RunArgs};
+use zspy::schema;
+use zspy::selftest::{self, SelftestArgs};
+use zspy::session_config::{self, Mode, SessionArgs};
+use zspy::snapshot::{self, SnapshotArgs};
+use zspy::stats::{self, format_bandwidth, format_duration, StatsAggregator};
+use zspy::template::Template;
+use zspy::terminal::{self, TerminalArgs};
+use zspy::trace::{self, TraceArgs};
+use zspy::transform::Transform;
+use zspy::tree::KeyTree;
+use zspy::type_map::TypeMap;
+use zspy::validate::{self, ValidateArgs};
+use zspy::wait::{self, WaitArgs};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Load .proto files or compiled descriptor sets from this path at startup (repeatable)
+    #[arg(long, global = true)]
+    proto_path: Vec<PathBuf>,
+    /// Reload the --proto-path schemas whenever they change, so long-running commands pick up
+    /// edits without a restart
+    #[arg(long, global = true)]
+    watch: bool,
+    /// Load decoder plugins from this directory (or library) at startup (repeatable)
+    #[arg(long, global = true)]
+    plugin_dir: Vec<PathBuf>,
+    /// Message types of key expressions, used by echo, monitor and record when no --type is
+    /// given (default: `types.toml` next to the config file, if present)
+    #[arg(long, global = true, value_name = "FILE")]
+    type_map: Option<PathBuf>,
+    /// JSON form of protobuf messages: the protobuf JSON mapping, or the serde derives zspy
+    /// used before for its built-in types
+    #[arg(long, global = true, value_enum, default_value = "canonical")]
+    json_style: JsonStyle,
+    #[command(flatten)]
+    session: SessionArgs,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(flatten)]
+    terminal: TerminalArgs,
+    /// Report errors as a single line of JSON on stderr, e.g. for CI scripts
+    #[arg(long, global = true)]
+    quiet: bool,
+    /// Use this profile from `~/.config/zspy/config.toml` for connection and default options
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Serve Prometheus metrics (messages, bytes and decode errors per key) over HTTP on this
+    /// port at `/metrics`
+    #[arg(long, global = true)]
+    metrics_port: Option<u16>,
+    /// Address to serve metrics on; use `0.0.0.0` to let a Prometheus server on another host
+    /// scrape them
+    #[arg(
+        long,
+        global = true,
+        default_value = "127.0.0.1",
+        requires = "metrics_port"
+    )]
+    metrics_bind: IpAddr,
     #[command(subcommand)]
     command: Commands,
 }
@@ -29,185 +122,1658 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Subscribe and display messages for a given key
-    Echo {
-        /// The key expression to subscribe to
+    Echo(EchoArgs),
+    /// Publish a message to a given key
+    Pub(PubArgs),
+    /// Delete a key expression, e.g. to remove values from a storage
+    Delete {
+        /// The key expression to delete
         key: String,
+    },
+    /// Republish samples from one key expression under another prefix, optionally
+    /// transforming them
+    Bridge(BridgeArgs),
+    /// Serve an HTTP gateway: stream samples as Server-Sent Events and publish POSTed bodies
+    Gateway(GatewayArgs),
+    /// Subscribe on two separate sessions and report messages seen on only one of them, and
+    /// how much later they arrive on one than the other
+    Compare(CompareArgs),
+    /// Write selected numeric fields of each message to CSV, live or from a capture
+    Export(ExportArgs),
+    /// Query a selector and print all replies
+    Get(GetArgs),
+    /// Declare a queryable that answers queries with a fixed payload
+    Serve {
+        /// The key expression to serve
+        key: String,
+        /// Reply payload (JSON format if type is specified). A template where `{{key}}` and
+        /// `{{parameters}}` expand to the incoming query's key expression and parameters
+        #[arg(long, required_unless_present = "exec", conflicts_with = "exec")]
+        value: Option<String>,
+        /// Shell command run for each query: it gets the query as JSON on stdin and its stdout
+        /// is the reply payload
+        #[arg(long)]
+        exec: Option<String>,
+        /// How long an `--exec` command may run before it is killed and the query answered with
+        /// an error
+        #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+        exec_timeout: Duration,
         /// Optional protobuf message type
         #[arg(long)]
         r#type: Option<String>,
+        /// Protobuf type used to decode query payloads for `--exec`
+        #[arg(long, requires = "exec")]
+        query_type: Option<String>,
     },
-    /// Publish a message to a given key
-    Pub {
-        /// The key to publish to
+    /// Keep the latest value of every key under a key expression and answer queries with it
+    Cache(CacheArgs),
+    /// Print rolling rate, bandwidth and inter-arrival statistics per key
+    Hz {
+        /// The key expression to subscribe to
         key: String,
-        /// The message to publish (JSON format if type is specified)
-        value: String,
-        /// Optional protobuf message type
+        /// Window over which statistics are computed
+        #[arg(long, default_value = "5s", value_parser = stats::parse_window)]
+        window: Duration,
+        /// Report the combined statistics of the keys matching this key expression on one
+        /// line instead of per key; may be repeated
+        #[arg(long = "group", value_name = "KEYEXPR")]
+        groups: Vec<String>,
+    },
+    /// Pair requests with their responses by a correlation ID and print each pair with the
+    /// service latency
+    Trace(TraceArgs),
+    /// Collect payload sizes and inter-arrival times for a while, then print their
+    /// percentiles and histograms
+    Stats(StatsArgs),
+    /// Check messages against field ranges, required fields and increasing values from a
+    /// rules file, and count the violations
+    Validate(ValidateArgs),
+    /// Measure round-trip latency against a `pong` responder
+    Ping {
+        /// Base key; pings are sent on `<key>/ping` and replies expected on `<key>/pong`
+        key: String,
+        /// Number of pings to send
+        #[arg(long, default_value = "10")]
+        count: u64,
+        /// Delay between pings
+        #[arg(long, default_value = "1s", value_parser = latency::parse_interval)]
+        interval: Duration,
+        /// Payload size in bytes (at least 16)
+        #[arg(long, default_value = "64")]
+        size: usize,
+        /// How long to wait for outstanding replies after the last ping
+        #[arg(long, default_value = "2s", value_parser = humantime::parse_duration)]
+        timeout: Duration,
+    },
+    /// Reply to pings sent by `zspy ping`
+    Pong {
+        /// Base key, as passed to `zspy ping`
+        key: String,
+    },
+    /// Measure publish/subscribe throughput
+    Bench {
+        #[command(subcommand)]
+        command: BenchCommands,
+    },
+    /// Record samples from one or more keys to a capture file
+    Record {
+        /// The key expressions to subscribe to
+        #[arg(required = true)]
+        keys: Vec<String>,
+        /// Output capture file
+        #[arg(short, long, default_value = "capture.zbag")]
+        output: PathBuf,
+        /// Capture format (default: from the output extension, `.mcap` for MCAP)
+        #[arg(long, value_enum)]
+        format: Option<CaptureFormat>,
+        /// Protobuf type of the recorded payloads, embedded as the schema of MCAP channels
+        /// (default: the type of each key in the type map)
         #[arg(long)]
         r#type: Option<String>,
-        /// Number of messages to publish (0 for infinite)
-        #[arg(long, default_value = "1")]
-        repeat: u64,
-        /// Publishing rate in Hz
+        /// Instead of a capture file, write each key's samples as JSON lines to its own file
+        /// under this directory, e.g. `robot/imu.jsonl`
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "format", "type"])]
+        split_output: Option<PathBuf>,
+        /// Hold samples for this long, e.g. `200ms`, and write them sorted by their Zenoh
+        /// timestamps, which become their capture times
+        #[arg(long, value_name = "WINDOW", value_parser = humantime::parse_duration)]
+        order_by_timestamp: Option<Duration>,
+    },
+    /// Republish a recorded capture file with its original timing
+    Play {
+        /// Capture file to play back
+        input: PathBuf,
+        /// Playback speed multiplier
         #[arg(long, default_value = "1.0")]
         rate: f64,
+        /// Repeat the capture indefinitely
+        #[arg(long = "loop")]
+        looping: bool,
+        /// Publish recorded keys under `old/key` on `new/key` instead (repeatable)
+        #[arg(long, value_name = "OLD=NEW", value_parser = attachment::parse_pair)]
+        remap: Vec<(String, String)>,
+        /// Transform pipeline (YAML) rewriting each payload before it is published
+        #[arg(long, value_name = "FILE")]
+        transform: Option<PathBuf>,
+    },
+    /// Summarize a capture file: its time range and the samples, size and rate of each key
+    Info(InfoArgs),
+    /// Copy the samples of a capture file on some keys or within a time range to a new file
+    Filter(FilterArgs),
+    /// Interactive monitor of active keys with rates and payload previews
+    Monitor {
+        /// The key expression to monitor
+        #[arg(default_value = "**")]
+        key: String,
+        /// Optional protobuf message type used to decode payloads
+        #[arg(long)]
+        r#type: Option<String>,
+        /// Also show the combined rate and bandwidth of the keys matching this key expression;
+        /// may be repeated
+        #[arg(long = "group", value_name = "KEYEXPR")]
+        groups: Vec<String>,
+    },
+    /// Block until a (matching) message arrives, exiting non-zero on timeout
+    Wait(WaitArgs),
+    /// Run the publishers described in a YAML scenario file concurrently
+    Run(RunArgs),
+    /// Chart numeric fields of decoded messages live in the terminal
+    Plot(PlotArgs),
+    /// Record the active keys (and optionally their values), or compare them with an earlier
+    /// snapshot
+    Snapshot(SnapshotArgs),
+    /// Observe traffic briefly and print the key space as a tree with message counts
+    Tree {
+        /// Only explore keys under this prefix
+        prefix: Option<String>,
+        /// How long to observe traffic
+        #[arg(long, default_value = "3s", value_parser = humantime::parse_duration)]
+        duration: Duration,
+        /// Also include publishers and queryables declared in the admin space, even without
+        /// traffic
+        #[arg(long)]
+        admin: bool,
     },
     /// List active publishers/subscribers
-    List,
+    List {
+        /// How long to wait for scouting and admin space replies
+        #[arg(long, default_value = "1s", value_parser = humantime::parse_duration)]
+        timeout: Duration,
+        /// Output format
+        #[arg(long, value_enum, default_value = "table")]
+        output: ListFormat,
+    },
+    /// Show the admin space of routers and peers (plugins, transports, links, routing) as a tree
+    Admin(AdminArgs),
+    /// Scout the network for Zenoh nodes without opening a session
+    Scout {
+        /// Kinds of node to look for (repeatable or comma-separated; default: peer,router)
+        #[arg(long, value_enum, value_delimiter = ',')]
+        what: Vec<Mode>,
+        /// How long to wait for scouting replies
+        #[arg(long, default_value = "5s", value_parser = humantime::parse_duration)]
+        timeout: Duration,
+    },
+    /// Check scouting, endpoints, multicast, the admin space and a pub/sub round trip, and
+    /// suggest fixes for what fails
+    Doctor(DoctorArgs),
+    /// Publish random messages of a type and receive them in the same process, checking that
+    /// they decode to what was sent and measuring the latency
+    Selftest(SelftestArgs),
+    /// Interactive prompt running commands on one shared session, with history and key
+    /// completion
+    Repl,
+    /// Print a shell completion script, including registered message type names
+    Completions {
+        /// The shell to generate completions for
+        shell: clap_complete::Shell,
+    },
     /// Message type operations
     Types {
         #[command(subcommand)]
         command: TypeCommands,
     },
+    /// Announce presence or observe other nodes with liveliness tokens
+    Liveliness {
+        #[command(subcommand)]
+        command: LivelinessCommands,
+    },
+    /// Validate, canonicalize and compare key expressions, without connecting
+    Key {
+        #[command(subcommand)]
+        command: KeyCommands,
+    },
+}
+
+#[derive(Args)]
+struct GetArgs {
+    /// The selector to query, optionally with parameters, e.g. `robot/status?verbose=true`
+    selector: String,
+    /// Optional protobuf message type used to decode replies
+    #[arg(long)]
+    r#type: Option<String>,
+    /// How long to wait for replies
+    #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+    timeout: Duration,
+    /// Which queryables should answer the query
+    #[arg(long, value_enum, ignore_case = true, default_value = "best-matching")]
+    target: Target,
+    /// Add a `name=value` selector parameter (repeatable)
+    #[arg(long, value_parser = attachment::parse_pair)]
+    param: Vec<(String, String)>,
+    /// Payload sent with the query (JSON format if a payload type is given)
+    #[arg(long)]
+    payload: Option<String>,
+    /// Protobuf type used to encode the query payload (default: --type)
+    #[arg(long, requires = "payload")]
+    payload_type: Option<String>,
+    /// Print each JSON reply on a single line
+    #[arg(long)]
+    compact: bool,
+    /// Print replies directly instead of through a pager
+    #[arg(long)]
+    no_pager: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Target {
+    /// Only the queryable best matching the selector
+    #[value(alias = "best_matching")]
+    BestMatching,
+    /// All matching queryables
+    All,
+    /// All matching queryables that are complete for the selector
+    #[value(alias = "all_complete")]
+    AllComplete,
+}
+
+impl Commands {
+    /// Replaces the message type names the command was given with the registered names they
+    /// resolve to, e.g. `ImuMessage` with `zspy.ImuMessage`, so short and mistyped names are
+    /// looked up once rather than for every message.
+    fn resolve_types(&mut self, registry: &MessageRegistry) -> Result<(), ZspyError> {
+        let names: Vec<&mut String> = match self {
+            Commands::Echo(args) => args
+                .r#type
+                .iter_mut()
+                .chain(args.decode_field.iter_mut().map(|(_, msg_type)| msg_type))
+                .collect(),
+            Commands::Bridge(args) => args
+                .r#type
+                .iter_mut()
+                .chain(args.output_type.iter_mut())
+                .collect(),
+            Commands::Get(args) => args
+                .r#type
+                .iter_mut()
+                .chain(args.payload_type.iter_mut())
+                .collect(),
+            Commands::Serve {
+                r#type, query_type, ..
+            } => r#type.iter_mut().chain(query_type.iter_mut()).collect(),
+            Commands::Trace(args) => args
+                .r#type
+                .iter_mut()
+                .chain(args.response_type.iter_mut())
+                .collect(),
+            Commands::Pub(PubArgs { r#type, .. })
+            | Commands::Export(ExportArgs { r#type, .. })
+            | Commands::Validate(ValidateArgs { r#type, .. })
+            | Commands::Record { r#type, .. }
+            | Commands::Monitor { r#type, .. }
+            | Commands::Wait(WaitArgs { r#type, .. })
+            | Commands::Plot(PlotArgs { r#type, .. })
+            | Commands::Snapshot(SnapshotArgs { r#type, .. }) => r#type.iter_mut().collect(),
+            Commands::Selftest(args) => vec![&mut args.r#type],
+            _ => Vec::new(),
+        };
+        for name in names {
+            *name = registry.resolve_type(name)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Target> for QueryTarget {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::BestMatching => QueryTarget::BestMatching,
+            Target::All => QueryTarget::All,
+            Target::AllComplete => QueryTarget::AllComplete,
+        }
+    }
 }
 
 #[derive(Subcommand)]
 enum TypeCommands {
-    /// List available message types
-    List,
+    /// List available message types with the file or plugin they come from
+    List {
+        /// Only list types whose name contains this text (ignoring case)
+        #[arg(long)]
+        filter: Option<String>,
+        /// Output format
+        #[arg(long, value_enum, default_value = "table")]
+        output: ListFormat,
+    },
+    /// Find the message types containing a field whose name contains this text
+    Grep {
+        /// The field name to search for (ignoring case)
+        field: String,
+    },
     /// Show schema for a message type
     Show {
         /// The message type to show
         name: String,
+        /// Print the schema directly instead of through a pager
+        #[arg(long)]
+        no_pager: bool,
+    },
+    /// Write machine-readable schemas of all registered protobuf types to a directory
+    Export {
+        /// What to write
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+        /// Directory to write to, created if missing
+        #[arg(long, short, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Compare two FileDescriptorSets and report breaking changes, exiting with an error if
+    /// there are any
+    Diff {
+        /// Descriptors of the old schema, e.g. written by `types export --format descriptor`
+        old: PathBuf,
+        /// Descriptors of the new schema
+        new: PathBuf,
     },
 }
 
-fn create_message_registry() -> MessageRegistry {
-    let mut registry = MessageRegistry::new();
-    registry::register_messages(&mut registry);
-    registry
+/// Output format of `list` and `types list`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ListFormat {
+    /// Aligned, colored columns
+    Table,
+    /// A JSON array of objects, for scripts
+    Json,
 }
 
-async fn handle_echo(
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// A binary FileDescriptorSet, `descriptors.binpb`, of every file defining a type
+    Descriptor,
+    /// A `<type>.schema.json` JSON Schema for every type
+    Jsonschema,
+    /// Reconstructed `.proto` source of every file defining a type
+    Proto,
+}
+
+#[derive(Subcommand)]
+enum LivelinessCommands {
+    /// Declare a liveliness token until Ctrl-C
+    Declare {
+        /// The key expression of the token
+        key: String,
+    },
+    /// Print tokens appearing (alive) and disappearing (dropped)
+    Watch {
+        /// The key expression to watch
+        key: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeyCommands {
+    /// Check that a key expression is valid and canonical, printing its canonical form
+    Check {
+        /// The key expression to check
+        expr: String,
+    },
+    /// Report whether two key expressions intersect and whether one includes the other
+    Intersect {
+        /// The first key expression, e.g. a subscriber's
+        a: String,
+        /// The second key expression, e.g. a publisher's
+        b: String,
+    },
+}
+
+fn create_message_registry(
+    proto_paths: &[PathBuf],
+    plugin_dirs: &[PathBuf],
+    json_style: JsonStyle,
+    type_map: Option<&Path>,
+) -> Result<MessageRegistry, Box<dyn Error + Send + Sync>> {
+    let mut registry = MessageRegistry::with_builtin_types();
+    registry.set_json_style(json_style);
+    for path in proto_paths {
+        registry.load_path(path)?;
+    }
+    for path in plugin_dirs {
+        registry.register_plugin(path)?;
+    }
+    let type_map = TypeMap::load(type_map)?;
+    for msg_type in type_map.types() {
+        registry
+            .check_type(msg_type)
+            .map_err(|e| format!("Type map: {}", e))?;
+    }
+    registry.set_type_map(type_map);
+    Ok(registry)
+}
+
+async fn handle_get(
     session: &Session,
+    registry: &MessageRegistry,
+    args: &GetArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let msg_type = args.r#type.as_deref();
+    let payload_type = args.payload_type.as_deref().or(msg_type);
+    for type_name in [msg_type, payload_type].into_iter().flatten() {
+        registry.check_type(type_name)?;
+    }
+    let (key_expr, mut parameters) = Selector::try_from(args.selector.as_str())?.split();
+    for (name, value) in &args.param {
+        parameters.insert(name.as_str(), value.as_str());
+    }
+    let selector = Selector::owned(key_expr, parameters);
+    // Shown before waiting for replies; only the replies go through the pager
+    println!("Querying selector: {}", selector.to_string().cyan());
+    let mut pager = Pager::new(!args.no_pager);
+
+    let mut get = session
+        .get(selector)
+        .target(args.target.into())
+        .timeout(args.timeout);
+    if let Some(value) = &args.payload {
+        let payload = match payload_type {
+            Some(type_name) => codec::to_json(value)
+                .and_then(|json| registry.encode(type_name, &json))
+                .map_err(ZspyError::encode)?,
+            None => value.clone().into_bytes(),
+        };
+        get = get.payload(payload);
+    }
+    let replies = get.await?;
+
+    let mut count = 0;
+    while let Ok(reply) = replies.recv_async().await {
+        count += 1;
+        match reply.result() {
+            Ok(sample) => {
+                let payload = sample.payload().to_bytes();
+                let text = display_payload(registry, msg_type, &payload);
+                let text = match display::compact(&text).filter(|_| args.compact) {
+                    Some(compact) => compact,
+                    None => text,
+                };
+                pager.line(format!(
+                    ">> [{}] '{}': '{}'",
+                    "Reply".green(),
+                    sample.key_expr().as_str().cyan(),
+                    display::highlight(&text)
+                ));
+            }
+            Err(err) => {
+                let payload = err.payload().to_bytes();
+                pager.line(format!(
+                    ">> [{}] '{}'",
+                    "Error".red(),
+                    String::from_utf8_lossy(&payload).yellow()
+                ));
+            }
+        }
+    }
+    pager.line(format!("Received {} replies", count).green());
+    pager.finish()?;
+
+    Ok(())
+}
+
+/// The query handed to a `serve --exec` command: key, selector, parameters and the payload
+/// decoded with `query_type` (or as JSON or text without one).
+fn query_json(
+    registry: &MessageRegistry,
+    query_type: Option<&str>,
+    query: &Query,
+) -> Result<serde_json::Value, Box<dyn Error + Send + Sync>> {
+    let payload = match query.payload().map(|p| p.to_bytes()) {
+        None => serde_json::Value::Null,
+        Some(bytes) => match query_type {
+            Some(type_name) => serde_json::from_str(&registry.decode(type_name, &bytes)?)?,
+            None => {
+                let text = String::from_utf8_lossy(&bytes).into_owned();
+                serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text))
+            }
+        },
+    };
+    let parameters: serde_json::Map<String, serde_json::Value> = query
+        .parameters()
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.into()))
+        .collect();
+    Ok(serde_json::json!({
+        "key": query.key_expr().as_str(),
+        "selector": query.selector().to_string(),
+        "parameters": parameters,
+        "payload": payload,
+    }))
+}
+
+/// Runs `command` through the shell with `input` on stdin and returns its stdout, minus one
+/// trailing newline. The command is killed if it runs for longer than `timeout`.
+async fn exec_reply(
+    command: &str,
+    input: &serde_json::Value,
+    timeout: Duration,
+) -> Result<String, String> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = tokio::process::Command::new(shell)
+        .args([flag, command])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to run command: {}", e))?;
+    let run = async {
+        if let Some(mut stdin) = child.stdin.take() {
+            // A command that ignores its input may exit before reading it
+            let _ = stdin.write_all(format!("{}\n", input).as_bytes()).await;
+        }
+        child.wait_with_output().await
+    };
+    // Dropping the child on timeout kills it
+    let output = tokio::time::timeout(timeout, run)
+        .await
+        .map_err(|_| {
+            format!(
+                "Command timed out after {}",
+                humantime::format_duration(timeout)
+            )
+        })?
+        .map_err(|e| format!("Failed to run command: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "Command failed ({}): {}",
+            output.status,
+            stderr.trim()
+        ));
+    }
+    let mut stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if stdout.ends_with('\n') {
+        stdout.pop();
+    }
+    Ok(stdout)
+}
+
+async fn handle_serve(
+    session: &Session,
+    registry: &MessageRegistry,
     key: &str,
+    value: Option<&str>,
+    exec: Option<(&str, Duration)>,
     msg_type: Option<&str>,
+    query_type: Option<&str>,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
-    println!("Subscribing to key: {}", key.cyan());
-    let registry = create_message_registry();
-    let subscriber = session.declare_subscriber(key).await?;
+    for type_name in [msg_type, query_type].into_iter().flatten() {
+        registry.check_type(type_name)?;
+    }
+    println!("Serving key: {}", key.cyan());
+    let template = match value {
+        Some(value) => {
+            println!("Value: {}", value.yellow());
+            let template = Template::parse(value, &["key", "parameters"])?;
+            // Validate the payload up front so bad JSON is reported before any query arrives
+            if let Some(type_name) = msg_type {
+                let vars = HashMap::from([("key", key.to_string()), ("parameters", String::new())]);
+                registry.encode(type_name, &template.render(0, &vars))?;
+            }
+            Some(template)
+        }
+        None => {
+            let command = exec.map(|(command, _)| command).unwrap_or_default();
+            println!("Command: {}", command.yellow());
+            None
+        }
+    };
 
-    while let Ok(sample) = subscriber.recv_async().await {
-        let payload = sample.payload().to_bytes();
-        let display_value = if let Some(type_name) = msg_type {
-            registry
-                .decode(type_name, &payload)
-                .unwrap_or_else(|e| format!("Error decoding message: {}", e))
-        } else {
-            String::from_utf8_lossy(&payload).to_string()
+    let queryable = session.declare_queryable(key).await?;
+    let mut count = 0u64;
+    loop {
+        let query = tokio::select! {
+            query = queryable.recv_async() => match query {
+                Ok(query) => query,
+                Err(_) => break,
+            },
+            _ = tokio::signal::ctrl_c() => break,
         };
 
+        let reply_key = if queryable.key_expr().is_wild() {
+            query.key_expr().clone()
+        } else {
+            queryable.key_expr().clone()
+        };
+        let text = match (&template, exec) {
+            (Some(template), _) => {
+                let vars = HashMap::from([
+                    ("key", query.key_expr().to_string()),
+                    ("parameters", query.parameters().to_string()),
+                ]);
+                Ok(template.render(count, &vars))
+            }
+            (None, command) => match query_json(registry, query_type, &query) {
+                Ok(input) => {
+                    let (command, timeout) = command.unwrap_or_default();
+                    exec_reply(command, &input, timeout).await
+                }
+                Err(e) => Err(format!("Error decoding query payload: {}", e)),
+            },
+        };
+        let payload = text.and_then(|text| match msg_type {
+            Some(type_name) => registry
+                .encode(type_name, &text)
+                .map_err(|e| format!("Error encoding reply: {}", e)),
+            None => Ok(text.into_bytes()),
+        });
+        match payload {
+            Ok(payload) => query.reply(reply_key, payload).await?,
+            Err(message) => {
+                eprintln!("{}", message.yellow());
+                query.reply_err(message).await?
+            }
+        }
+        count += 1;
+        let body = query
+            .payload()
+            .map(|p| format!(" with {} byte payload", p.len()))
+            .unwrap_or_default();
         println!(
-            ">> [{}] '{}': '{}'",
-            "Received".green(),
-            sample.key_expr().as_str().cyan(),
-            display_value.yellow()
+            ">> [{}] '{}'{}",
+            "Query".green(),
+            query.selector().to_string().cyan(),
+            body
         );
     }
+    println!("{}", format!("Answered {} queries", count).green());
 
     Ok(())
 }
 
-async fn handle_pub(
+async fn handle_hz(
     session: &Session,
     key: &str,
-    value: &str,
-    msg_type: Option<&str>,
-    repeat: u64,
-    rate: f64,
+    window: Duration,
+    groups: &[String],
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
-    println!("Publishing to key: {}", key.cyan());
-    println!("Value: {}", value.yellow());
-    if repeat == 0 {
-        println!("Publishing continuously at {} Hz", rate);
-    } else {
-        println!("Publishing {} messages at {} Hz", repeat, rate);
+    println!("Measuring key: {}", key.cyan());
+    let mut aggregator = StatsAggregator::new(window);
+    for group in groups {
+        aggregator.add_group(group)?;
     }
+    let subscriber = session.declare_subscriber(key).await?;
+    let mut report = time::interval(Duration::from_secs(1));
+    report.tick().await;
 
-    let registry = create_message_registry();
-    let interval = Duration::from_secs_f64(1.0 / rate);
-    let mut interval_timer = time::interval(interval);
-    let mut count = 0;
+    loop {
+        tokio::select! {
+            sample = subscriber.recv_async() => {
+                let sample = sample?;
+                aggregator.record(
+                    sample.key_expr().as_str(),
+                    Instant::now(),
+                    sample.payload().len(),
+                );
+            }
+            _ = report.tick() => {
+                aggregator.prune(Instant::now());
+                for group in aggregator.groups() {
+                    println!(
+                        "{} [{} keys]: {} | {} | window {}",
+                        group.pattern.as_str().magenta(),
+                        group.keys,
+                        format!("{:.2} Hz", group.stats.rate()).green(),
+                        format_bandwidth(group.stats.bandwidth()).yellow(),
+                        group.stats.window_count()
+                    );
+                }
+                for (key, stats) in aggregator.ungrouped() {
+                    let intervals = match stats.intervals() {
+                        Some(i) => format!(
+                            "min {} max {} avg {} jitter {}",
+                            format_duration(i.min),
+                            format_duration(i.max),
+                            format_duration(i.mean),
+                            format_duration(i.jitter)
+                        ),
+                        None => "no recent samples".to_string(),
+                    };
+                    println!(
+                        "{}: {} | {} | {} | window {}",
+                        key.cyan(),
+                        format!("{:.2} Hz", stats.rate()).green(),
+                        format_bandwidth(stats.bandwidth()).yellow(),
+                        intervals,
+                        stats.window_count()
+                    );
+                }
+            }
+        }
+    }
+}
 
-    let payload = if let Some(type_name) = msg_type {
-        registry.encode(type_name, value)?
-    } else {
-        value.as_bytes().to_vec()
-    };
+async fn handle_ping(
+    session: &Session,
+    key: &str,
+    options: &PingOptions,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    println!("Pinging key: {}", key.cyan());
+    let mut rtts = latency::run_ping(session, key, options).await?;
+    rtts.sort();
 
-    loop {
-        interval_timer.tick().await;
-        session.put(key, payload.clone()).await?;
-        count += 1;
+    let received = rtts.len() as u64;
+    let loss = 100.0 * (options.count - received) as f64 / options.count.max(1) as f64;
+    println!(
+        "\n{} sent, {} received, {:.1}% loss",
+        options.count, received, loss
+    );
+    if !rtts.is_empty() {
+        let avg = rtts.iter().sum::<Duration>() / rtts.len() as u32;
+        println!(
+            "rtt min/avg/max/p99 = {}/{}/{}/{}",
+            format_duration(rtts[0]),
+            format_duration(avg),
+            format_duration(rtts[rtts.len() - 1]),
+            format_duration(latency::percentile(&rtts, 99.0))
+        );
+    }
+    Ok(())
+}
 
-        if repeat > 0 && count >= repeat {
-            break;
-        }
+async fn handle_pong(session: &Session, key: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    println!("Answering pings on key: {}", key.cyan());
+    let count = latency::run_pong(session, key).await?;
+    println!("\n{}", format!("Answered {} pings", count).green());
+    Ok(())
+}
 
-        print!("\rPublished {} messages", count);
-        std::io::Write::flush(&mut std::io::stdout())?;
+/// Records `keys` to `writer` until Ctrl-C; `output` names where it writes to.
+async fn handle_record(
+    session: &Session,
+    keys: &[String],
+    output: &Path,
+    order_by_timestamp: Option<Duration>,
+    mut writer: Box<dyn SampleWriter>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for key in keys {
+        println!("Recording key: {}", key.cyan());
     }
-    println!("\n{}", "Publishing completed!".green());
+    println!("Writing to {} (press Ctrl-C to stop)", output.display());
 
+    let live = terminal::live_updates();
+    let mut count = 0u64;
+    let mut reorder = order_by_timestamp.map(Reorder::new);
+    tokio::select! {
+        result = recording::record(session, keys, &mut *writer, reorder.as_mut(), |_| {
+            count += 1;
+            if live {
+                print!("\rRecorded {} samples", count);
+                let _ = std::io::Write::flush(&mut std::io::stdout());
+            }
+        }) => result?,
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    // Samples still waiting for their turn when recording stopped
+    if let Some(reorder) = reorder.as_mut() {
+        for sample in reorder.drain() {
+            writer.write(&sample)?;
+            count += 1;
+        }
+    }
+    writer.finish()?;
+    if live {
+        println!();
+    }
+    println!(
+        "{}",
+        format!("Recorded {} samples to {}", count, output.display()).green()
+    );
+    if let Some(reorder) = reorder.as_ref().filter(|r| r.late > 0) {
+        println!(
+            "{}",
+            format!(
+                "{} samples arrived too late to put in timestamp order",
+                reorder.late
+            )
+            .yellow()
+        );
+    }
+    if let Some(reorder) = reorder.filter(|r| r.untimestamped > 0) {
+        println!(
+            "{}",
+            format!(
+                "{} samples had no timestamp to order by; enable timestamping on publishers",
+                reorder.untimestamped
+            )
+            .yellow()
+        );
+    }
+
+    Ok(())
+}
+
+async fn handle_play(
+    session: &Session,
+    registry: &MessageRegistry,
+    input: &Path,
+    rate: f64,
+    looping: bool,
+    remap: &[(String, String)],
+    transform: Option<&Path>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    println!("Playing back {} at {}x", input.display(), rate);
+    for (old, new) in remap {
+        println!("Remapping {} to {}", old.cyan(), new.cyan());
+    }
+    let options = PlaybackOptions {
+        rate,
+        looping,
+        remap: remap.to_vec(),
+        transform: transform.map(Transform::load).transpose()?,
+    };
+    let published = tokio::select! {
+        result = playback::play(session, registry, input, &options) => Some(result?),
+        _ = tokio::signal::ctrl_c() => None,
+    };
+    match published {
+        Some(count) => println!("{}", format!("Played back {} samples", count).green()),
+        None => println!("\n{}", "Playback interrupted".yellow()),
+    }
     Ok(())
 }
 
-async fn handle_list(session: &Session) -> Result<(), Box<dyn Error + Send + Sync>> {
+async fn handle_list(
+    session: &Session,
+    config: &Config,
+    timeout: Duration,
+    output: ListFormat,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if output == ListFormat::Json {
+        let discovery = discovery::discover(session, config, timeout).await?;
+        println!("{}", serde_json::to_string_pretty(&discovery.to_json())?);
+        return Ok(());
+    }
     println!("Discovering active publishers and subscribers...");
-    // TODO: Implement proper discovery using Zenoh's discovery mechanisms
     println!("Connected to Zenoh session with ID: {}", session.zid());
+    let discovery = discovery::discover(session, config, timeout).await?;
+
+    for (title, whatami) in [
+        ("Routers", "router"),
+        ("Peers", "peer"),
+        ("Clients", "client"),
+    ] {
+        let nodes = discovery.nodes_of(whatami);
+        if nodes.is_empty() {
+            continue;
+        }
+        println!("\n{} ({})", title.bold(), nodes.len());
+        for (zid, node) in nodes {
+            let locators: Vec<&str> = node.locators.iter().map(String::as_str).collect();
+            println!("  {} {}", zid.cyan(), locators.join(", ").dimmed());
+        }
+    }
+
+    for (title, kind) in [
+        ("Publishers", EntityKind::Publisher),
+        ("Subscribers", EntityKind::Subscriber),
+        ("Queryables", EntityKind::Queryable),
+    ] {
+        let Some(entities) = discovery.entities.get(&kind) else {
+            continue;
+        };
+        println!("\n{} ({})", title.bold(), entities.len());
+        for (key_expr, zids) in entities {
+            let zids: Vec<&str> = zids.iter().map(String::as_str).collect();
+            println!(
+                "  {} {}",
+                key_expr.yellow(),
+                format!("[{}]", zids.join(", ")).dimmed()
+            );
+        }
+    }
+
     Ok(())
 }
 
-fn handle_types_list() {
-    let registry = create_message_registry();
-    println!("Available message types:");
-    for msg_type in registry.list_types() {
-        println!("  - {}", msg_type);
+async fn handle_tree(
+    session: &Session,
+    config: &Config,
+    prefix: Option<&str>,
+    duration: Duration,
+    admin: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let key_expr = match prefix {
+        Some(prefix) => KeyExpr::try_from(format!("{}/**", prefix.trim_end_matches('/')))?,
+        None => KeyExpr::try_from("**")?,
+    };
+    println!(
+        "Observing {} for {}...",
+        key_expr.as_str().cyan(),
+        humantime::format_duration(duration)
+    );
+
+    let subscriber = session.declare_subscriber(&key_expr).await?;
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    let _ = time::timeout(duration, async {
+        while let Ok(sample) = subscriber.recv_async().await {
+            *counts.entry(sample.key_expr().to_string()).or_default() += 1;
+        }
+    })
+    .await;
+    if admin {
+        let discovery = discovery::discover(session, config, Duration::from_secs(1)).await?;
+        for kind in [EntityKind::Publisher, EntityKind::Queryable] {
+            for key in discovery
+                .entities
+                .get(&kind)
+                .into_iter()
+                .flatten()
+                .map(|(k, _)| k)
+            {
+                if KeyExpr::try_from(key.as_str()).is_ok_and(|k| k.intersects(&key_expr)) {
+                    counts.entry(key.clone()).or_default();
+                }
+            }
+        }
+    }
+
+    if counts.is_empty() {
+        println!("{}", "No keys observed".yellow());
+        return Ok(());
     }
+    let mut tree = KeyTree::default();
+    for (key, count) in &counts {
+        tree.insert(key, *count);
+    }
+    print!("{}", tree.render(key_expr.as_str()));
+    println!(
+        "{}",
+        format!(
+            "{} keys, {} messages",
+            counts.len(),
+            counts.values().sum::<u64>()
+        )
+        .green()
+    );
+    Ok(())
 }
 
-fn handle_types_show(name: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let registry = create_message_registry();
-    if let Some(schema) = registry.get_schema(name) {
-        println!("Message type: {}", name);
-        println!("Schema:\n{}", schema);
-        Ok(())
+async fn handle_scout(
+    config: &Config,
+    what: &[Mode],
+    timeout: Duration,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let matcher = match what {
+        [] => WhatAmI::Router | WhatAmI::Peer,
+        [first, rest @ ..] => rest
+            .iter()
+            .fold(WhatAmIMatcher::from(WhatAmI::from(*first)), |m, w| {
+                m | WhatAmI::from(*w)
+            }),
+    };
+    println!(
+        "Scouting for {} ({})...",
+        matcher,
+        format!("{:?}", timeout).dimmed()
+    );
+    let scout = zenoh::scout(matcher, config.clone()).await?;
+
+    let mut seen = HashSet::new();
+    let _ = time::timeout(timeout, async {
+        while let Ok(hello) = scout.recv_async().await {
+            let zid = hello.zid().to_string();
+            if !seen.insert(zid.clone()) {
+                continue;
+            }
+            let locators: Vec<String> = hello.locators().iter().map(|l| l.to_string()).collect();
+            println!(
+                "  {} {:<6} {}",
+                zid.cyan(),
+                hello.whatami().to_str(),
+                locators.join(", ").dimmed()
+            );
+        }
+    })
+    .await;
+
+    if seen.is_empty() {
+        println!("{}", "No nodes found".yellow());
     } else {
-        Err(format!("Unknown message type: {}", name).into())
+        println!("{}", format!("Found {} nodes", seen.len()).green());
     }
+    Ok(())
+}
+
+fn handle_types_list(
+    registry: &MessageRegistry,
+    filter: Option<&str>,
+    output: ListFormat,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let filter = filter.map(str::to_lowercase);
+    let types: Vec<String> = registry
+        .list_types()
+        .into_iter()
+        .filter(|t| filter.as_ref().is_none_or(|f| t.to_lowercase().contains(f)))
+        .collect();
+    if output == ListFormat::Json {
+        let types: Vec<serde_json::Value> = types
+            .iter()
+            .map(|name| {
+                let descriptor = registry.descriptor(name);
+                // Plugin types have no descriptor to take the package from
+                let package = match &descriptor {
+                    Some(descriptor) => descriptor.package_name().to_string(),
+                    None => name.rsplit_once('.').map_or("", |(p, _)| p).to_string(),
+                };
+                serde_json::json!({
+                    "name": name,
+                    "package": package,
+                    "source": registry.type_source(name),
+                    "fields": descriptor.map(|d| d.fields().len()),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&types)?);
+        return Ok(());
+    }
+    if types.is_empty() {
+        println!("{}", "No matching message types".yellow());
+        return Ok(());
+    }
+    let width = types.iter().map(|t| t.len()).max().unwrap_or(0);
+    println!("Available message types:");
+    for msg_type in types {
+        let source = registry.type_source(&msg_type).unwrap_or_default();
+        println!("  - {:<width$}  {}", msg_type, source.blue(), width = width);
+    }
+    Ok(())
+}
+
+fn handle_types_grep(registry: &MessageRegistry, field: &str) {
+    let found = registry.find_fields(field);
+    if found.is_empty() {
+        println!(
+            "{}",
+            format!("No message type has a field matching '{}'", field).yellow()
+        );
+        return;
+    }
+    for (msg_type, path, field_type) in found {
+        println!("{}: {} ({})", msg_type.cyan(), path, field_type);
+    }
+}
+
+fn handle_types_show(
+    registry: &MessageRegistry,
+    name: &str,
+    no_pager: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let name = registry.resolve_type(name)?;
+    let schema = registry.get_schema(&name).unwrap_or_default();
+    let mut pager = Pager::new(!no_pager);
+    pager.line(format!("Message type: {}", name));
+    pager.line(format!("Schema:\n{}", schema));
+    pager.finish()?;
+    Ok(())
+}
+
+fn handle_types_diff(old: &Path, new: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let load = |path: &Path| -> Result<DescriptorPool, Box<dyn Error + Send + Sync>> {
+        DescriptorPool::decode(fs::read(path)?.as_slice()).map_err(|e| {
+            ZspyError::Decode(format!("Invalid descriptor set {}: {}", path.display(), e)).into()
+        })
+    };
+    let changes = schema::diff(&load(old)?, &load(new)?);
+    if changes.is_empty() {
+        println!("{}", "No schema changes".green());
+        return Ok(());
+    }
+    for change in &changes {
+        let tag = if change.breaking {
+            "breaking".red()
+        } else {
+            "compatible".green()
+        };
+        println!(
+            "[{}] {}: {}",
+            tag,
+            change.type_name.cyan(),
+            change.description
+        );
+    }
+    let breaking = changes.iter().filter(|c| c.breaking).count();
+    if breaking > 0 {
+        return Err(format!(
+            "{} of {} schema changes are breaking",
+            breaking,
+            changes.len()
+        )
+        .into());
+    }
+    println!(
+        "{}",
+        format!("All {} schema changes are compatible", changes.len()).green()
+    );
+    Ok(())
+}
+
+fn handle_types_export(
+    registry: &MessageRegistry,
+    format: ExportFormat,
+    dir: &Path,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Plugin types have no protobuf descriptor to export
+    let types: Vec<String> = registry
+        .list_types()
+        .into_iter()
+        .filter(|t| registry.descriptor(t).is_some())
+        .collect();
+    let descriptors: Vec<_> = types
+        .iter()
+        .filter_map(|t| registry.descriptor(t))
+        .collect();
+    fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    match format {
+        ExportFormat::Descriptor => {
+            let path = dir.join("descriptors.binpb");
+            fs::write(&path, schema::descriptor_set(&schema::files(&descriptors)))?;
+            written.push(path);
+        }
+        ExportFormat::Jsonschema => {
+            for msg_type in &types {
+                let Some(json_schema) = registry.json_schema(msg_type) else {
+                    continue;
+                };
+                let path = dir.join(format!("{}.schema.json", msg_type));
+                fs::write(&path, serde_json::to_string_pretty(&json_schema)? + "\n")?;
+                written.push(path);
+            }
+        }
+        ExportFormat::Proto => {
+            for file in schema::files(&descriptors) {
+                let path = dir.join(file.name());
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&path, schema::format_file(&file))?;
+                written.push(path);
+            }
+        }
+    }
+    for path in &written {
+        println!("Wrote {}", path.display());
+    }
+    println!(
+        "{}",
+        format!("Exported {} message types", types.len()).green()
+    );
+    Ok(())
+}
+
+fn handle_key(command: &KeyCommands) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match command {
+        KeyCommands::Check { expr } => {
+            let canonical = canonical_key_expr(expr)?;
+            let kind = if canonical.is_wild() {
+                "key expression with wildcards"
+            } else {
+                "key"
+            };
+            if canonical.as_str() != expr {
+                println!("Canonical form: {}", canonical.as_str().cyan());
+                return Err(format!("'{}' is a valid {} but not canonical", expr, kind).into());
+            }
+            println!(
+                "{} '{}' is a canonical {}",
+                "Valid:".green(),
+                expr.cyan(),
+                kind
+            );
+        }
+        KeyCommands::Intersect { a, b } => {
+            let (a, b) = (canonical_key_expr(a)?, canonical_key_expr(b)?);
+            let relation = if a == b {
+                "are equal"
+            } else if a.includes(&b) {
+                "intersect: the first includes the second"
+            } else if b.includes(&a) {
+                "intersect: the second includes the first"
+            } else if a.intersects(&b) {
+                "intersect: some keys match both, neither includes the other"
+            } else {
+                "are disjoint: no key matches both"
+            };
+            println!(
+                "'{}' and '{}' {}",
+                a.as_str().cyan(),
+                b.as_str().cyan(),
+                relation
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Parses `expr`, canonicalizing it (e.g. `a/**/**` to `a/**`) and noting when that changed it.
+fn canonical_key_expr(expr: &str) -> Result<KeyExpr<'static>, Box<dyn Error + Send + Sync>> {
+    let canonical = KeyExpr::autocanonize(expr.to_string())?;
+    if canonical.as_str() != expr {
+        tracing::info!("Canonicalized '{}' to '{}'", expr, canonical);
+    }
+    Ok(canonical)
+}
+
+async fn handle_liveliness_declare(
+    session: &Session,
+    key: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let token = session.liveliness().declare_token(key).await?;
+    println!(
+        "Declared liveliness token: {} (press Ctrl-C to drop)",
+        key.cyan()
+    );
+    tokio::signal::ctrl_c().await?;
+    token.undeclare().await?;
+    println!("{}", "Token dropped".green());
+    Ok(())
+}
+
+async fn handle_liveliness_watch(
+    session: &Session,
+    key: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    println!("Watching liveliness tokens on: {}", key.cyan());
+    // History reports the tokens that are already alive when we start
+    let subscriber = session
+        .liveliness()
+        .declare_subscriber(key)
+        .history(true)
+        .await?;
+    loop {
+        let sample = tokio::select! {
+            sample = subscriber.recv_async() => match sample {
+                Ok(sample) => sample,
+                Err(_) => break,
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let now = humantime::format_rfc3339_millis(SystemTime::now());
+        let status = match sample.kind() {
+            SampleKind::Put => "alive".green(),
+            SampleKind::Delete => "dropped".red(),
+        };
+        println!(
+            "{} [{}] '{}'",
+            now,
+            status,
+            sample.key_expr().as_str().cyan()
+        );
+    }
+    Ok(())
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
-    let cli = Cli::parse();
+async fn main() -> ExitCode {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let quiet = cli.quiet;
+    let Err(err) = run(cli, &matches).await else {
+        return ExitCode::SUCCESS;
+    };
+    let (kind, code) = error::classify(err.as_ref());
+    if quiet {
+        let report = serde_json::json!({
+            "error": kind,
+            "code": code,
+            "message": err.to_string(),
+        });
+        eprintln!("{}", report);
+    } else {
+        eprintln!("{} {}", "Error:".red(), err);
+    }
+    ExitCode::from(code)
+}
+
+/// Applies the selected config file profile to the options not given on the command line.
+fn apply_profile(cli: &mut Cli, matches: &ArgMatches) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let Some(profile) = profile::load(cli.profile.as_deref())? else {
+        return Ok(());
+    };
+    cli.session.apply_profile(&profile)?;
+    cli.proto_path
+        .splice(0..0, profile.proto_path.iter().cloned());
+    cli.plugin_dir
+        .splice(0..0, profile.plugin_dir.iter().cloned());
+    if let (Commands::Echo(args), Some(output)) = (&mut cli.command, &profile.output) {
+        let defaulted = matches
+            .subcommand_matches("echo")
+            .and_then(|m| m.value_source("output"))
+            == Some(ValueSource::DefaultValue);
+        if defaulted {
+            args.output = OutputFormat::from_str(output, true)
+                .map_err(|_| format!("Invalid output format '{}' in profile", output))?;
+        }
+    }
+    Ok(())
+}
+
+async fn run(mut cli: Cli, matches: &ArgMatches) -> Result<(), Box<dyn Error + Send + Sync>> {
+    cli.terminal.init();
+    cli.log.init()?;
+    apply_profile(&mut cli, matches)?;
+    if let Some(port) = cli.metrics_port {
+        metrics::serve(SocketAddr::new(cli.metrics_bind, port)).await?;
+    }
+    let registry = create_message_registry(
+        &cli.proto_path,
+        &cli.plugin_dir,
+        cli.json_style,
+        cli.type_map.as_deref(),
+    )?;
+    cli.command.resolve_types(&registry)?;
+
+    if let Commands::Completions { shell } = &cli.command {
+        completions::generate(Cli::command(), *shell, &registry.list_types());
+        return Ok(());
+    }
+    if let Commands::Key { command } = &cli.command {
+        return handle_key(command);
+    }
+    if let Commands::Compare(args) = &cli.command {
+        // Opens its own two sessions
+        return compare::run(args).await;
+    }
+    if let Commands::Info(args) = &cli.command {
+        return capture::info(args);
+    }
+    if let Commands::Filter(args) = &cli.command {
+        return capture::filter(args);
+    }
 
     // Initialize Zenoh session
-    let config = Config::default();
-    let session = zenoh::open(config).await?;
+    let mut config = cli.session.build_config()?;
+    if let Commands::Scout { what, timeout } = &cli.command {
+        // Scouting works without a session, so our own node doesn't show up
+        return handle_scout(&config, what, *timeout).await;
+    }
+    if let Commands::Doctor(args) = &cli.command {
+        // Opens its own sessions, so a failing one is reported rather than fatal
+        return doctor::run(&config, args).await;
+    }
+    if matches!(
+        cli.command,
+        Commands::List { .. } | Commands::Admin(_) | Commands::Repl
+    ) {
+        // Expose our own admin space so local entities show up in discovery
+        config.insert_json5("adminspace/enabled", "true")?;
+    }
+    tracing::debug!(%config, "Opening Zenoh session");
+    if cli.session.reconnect
+        && config
+            .get_json("mode")
+            .is_ok_and(|mode| mode == "\"client\"")
+    {
+        // Opening a client session waits for a router
+        eprintln!(
+            "{}",
+            "Connecting (retrying until a router answers)...".dimmed()
+        );
+    }
+    let session = zenoh::open(config.clone())
+        .await
+        .map_err(|e| ZspyError::Connection(e.to_string()))?;
+    tracing::info!(zid = %session.zid(), "Zenoh session opened");
+    if cli.session.reconnect {
+        tokio::spawn(session_config::watch_connection(session.clone()));
+    }
+
+    // Shared with the gateway's connection tasks
+    let registry = Arc::new(registry);
+    // Reloads stop when the watcher is dropped at the end of the command
+    let _watcher = if cli.watch {
+        if cli.proto_path.is_empty() {
+            return Err("--watch needs a --proto-path to watch".into());
+        }
+        Some(registry.watch(&cli.proto_path)?)
+    } else {
+        None
+    };
+    if let Commands::Repl = cli.command {
+        repl::run(&session, &registry, &config).await?;
+    } else {
+        execute(&session, &registry, &config, &cli.command).await?;
+    }
+    session.close().await?;
+    Ok(())
+}
 
-    match &cli.command {
-        Commands::Echo { key, r#type } => {
-            handle_echo(&session, key, r#type.as_deref()).await?;
+/// Runs a command on an open session, for the command line and the REPL alike.
+async fn execute(
+    session: &Session,
+    registry: &Arc<MessageRegistry>,
+    config: &Config,
+    command: &Commands,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match command {
+        Commands::Echo(args) => {
+            echo::run(session, registry, args).await?;
+        }
+        Commands::Pub(args) => {
+            publish::run(session, registry, args).await?;
         }
-        Commands::Pub {
+        Commands::Delete { key } => {
+            session.delete(key.as_str()).await?;
+            println!("{} {}", "Deleted".green(), key.cyan());
+        }
+        Commands::Cache(args) => {
+            cache::run(session, args).await?;
+        }
+        Commands::Bridge(args) => {
+            bridge::run(session, registry, args).await?;
+        }
+        Commands::Gateway(args) => {
+            // Connections are served from their own tasks, which need owned handles
+            gateway::run(session.clone(), registry.clone(), args).await?;
+        }
+        Commands::Compare(args) => {
+            compare::run(args).await?;
+        }
+        Commands::Export(args) => {
+            export::run(session, registry, args).await?;
+        }
+        Commands::Get(args) => {
+            handle_get(session, registry, args).await?;
+        }
+        Commands::Serve {
             key,
             value,
+            exec,
+            exec_timeout,
+            r#type,
+            query_type,
+        } => {
+            handle_serve(
+                session,
+                registry,
+                key,
+                value.as_deref(),
+                exec.as_deref().map(|command| (command, *exec_timeout)),
+                r#type.as_deref(),
+                query_type.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Hz {
+            key,
+            window,
+            groups,
+        } => {
+            handle_hz(session, key, *window, groups).await?;
+        }
+        Commands::Trace(args) => {
+            trace::run(session, registry, args).await?;
+        }
+        Commands::Stats(args) => {
+            histogram::run(session, args).await?;
+        }
+        Commands::Validate(args) => {
+            validate::run(session, registry, args).await?;
+        }
+        Commands::Ping {
+            key,
+            count,
+            interval,
+            size,
+            timeout,
+        } => {
+            let options = PingOptions {
+                count: *count,
+                interval: *interval,
+                size: *size,
+                timeout: *timeout,
+            };
+            handle_ping(session, key, &options).await?;
+        }
+        Commands::Pong { key } => {
+            handle_pong(session, key).await?;
+        }
+        Commands::Bench { command } => {
+            bench::run(session, command).await?;
+        }
+        Commands::Record {
+            keys,
+            output,
+            format,
             r#type,
-            repeat,
+            split_output,
+            order_by_timestamp,
+        } => {
+            if let Some(dir) = split_output {
+                let writer = Box::new(SplitOutput::new(dir)?);
+                return handle_record(session, keys, dir, *order_by_timestamp, writer).await;
+            }
+            let format = format.unwrap_or_else(|| CaptureFormat::from_path(output));
+            let key_types: Option<KeyTypes> = match r#type {
+                Some(type_name) => {
+                    registry.check_type(type_name)?;
+                    if format != CaptureFormat::Mcap {
+                        return Err("--type is only stored in MCAP captures".into());
+                    }
+                    let descriptor = registry.descriptor(type_name).ok_or_else(|| {
+                        format!("Type {} has no protobuf descriptor to embed", type_name)
+                    })?;
+                    Some(Box::new(move |_: &str| Some(descriptor.clone())))
+                }
+                None => {
+                    let registry = Arc::clone(registry);
+                    Some(Box::new(move |key: &str| {
+                        registry
+                            .type_for_key(key)
+                            .and_then(|msg_type| registry.descriptor(msg_type))
+                    }))
+                }
+            };
+            let writer = recording::create(output, format, key_types)?;
+            handle_record(session, keys, output, *order_by_timestamp, writer).await?;
+        }
+        Commands::Play {
+            input,
             rate,
+            looping,
+            remap,
+            transform,
+        } => {
+            handle_play(
+                session,
+                registry,
+                input,
+                *rate,
+                *looping,
+                remap,
+                transform.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Info(args) => capture::info(args)?,
+        Commands::Filter(args) => capture::filter(args)?,
+        Commands::Monitor {
+            key,
+            r#type,
+            groups,
+        } => {
+            if let Some(type_name) = r#type {
+                registry.check_type(type_name)?;
+            }
+            monitor::run(session, registry, key, r#type.as_deref(), groups).await?;
+        }
+        Commands::Wait(args) => {
+            wait::run(session, registry, args).await?;
+        }
+        Commands::Run(args) => {
+            scenario::run(session, registry, args).await?;
+        }
+        Commands::Plot(args) => {
+            plot::run(session, registry, args).await?;
+        }
+        Commands::Snapshot(args) => {
+            snapshot::run(session, registry, args).await?;
+        }
+        Commands::Tree {
+            prefix,
+            duration,
+            admin,
         } => {
-            handle_pub(&session, key, value, r#type.as_deref(), *repeat, *rate).await?;
+            handle_tree(session, config, prefix.as_deref(), *duration, *admin).await?;
+        }
+        Commands::List { timeout, output } => {
+            handle_list(session, config, *timeout, *output).await?;
+        }
+        Commands::Admin(args) => {
+            admin::run(session, args).await?;
+        }
+        Commands::Scout { what, timeout } => {
+            handle_scout(config, what, *timeout).await?;
         }
-        Commands::List => {
-            handle_list(&session).await?;
+        Commands::Selftest(args) => {
+            selftest::run(session, config, registry, args).await?;
         }
+        Commands::Doctor(args) => {
+            doctor::run(config, args).await?;
+        }
+        Commands::Completions { shell } => {
+            completions::generate(Cli::command(), *shell, &registry.list_types());
+        }
+        Commands::Repl => return Err("Already in the REPL".into()),
         Commands::Types { command } => match command {
-            TypeCommands::List => handle_types_list(),
-            TypeCommands::Show { name } => handle_types_show(name)?,
+            TypeCommands::List { filter, output } => {
+                handle_types_list(registry, filter.as_deref(), *output)?
+            }
+            TypeCommands::Grep { field } => handle_types_grep(registry, field),
+            TypeCommands::Export { format, output } => {
+                handle_types_export(registry, *format, output)?
+            }
+            TypeCommands::Show { name, no_pager } => handle_types_show(registry, name, *no_pager)?,
+            TypeCommands::Diff { old, new } => handle_types_diff(old, new)?,
+        },
+        Commands::Liveliness { command } => match command {
+            LivelinessCommands::Declare { key } => handle_liveliness_declare(session, key).await?,
+            LivelinessCommands::Watch { key } => handle_liveliness_watch(session, key).await?,
         },
+        Commands::Key { command } => handle_key(command)?,
     }
-
-    session.close().await?;
     Ok(())
 }
@@ -1,26 +1,97 @@
+use crate::codec::{CborCodec, Codec, PayloadEncoding};
+use crate::error::ZspyError;
+use crate::expr;
+use crate::plugin;
+use crate::presence::{self, PresenceOptions};
+use crate::schema;
+use crate::type_map::TypeMap;
+use crate::well_known;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::ValueEnum;
+use colored::Colorize;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use prost::Message;
+use prost_reflect::prost_types::FileDescriptorSet;
+use prost_reflect::text_format::FormatOptions;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, SerializeOptions};
 use serde_json::Value;
-use std::{collections::HashMap, error::Error};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, PoisonError, RwLock, RwLockReadGuard},
+    time::Duration,
+};
 
-pub trait MessageFactory: Send + Sync {
-    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>>;
-    fn encode(&self, json: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+/// Descriptors of the message types compiled into zspy, generated by build.rs.
+const BUILTIN_DESCRIPTORS: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/file_descriptor_set.bin"));
+
+/// How long schema files must stay unchanged before [`MessageRegistry::watch`] reloads them;
+/// saving a file often takes several events
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// How protobuf messages are converted to and from JSON.
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum JsonStyle {
+    /// The protobuf JSON mapping: camelCase names, enums by name, 64-bit integers and bytes as
+    /// strings, with fields at their default value included. Either field name is accepted
+    /// when encoding
+    #[default]
+    Canonical,
+    /// The serde derives of the built-in types: proto field names, enums as numbers and bytes
+    /// as arrays. Types loaded at runtime keep the protobuf JSON mapping
+    Serde,
+}
+
+/// A protobuf message type: a [`Codec`] with a schema.
+pub trait MessageFactory: Codec {
     fn get_schema(&self) -> String;
+    /// Where the type comes from: its `.proto` file, or the plugin providing it
+    fn source(&self) -> String;
+    /// The protobuf descriptor of the type, if it has one
+    fn descriptor(&self) -> Option<&MessageDescriptor>;
+    /// JSON Schema of the JSON the type decodes to and encodes from, see
+    /// [`schema::json_schema`]
+    fn json_schema(&self) -> Option<Value>;
+    /// Decodes `bytes` and encodes the result again without any unknown fields. A payload that
+    /// survives this unchanged is a clean instance of the type.
+    fn reencode(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+    /// The type converted with the protobuf JSON mapping, for [`JsonStyle::Canonical`], if it
+    /// converts itself another way
+    fn canonical(&self) -> Option<&dyn MessageFactory> {
+        None
+    }
+}
+
+/// Result of guessing the format of an untyped payload.
+pub struct AutoDecoded {
+    /// Detected format: `json`, `text`, `cbor`, `protobuf <type>` or `binary`
+    pub format: String,
+    pub text: String,
 }
 
 struct ProtoMessageFactory<T: Message + Default + serde::Serialize> {
+    descriptor: Option<MessageDescriptor>,
+    /// The same type under the protobuf JSON mapping, built once at registration
+    canonical: Option<DynamicMessageFactory>,
     phantom: std::marker::PhantomData<T>,
 }
 
 impl<T: Message + Default + serde::Serialize + serde::de::DeserializeOwned> ProtoMessageFactory<T> {
-    fn new() -> Self {
+    fn new(descriptor: Option<MessageDescriptor>) -> Self {
         Self {
+            canonical: descriptor
+                .clone()
+                .map(|descriptor| DynamicMessageFactory { descriptor }),
+            descriptor,
             phantom: std::marker::PhantomData,
         }
     }
 }
 
-impl<T: Message + Default + serde::Serialize + serde::de::DeserializeOwned> MessageFactory
+impl<T: Message + Default + serde::Serialize + serde::de::DeserializeOwned> Codec
     for ProtoMessageFactory<T>
 {
     fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
@@ -35,42 +106,290 @@ impl<T: Message + Default + serde::Serialize + serde::de::DeserializeOwned> Mess
         msg.encode(&mut buf)?;
         Ok(buf)
     }
+}
 
+impl<T: Message + Default + serde::Serialize + serde::de::DeserializeOwned> MessageFactory
+    for ProtoMessageFactory<T>
+{
     fn get_schema(&self) -> String {
-        // For now, return a placeholder. In a full implementation,
-        // we could use the protobuf descriptors to generate this.
-        "Schema not available yet".to_string()
+        match &self.descriptor {
+            Some(descriptor) => schema::format_message(descriptor),
+            None => "Schema not available".to_string(),
+        }
+    }
+
+    fn source(&self) -> String {
+        match &self.descriptor {
+            Some(descriptor) => descriptor.parent_file().name().to_string(),
+            None => "built-in".to_string(),
+        }
+    }
+
+    fn descriptor(&self) -> Option<&MessageDescriptor> {
+        self.descriptor.as_ref()
+    }
+
+    fn json_schema(&self) -> Option<Value> {
+        self.descriptor
+            .as_ref()
+            .map(|descriptor| schema::json_schema(descriptor, true))
+    }
+
+    fn reencode(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        Ok(T::decode(bytes)?.encode_to_vec())
+    }
+
+    fn canonical(&self) -> Option<&dyn MessageFactory> {
+        self.canonical.as_ref().map(|c| c as &dyn MessageFactory)
+    }
+}
+
+/// A type converted with the protobuf JSON mapping, fields at their default value included.
+struct DynamicMessageFactory {
+    descriptor: MessageDescriptor,
+}
+
+impl Codec for DynamicMessageFactory {
+    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let msg = DynamicMessage::decode(self.descriptor.clone(), bytes)?;
+        let options = SerializeOptions::new().skip_default_fields(false);
+        let mut value = msg.serialize_with_options(serde_json::value::Serializer, &options)?;
+        well_known::humanize_durations(&self.descriptor, &mut value);
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    fn encode(&self, json: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let mut value: Value = serde_json::from_str(json)?;
+        well_known::canonicalize_durations(&self.descriptor, &mut value)?;
+        let msg = DynamicMessage::deserialize(self.descriptor.clone(), value)?;
+        Ok(msg.encode_to_vec())
+    }
+}
+
+impl MessageFactory for DynamicMessageFactory {
+    fn get_schema(&self) -> String {
+        schema::format_message(&self.descriptor)
+    }
+
+    fn source(&self) -> String {
+        self.descriptor.parent_file().name().to_string()
+    }
+
+    fn descriptor(&self) -> Option<&MessageDescriptor> {
+        Some(&self.descriptor)
+    }
+
+    fn json_schema(&self) -> Option<Value> {
+        Some(schema::json_schema(&self.descriptor, false))
+    }
+
+    fn reencode(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let mut msg = DynamicMessage::decode(self.descriptor.clone(), bytes)?;
+        msg.take_unknown_fields().for_each(drop);
+        Ok(msg.encode_to_vec())
     }
 }
 
 pub struct MessageRegistry {
-    factories: HashMap<String, Box<dyn MessageFactory>>,
+    builtin: DescriptorPool,
+    /// Behind a lock so [`MessageRegistry::reload`] can swap in re-read schemas while the
+    /// registry is shared
+    types: RwLock<Types>,
+    json_style: JsonStyle,
+    type_map: TypeMap,
+}
+
+#[derive(Default)]
+struct Types {
+    factories: HashMap<String, Arc<dyn MessageFactory>>,
+    /// Every schema loaded at runtime, so `google.protobuf.Any` fields can hold types from
+    /// other files
+    pool: DescriptorPool,
+    /// Types registered from Rust code and from decoder plugins, which a reload keeps; schemas
+    /// loaded at runtime override the former and plugins override both
+    compiled: Vec<(String, Arc<dyn MessageFactory>)>,
+    plugins: Vec<(String, Arc<dyn MessageFactory>)>,
+}
+
+impl Default for MessageRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MessageRegistry {
+    /// Creates an empty registry; see [`MessageRegistry::with_builtin_types`].
     pub fn new() -> Self {
         Self {
-            factories: HashMap::new(),
+            builtin: DescriptorPool::decode(BUILTIN_DESCRIPTORS)
+                .expect("Embedded descriptor set is invalid"),
+            types: RwLock::new(Types::default()),
+            json_style: JsonStyle::default(),
+            type_map: TypeMap::default(),
         }
     }
 
+    /// Sets how messages are converted to and from JSON by every method of the registry.
+    pub fn set_json_style(&mut self, json_style: JsonStyle) {
+        self.json_style = json_style;
+    }
+
+    /// Sets the types of keys decoded without an explicit type, see [`type_for_key`].
+    ///
+    /// [`type_for_key`]: Self::type_for_key
+    pub fn set_type_map(&mut self, type_map: TypeMap) {
+        self.type_map = type_map;
+    }
+
+    /// The message type mapped to `key` in the type map, if any.
+    pub fn type_for_key(&self, key: &str) -> Option<&str> {
+        self.type_map.type_for(key)
+    }
+
+    fn types(&self) -> RwLockReadGuard<'_, Types> {
+        self.types.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn types_mut(&mut self) -> &mut Types {
+        self.types.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Creates a registry with every message type compiled from `proto/` registered.
+    pub fn with_builtin_types() -> Self {
+        let mut registry = Self::new();
+        crate::registry::register_messages(&mut registry);
+        registry
+    }
+
     pub fn register<T>(&mut self, name: &str)
     where
         T: Message + Default + serde::Serialize + serde::de::DeserializeOwned + 'static,
     {
-        self.factories
-            .insert(name.to_string(), Box::new(ProtoMessageFactory::<T>::new()));
+        let descriptor = self.builtin.get_message_by_name(name);
+        let factory: Arc<dyn MessageFactory> = Arc::new(ProtoMessageFactory::<T>::new(descriptor));
+        let types = self.types_mut();
+        types.factories.insert(name.to_string(), factory.clone());
+        types.compiled.push((name.to_string(), factory));
+    }
+
+    /// Registers every message type found under `path` at runtime.
+    ///
+    /// `path` may be a `.proto` file, a compiled `FileDescriptorSet` (`.desc`, `.pb`, `.binpb`
+    /// or `.bin`), or a directory which is searched recursively for both. Returns the number
+    /// of message types registered.
+    pub fn load_path(&mut self, path: &Path) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        self.types_mut().load_path(path)
+    }
+
+    /// Reads `proto_paths` again, as given to [`MessageRegistry::load_path`], replacing the
+    /// types loaded at runtime. On error the current types are left in place. Returns the
+    /// number of message types loaded.
+    pub fn reload(&self, proto_paths: &[PathBuf]) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let mut fresh = {
+            let types = self.types();
+            Types {
+                factories: types.compiled.iter().cloned().collect(),
+                compiled: types.compiled.clone(),
+                plugins: types.plugins.clone(),
+                ..Types::default()
+            }
+        };
+        let mut count = 0;
+        for path in proto_paths {
+            count += fresh.load_path(path)?;
+        }
+        let plugins = fresh.plugins.clone();
+        fresh.factories.extend(plugins);
+        *self.types.write().unwrap_or_else(PoisonError::into_inner) = fresh;
+        Ok(count)
     }
 
+    /// Reloads `proto_paths` whenever a schema file under them changes, reporting the outcome
+    /// on stderr, until the returned watcher is dropped.
+    pub fn watch(
+        self: &Arc<Self>,
+        proto_paths: &[PathBuf],
+    ) -> Result<RecommendedWatcher, Box<dyn Error + Send + Sync>> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        for path in proto_paths {
+            if path.is_dir() {
+                watcher.watch(path, RecursiveMode::Recursive)?;
+            } else {
+                // Editors often replace a file rather than write to it, which a watch on the
+                // file itself would not survive
+                let parent = path
+                    .parent()
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .unwrap_or_else(|| Path::new("."));
+                watcher.watch(parent, RecursiveMode::NonRecursive)?;
+            }
+        }
+        let registry = Arc::clone(self);
+        let proto_paths = proto_paths.to_vec();
+        std::thread::spawn(move || {
+            while let Ok(event) = rx.recv() {
+                if !is_schema_change(event, &proto_paths) {
+                    continue;
+                }
+                while rx.recv_timeout(RELOAD_DEBOUNCE).is_ok() {}
+                match registry.reload(&proto_paths) {
+                    Ok(count) => {
+                        eprintln!("{}", format!("Reloaded {} message types", count).green())
+                    }
+                    Err(e) => eprintln!(
+                        "{}",
+                        format!("Failed to reload schemas, keeping the previous ones: {}", e)
+                            .yellow()
+                    ),
+                }
+            }
+        });
+        Ok(watcher)
+    }
+
+    /// Registers the message types of the decoder plugin at `path`, or of every plugin library
+    /// directly inside `path` if it is a directory. Returns the number of types registered.
+    pub fn register_plugin(&mut self, path: &Path) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let libraries = if path.is_dir() {
+            let mut libraries = Vec::new();
+            for entry in fs::read_dir(path)? {
+                let entry = entry?.path();
+                if entry.is_file() && plugin::is_library(&entry) {
+                    libraries.push(entry);
+                }
+            }
+            libraries.sort();
+            libraries
+        } else if path.is_file() {
+            vec![path.to_path_buf()]
+        } else {
+            return Err(format!("Plugin path not found: {}", path.display()).into());
+        };
+
+        let types = self.types_mut();
+        let mut count = 0;
+        for library in libraries {
+            let plugin_types = plugin::load(&library)?;
+            tracing::info!(path = %library.display(), count = plugin_types.len(), "Loaded plugin");
+            for plugin_type in plugin_types {
+                let name = plugin_type.name().to_string();
+                let factory: Arc<dyn MessageFactory> = Arc::new(plugin_type);
+                types.factories.insert(name.clone(), factory.clone());
+                types.plugins.push((name, factory));
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
     pub fn decode(
         &self,
         msg_type: &str,
         bytes: &[u8],
     ) -> Result<String, Box<dyn Error + Send + Sync>> {
-        self.factories
-            .get(msg_type)
-            .ok_or_else(|| format!("Unknown message type: {}", msg_type).into())
-            .and_then(|factory| factory.decode(bytes))
+        self.styled(&self.factory(msg_type)?)
+            .decode(bytes)
+            .map_err(ZspyError::decode)
     }
 
     pub fn encode(
@@ -78,17 +397,472 @@ impl MessageRegistry {
         msg_type: &str,
         json: &str,
     ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
-        self.factories
-            .get(msg_type)
-            .ok_or_else(|| format!("Unknown message type: {}", msg_type).into())
-            .and_then(|factory| factory.encode(json))
+        self.styled(&self.factory(msg_type)?)
+            .encode(json)
+            .map_err(ZspyError::encode)
+    }
+
+    /// Decodes `payload` into a JSON value: as `msg_type` if given, else with `encoding`, else
+    /// as JSON text, falling back to a string for text that isn't JSON.
+    pub fn decode_with(
+        &self,
+        msg_type: Option<&str>,
+        encoding: Option<PayloadEncoding>,
+        payload: &[u8],
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let json = match (msg_type, encoding) {
+            (Some(msg_type), _) => self.decode(msg_type, payload)?,
+            (None, Some(encoding)) => encoding.codec().decode(payload)?,
+            (None, None) => {
+                let text = String::from_utf8_lossy(payload).into_owned();
+                return Ok(serde_json::from_str(&text).unwrap_or(Value::String(text)));
+            }
+        };
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Replaces the bytes field at `path` of a decoded message with its contents decoded as
+    /// `msg_type`. The bytes may be base64, as dynamic types encode them in JSON, or an array of
+    /// byte values. A missing field (empty bytes) is left alone.
+    pub fn decode_embedded(
+        &self,
+        value: &mut Value,
+        path: &str,
+        msg_type: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let bytes = match expr::get_path(value, path) {
+            None | Some(Value::Null) => return Ok(()),
+            Some(Value::String(text)) => BASE64_STANDARD.decode(text).ok(),
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| item.as_u64().and_then(|b| u8::try_from(b).ok()))
+                .collect(),
+            Some(_) => None,
+        }
+        .ok_or_else(|| ZspyError::Decode(format!("Field {} is not a bytes field", path)))?;
+        let decoded = self.decode(msg_type, &bytes)?;
+        expr::set_path(value, path, serde_json::from_str(&decoded)?);
+        Ok(())
     }
 
+    /// Guesses the format of an untyped payload.
+    ///
+    /// UTF-8 payloads are shown as pretty JSON when they hold an object or array, or as text
+    /// when printable. Otherwise every registered protobuf type is tried and the cleanest decode
+    /// (no unknown fields, byte-identical re-encoding, most populated fields) wins, followed by
+    /// CBOR. Anything else falls back to lossy text.
+    pub fn auto_decode(&self, bytes: &[u8]) -> AutoDecoded {
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            if let Ok(value @ (Value::Object(_) | Value::Array(_))) =
+                serde_json::from_str::<Value>(text)
+            {
+                if let Ok(pretty) = serde_json::to_string_pretty(&value) {
+                    return AutoDecoded {
+                        format: "json".to_string(),
+                        text: pretty,
+                    };
+                }
+            }
+            if text
+                .chars()
+                .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+            {
+                return AutoDecoded {
+                    format: "text".to_string(),
+                    text: text.to_string(),
+                };
+            }
+        }
+
+        let mut best: Option<(usize, &String, String)> = None;
+        let types = self.types();
+        let mut names: Vec<&String> = types.factories.keys().collect();
+        names.sort();
+        for name in names {
+            let factory = &types.factories[name];
+            if !factory.reencode(bytes).is_ok_and(|b| b == bytes) {
+                continue;
+            }
+            let Ok(text) = factory.decode(bytes) else {
+                continue;
+            };
+            let score = serde_json::from_str::<Value>(&text)
+                .map(|v| populated_leaves(&v))
+                .unwrap_or(0);
+            if best.as_ref().is_none_or(|(s, _, _)| score > *s) {
+                best = Some((score, name, text));
+            }
+        }
+        if let Some((score, name, text)) = best {
+            if score > 0 || bytes.is_empty() {
+                // Scored in the form each type converts to by itself, where defaults and enum
+                // numbers don't count as populated
+                let text = self
+                    .styled(&types.factories[name])
+                    .decode(bytes)
+                    .unwrap_or(text);
+                return AutoDecoded {
+                    format: format!("protobuf {}", name),
+                    text,
+                };
+            }
+        }
+
+        if let Ok(pretty) = CborCodec.decode(bytes) {
+            return AutoDecoded {
+                format: "cbor".to_string(),
+                text: pretty,
+            };
+        }
+
+        AutoDecoded {
+            format: "binary".to_string(),
+            text: String::from_utf8_lossy(bytes).to_string(),
+        }
+    }
+
+    /// Looks up `msg_type` as [`resolve_type`](Self::resolve_type) does, cloning its factory
+    /// so the lock isn't held while it is used.
+    fn factory(&self, msg_type: &str) -> Result<Arc<dyn MessageFactory>, ZspyError> {
+        let types = self.types();
+        let name = types.resolve(msg_type)?;
+        Ok(types.factories[&name].clone())
+    }
+
+    /// The registered name `msg_type` refers to: itself, or else the type with that name in
+    /// another case, or else the only type whose name without its package is `msg_type`, e.g.
+    /// `ImuMessage` for `zspy.ImuMessage`. Fails with [`ZspyError::AmbiguousType`] if several
+    /// types match, and with [`ZspyError::UnknownType`] and similar names if none does.
+    pub fn resolve_type(&self, msg_type: &str) -> Result<String, ZspyError> {
+        self.types().resolve(msg_type)
+    }
+
+    /// `factory`, or under [`JsonStyle::Canonical`] its [canonical](MessageFactory::canonical)
+    /// form, in place of the serde derives of a built-in type. Plugins convert their types
+    /// themselves either way.
+    fn styled<'a>(&self, factory: &'a Arc<dyn MessageFactory>) -> &'a dyn MessageFactory {
+        match (self.json_style, factory.canonical()) {
+            (JsonStyle::Canonical, Some(canonical)) => canonical,
+            _ => factory.as_ref(),
+        }
+    }
+
+    /// Fails with [`ZspyError::UnknownType`] unless `msg_type` is registered, see
+    /// [`resolve_type`](Self::resolve_type).
+    pub fn check_type(&self, msg_type: &str) -> Result<(), ZspyError> {
+        self.factory(msg_type).map(|_| ())
+    }
+
+    /// Names of all registered types, sorted.
     pub fn list_types(&self) -> Vec<String> {
-        self.factories.keys().cloned().collect()
+        let mut types: Vec<String> = self.types().factories.keys().cloned().collect();
+        types.sort();
+        types
+    }
+
+    /// Where `msg_type` was defined, see [`MessageFactory::source`].
+    pub fn type_source(&self, msg_type: &str) -> Option<String> {
+        self.factory(msg_type).ok().map(|f| f.source())
+    }
+
+    /// The protobuf descriptor of `msg_type`, unless it is unknown or provided by a plugin.
+    pub fn descriptor(&self, msg_type: &str) -> Option<MessageDescriptor> {
+        self.factory(msg_type).ok()?.descriptor().cloned()
+    }
+
+    /// Decodes `bytes` as `msg_type` into JSON that shows field presence, see [`presence`].
+    /// Types without a protobuf schema decode as [`decode`](Self::decode) does.
+    pub fn decode_presence(
+        &self,
+        msg_type: &str,
+        bytes: &[u8],
+        options: PresenceOptions,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let factory = self.factory(msg_type)?;
+        let Some(descriptor) = factory.descriptor().cloned() else {
+            return self.decode(msg_type, bytes);
+        };
+        // Built-in types are named as their serde derives name them under the serde style
+        let options = PresenceOptions {
+            proto_names: self.json_style == JsonStyle::Serde && factory.canonical().is_some(),
+            ..options
+        };
+        let message = DynamicMessage::decode(descriptor.clone(), bytes)
+            .map_err(|e| ZspyError::Decode(e.to_string()))?;
+        let mut value =
+            presence::to_json(&message, options).map_err(|e| ZspyError::Decode(e.to_string()))?;
+        well_known::humanize_durations(&descriptor, &mut value);
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    /// Decodes `bytes` as `msg_type` into protobuf text format, one field per line.
+    pub fn decode_text_format(
+        &self,
+        msg_type: &str,
+        bytes: &[u8],
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let descriptor = self.text_format_descriptor(msg_type)?;
+        let message = DynamicMessage::decode(descriptor, bytes)
+            .map_err(|e| ZspyError::Decode(e.to_string()))?;
+        Ok(message.to_text_format_with_options(&FormatOptions::new().pretty(true)))
+    }
+
+    /// Encodes `text`, a `msg_type` message in protobuf text format.
+    pub fn encode_text_format(
+        &self,
+        msg_type: &str,
+        text: &str,
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let descriptor = self.text_format_descriptor(msg_type)?;
+        let message = DynamicMessage::parse_text_format(descriptor, text)
+            .map_err(|e| ZspyError::Encode(e.to_string()))?;
+        Ok(message.encode_to_vec())
+    }
+
+    fn text_format_descriptor(
+        &self,
+        msg_type: &str,
+    ) -> Result<MessageDescriptor, Box<dyn Error + Send + Sync>> {
+        self.factory(msg_type)?
+            .descriptor()
+            .cloned()
+            .ok_or_else(|| {
+                ZspyError::Encode(format!("{} has no schema for text format", msg_type)).into()
+            })
+    }
+
+    /// JSON Schema of `msg_type`, unless it is unknown or provided by a plugin.
+    pub fn json_schema(&self, msg_type: &str) -> Option<Value> {
+        self.styled(&self.factory(msg_type).ok()?).json_schema()
+    }
+
+    /// Finds fields whose name contains `pattern` (ignoring case) in every registered protobuf
+    /// type, as `(type, field path, field type)` sorted by type. Paths use the field names of
+    /// the JSON the type decodes to.
+    pub fn find_fields(&self, pattern: &str) -> Vec<(String, String, String)> {
+        let mut found = Vec::new();
+        let types = self.types();
+        let mut names: Vec<&String> = types.factories.keys().collect();
+        names.sort();
+        for name in names {
+            let factory = &types.factories[name];
+            let Some(descriptor) = factory.descriptor() else {
+                continue;
+            };
+            let serde_derived = self.json_style == JsonStyle::Serde
+                && types.compiled.iter().any(|(_, f)| Arc::ptr_eq(f, factory));
+            for (path, field_type) in schema::find_fields(descriptor, pattern, !serde_derived) {
+                found.push((name.clone(), path, field_type));
+            }
+        }
+        found
     }
 
     pub fn get_schema(&self, msg_type: &str) -> Option<String> {
-        self.factories.get(msg_type).map(|f| f.get_schema())
+        self.factory(msg_type).ok().map(|f| f.get_schema())
+    }
+}
+
+impl Types {
+    /// See [`MessageRegistry::resolve_type`].
+    fn resolve(&self, msg_type: &str) -> Result<String, ZspyError> {
+        if self.factories.contains_key(msg_type) {
+            return Ok(msg_type.to_string());
+        }
+        let short = |name: &str| name.rsplit('.').next().unwrap_or(name).to_string();
+        let rules: [&dyn Fn(&str) -> bool; 3] = [
+            &|name| name.eq_ignore_ascii_case(msg_type),
+            &|name| short(name) == msg_type,
+            &|name| short(name).eq_ignore_ascii_case(msg_type),
+        ];
+        for rule in rules {
+            let mut found: Vec<String> = self
+                .factories
+                .keys()
+                .filter(|name| rule(name))
+                .cloned()
+                .collect();
+            found.sort();
+            match found.len() {
+                0 => continue,
+                1 => return Ok(found.remove(0)),
+                _ => return Err(ZspyError::AmbiguousType(msg_type.to_string(), found)),
+            }
+        }
+
+        // Suggest names a few typos away, comparing short names too
+        let wanted = msg_type.to_lowercase();
+        let limit = (wanted.chars().count() / 3).max(2);
+        let mut close: Vec<(usize, &String)> = self
+            .factories
+            .keys()
+            .map(|name| {
+                let lower = name.to_lowercase();
+                let distance =
+                    edit_distance(&wanted, &lower).min(edit_distance(&wanted, &short(&lower)));
+                (distance, name)
+            })
+            .filter(|(distance, _)| *distance <= limit)
+            .collect();
+        close.sort();
+        let suggestions = close
+            .into_iter()
+            .take(3)
+            .map(|(_, name)| name.clone())
+            .collect();
+        Err(ZspyError::UnknownType(msg_type.to_string(), suggestions))
+    }
+
+    /// See [`MessageRegistry::load_path`].
+    fn load_path(&mut self, path: &Path) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let (include, proto_files, descriptor_files) = if path.is_dir() {
+            let mut proto_files = Vec::new();
+            let mut descriptor_files = Vec::new();
+            collect_schema_files(path, &mut proto_files, &mut descriptor_files)?;
+            (path.to_path_buf(), proto_files, descriptor_files)
+        } else if is_proto_file(path) {
+            let include = path
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."))
+                .to_path_buf();
+            (include, vec![path.to_path_buf()], Vec::new())
+        } else if path.is_file() {
+            (PathBuf::new(), Vec::new(), vec![path.to_path_buf()])
+        } else {
+            return Err(format!("Proto path not found: {}", path.display()).into());
+        };
+
+        let mut count = 0;
+        if !proto_files.is_empty() {
+            let mut compiler = protox::Compiler::new([include])?;
+            compiler.include_imports(true).open_files(&proto_files)?;
+            count += self.register_files(compiler.file_descriptor_set())?;
+        }
+        for file in descriptor_files {
+            let files = FileDescriptorSet::decode(fs::read(&file)?.as_slice())
+                .map_err(|e| format!("Invalid descriptor set {}: {}", file.display(), e))?;
+            count += self
+                .register_files(files)
+                .map_err(|e| format!("Invalid descriptor set {}: {}", file.display(), e))?;
+        }
+        tracing::info!(path = %path.display(), count, "Loaded message types");
+        Ok(count)
+    }
+
+    /// Registers the message types defined in `files`, merged into the shared pool. Files that
+    /// clash with a different file of the same name already loaded get a pool of their own.
+    fn register_files(
+        &mut self,
+        files: FileDescriptorSet,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let names: HashSet<String> = files.file.iter().map(|f| f.name().to_string()).collect();
+        let clashes = files.file.iter().any(|file| {
+            self.pool
+                .get_file_by_name(file.name())
+                .is_some_and(|existing| existing.file_descriptor_proto() != file)
+        });
+        let previous = self.pool.clone();
+        let pool = if clashes {
+            DescriptorPool::from_file_descriptor_set(files)?
+        } else {
+            let mut merged = self.pool.clone();
+            merged.add_file_descriptor_set(files)?;
+            self.pool = merged.clone();
+            merged
+        };
+        let mut count = 0;
+        for descriptor in pool.all_messages().filter(|m| !m.is_map_entry()) {
+            let added = names.contains(descriptor.parent_file().name());
+            // Types loaded earlier move to the merged pool, so their `Any` fields see the new files
+            let rebound = self
+                .factories
+                .get(descriptor.full_name())
+                .and_then(|factory| factory.descriptor())
+                .is_some_and(|existing| existing.parent_pool() == &previous);
+            if added || rebound {
+                self.factories.insert(
+                    descriptor.full_name().to_string(),
+                    Arc::new(DynamicMessageFactory { descriptor }),
+                );
+            }
+            count += usize::from(added);
+        }
+        Ok(count)
+    }
+}
+
+/// Counts the leaves of a decoded message that hold a non-default value.
+fn populated_leaves(value: &Value) -> usize {
+    match value {
+        Value::Null => 0,
+        Value::Bool(b) => usize::from(*b),
+        Value::Number(n) => usize::from(n.as_f64() != Some(0.0)),
+        Value::String(s) => usize::from(!s.is_empty()),
+        Value::Array(items) => items.iter().map(populated_leaves).sum(),
+        Value::Object(fields) => fields.values().map(populated_leaves).sum(),
+    }
+}
+
+fn is_proto_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "proto")
+}
+
+fn is_descriptor_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "desc" || ext == "pb" || ext == "binpb" || ext == "bin")
+}
+
+/// Whether `event` touched a schema file under `proto_paths`.
+fn is_schema_change(event: notify::Result<notify::Event>, proto_paths: &[PathBuf]) -> bool {
+    let Ok(event) = event else {
+        return false;
+    };
+    if event.kind.is_access() {
+        return false;
+    }
+    event.paths.iter().any(|changed| {
+        proto_paths.iter().any(|path| {
+            if path.is_dir() {
+                is_proto_file(changed) || is_descriptor_file(changed)
+            } else {
+                // Only the file itself counts when its directory is watched for it
+                changed.file_name() == path.file_name()
+            }
+        })
+    })
+}
+
+fn collect_schema_files(
+    dir: &Path,
+    proto_files: &mut Vec<PathBuf>,
+    descriptor_files: &mut Vec<PathBuf>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_schema_files(&path, proto_files, descriptor_files)?;
+        } else if is_proto_file(&path) {
+            proto_files.push(path);
+        } else if is_descriptor_file(&path) {
+            descriptor_files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Levenshtein distance between `a` and `b`, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
     }
+    row[b.len()]
 }
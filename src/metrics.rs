@@ -0,0 +1,176 @@
+//! Prometheus metrics for `--metrics-port`.
+//!
+//! Commands report traffic through the free functions below, which do nothing until [`serve`]
+//! has installed the process-wide registry. The endpoint answers `GET /metrics` in the
+//! Prometheus text format.
+
+use crate::http;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tokio::net::{TcpListener, TcpStream};
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+#[derive(Default)]
+struct KeyCounters {
+    messages_received: u64,
+    bytes_received: u64,
+    messages_published: u64,
+    bytes_published: u64,
+    decode_errors: u64,
+}
+
+/// Name, help text and value of an exported per-key counter
+type Counter = (&'static str, &'static str, fn(&KeyCounters) -> u64);
+
+const COUNTERS: [Counter; 5] = [
+    (
+        "zspy_messages_received_total",
+        "Samples received per key",
+        |c| c.messages_received,
+    ),
+    (
+        "zspy_bytes_received_total",
+        "Payload bytes received per key",
+        |c| c.bytes_received,
+    ),
+    (
+        "zspy_messages_published_total",
+        "Samples published per key",
+        |c| c.messages_published,
+    ),
+    (
+        "zspy_bytes_published_total",
+        "Payload bytes published per key",
+        |c| c.bytes_published,
+    ),
+    (
+        "zspy_decode_errors_total",
+        "Payloads that failed to decode per key",
+        |c| c.decode_errors,
+    ),
+];
+
+struct Metrics {
+    start: Instant,
+    keys: Mutex<BTreeMap<String, KeyCounters>>,
+}
+
+impl Metrics {
+    fn update(&self, key: &str, f: impl FnOnce(&mut KeyCounters)) {
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        match keys.get_mut(key) {
+            Some(counters) => f(counters),
+            None => f(keys.entry(key.to_string()).or_default()),
+        }
+    }
+
+    fn render(&self) -> String {
+        let keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        for (name, help, value) in COUNTERS {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (key, key_counters) in keys.iter() {
+                let value = value(key_counters);
+                if value > 0 {
+                    let _ = writeln!(out, "{}{{key=\"{}\"}} {}", name, escape(key), value);
+                }
+            }
+        }
+        let _ = writeln!(out, "# HELP zspy_uptime_seconds Time since zspy started");
+        let _ = writeln!(out, "# TYPE zspy_uptime_seconds gauge");
+        let _ = writeln!(
+            out,
+            "zspy_uptime_seconds {:.3}",
+            self.start.elapsed().as_secs_f64()
+        );
+        out
+    }
+}
+
+/// Escapes a label value as required by the Prometheus text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Counts a sample of `bytes` payload bytes received on `key`.
+pub fn received(key: &str, bytes: usize) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.update(key, |c| {
+            c.messages_received += 1;
+            c.bytes_received += bytes as u64;
+        });
+    }
+}
+
+/// Counts a sample of `bytes` payload bytes published on `key`.
+pub fn published(key: &str, bytes: usize) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.update(key, |c| {
+            c.messages_published += 1;
+            c.bytes_published += bytes as u64;
+        });
+    }
+}
+
+/// Counts a payload received on `key` that could not be decoded.
+pub fn decode_error(key: &str) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.update(key, |c| c.decode_errors += 1);
+    }
+}
+
+/// Starts recording metrics and serves them on `addr` until the process exits.
+pub async fn serve(addr: SocketAddr) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let listener = TcpListener::bind(addr).await?;
+    METRICS.get_or_init(|| Metrics {
+        start: Instant::now(),
+        keys: Mutex::new(BTreeMap::new()),
+    });
+    tracing::info!(%addr, "Serving Prometheus metrics");
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(async move {
+                        if let Err(err) = handle(stream).await {
+                            tracing::debug!("Metrics request failed: {}", err);
+                        }
+                    });
+                }
+                Err(err) => tracing::warn!("Failed to accept metrics connection: {}", err),
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn handle(mut stream: TcpStream) -> std::io::Result<()> {
+    let Some(request) = http::read_request(&mut stream).await? else {
+        return Ok(());
+    };
+    let (status, body) = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => (
+            "200 OK",
+            METRICS.get().map(Metrics::render).unwrap_or_default(),
+        ),
+        ("GET", _) => ("404 Not Found", "Not found, try /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+    http::respond(
+        &mut stream,
+        status,
+        "text/plain; version=0.0.4",
+        None,
+        body.as_bytes(),
+    )
+    .await
+}
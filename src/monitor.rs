@@ -0,0 +1,291 @@
+//! Interactive terminal monitor for `zspy monitor`.
+//!
+//! Shows one row per concrete key with its message rate, bandwidth, publisher count and a
+//! preview of the last payload, below the combined rate and bandwidth of each `--group`.
+//! Pressing Enter on a row drills into the decoded messages received on that key.
+
+use crate::message_registry::MessageRegistry;
+use crate::metrics;
+use crate::stats::{format_bandwidth, GroupStats, KeyStats};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table, TableState},
+    DefaultTerminal, Frame,
+};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use zenoh::{sample::Sample, Session};
+
+/// Window over which rates and bandwidth are computed
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+/// Number of decoded messages kept per key for the detail view
+const HISTORY_LEN: usize = 100;
+const TICK: Duration = Duration::from_millis(250);
+
+struct KeyState {
+    stats: KeyStats,
+    publishers: HashSet<String>,
+    history: VecDeque<String>,
+}
+
+impl KeyState {
+    fn new() -> Self {
+        Self {
+            stats: KeyStats::new(RATE_WINDOW),
+            publishers: HashSet::new(),
+            history: VecDeque::new(),
+        }
+    }
+}
+
+struct App<'a> {
+    registry: &'a MessageRegistry,
+    msg_type: Option<&'a str>,
+    keys: BTreeMap<String, KeyState>,
+    groups: Vec<GroupStats>,
+    table: TableState,
+    selected: Option<String>,
+}
+
+impl<'a> App<'a> {
+    fn record(&mut self, sample: Sample) {
+        let payload = sample.payload().to_bytes();
+        metrics::received(sample.key_expr().as_str(), payload.len());
+        let msg_type = self
+            .msg_type
+            .or_else(|| self.registry.type_for_key(sample.key_expr().as_str()));
+        let decoded = match msg_type {
+            Some(type_name) => self
+                .registry
+                .decode(type_name, &payload)
+                .unwrap_or_else(|e| {
+                    metrics::decode_error(sample.key_expr().as_str());
+                    format!("Error decoding message: {}", e)
+                }),
+            None => String::from_utf8_lossy(&payload).to_string(),
+        };
+
+        let key = sample.key_expr().as_str();
+        let now = Instant::now();
+        let new_key = !self.keys.contains_key(key);
+        for group in &mut self.groups {
+            group.record(key, new_key, now, payload.len());
+        }
+        let state = self
+            .keys
+            .entry(key.to_string())
+            .or_insert_with(KeyState::new);
+        state.stats.record(now, payload.len());
+        if let Some(timestamp) = sample.timestamp() {
+            state.publishers.insert(timestamp.get_id().to_string());
+        }
+        state.history.push_back(decoded);
+        if state.history.len() > HISTORY_LEN {
+            state.history.pop_front();
+        }
+    }
+
+    fn selected_key(&self) -> Option<String> {
+        self.table
+            .selected()
+            .and_then(|i| self.keys.keys().nth(i).cloned())
+    }
+
+    /// Handles a key press, returning false when the monitor should exit.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        match (code, self.selected.is_some()) {
+            (KeyCode::Char('q'), _) => return false,
+            (KeyCode::Esc | KeyCode::Backspace, true) => self.selected = None,
+            (KeyCode::Esc, false) => return false,
+            (KeyCode::Down | KeyCode::Char('j'), false) => self.table.select_next(),
+            (KeyCode::Up | KeyCode::Char('k'), false) => self.table.select_previous(),
+            (KeyCode::Enter, false) => self.selected = self.selected_key(),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        // Borders and header around one row per group, if there are any
+        let groups_height = match self.groups.len() {
+            0 => 0,
+            n => n as u16 + 3,
+        };
+        let [groups, main, help] = Layout::vertical([
+            Constraint::Length(groups_height),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        if !self.groups.is_empty() {
+            self.draw_groups(frame, groups);
+        }
+
+        match self.selected.clone() {
+            Some(key) => {
+                let items: Vec<ListItem> = self
+                    .keys
+                    .get(&key)
+                    .map(|state| {
+                        state
+                            .history
+                            .iter()
+                            .rev()
+                            .map(|msg| ListItem::new(msg.as_str()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL).title(key.as_str()));
+                frame.render_widget(list, main);
+                frame.render_widget(Paragraph::new("Esc: back  q: quit"), help);
+            }
+            None => {
+                let now = Instant::now();
+                let rows: Vec<Row> = self
+                    .keys
+                    .iter_mut()
+                    .map(|(key, state)| {
+                        state.stats.prune(now);
+                        let preview = state
+                            .history
+                            .back()
+                            .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+                            .unwrap_or_default();
+                        let publishers = if state.publishers.is_empty() {
+                            "-".to_string()
+                        } else {
+                            state.publishers.len().to_string()
+                        };
+                        Row::new(vec![
+                            key.clone(),
+                            format!("{:.1}", state.stats.rate()),
+                            format_bandwidth(state.stats.bandwidth()),
+                            state.stats.total_count.to_string(),
+                            publishers,
+                            preview,
+                        ])
+                    })
+                    .collect();
+                let header = Row::new(vec!["Key", "Hz", "Bandwidth", "Total", "Pubs", "Last"])
+                    .style(Style::default().add_modifier(Modifier::BOLD));
+                let table = Table::new(
+                    rows,
+                    [
+                        Constraint::Percentage(30),
+                        Constraint::Length(8),
+                        Constraint::Length(12),
+                        Constraint::Length(8),
+                        Constraint::Length(5),
+                        Constraint::Min(10),
+                    ],
+                )
+                .header(header)
+                .row_highlight_style(Style::default().bg(Color::DarkGray))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(Line::from(" zspy monitor ").centered()),
+                );
+                frame.render_stateful_widget(table, main, &mut self.table);
+                frame.render_widget(
+                    Paragraph::new("↑/↓ or j/k: select  Enter: inspect  q: quit"),
+                    help,
+                );
+            }
+        }
+    }
+
+    fn draw_groups(&mut self, frame: &mut Frame, area: Rect) {
+        let now = Instant::now();
+        let rows: Vec<Row> = self
+            .groups
+            .iter_mut()
+            .map(|group| {
+                group.stats.prune(now);
+                Row::new(vec![
+                    group.pattern.to_string(),
+                    format!("{:.1}", group.stats.rate()),
+                    format_bandwidth(group.stats.bandwidth()),
+                    group.stats.total_count.to_string(),
+                    group.keys.to_string(),
+                ])
+            })
+            .collect();
+        let header = Row::new(vec!["Group", "Hz", "Bandwidth", "Total", "Keys"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Percentage(30),
+                Constraint::Length(8),
+                Constraint::Length(12),
+                Constraint::Length(8),
+                Constraint::Length(5),
+            ],
+        )
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(" Groups "));
+        frame.render_widget(table, area);
+    }
+}
+
+pub async fn run(
+    session: &Session,
+    registry: &MessageRegistry,
+    key: &str,
+    msg_type: Option<&str>,
+    groups: &[String],
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let groups = groups
+        .iter()
+        .map(|pattern| GroupStats::new(pattern, RATE_WINDOW))
+        .collect::<Result<_, _>>()?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let _subscriber = session
+        .declare_subscriber(key)
+        .callback(move |sample| {
+            let _ = tx.send(sample);
+        })
+        .await?;
+
+    let mut app = App {
+        registry,
+        msg_type,
+        keys: BTreeMap::new(),
+        groups,
+        table: TableState::default().with_selected(0),
+        selected: None,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = tokio::task::block_in_place(|| event_loop(&mut terminal, &mut app, &mut rx));
+    ratatui::restore();
+    result
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    rx: &mut mpsc::UnboundedReceiver<Sample>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    loop {
+        while let Ok(sample) = rx.try_recv() {
+            app.record(sample);
+        }
+        terminal.draw(|frame| app.draw(frame))?;
+
+        if event::poll(TICK)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !app.handle_key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
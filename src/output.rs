@@ -0,0 +1,769 @@
+//! Rendering of received samples for `zspy echo`.
+
+use crate::attachment;
+use crate::display;
+use crate::message_registry::MessageRegistry;
+use crate::terminal;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::ValueEnum;
+use colored::{Color, Colorize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use zenoh::qos::{CongestionControl, Priority, Reliability};
+use zenoh::sample::SampleKind;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored human-readable lines
+    #[default]
+    Human,
+    /// One pretty-printed JSON object per sample
+    Json,
+    /// One compact JSON object per line
+    Jsonl,
+    /// CSV with a header row
+    Csv,
+    /// xxd-style hex dump of the payload
+    Hex,
+    /// Payload bytes verbatim, one sample per line
+    Raw,
+    /// Protobuf messages in text format, each under a `#` comment naming its key
+    Textproto,
+}
+
+/// What `zspy echo` does with a payload that fails to decode.
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnDecodeError {
+    /// Drop the message
+    Skip,
+    /// Show the error and the payload bytes verbatim
+    Raw,
+    /// Show the error and a hex dump of the payload
+    #[default]
+    Hex,
+    /// Stop with a decode error
+    Fail,
+}
+
+impl OnDecodeError {
+    /// How the payload of an undecodable message is rendered in structured output: as text for
+    /// `raw`, otherwise as a hex string.
+    pub fn fallback(self, payload: &[u8]) -> Value {
+        match self {
+            OnDecodeError::Raw => Value::String(String::from_utf8_lossy(payload).into_owned()),
+            _ => Value::String(payload.iter().map(|b| format!("{:02x}", b)).collect()),
+        }
+    }
+}
+
+/// How `zspy echo` shows payloads that aren't text when no type is given. Without a choice, a
+/// hex dump of the start of the payload is shown.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RawEscape {
+    /// Replace bytes that aren't UTF-8 with U+FFFD
+    Lossy,
+    /// Keep valid text, escape control characters and bytes that aren't UTF-8, e.g. `\x9f`
+    Escape,
+    /// A hex dump of the whole payload, with printable ASCII alongside
+    Hex,
+    /// The payload as base64
+    Base64,
+}
+
+impl RawEscape {
+    /// The payload as a single string, as structured output shows it. A hex dump becomes a
+    /// plain hex string.
+    pub fn text(self, payload: &[u8]) -> String {
+        match self {
+            RawEscape::Lossy => String::from_utf8_lossy(payload).into_owned(),
+            RawEscape::Escape => escape(payload),
+            RawEscape::Hex => payload.iter().map(|b| format!("{:02x}", b)).collect(),
+            RawEscape::Base64 => BASE64_STANDARD.encode(payload),
+        }
+    }
+}
+
+/// Bytes of a binary payload shown when no `--raw-escape` is given
+const BINARY_PREVIEW_LEN: usize = 256;
+
+/// Whether `payload` would garble the terminal printed as text: it isn't UTF-8, or it holds
+/// control characters other than whitespace, such as NUL or the escape that starts ANSI codes.
+pub fn is_binary(payload: &[u8]) -> bool {
+    std::str::from_utf8(payload).map_or(true, |text| {
+        text.chars()
+            .any(|c| c.is_control() && !c.is_ascii_whitespace())
+    })
+}
+
+/// `payload` as text with backslashes and control characters other than line breaks and tabs
+/// escaped, e.g. `\u{1b}`, and bytes that aren't UTF-8 as `\xNN`.
+pub fn escape(payload: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in payload.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\n' | '\t' => text.push(c),
+                '\\' => text.push_str("\\\\"),
+                c if c.is_control() => text.extend(c.escape_default()),
+                c => text.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            text.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    text
+}
+
+/// A binary payload rendered by `raw_escape`: the text shown on the message line, the hex dump
+/// shown below it if any, and the payload as structured output shows it.
+pub fn render_binary(
+    raw_escape: Option<RawEscape>,
+    payload: &[u8],
+) -> (String, Option<String>, Value) {
+    let summary = format!("<{} bytes of binary data>", payload.len());
+    let decoded = Value::String(raw_escape.unwrap_or(RawEscape::Hex).text(payload));
+    match raw_escape {
+        None => {
+            let mut dump = hex_dump(&payload[..payload.len().min(BINARY_PREVIEW_LEN)]);
+            if payload.len() > BINARY_PREVIEW_LEN {
+                dump.push_str(&format!(
+                    "... {} more bytes (--raw-escape hex shows them all)\n",
+                    payload.len() - BINARY_PREVIEW_LEN
+                ));
+            }
+            (summary, Some(dump), decoded)
+        }
+        Some(RawEscape::Hex) => (summary, Some(hex_dump(payload)), decoded),
+        Some(raw_escape) => (raw_escape.text(payload), None, decoded),
+    }
+}
+
+/// Colors keys are drawn from; yellow and red are left out as they mark payloads and deletes.
+const KEY_COLORS: [Color; 8] = [
+    Color::Cyan,
+    Color::Green,
+    Color::Magenta,
+    Color::Blue,
+    Color::BrightCyan,
+    Color::BrightGreen,
+    Color::BrightMagenta,
+    Color::BrightBlue,
+];
+
+/// Longest payload preview shown in the `--group-by-key` table
+const PREVIEW_LEN: usize = 80;
+
+/// A color derived from `key`, the same for a given key on every run, so interleaved output from
+/// wildcard subscriptions is easy to follow.
+pub fn key_color(key: &str) -> Color {
+    // FNV-1a, which unlike the std hasher is stable across runs and Rust versions
+    let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    KEY_COLORS[(hash % KEY_COLORS.len() as u64) as usize]
+}
+
+/// Renders a payload as text, decoding it as `msg_type` when given.
+pub fn display_payload(
+    registry: &MessageRegistry,
+    msg_type: Option<&str>,
+    payload: &[u8],
+) -> String {
+    if let Some(type_name) = msg_type {
+        registry
+            .decode(type_name, payload)
+            .unwrap_or_else(|e| format!("Error decoding message: {}", e))
+    } else {
+        String::from_utf8_lossy(payload).to_string()
+    }
+}
+
+/// A received sample prepared for output.
+pub struct EchoRecord<'a> {
+    pub key: &'a str,
+    /// Key expression of the subscription that received the sample, when echoing several
+    pub subscription: Option<&'a str>,
+    pub timestamp: SystemTime,
+    /// ZID of the node that timestamped the sample, if it was timestamped
+    pub source: Option<String>,
+    pub kind: SampleKind,
+    pub priority: Priority,
+    pub reliability: Reliability,
+    pub congestion_control: CongestionControl,
+    pub express: bool,
+    pub encoding: String,
+    pub payload: &'a [u8],
+    /// Human-readable rendering of the payload
+    pub display: String,
+    /// Structured rendering of the payload for machine-readable formats
+    pub decoded: Value,
+    /// Detected format when auto-decoding
+    pub detected: Option<String>,
+    /// Decoded attachment, see [`attachment::decode`]
+    pub attachment: Option<Value>,
+    /// Whether the sample was returned by the `--history` query rather than received live
+    pub history: bool,
+    /// Whether decoding the payload failed, in which case `display` holds the error and
+    /// `decoded` the payload per [`OnDecodeError::fallback`]
+    pub decode_failed: bool,
+    /// Whether the payload arrived in a shared-memory buffer (only detected when built with the
+    /// `shm` feature)
+    pub shm: bool,
+    /// Algorithm and compressed size of a payload decompressed by `echo --decompress`
+    pub compression: Option<(&'static str, usize)>,
+    /// Rate of change per second of each `echo --derive` field since the previous message on
+    /// the key
+    pub rates: Vec<(String, f64)>,
+    /// Hex dump of a binary payload shown on the lines below the message in human output, see
+    /// [`render_binary`]
+    pub dump: Option<String>,
+}
+
+impl EchoRecord<'_> {
+    /// Priority name as accepted by `zspy pub --priority`.
+    fn priority_name(&self) -> &'static str {
+        match self.priority {
+            Priority::RealTime => "real-time",
+            Priority::InteractiveHigh => "interactive-high",
+            Priority::InteractiveLow => "interactive-low",
+            Priority::DataHigh => "data-high",
+            Priority::Data => "data",
+            Priority::DataLow => "data-low",
+            Priority::Background => "background",
+        }
+    }
+
+    fn congestion_control_name(&self) -> String {
+        format!("{:?}", self.congestion_control).to_lowercase()
+    }
+
+    /// Reliability name as accepted by `zspy pub --reliability`.
+    fn reliability_name(&self) -> &'static str {
+        match self.reliability {
+            Reliability::Reliable => "reliable",
+            Reliability::BestEffort => "best-effort",
+        }
+    }
+
+    /// Renders `display` again from `decoded` after a transform changed it, which also drops the
+    /// hex dump of a binary payload.
+    pub fn render_decoded(&mut self) -> serde_json::Result<()> {
+        self.display = match &self.decoded {
+            Value::String(s) => s.clone(),
+            other => serde_json::to_string_pretty(other)?,
+        };
+        self.dump = None;
+        Ok(())
+    }
+
+    /// The record as written by the `json` and `jsonl` formats.
+    pub fn to_json(&self, verbose: bool) -> Value {
+        let mut value = json!({
+            "key": self.key,
+            "timestamp": humantime::format_rfc3339_nanos(self.timestamp).to_string(),
+            "kind": self.kind.to_string(),
+            "encoding": self.encoding,
+            "payload": self.decoded,
+        });
+        if let Some(subscription) = self.subscription {
+            value["subscription"] = json!(subscription);
+        }
+        if let Some(source) = &self.source {
+            value["source"] = json!(source);
+        }
+        if verbose {
+            value["priority"] = json!(self.priority_name());
+            value["congestion_control"] = json!(self.congestion_control_name());
+            value["reliability"] = json!(self.reliability_name());
+            value["express"] = json!(self.express);
+            value["shm"] = json!(self.shm);
+            if let Some((algorithm, compressed_size)) = self.compression {
+                value["compression"] = json!({
+                    "algorithm": algorithm,
+                    "compressed_size": compressed_size,
+                    "size": self.payload.len(),
+                });
+            }
+        }
+        if let Some(detected) = &self.detected {
+            value["format"] = json!(detected);
+        }
+        if let Some(attachment) = &self.attachment {
+            value["attachment"] = attachment.clone();
+        }
+        if !self.rates.is_empty() {
+            value["rates"] = Value::Object(
+                self.rates
+                    .iter()
+                    .map(|(field, rate)| (field.clone(), json!(rate)))
+                    .collect(),
+            );
+        }
+        if self.history {
+            value["history"] = json!(true);
+        }
+        if self.decode_failed {
+            value["decode_error"] = json!(self.display);
+        }
+        value
+    }
+}
+
+/// Where a command writes its output, status lines and warnings: stdout and stderr, or any
+/// pair of writers when it is embedded or tested. Lines are written like `println!` and
+/// `eprintln!`, except that a closed stream is ignored rather than a panic.
+#[derive(Clone)]
+pub struct Console {
+    out: Arc<Mutex<Box<dyn Write + Send>>>,
+    err: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl Console {
+    pub fn new(out: Box<dyn Write + Send>, err: Box<dyn Write + Send>) -> Self {
+        Self {
+            out: Arc::new(Mutex::new(out)),
+            err: Arc::new(Mutex::new(err)),
+        }
+    }
+
+    /// The process's stdout and stderr.
+    pub fn stdio() -> Self {
+        Self::new(Box::new(io::stdout()), Box::new(io::stderr()))
+    }
+
+    /// Prints a line to the output.
+    pub fn println(&self, line: impl std::fmt::Display) {
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", line).and_then(|_| out.flush());
+    }
+
+    /// Prints text to the output as it is, without a newline.
+    pub fn print(&self, text: impl std::fmt::Display) {
+        let mut out = self.out.lock().unwrap();
+        let _ = write!(out, "{}", text).and_then(|_| out.flush());
+    }
+
+    /// Prints a line to the error stream.
+    pub fn eprintln(&self, line: impl std::fmt::Display) {
+        let mut err = self.err.lock().unwrap();
+        let _ = writeln!(err, "{}", line).and_then(|_| err.flush());
+    }
+
+    /// A writer onto the output, for handing to a [`Printer`].
+    pub fn out(&self) -> Box<dyn Write + Send> {
+        Box::new(ConsoleOut(Arc::clone(&self.out)))
+    }
+}
+
+struct ConsoleOut(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl Write for ConsoleOut {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
+
+/// Most files [`SplitOutput`] keeps open at once, to stay well under the process's file limit
+pub const MAX_OPEN_FILES: usize = 64;
+
+/// Writes each key's samples as JSON lines to a file of its own under a directory, for
+/// `--split-output`: `robot/imu/0` goes to `<dir>/robot/imu/0.jsonl`.
+///
+/// Only the [`MAX_OPEN_FILES`] most recently written files stay open; the others are flushed
+/// and closed, and reopened for appending when their key comes back.
+pub struct SplitOutput {
+    dir: PathBuf,
+    /// Open files, with the write count at which each was last used
+    files: HashMap<String, (BufWriter<File>, u64)>,
+    /// Every key written so far, open or not
+    keys: HashSet<String>,
+    writes: u64,
+}
+
+impl SplitOutput {
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            files: HashMap::new(),
+            keys: HashSet::new(),
+            writes: 0,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// How many keys have a file so far.
+    pub fn keys(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Where `key`'s samples go. Chunks that would leave the directory, `.` and `..`, get a
+    /// `_` prefix.
+    fn path(&self, key: &str) -> PathBuf {
+        let mut path = self.dir.clone();
+        for chunk in key.split('/') {
+            match chunk {
+                "." | ".." => path.push(format!("_{}", chunk)),
+                _ => path.push(chunk),
+            }
+        }
+        path.set_extension(match path.extension() {
+            Some(extension) => format!("{}.jsonl", extension.to_string_lossy()),
+            None => "jsonl".to_string(),
+        });
+        path
+    }
+
+    /// Appends `line` to `key`'s file, creating (or truncating) it and its directories on the
+    /// key's first sample.
+    pub fn write(&mut self, key: &str, line: &Value) -> io::Result<()> {
+        self.writes += 1;
+        if !self.files.contains_key(key) {
+            if self.files.len() >= MAX_OPEN_FILES {
+                self.close_least_recent()?;
+            }
+            let path = self.path(key);
+            let file = if self.keys.contains(key) {
+                File::options().append(true).open(&path)?
+            } else {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                self.keys.insert(key.to_string());
+                File::create(&path)?
+            };
+            self.files
+                .insert(key.to_string(), (BufWriter::new(file), self.writes));
+        }
+        let (out, last_used) = self.files.get_mut(key).expect("opened above");
+        *last_used = self.writes;
+        serde_json::to_writer(&mut *out, line)?;
+        out.write_all(b"\n")
+    }
+
+    fn close_least_recent(&mut self) -> io::Result<()> {
+        let least_recent = self
+            .files
+            .iter()
+            .min_by_key(|(_, (_, last_used))| *last_used)
+            .map(|(key, _)| key.clone());
+        if let Some((mut out, _)) = least_recent.and_then(|key| self.files.remove(&key)) {
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.files.values_mut().try_for_each(|(out, _)| out.flush())
+    }
+}
+
+pub struct Printer {
+    out: Box<dyn Write + Send>,
+    format: OutputFormat,
+    /// Also print the QoS the sample was sent with
+    verbose: bool,
+    /// How human output shows the payload of a message that failed to decode
+    on_decode_error: OnDecodeError,
+    wrote_header: bool,
+}
+
+impl Printer {
+    /// A printer writing to stdout.
+    pub fn new(format: OutputFormat, verbose: bool, on_decode_error: OnDecodeError) -> Self {
+        Self::with_writer(format, verbose, on_decode_error, Box::new(io::stdout()))
+    }
+
+    pub fn with_writer(
+        format: OutputFormat,
+        verbose: bool,
+        on_decode_error: OnDecodeError,
+        out: Box<dyn Write + Send>,
+    ) -> Self {
+        Self {
+            out,
+            format,
+            verbose,
+            on_decode_error,
+            wrote_header: false,
+        }
+    }
+
+    pub fn print(&mut self, record: &EchoRecord) -> io::Result<()> {
+        let out = &mut self.out;
+        match self.format {
+            OutputFormat::Human => {
+                if let Some(subscription) = record.subscription {
+                    write!(out, "[{}] ", subscription.blue())?;
+                }
+                let kind = match record.kind {
+                    SampleKind::Put => record.kind.to_string().normal(),
+                    SampleKind::Delete => record.kind.to_string().red(),
+                };
+                let status = if record.history {
+                    "History".blue()
+                } else {
+                    "Received".green()
+                };
+                write!(
+                    out,
+                    ">> [{}] {} {} '{}'",
+                    status,
+                    humantime::format_rfc3339_millis(record.timestamp),
+                    kind,
+                    record.key.color(key_color(record.key))
+                )?;
+                if let Some(detected) = &record.detected {
+                    write!(out, " ({})", detected.magenta())?;
+                }
+                if let Some(source) = &record.source {
+                    write!(out, " from {}", source.blue())?;
+                }
+                if record.kind == SampleKind::Put {
+                    write!(out, ": '{}'", display::highlight(&record.display))?;
+                }
+                if let Some(attachment) = &record.attachment {
+                    write!(out, " {{{}}}", attachment::display(attachment).blue())?;
+                }
+                if !record.rates.is_empty() {
+                    let rates: Vec<String> = record
+                        .rates
+                        .iter()
+                        .map(|(field, rate)| format!("{}: {:.3}/s", field, rate))
+                        .collect();
+                    write!(out, " [{}]", rates.join(", ").magenta())?;
+                }
+                if self.verbose {
+                    let compression = record.compression.map(|(algorithm, compressed_size)| {
+                        format!(
+                            " {}={}{}{} bytes",
+                            algorithm,
+                            compressed_size,
+                            terminal::symbol("→", "->"),
+                            record.payload.len()
+                        )
+                    });
+                    write!(
+                        out,
+                        " [priority={} congestion={} reliability={} express={}{}{}]",
+                        record.priority_name(),
+                        record.congestion_control_name(),
+                        record.reliability_name(),
+                        record.express,
+                        if record.shm { " shm" } else { "" },
+                        compression.unwrap_or_default()
+                    )?;
+                }
+                writeln!(out)?;
+                if let Some(dump) = &record.dump {
+                    write!(out, "{}", dump)?;
+                }
+                if record.decode_failed && record.kind == SampleKind::Put {
+                    match self.on_decode_error {
+                        OnDecodeError::Raw => {
+                            out.write_all(record.payload)?;
+                            out.write_all(b"\n")?
+                        }
+                        _ => write!(out, "{}", hex_dump(record.payload))?,
+                    }
+                }
+            }
+            OutputFormat::Json => {
+                let json = record.to_json(self.verbose);
+                writeln!(out, "{}", serde_json::to_string_pretty(&json)?)?
+            }
+            OutputFormat::Jsonl => writeln!(out, "{}", record.to_json(self.verbose))?,
+            OutputFormat::Csv => {
+                if !self.wrote_header {
+                    if record.subscription.is_some() {
+                        write!(out, "subscription,")?;
+                    }
+                    write!(out, "key,timestamp,source,kind,encoding,")?;
+                    if self.verbose {
+                        write!(out, "priority,congestion_control,reliability,express,")?;
+                    }
+                    writeln!(out, "payload")?;
+                    self.wrote_header = true;
+                }
+                if let Some(subscription) = record.subscription {
+                    write!(out, "{},", csv_field(subscription))?;
+                }
+                let payload = match &record.decoded {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                write!(
+                    out,
+                    "{},{},{},{},{},",
+                    csv_field(record.key),
+                    humantime::format_rfc3339_nanos(record.timestamp),
+                    record.source.as_deref().unwrap_or_default(),
+                    record.kind,
+                    csv_field(&record.encoding)
+                )?;
+                if self.verbose {
+                    write!(
+                        out,
+                        "{},{},{},{},",
+                        record.priority_name(),
+                        record.congestion_control_name(),
+                        record.reliability_name(),
+                        record.express
+                    )?;
+                }
+                writeln!(out, "{}", csv_field(&payload))?
+            }
+            OutputFormat::Hex => {
+                if let Some(subscription) = record.subscription {
+                    write!(out, "[{}] ", subscription.blue())?;
+                }
+                writeln!(
+                    out,
+                    "{} ({} bytes)",
+                    record.key.color(key_color(record.key)),
+                    record.payload.len()
+                )?;
+                write!(out, "{}", hex_dump(record.payload))?
+            }
+            OutputFormat::Raw => {
+                out.write_all(record.payload)?;
+                out.write_all(b"\n")?
+            }
+            OutputFormat::Textproto => {
+                writeln!(
+                    out,
+                    "# {} {} {}",
+                    record.key,
+                    humantime::format_rfc3339_nanos(record.timestamp),
+                    record.kind
+                )?;
+                if record.kind == SampleKind::Put {
+                    writeln!(out, "{}", record.display.trim_end())?;
+                }
+                writeln!(out)?
+            }
+        }
+        out.flush()
+    }
+}
+
+/// Last value received on each key, for `zspy echo --group-by-key`.
+#[derive(Default)]
+pub struct KeyTable {
+    rows: BTreeMap<String, KeyRow>,
+}
+
+struct KeyRow {
+    count: u64,
+    timestamp: SystemTime,
+    kind: SampleKind,
+    preview: String,
+}
+
+impl KeyTable {
+    pub fn update(&mut self, record: &EchoRecord) {
+        // Collapse pretty-printed JSON onto one line
+        let preview = match &record.decoded {
+            Value::String(s) => s.split_whitespace().collect::<Vec<_>>().join(" "),
+            other => other.to_string(),
+        };
+        let preview = match preview.char_indices().nth(PREVIEW_LEN) {
+            Some((end, _)) => format!("{}...", &preview[..end]),
+            None => preview,
+        };
+        let row = self
+            .rows
+            .entry(record.key.to_string())
+            .or_insert_with(|| KeyRow {
+                count: 0,
+                timestamp: record.timestamp,
+                kind: record.kind,
+                preview: String::new(),
+            });
+        row.count += 1;
+        row.timestamp = record.timestamp;
+        row.kind = record.kind;
+        row.preview = preview;
+    }
+
+    /// Draws one row per key, sorted by key. On a terminal that allows live updates the screen
+    /// is cleared first, so the table redraws in place; otherwise it is printed as plain lines.
+    pub fn draw(&self, console: &Console) {
+        let width = self.rows.keys().map(|k| k.len()).max().unwrap_or(0).max(3);
+        let mut screen = match terminal::live_updates() {
+            true => String::from("\x1b[2J\x1b[H"),
+            false => String::new(),
+        };
+        let _ = writeln!(
+            screen,
+            "{:<width$}  {:>8}  {:<24}  LAST VALUE",
+            "KEY",
+            "COUNT",
+            "LAST RECEIVED",
+            width = width
+        );
+        for (key, row) in &self.rows {
+            let value = match row.kind {
+                SampleKind::Put => row.preview.yellow(),
+                SampleKind::Delete => "DELETE".red(),
+            };
+            let _ = writeln!(
+                screen,
+                "{}  {:>8}  {:<24}  {}",
+                format!("{:<width$}", key, width = width).color(key_color(key)),
+                row.count,
+                humantime::format_rfc3339_millis(row.timestamp).to_string(),
+                value
+            );
+        }
+        console.print(screen);
+    }
+}
+
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Formats `bytes` like `xxd`: offset, 16 bytes per line in groups of two, printable ASCII.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (j, byte) in chunk.iter().enumerate() {
+            if j > 0 && j % 2 == 0 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x}", byte));
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        dump.push_str(&format!("{:08x}: {:<40} {}\n", i * 16, hex, ascii));
+    }
+    dump
+}
@@ -0,0 +1,73 @@
+//! Paging the output of one-shot commands such as `zspy get` and `zspy types show`.
+//!
+//! When stdout is a terminal, the output is collected and, if it doesn't fit on the screen,
+//! piped into `$PAGER` (default: `less -R`, which keeps colors) once complete. Otherwise it is
+//! printed as it is produced.
+
+use ratatui::crossterm::terminal;
+use std::fmt::Display;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+pub struct Pager {
+    /// Collected output, or `None` to print directly
+    buffer: Option<String>,
+}
+
+impl Pager {
+    /// A pager, unless `enabled` is false or stdout isn't a terminal.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            buffer: (enabled && io::stdout().is_terminal()).then(String::new),
+        }
+    }
+
+    pub fn line(&mut self, line: impl Display) {
+        match &mut self.buffer {
+            Some(buffer) => {
+                buffer.push_str(&line.to_string());
+                buffer.push('\n');
+            }
+            None => println!("{}", line),
+        }
+    }
+
+    /// Shows the collected output, in the pager if it is taller than the terminal.
+    pub fn finish(self) -> io::Result<()> {
+        let Some(text) = self.buffer else {
+            return Ok(());
+        };
+        let rows = terminal::size().map_or(usize::MAX, |(_, rows)| rows as usize);
+        if text.lines().count() < rows {
+            print!("{}", text);
+            return Ok(());
+        }
+        let command = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+        let mut words = command.split_whitespace();
+        let Some(program) = words.next() else {
+            print!("{}", text);
+            return Ok(());
+        };
+        let mut child = match Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            // No pager installed
+            Err(_) => {
+                print!("{}", text);
+                return Ok(());
+            }
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            // The pager closes its input when quit before reaching the end
+            match stdin.write_all(text.as_bytes()) {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+                _ => {}
+            }
+        }
+        child.wait()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! Playback engine for `zspy play`.
+//!
+//! Samples are republished on their recorded keys, or on keys remapped by prefix, scheduled
+//! relative to the first sample in the capture so the original inter-message timing is
+//! preserved (scaled by `rate`). Both zbag and MCAP captures are accepted.
+
+use crate::message_registry::MessageRegistry;
+use crate::recording::{self, RecordedSample};
+use crate::transform::Transform;
+use colored::Colorize;
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use zenoh::{key_expr::KeyExpr, sample::SampleKind, Session};
+
+pub struct PlaybackOptions {
+    /// Speed multiplier, e.g. 2.0 plays back twice as fast
+    pub rate: f64,
+    /// Restart from the beginning once the capture is exhausted
+    pub looping: bool,
+    /// `(old, new)` key prefixes: a recorded key equal to or under `old` is published under
+    /// `new` instead. The first matching pair applies
+    pub remap: Vec<(String, String)>,
+    /// Pipeline rewriting each payload before it is published
+    pub transform: Option<Transform>,
+}
+
+/// The key `key` is published on after applying `remap`.
+fn remap_key(key: &str, remap: &[(String, String)]) -> String {
+    for (old, new) in remap {
+        if key == old {
+            return new.clone();
+        }
+        if let Some(rest) = key
+            .strip_prefix(old.as_str())
+            .and_then(|r| r.strip_prefix('/'))
+        {
+            return format!("{}/{}", new, rest);
+        }
+    }
+    key.to_string()
+}
+
+/// Plays back the capture at `path`, returning the total number of samples published.
+pub async fn play(
+    session: &Session,
+    registry: &MessageRegistry,
+    path: &Path,
+    options: &PlaybackOptions,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    if options.rate <= 0.0 {
+        return Err("Playback rate must be positive".into());
+    }
+    for (old, new) in &options.remap {
+        for key in [old, new] {
+            KeyExpr::try_from(key.as_str())?;
+            if key.contains('*') {
+                return Err(format!("Remapped key '{}' must not contain wildcards", key).into());
+            }
+        }
+    }
+    if let Some(transform) = &options.transform {
+        transform.check_types(registry)?;
+    }
+
+    let mut published = 0;
+    loop {
+        let mut reader = recording::open(path)?;
+        let start = Instant::now();
+        let mut first_ns = None;
+
+        while let Some(mut sample) = reader.read()? {
+            let first = *first_ns.get_or_insert(sample.timestamp_ns);
+            let offset_ns = sample.timestamp_ns.saturating_sub(first) as f64 / options.rate;
+            time::sleep_until(start + Duration::from_nanos(offset_ns as u64)).await;
+            sample.key = remap_key(&sample.key, &options.remap);
+            if let Some(transform) = options.transform.as_ref().filter(|t| !t.is_identity()) {
+                if sample.kind == SampleKind::Put {
+                    match transform.apply(registry, &sample.payload) {
+                        Ok((payload, encoding)) => {
+                            sample.payload = payload;
+                            sample.encoding = encoding.to_string();
+                        }
+                        Err(e) => {
+                            // One bad message shouldn't end the playback
+                            eprintln!(
+                                "{}",
+                                format!("Skipping sample on {}: {}", sample.key, e).yellow()
+                            );
+                            continue;
+                        }
+                    }
+                }
+            }
+            publish(session, sample).await?;
+            published += 1;
+        }
+
+        if !options.looping || first_ns.is_none() {
+            break;
+        }
+    }
+
+    Ok(published)
+}
+
+async fn publish(
+    session: &Session,
+    sample: RecordedSample,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match sample.kind {
+        SampleKind::Put => {
+            session
+                .put(sample.key, sample.payload)
+                .encoding(sample.encoding)
+                .attachment(sample.attachment)
+                .await?
+        }
+        SampleKind::Delete => {
+            session
+                .delete(sample.key)
+                .attachment(sample.attachment)
+                .await?
+        }
+    }
+    Ok(())
+}
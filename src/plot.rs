@@ -0,0 +1,271 @@
+//! Live terminal chart for `zspy plot`.
+//!
+//! Every selected field of every matching key is a series, drawn as a braille line over a
+//! scrolling time window. The y axis fits the visible values.
+
+use crate::codec::PayloadEncoding;
+use crate::expr;
+use crate::message_registry::MessageRegistry;
+use crate::metrics;
+use clap::Args;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Style},
+    symbols::Marker,
+    text::{Line, Span},
+    widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph},
+    DefaultTerminal, Frame,
+};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use zenoh::{sample::Sample, Session};
+
+const TICK: Duration = Duration::from_millis(100);
+const SERIES_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Magenta,
+    Color::Green,
+    Color::Red,
+    Color::Blue,
+];
+
+#[derive(Args)]
+pub struct PlotArgs {
+    /// The key expression to plot, e.g. `robot/imu`
+    pub key_expr: String,
+    /// Protobuf type of the payloads
+    #[arg(long)]
+    pub r#type: Option<String>,
+    /// Schemaless format of the payloads (default: JSON)
+    #[arg(long, value_enum, conflicts_with = "type")]
+    pub encoding: Option<PayloadEncoding>,
+    /// Numeric field path to plot, e.g. `orientation.x` (repeatable or comma-separated)
+    #[arg(long, required = true, value_delimiter = ',')]
+    pub field: Vec<String>,
+    /// Time span shown on the x axis
+    #[arg(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    pub window: Duration,
+}
+
+impl PlotArgs {
+    fn decode(
+        &self,
+        registry: &MessageRegistry,
+        payload: &[u8],
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        registry.decode_with(self.r#type.as_deref(), self.encoding, payload)
+    }
+}
+
+/// A field value as a number: booleans count as 1/0 and numeric strings are parsed.
+fn numeric(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::Bool(b) => Some(f64::from(u8::from(*b))),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+struct App<'a> {
+    registry: &'a MessageRegistry,
+    args: &'a PlotArgs,
+    /// Recent points per (key, field)
+    series: BTreeMap<(String, String), VecDeque<(Instant, f64)>>,
+    decode_errors: u64,
+    paused: bool,
+}
+
+impl App<'_> {
+    fn record(&mut self, sample: Sample, now: Instant) {
+        let key = sample.key_expr().as_str();
+        let payload = sample.payload().to_bytes();
+        metrics::received(key, payload.len());
+        let value = match self.args.decode(self.registry, &payload) {
+            Ok(value) => value,
+            Err(_) => {
+                metrics::decode_error(key);
+                self.decode_errors += 1;
+                return;
+            }
+        };
+        for field in &self.args.field {
+            if let Some(y) = expr::get_path(&value, field).and_then(numeric) {
+                self.series
+                    .entry((key.to_string(), field.clone()))
+                    .or_default()
+                    .push_back((now, y));
+            }
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        for points in self.series.values_mut() {
+            while points
+                .front()
+                .is_some_and(|(t, _)| now.duration_since(*t) > self.args.window)
+            {
+                points.pop_front();
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame, now: Instant) {
+        let [main, help] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+
+        let multiple_keys = self
+            .series
+            .keys()
+            .map(|(key, _)| key)
+            .collect::<BTreeSet<_>>()
+            .len()
+            > 1;
+        let points: Vec<Vec<(f64, f64)>> = self
+            .series
+            .values()
+            .map(|points| {
+                points
+                    .iter()
+                    .map(|(t, y)| (-now.duration_since(*t).as_secs_f64(), *y))
+                    .collect()
+            })
+            .collect();
+        let datasets: Vec<Dataset> = self
+            .series
+            .iter()
+            .zip(&points)
+            .enumerate()
+            .map(|(i, (((key, field), recent), data))| {
+                let name = if multiple_keys {
+                    format!("{} {}", key, field)
+                } else {
+                    field.clone()
+                };
+                let last = recent.back().map(|(_, y)| format!(" = {}", y));
+                Dataset::default()
+                    .name(format!("{}{}", name, last.unwrap_or_default()))
+                    .marker(Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(SERIES_COLORS[i % SERIES_COLORS.len()]))
+                    .data(data)
+            })
+            .collect();
+
+        let (mut min, mut max) = points
+            .iter()
+            .flatten()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), (_, y)| {
+                (lo.min(*y), hi.max(*y))
+            });
+        if min > max {
+            (min, max) = (0.0, 1.0);
+        } else {
+            // A flat series gets a band around its value instead of a zero-height axis
+            let margin = if max > min {
+                (max - min) * 0.05
+            } else {
+                max.abs().max(1.0) * 0.1
+            };
+            min -= margin;
+            max += margin;
+        }
+        let window = self.args.window.as_secs_f64();
+
+        let mut title = format!(" zspy plot {} ", self.args.key_expr);
+        if self.decode_errors > 0 {
+            title.push_str(&format!("({} decode errors) ", self.decode_errors));
+        }
+        let chart = Chart::new(datasets)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(Line::from(title).centered()),
+            )
+            .x_axis(
+                Axis::default()
+                    .bounds([-window, 0.0])
+                    .labels([format!("-{:.0}s", window), "now".to_string()]),
+            )
+            .y_axis(Axis::default().bounds([min, max]).labels([
+                Span::raw(format!("{:.3}", min)),
+                Span::raw(format!("{:.3}", (min + max) / 2.0)),
+                Span::raw(format!("{:.3}", max)),
+            ]))
+            .hidden_legend_constraints((Constraint::Percentage(60), Constraint::Percentage(60)));
+        frame.render_widget(chart, main);
+        let help_text = if self.paused {
+            "Paused  space: resume  q: quit"
+        } else {
+            "space: pause  q: quit"
+        };
+        frame.render_widget(Paragraph::new(help_text), help);
+    }
+}
+
+pub async fn run(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &PlotArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(msg_type) = &args.r#type {
+        registry.check_type(msg_type)?;
+    }
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let _subscriber = session
+        .declare_subscriber(args.key_expr.as_str())
+        .callback(move |sample| {
+            let _ = tx.send((sample, Instant::now()));
+        })
+        .await?;
+
+    let mut app = App {
+        registry,
+        args,
+        series: BTreeMap::new(),
+        decode_errors: 0,
+        paused: false,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = tokio::task::block_in_place(|| event_loop(&mut terminal, &mut app, &mut rx));
+    ratatui::restore();
+    result
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    rx: &mut mpsc::UnboundedReceiver<(Sample, Instant)>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // While paused, the chart stays frozen at the moment of pausing
+    let mut frozen_at = Instant::now();
+    loop {
+        if !app.paused {
+            while let Ok((sample, received)) = rx.try_recv() {
+                app.record(sample, received);
+            }
+            frozen_at = Instant::now();
+            app.prune(frozen_at);
+        }
+        terminal.draw(|frame| app.draw(frame, frozen_at))?;
+
+        if event::poll(TICK)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Char(' ') => app.paused = !app.paused,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Decoder plugins: shared libraries implementing the C ABI in `examples/plugin/zspy_plugin.h`.
+//!
+//! Each type a plugin lists is registered like a protobuf message type, so `--type` decodes
+//! (and, if the plugin exports `zspy_plugin_encode`, encodes) it through the plugin.
+
+use crate::codec::Codec;
+use crate::message_registry::MessageFactory;
+use libloading::Library;
+use prost_reflect::MessageDescriptor;
+use serde_json::Value;
+use std::error::Error;
+use std::ffi::{c_char, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const ABI_VERSION: u32 = 1;
+
+#[repr(C)]
+struct Buffer {
+    data: *mut u8,
+    len: usize,
+}
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type TypesFn = unsafe extern "C" fn() -> *const *const c_char;
+type CodecFn = unsafe extern "C" fn(*const c_char, *const u8, usize, *mut Buffer) -> i32;
+type FreeFn = unsafe extern "C" fn(Buffer);
+
+/// A loaded plugin library.
+struct Plugin {
+    path: PathBuf,
+    decode: CodecFn,
+    encode: Option<CodecFn>,
+    free: FreeFn,
+    // Keeps the function pointers above valid; declared last so it is dropped last
+    _library: Library,
+}
+
+impl Plugin {
+    /// Calls `function` with `input` and converts what it wrote to `out` into a result.
+    fn call(
+        &self,
+        function: CodecFn,
+        type_name: &CStr,
+        input: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let mut out = Buffer {
+            data: std::ptr::null_mut(),
+            len: 0,
+        };
+        // SAFETY: the plugin ABI requires `function` to only read `input.len()` bytes of
+        // `input`, and to leave `out` empty or pointing at `out.len` bytes it allocated.
+        let status = unsafe { function(type_name.as_ptr(), input.as_ptr(), input.len(), &mut out) };
+        let bytes = if out.data.is_null() {
+            Vec::new()
+        } else {
+            // SAFETY: see above; the buffer is copied before it is handed back to the plugin.
+            let bytes = unsafe { std::slice::from_raw_parts(out.data, out.len) }.to_vec();
+            unsafe { (self.free)(out) };
+            bytes
+        };
+        if status == 0 {
+            Ok(bytes)
+        } else if bytes.is_empty() {
+            Err(format!(
+                "Plugin {} failed with status {}",
+                self.path.display(),
+                status
+            )
+            .into())
+        } else {
+            Err(String::from_utf8_lossy(&bytes).into_owned().into())
+        }
+    }
+}
+
+/// A message type provided by a plugin.
+pub struct PluginType {
+    plugin: Arc<Plugin>,
+    name: CString,
+}
+
+impl PluginType {
+    pub fn name(&self) -> &str {
+        self.name.to_str().unwrap_or_default()
+    }
+}
+
+impl Codec for PluginType {
+    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let json = self.plugin.call(self.plugin.decode, &self.name, bytes)?;
+        let value: Value = serde_json::from_slice(&json).map_err(|e| {
+            format!(
+                "Plugin {} returned invalid JSON: {}",
+                self.plugin.path.display(),
+                e
+            )
+        })?;
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    fn encode(&self, json: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let encode = self.plugin.encode.ok_or_else(|| {
+            format!(
+                "Plugin {} does not support encoding",
+                self.plugin.path.display()
+            )
+        })?;
+        self.plugin.call(encode, &self.name, json.as_bytes())
+    }
+}
+
+impl MessageFactory for PluginType {
+    fn get_schema(&self) -> String {
+        format!("Provided by plugin {}", self.plugin.path.display())
+    }
+
+    fn source(&self) -> String {
+        self.plugin.path.display().to_string()
+    }
+
+    fn descriptor(&self) -> Option<&MessageDescriptor> {
+        None
+    }
+
+    fn json_schema(&self) -> Option<Value> {
+        None
+    }
+
+    fn reencode(&self, _bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        // Opaque formats can't be validated this way, so plugins don't take part in
+        // --auto-decode
+        Err("Plugin types are not auto-detected".into())
+    }
+}
+
+/// Loads the plugin at `path` and returns the types it provides.
+pub fn load(path: &Path) -> Result<Vec<PluginType>, Box<dyn Error + Send + Sync>> {
+    let context = |e: libloading::Error| format!("Failed to load plugin {}: {}", path.display(), e);
+    // SAFETY: loading a library runs its initializers; plugins are trusted like zspy itself.
+    let library = unsafe { Library::new(path) }.map_err(context)?;
+    // SAFETY: the symbol types match the declarations in zspy_plugin.h.
+    let (abi_version, types, decode, encode, free) = unsafe {
+        (
+            *library
+                .get::<AbiVersionFn>(b"zspy_plugin_abi_version\0")
+                .map_err(context)?,
+            *library
+                .get::<TypesFn>(b"zspy_plugin_types\0")
+                .map_err(context)?,
+            *library
+                .get::<CodecFn>(b"zspy_plugin_decode\0")
+                .map_err(context)?,
+            library
+                .get::<CodecFn>(b"zspy_plugin_encode\0")
+                .ok()
+                .map(|f| *f),
+            *library
+                .get::<FreeFn>(b"zspy_plugin_free\0")
+                .map_err(context)?,
+        )
+    };
+
+    // SAFETY: plain calls into the plugin as described by its ABI.
+    let version = unsafe { abi_version() };
+    if version != ABI_VERSION {
+        return Err(format!(
+            "Plugin {} uses ABI version {}, expected {}",
+            path.display(),
+            version,
+            ABI_VERSION
+        )
+        .into());
+    }
+    let mut names = Vec::new();
+    let mut entry = unsafe { types() };
+    // SAFETY: `types` returns a NULL-terminated array of NUL-terminated strings that live as
+    // long as the library.
+    while !entry.is_null() && !unsafe { *entry }.is_null() {
+        names.push(unsafe { CStr::from_ptr(*entry) }.to_owned());
+        entry = unsafe { entry.add(1) };
+    }
+
+    let plugin = Arc::new(Plugin {
+        path: path.to_path_buf(),
+        decode,
+        encode,
+        free,
+        _library: library,
+    });
+    Ok(names
+        .into_iter()
+        .map(|name| PluginType {
+            plugin: plugin.clone(),
+            name,
+        })
+        .collect())
+}
+
+/// Whether `path` looks like a shared library on this platform.
+pub fn is_library(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == std::env::consts::DLL_EXTENSION)
+}
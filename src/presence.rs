@@ -0,0 +1,134 @@
+//! Presence-aware JSON for decoded protobuf messages, for `echo --presence` and
+//! `--skip-defaults`.
+//!
+//! The protobuf JSON mapping with defaults included can't tell an unset `optional` field or
+//! submessage from one set to its default, and lists every member of a oneof. Here fields
+//! that support presence are `null` when unset, only the active member of a oneof is kept, and
+//! the oneof's own name holds the name of that member (or `null`); if a field already goes by
+//! that name, the oneof uses `<name>_case` instead. Fields without presence are left as they
+//! are, unless `skip_defaults` drops them along with unset fields.
+
+use prost_reflect::{
+    DynamicMessage, FieldDescriptor, MapKey, ReflectMessage, SerializeOptions, Value as ProtoValue,
+};
+use serde_json::Value;
+
+#[derive(Clone, Copy, Default)]
+pub struct PresenceOptions {
+    /// Show unset fields as `null` and name the active member of each oneof
+    pub mark: bool,
+    /// Leave out unset fields and fields at their default value
+    pub skip_defaults: bool,
+    /// Name fields as in the `.proto` file rather than in lowerCamelCase
+    pub proto_names: bool,
+}
+
+impl PresenceOptions {
+    /// The JSON key of `field`.
+    fn key(self, field: &FieldDescriptor) -> &str {
+        match self.proto_names {
+            true => field.name(),
+            false => field.json_name(),
+        }
+    }
+}
+
+/// Renders `message` with the protobuf JSON mapping, adjusted for field presence.
+pub fn to_json(
+    message: &DynamicMessage,
+    options: PresenceOptions,
+) -> Result<Value, serde_json::Error> {
+    let serialize = SerializeOptions::new()
+        .skip_default_fields(options.skip_defaults)
+        .use_proto_field_name(options.proto_names);
+    let mut value = message.serialize_with_options(serde_json::value::Serializer, &serialize)?;
+    if options.mark {
+        mark_message(message, options, &mut value);
+    }
+    Ok(value)
+}
+
+fn mark_message(message: &DynamicMessage, options: PresenceOptions, value: &mut Value) {
+    let descriptor = message.descriptor();
+    // Well-known types have JSON forms of their own
+    if descriptor.full_name().starts_with("google.protobuf.") {
+        return;
+    }
+    let Value::Object(fields) = value else {
+        return;
+    };
+    for field in descriptor.fields() {
+        let key = options.key(&field);
+        let set = message.has_field(&field);
+        let oneof = field
+            .containing_oneof()
+            .filter(|_| !field.field_descriptor_proto().proto3_optional());
+        if oneof.is_some() && !set {
+            fields.remove(key);
+            continue;
+        }
+        if !set && field.supports_presence() {
+            if !options.skip_defaults {
+                fields.insert(key.to_string(), Value::Null);
+            }
+            continue;
+        }
+        if let Some(value) = fields.get_mut(key) {
+            mark_value(&message.get_field(&field), options, value);
+        }
+    }
+    for oneof in descriptor.oneofs() {
+        // The synthetic oneof of a proto3 `optional` field
+        if oneof
+            .fields()
+            .any(|f| f.field_descriptor_proto().proto3_optional())
+        {
+            continue;
+        }
+        // Don't overwrite a field that goes by the oneof's name
+        let taken = |name: &str| descriptor.fields().any(|f| options.key(&f) == name);
+        let key = [oneof.name().to_string(), format!("{}_case", oneof.name())]
+            .into_iter()
+            .find(|key| !taken(key));
+        let Some(key) = key else {
+            continue;
+        };
+        let active = oneof.fields().find(|f| message.has_field(f));
+        fields.insert(
+            key,
+            active.map_or(Value::Null, |f| Value::String(options.key(&f).to_string())),
+        );
+    }
+}
+
+/// Marks the messages inside a field's value, including lists and map values of messages.
+fn mark_value(proto: &ProtoValue, options: PresenceOptions, value: &mut Value) {
+    match (proto, value) {
+        (ProtoValue::Message(message), value) => mark_message(message, options, value),
+        (ProtoValue::List(items), Value::Array(values)) => {
+            for (item, value) in items.iter().zip(values) {
+                mark_value(item, options, value);
+            }
+        }
+        (ProtoValue::Map(entries), Value::Object(values)) => {
+            for (key, item) in entries {
+                if let Some(value) = values.get_mut(&map_key(key)) {
+                    mark_value(item, options, value);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A map key as it appears in JSON.
+fn map_key(key: &MapKey) -> String {
+    match key {
+        MapKey::Bool(b) => b.to_string(),
+        MapKey::I32(n) => n.to_string(),
+        MapKey::I64(n) => n.to_string(),
+        MapKey::U32(n) => n.to_string(),
+        MapKey::U64(n) => n.to_string(),
+        MapKey::String(s) => s.clone(),
+    }
+}
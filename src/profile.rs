@@ -0,0 +1,104 @@
+//! Named connection profiles from the user config file, selected with `--profile`.
+//!
+//! The file lives at `$XDG_CONFIG_HOME/zspy/config.toml` (usually `~/.config/zspy/config.toml`):
+//!
+//! ```toml
+//! # Used when no --profile is given
+//! default_profile = "lab"
+//!
+//! [profiles.lab]
+//! connect = ["tcp/10.0.0.2:7447"]
+//! mode = "client"
+//! proto_path = ["/opt/lab/protos"]
+//! output = "jsonl"
+//! ```
+//!
+//! Every field is optional. Flags given on the command line take precedence, except that
+//! `proto_path` and `plugin_dir` entries are loaded in addition to the ones passed as flags.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Zenoh configuration file (JSON5)
+    pub config: Option<PathBuf>,
+    #[serde(default)]
+    pub connect: Vec<String>,
+    #[serde(default)]
+    pub listen: Vec<String>,
+    /// `client`, `peer` or `router`
+    pub mode: Option<String>,
+    pub tls_ca: Option<PathBuf>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// User for user/password authentication; the password comes from `ZENOH_PASSWORD`
+    pub user: Option<String>,
+    #[serde(default)]
+    pub proto_path: Vec<PathBuf>,
+    #[serde(default)]
+    pub plugin_dir: Vec<PathBuf>,
+    /// Default `echo --output` format
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Location of the user config file, if a home or config directory is known.
+pub fn config_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("zspy").join("config.toml"))
+}
+
+/// Loads the profile named `name`, or the file's `default_profile` without a name. Returns
+/// `None` when no profile applies; naming a profile that doesn't exist is an error.
+pub fn load(name: Option<&str>) -> Result<Option<Profile>, Box<dyn Error + Send + Sync>> {
+    let Some(path) = config_path() else {
+        return match name {
+            Some(_) => Err("Cannot locate the config file: HOME is not set".into()),
+            None => Ok(None),
+        };
+    };
+    let file = match std::fs::read_to_string(&path) {
+        Ok(text) => toml::from_str::<ConfigFile>(&text)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?,
+        Err(_) if name.is_none() => return Ok(None),
+        Err(e) => return Err(format!("Cannot read {}: {}", path.display(), e).into()),
+    };
+    let ConfigFile {
+        default_profile,
+        mut profiles,
+    } = file;
+    let Some(name) = name.map(str::to_string).or(default_profile) else {
+        return Ok(None);
+    };
+    match profiles.remove(&name) {
+        Some(profile) => Ok(Some(profile)),
+        None => {
+            let known: Vec<&str> = profiles.keys().map(String::as_str).collect();
+            Err(format!(
+                "Unknown profile '{}' in {} (available: {})",
+                name,
+                path.display(),
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            )
+            .into())
+        }
+    }
+}
@@ -0,0 +1,992 @@
+//! `zspy pub`: publish a literal value, or one message per line of a file or stdin.
+
+use crate::attachment;
+use crate::codec::{self, PayloadEncoding};
+use crate::compression::Compression;
+use crate::error::ZspyError;
+use crate::message_registry::MessageRegistry;
+use crate::metrics;
+use crate::output::Console;
+use crate::random::{self, Generator};
+use crate::stats::format_summary;
+use crate::template::Template;
+use crate::terminal;
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+use prost_reflect::prost::Message;
+use rand::Rng;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+use tokio::time;
+use zenoh::{
+    bytes::{Encoding, ZBytes},
+    handlers::FifoChannelHandler,
+    key_expr::KeyExpr,
+    matching::{MatchingListener, MatchingStatus},
+    pubsub::Publisher,
+    qos::{CongestionControl, Priority, Reliability},
+    query::Queryable,
+    time::{Timestamp, TimestampId},
+    Session,
+};
+
+/// Syntax of the values given to a typed `zspy pub`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ValueFormat {
+    Json,
+    /// Protobuf text format, e.g. `angular_velocity { x: 1.0 }`
+    Textproto,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PriorityArg {
+    RealTime,
+    InteractiveHigh,
+    InteractiveLow,
+    DataHigh,
+    Data,
+    DataLow,
+    Background,
+}
+
+impl From<PriorityArg> for Priority {
+    fn from(priority: PriorityArg) -> Self {
+        match priority {
+            PriorityArg::RealTime => Priority::RealTime,
+            PriorityArg::InteractiveHigh => Priority::InteractiveHigh,
+            PriorityArg::InteractiveLow => Priority::InteractiveLow,
+            PriorityArg::DataHigh => Priority::DataHigh,
+            PriorityArg::Data => Priority::Data,
+            PriorityArg::DataLow => Priority::DataLow,
+            PriorityArg::Background => Priority::Background,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CongestionControlArg {
+    /// Wait for room in the transmission queue
+    Block,
+    /// Drop the message when the transmission queue is full
+    Drop,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReliabilityArg {
+    Reliable,
+    #[value(alias = "besteffort")]
+    BestEffort,
+}
+
+impl From<ReliabilityArg> for Reliability {
+    fn from(reliability: ReliabilityArg) -> Self {
+        match reliability {
+            ReliabilityArg::Reliable => Reliability::Reliable,
+            ReliabilityArg::BestEffort => Reliability::BestEffort,
+        }
+    }
+}
+
+impl From<CongestionControlArg> for CongestionControl {
+    fn from(congestion_control: CongestionControlArg) -> Self {
+        match congestion_control {
+            CongestionControlArg::Block => CongestionControl::Block,
+            CongestionControlArg::Drop => CongestionControl::Drop,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct PubArgs {
+    /// The key to publish to
+    pub key: String,
+    /// The message to publish (JSON, JSON5 or YAML if a type or encoding is specified). May
+    /// contain template placeholders such as `{{seq}}`, `{{now_ms}}` or `{{rand(0,1)}}`
+    #[arg(required_unless_present_any = ["from_file", "stdin", "binary_file", "hex", "random"])]
+    pub value: Option<String>,
+    /// Publish the contents of this file verbatim, e.g. an already-encoded protobuf message
+    #[arg(long, conflicts_with_all = ["value", "from_file", "stdin", "hex", "type", "encoding"])]
+    pub binary_file: Option<PathBuf>,
+    /// Publish these hex-encoded bytes verbatim, e.g. `0a03666f6f` (whitespace is ignored)
+    #[arg(long, conflicts_with_all = ["value", "from_file", "stdin", "type", "encoding"])]
+    pub hex: Option<String>,
+    /// Optional protobuf message type
+    #[arg(long)]
+    pub r#type: Option<String>,
+    /// Syntax of the typed value (default: JSON, which also accepts JSON5 and YAML)
+    #[arg(long, value_enum, requires = "type", conflicts_with = "random")]
+    pub format: Option<ValueFormat>,
+    /// Publish a new random instance of --type for every message
+    #[arg(
+        long,
+        requires = "type",
+        conflicts_with_all = ["value", "from_file", "stdin", "binary_file", "hex"]
+    )]
+    pub random: bool,
+    /// Range of a numeric field's random values, or of a string field's length, e.g.
+    /// `angular_velocity.x=-1..1` (repeatable)
+    #[arg(long, value_name = "FIELD=MIN..MAX", requires = "random", value_parser = random::parse_range)]
+    pub range: Vec<(String, (f64, f64))>,
+    /// Seed for --random, to publish the same sequence of messages on every run
+    #[arg(long, requires = "random")]
+    pub seed: Option<u64>,
+    /// Encode the JSON value in a schemaless format instead of a protobuf type
+    #[arg(long, value_enum, conflicts_with = "type")]
+    pub encoding: Option<PayloadEncoding>,
+    /// Encoding metadata of the published samples, e.g. `application/json` or
+    /// `application/protobuf;my.pkg.Msg`, instead of the one --encoding implies
+    #[arg(long, value_name = "MIME")]
+    pub encoding_hint: Option<String>,
+    /// Timestamp the first message with this RFC 3339 time, e.g. `2024-05-01T12:00:00.250Z`,
+    /// and later ones that much later again as they are sent after it; or stamp every message
+    /// with the time it is sent (`now`)
+    #[arg(long, value_name = "RFC3339|now", value_parser = parse_timestamp)]
+    pub timestamp: Option<TimestampArg>,
+    /// Number of messages to publish (0 for infinite)
+    #[arg(long, default_value = "1", conflicts_with_all = ["from_file", "stdin"])]
+    pub repeat: u64,
+    /// Publishing rate in Hz (0 for as fast as possible)
+    #[arg(long, default_value = "1.0", value_parser = parse_rate)]
+    pub rate: f64,
+    /// Publish in bursts of this many messages, waiting for the rate once per burst rather than
+    /// once per message, for rates too high to time message by message
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u64).range(1..))]
+    pub batch: u64,
+    /// Publish each line of this file (newline-delimited JSON or raw text) as a message
+    #[arg(long, conflicts_with_all = ["value", "stdin"])]
+    pub from_file: Option<PathBuf>,
+    /// Publish each line read from stdin as a message
+    #[arg(long, conflicts_with = "value")]
+    pub stdin: bool,
+    /// Expand template placeholders such as `{{seq}}` in lines read with --from-file or
+    /// --stdin, which are otherwise published as they are
+    #[arg(long)]
+    pub template: bool,
+    /// Attach `key=value` metadata to every message (repeatable)
+    #[arg(long, value_parser = attachment::parse_pair)]
+    pub attach: Vec<(String, String)>,
+    /// Routing priority of the published messages
+    #[arg(long, value_enum, default_value = "data")]
+    pub priority: PriorityArg,
+    /// What to do when the transmission queue is full
+    #[arg(long, value_enum, default_value = "drop")]
+    pub congestion_control: CongestionControlArg,
+    /// Reliability the samples are marked with, used by Zenoh to pick a link when there are
+    /// several
+    #[arg(long, value_enum, default_value = "reliable")]
+    pub reliability: ReliabilityArg,
+    /// Send each message immediately instead of letting Zenoh batch it with others
+    #[arg(long)]
+    pub express: bool,
+    /// Skip each message with this probability (0 to 1), simulating loss
+    #[arg(long, value_name = "PROBABILITY", value_parser = parse_probability)]
+    pub drop: Option<f64>,
+    /// Truncate or flip a byte of each message with this probability (0 to 1)
+    #[arg(long, value_name = "PROBABILITY", value_parser = parse_probability)]
+    pub corrupt: Option<f64>,
+    /// Compress each encoded message before publishing it
+    #[arg(long, value_enum)]
+    pub compress: Option<Compression>,
+    /// Delay each message by a random 0 to MS milliseconds
+    #[arg(long, value_name = "MS")]
+    pub jitter: Option<u64>,
+    /// Wait until at least one subscriber matches the key before publishing, giving up after
+    /// TIMEOUT if given, e.g. `10s`
+    #[arg(long, value_name = "TIMEOUT", num_args = 0..=1, value_parser = humantime::parse_duration)]
+    pub wait_for_subscribers: Option<Option<Duration>>,
+    /// Report the matching subscribers every second and warn as soon as none are left
+    #[arg(short, long)]
+    pub verbose: bool,
+    /// Answer queries on the key with the last published message, like an MQTT retained
+    /// message, so late subscribers can fetch it with `get` or `echo --history`. Keeps serving it
+    /// after publishing until Ctrl-C
+    #[arg(long)]
+    pub retain: bool,
+}
+
+/// When `--timestamp` says a message was published.
+#[derive(Clone, Copy)]
+pub enum TimestampArg {
+    Now,
+    At(SystemTime),
+}
+
+fn parse_timestamp(s: &str) -> Result<TimestampArg, String> {
+    if s == "now" {
+        return Ok(TimestampArg::Now);
+    }
+    match humantime::parse_rfc3339_weak(s) {
+        Ok(time) if time >= UNIX_EPOCH => Ok(TimestampArg::At(time)),
+        Ok(_) => Err(format!("'{}' is before 1970", s)),
+        Err(e) => Err(format!(
+            "'{}' is not `now` or an RFC 3339 time, e.g. `2024-05-01T12:00:00Z`: {}",
+            s, e
+        )),
+    }
+}
+
+/// Parses `--rate`: 0 for as fast as possible, or a positive rate with a representable period
+/// between messages.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(rate) if rate == 0.0 => Ok(rate),
+        Ok(rate)
+            if rate > 0.0
+                && rate.is_finite()
+                && Duration::try_from_secs_f64(1.0 / rate).is_ok() =>
+        {
+            Ok(rate)
+        }
+        _ => Err(format!(
+            "'{}' is not 0 or a positive rate in messages per second",
+            s
+        )),
+    }
+}
+
+fn parse_probability(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        _ => Err(format!("'{}' is not a probability between 0 and 1", s)),
+    }
+}
+
+impl Default for PubArgs {
+    /// The CLI defaults, publishing nothing until `key` and `value` are set.
+    fn default() -> Self {
+        Self {
+            key: String::new(),
+            value: None,
+            binary_file: None,
+            hex: None,
+            r#type: None,
+            format: None,
+            random: false,
+            range: Vec::new(),
+            seed: None,
+            encoding: None,
+            encoding_hint: None,
+            timestamp: None,
+            repeat: 1,
+            rate: 1.0,
+            batch: 1,
+            from_file: None,
+            stdin: false,
+            template: false,
+            attach: Vec::new(),
+            priority: PriorityArg::Data,
+            congestion_control: CongestionControlArg::Drop,
+            reliability: ReliabilityArg::Reliable,
+            express: false,
+            drop: None,
+            corrupt: None,
+            jitter: None,
+            compress: None,
+            wait_for_subscribers: None,
+            verbose: false,
+            retain: false,
+        }
+    }
+}
+
+/// How often `--verbose` reports the matching subscribers.
+const SUBSCRIBER_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long `--verbose` waits for admin space replies when counting subscriptions.
+const SUBSCRIBER_QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// If publishing falls this far behind schedule (e.g. waiting on stdin), restart the schedule
+/// rather than bursting to catch up.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Paces messages at a fixed rate.
+///
+/// Send times are computed from the start of the schedule rather than by sleeping a fixed
+/// interval after each message, so timer granularity and per-message overhead don't
+/// accumulate into a lower rate.
+struct Pacer {
+    period: Option<f64>,
+    /// Messages sent back-to-back per wait, for `--batch`
+    batch: u64,
+    base: Instant,
+    sent: u64,
+}
+
+impl Pacer {
+    fn new(rate: f64, batch: u64) -> Self {
+        Self {
+            period: (rate > 0.0).then(|| 1.0 / rate),
+            batch: batch.max(1),
+            base: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    /// Waits until the next message is due; within a batch, only its first message waits.
+    async fn wait(&mut self) {
+        let Some(period) = self.period else {
+            return;
+        };
+        if !self.sent.is_multiple_of(self.batch) {
+            self.sent += 1;
+            return;
+        }
+        let Some(due) = Duration::try_from_secs_f64(period * self.sent as f64)
+            .ok()
+            .and_then(|offset| self.base.checked_add(offset))
+        else {
+            // Due later than an Instant can hold, i.e. never
+            return std::future::pending().await;
+        };
+        let now = Instant::now();
+        if due > now {
+            time::sleep_until(due.into()).await;
+        } else if now - due > MAX_LAG {
+            self.base = now;
+            self.sent = 0;
+        }
+        self.sent += 1;
+    }
+}
+
+/// Fault injection for `--drop`, `--corrupt` and `--jitter`, to test how subscribers cope with
+/// lost, malformed and late messages.
+#[derive(Default)]
+struct Faults {
+    drop: f64,
+    corrupt: f64,
+    jitter: Duration,
+    dropped: u64,
+    corrupted: u64,
+}
+
+impl Faults {
+    fn new(args: &PubArgs) -> Self {
+        Self {
+            drop: args.drop.unwrap_or_default(),
+            corrupt: args.corrupt.unwrap_or_default(),
+            jitter: Duration::from_millis(args.jitter.unwrap_or_default()),
+            ..Self::default()
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.drop > 0.0 || self.corrupt > 0.0 || !self.jitter.is_zero()
+    }
+
+    /// Delays the message, then returns it (possibly corrupted), or `None` to drop it.
+    async fn apply(&mut self, mut payload: Vec<u8>) -> Option<Vec<u8>> {
+        if !self.jitter.is_zero() {
+            let delay = rand::thread_rng().gen_range(Duration::ZERO..=self.jitter);
+            time::sleep(delay).await;
+        }
+        let mut rng = rand::thread_rng();
+        if rng.gen_bool(self.drop) {
+            self.dropped += 1;
+            return None;
+        }
+        if !payload.is_empty() && rng.gen_bool(self.corrupt) {
+            if rng.gen_bool(0.5) {
+                payload.truncate(rng.gen_range(0..payload.len()));
+            } else {
+                let i = rng.gen_range(0..payload.len());
+                payload[i] ^= 1 << rng.gen_range(0..8);
+            }
+            self.corrupted += 1;
+        }
+        Some(payload)
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "Injected faults: {} dropped, {} corrupted",
+            self.dropped, self.corrupted
+        )
+    }
+}
+
+/// The last message that went out, as `--retain` serves it.
+struct Retained {
+    payload: ZBytes,
+    attachment: Option<ZBytes>,
+    timestamp: Option<Timestamp>,
+}
+
+/// Where encoded messages go: compressed, put through fault injection, then published.
+struct Sink<'a> {
+    publisher: Publisher<'a>,
+    /// `--retain`: updated with every message published
+    retained: Option<&'a Mutex<Option<Retained>>>,
+    attachment: Option<ZBytes>,
+    /// `--timestamp`, with the session's ID as the timestamps' source
+    timestamp: Option<(TimestampArg, TimestampId)>,
+    /// When the first message was sent, which a fixed `--timestamp` stamps
+    first_sent: Option<Instant>,
+    compression: Option<Compression>,
+    faults: Faults,
+    /// Payload bytes before and after compression
+    uncompressed: u64,
+    compressed: u64,
+}
+
+impl Sink<'_> {
+    fn timestamp(&mut self) -> Option<Timestamp> {
+        let (timestamp, id) = self.timestamp?;
+        let time = match timestamp {
+            TimestampArg::Now => SystemTime::now(),
+            // Later messages keep their distance from the first, so the timestamps keep increasing
+            TimestampArg::At(time) => {
+                time + self.first_sent.get_or_insert_with(Instant::now).elapsed()
+            }
+        };
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Some(Timestamp::new(since_epoch.into(), id))
+    }
+
+    /// Publishes one message. Dropped messages still count as sent.
+    async fn send(
+        &mut self,
+        key: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let payload = match self.compression {
+            Some(compression) => {
+                let compressed = compression.compress(&payload)?;
+                self.uncompressed += payload.len() as u64;
+                self.compressed += compressed.len() as u64;
+                compressed
+            }
+            None => payload,
+        };
+        let Some(payload) = self.faults.apply(payload).await else {
+            return Ok(());
+        };
+        let len = payload.len();
+        let payload = ZBytes::from(payload);
+        let timestamp = self.timestamp();
+        self.publisher
+            .put(payload.clone())
+            .attachment(self.attachment.clone())
+            .timestamp(timestamp)
+            .await?;
+        if let Some(retained) = self.retained {
+            *retained.lock().unwrap() = Some(Retained {
+                payload,
+                attachment: self.attachment.clone(),
+                timestamp,
+            });
+        }
+        metrics::published(key, len);
+        Ok(())
+    }
+}
+
+/// Progress of a publishing loop on stderr: a spinner with the achieved rate, or for a known
+/// number of messages a bar with the percentage, the achieved vs. requested rate and the ETA.
+/// Nothing is drawn when stderr isn't a terminal, or in plain mode.
+pub(crate) struct Progress(ProgressBar);
+
+impl Progress {
+    pub(crate) fn new(verb: &'static str) -> Self {
+        let style = ProgressStyle::with_template("{spinner:.cyan} {msg} {pos} messages ({rate})")
+            .expect("valid template")
+            .with_key("rate", rate_key(0.0));
+        Self::hidden_if_plain(
+            ProgressBar::new_spinner()
+                .with_style(style)
+                .with_message(verb),
+        )
+    }
+
+    /// Progress towards `total` messages published at `rate` Hz (0 for as fast as possible).
+    pub(crate) fn bounded(verb: &'static str, total: u64, rate: f64) -> Self {
+        let style = ProgressStyle::with_template(
+            "{msg} [{bar:30.cyan/blue}] {pos}/{len} ({percent}%) {rate}, ETA {eta}",
+        )
+        .expect("valid template")
+        .with_key("rate", rate_key(rate))
+        .progress_chars("=> ");
+        Self::hidden_if_plain(ProgressBar::new(total).with_style(style).with_message(verb))
+    }
+
+    fn hidden_if_plain(bar: ProgressBar) -> Self {
+        if terminal::plain() {
+            bar.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        Self(bar)
+    }
+
+    pub(crate) fn update(&self, count: u64) {
+        self.0.set_position(count);
+    }
+
+    /// Runs `f`, e.g. printing a warning, with the progress line hidden.
+    pub(crate) fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.0.suspend(f)
+    }
+
+    /// Clears the progress line so the summary can take its place.
+    pub(crate) fn finish(&self) {
+        self.0.finish_and_clear();
+    }
+}
+
+/// Renders the achieved rate, followed by `requested` unless it is 0.
+fn rate_key(
+    requested: f64,
+) -> impl Fn(&ProgressState, &mut dyn std::fmt::Write) + Clone + Send + Sync + 'static {
+    move |state, w| {
+        // The average since the start, as in the summary
+        let achieved = state.pos() as f64 / state.elapsed().as_secs_f64().max(f64::EPSILON);
+        let _ = if requested > 0.0 {
+            write!(w, "{:.1}/{} Hz", achieved, requested)
+        } else {
+            write!(w, "{:.1} Hz", achieved)
+        };
+    }
+}
+
+pub(crate) fn encode(
+    registry: &MessageRegistry,
+    args: &PubArgs,
+    value: &str,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    match (&args.r#type, args.encoding) {
+        (Some(type_name), _) if args.format == Some(ValueFormat::Textproto) => {
+            registry.encode_text_format(type_name, value)
+        }
+        (Some(type_name), _) => {
+            let json = codec::to_json(value).map_err(ZspyError::encode)?;
+            registry.encode(type_name, &json)
+        }
+        (None, Some(encoding)) => codec::to_json(value)
+            .and_then(|json| encoding.codec().encode(&json))
+            .map_err(ZspyError::encode),
+        (None, None) => Ok(value.as_bytes().to_vec()),
+    }
+}
+
+/// Decodes a hex string such as `0a03666f6f`, ignoring whitespace and an optional `0x` prefix.
+fn parse_hex(input: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let digits: String = input.split_whitespace().collect();
+    let digits = digits.strip_prefix("0x").unwrap_or(&digits);
+    if !digits.len().is_multiple_of(2) {
+        return Err(
+            ZspyError::Encode("Hex payload has an odd number of digits".to_string()).into(),
+        );
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| {
+                    ZspyError::Encode(format!("Invalid hex digits at offset {}", i)).into()
+                })
+        })
+        .collect()
+}
+
+/// The verbatim payload given by `--binary-file` or `--hex`, if any.
+async fn raw_payload(
+    args: &PubArgs,
+    console: &Console,
+) -> Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>> {
+    if let Some(path) = &args.binary_file {
+        let bytes = tokio::fs::read(path).await?;
+        console.println(format!(
+            "Payload: {} bytes from {}",
+            bytes.len(),
+            path.display()
+        ));
+        return Ok(Some(bytes));
+    }
+    if let Some(hex) = &args.hex {
+        let bytes = parse_hex(hex)?;
+        console.println(format!("Payload: {} bytes", bytes.len()));
+        return Ok(Some(bytes));
+    }
+    Ok(None)
+}
+
+pub async fn run(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &PubArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    run_to(session, registry, args, &Console::stdio()).await
+}
+
+/// Like [`run`], but prints status lines and the summary to `console`.
+pub async fn run_to(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &PubArgs,
+    console: &Console,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(type_name) = &args.r#type {
+        registry.check_type(type_name)?;
+    }
+    console.println(format!("Publishing to key: {}", args.key.cyan()));
+    let lines: Option<Box<dyn AsyncBufRead + Unpin>> = if let Some(path) = &args.from_file {
+        console.println(format!("Reading messages from {}", path.display()));
+        Some(Box::new(BufReader::new(tokio::fs::File::open(path).await?)))
+    } else if args.stdin {
+        console.println("Reading messages from stdin");
+        Some(Box::new(BufReader::new(tokio::io::stdin())))
+    } else {
+        None
+    };
+
+    let mut publisher = session.declare_publisher(args.key.as_str());
+    if let Some(encoding) = args.encoding {
+        publisher = publisher.encoding(encoding.zenoh_encoding());
+    }
+    if let Some(hint) = &args.encoding_hint {
+        publisher = publisher.encoding(Encoding::from(hint.as_str()));
+    }
+    let publisher = publisher
+        .priority(args.priority.into())
+        .congestion_control(args.congestion_control.into())
+        .reliability(args.reliability.into())
+        .express(args.express)
+        .await?;
+    match args.wait_for_subscribers {
+        Some(timeout) => wait_for_subscribers(&publisher, &args.key, timeout, console).await?,
+        None if !publisher.matching_status().await?.matching() => {
+            console.println(format!("No subscribers currently match {}", args.key).yellow())
+        }
+        None => console.println(format!("Subscribers are matching {}", args.key.cyan())),
+    }
+    let listener = match args.verbose {
+        true => Some(publisher.matching_listener().await?),
+        false => None,
+    };
+    let matching = publisher.matching_status().await?.matching();
+    let queryable = match args.retain {
+        true => Some(session.declare_queryable(args.key.as_str()).await?),
+        false => None,
+    };
+    let encoding = publisher.encoding().clone();
+    let retained = Mutex::new(None);
+
+    let start = Instant::now();
+    let mut count = 0;
+    let mut sink = Sink {
+        publisher,
+        retained: args.retain.then_some(&retained),
+        attachment: (!args.attach.is_empty()).then(|| attachment::encode(&args.attach)),
+        timestamp: args.timestamp.map(|t| (t, session.zid().into())),
+        first_sent: None,
+        compression: args.compress,
+        faults: Faults::new(args),
+        uncompressed: 0,
+        compressed: 0,
+    };
+    let progress = if lines.is_none() && args.repeat > 0 {
+        Progress::bounded("Published", args.repeat, args.rate)
+    } else {
+        Progress::new("Published")
+    };
+    let watch = async {
+        match listener {
+            Some(listener) => {
+                watch_subscribers(session, &args.key, listener, matching, &progress, console).await
+            }
+            None => std::future::pending().await,
+        }
+    };
+    let serve = async {
+        match &queryable {
+            Some(queryable) => serve_retained(queryable, &args.key, &encoding, &retained).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(serve);
+    let interrupted = tokio::select! {
+        result = publish_all(registry, args, &mut sink, lines, &mut count, &progress, console) => {
+            result?;
+            false
+        }
+        result = watch => {
+            result?;
+            false
+        }
+        result = &mut serve => {
+            result?;
+            false
+        }
+        _ = tokio::signal::ctrl_c() => true,
+    };
+    progress.finish();
+    let summary = format!("Published {}", format_summary(count, start.elapsed()));
+    if interrupted {
+        console.println(summary.yellow());
+    } else {
+        console.println("Publishing completed!".green());
+        console.println(summary.green());
+    }
+    if let Some(compression) = sink.compression.filter(|_| sink.compressed > 0) {
+        console.println(format!(
+            "Compressed {} bytes to {} bytes with {} ({:.1}x)",
+            sink.uncompressed,
+            sink.compressed,
+            compression.name(),
+            sink.uncompressed as f64 / sink.compressed as f64
+        ));
+    }
+    if sink.faults.enabled() {
+        console.println(sink.faults.summary().yellow());
+    }
+    if queryable.is_some() && !interrupted {
+        console.println(format!(
+            "Retaining the last message on {}, press Ctrl-C to stop",
+            args.key.cyan()
+        ));
+        tokio::select! {
+            result = &mut serve => result?,
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Answers every query on `key` with the retained message, if one was published yet. Returns
+/// when the queryable is undeclared.
+async fn serve_retained(
+    queryable: &Queryable<FifoChannelHandler<zenoh::query::Query>>,
+    key: &str,
+    encoding: &Encoding,
+    retained: &Mutex<Option<Retained>>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    while let Ok(query) = queryable.recv_async().await {
+        let Some((payload, attachment, timestamp)) = retained
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|r| (r.payload.clone(), r.attachment.clone(), r.timestamp))
+        else {
+            continue;
+        };
+        query
+            .reply(key, payload)
+            .encoding(encoding.clone())
+            .attachment(attachment)
+            .timestamp(timestamp)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Prints the subscribers matching `key` every second, and a warning as soon as the last one
+/// goes away. Returns when the publisher is undeclared.
+async fn watch_subscribers(
+    session: &Session,
+    key: &str,
+    listener: MatchingListener<FifoChannelHandler<MatchingStatus>>,
+    mut matching: bool,
+    progress: &Progress,
+    console: &Console,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let key_expr = KeyExpr::try_from(key)?;
+    let mut ticker = time::interval_at(
+        time::Instant::now() + SUBSCRIBER_REPORT_INTERVAL,
+        SUBSCRIBER_REPORT_INTERVAL,
+    );
+    loop {
+        tokio::select! {
+            status = listener.recv_async() => {
+                let Ok(status) = status else {
+                    return Ok(());
+                };
+                match (matching, status.matching()) {
+                    (true, false) => progress.suspend(|| console.println(format!("Warning: no subscribers match {} anymore", key).yellow()
+                    )),
+                    (false, true) => progress.suspend(|| console.println(format!("A subscriber matches {} again", key).green()
+                    )),
+                    _ => {}
+                }
+                matching = status.matching();
+            }
+            _ = ticker.tick() => {
+                let line = match (matching, count_subscriptions(session, &key_expr).await) {
+                    (false, _) => "Subscribers: none".yellow().to_string(),
+                    (true, Some(count)) => format!("Subscribers: {} matching subscriptions", count),
+                    // Without admin space replies, only whether any subscriber matches is known
+                    (true, None) => "Subscribers: matching".to_string(),
+                };
+                progress.suspend(|| console.println(line));
+            }
+        }
+    }
+}
+
+/// The number of distinct subscriptions intersecting `key_expr` in the admin spaces of the
+/// nodes that expose one, or `None` if none report any.
+async fn count_subscriptions(session: &Session, key_expr: &KeyExpr<'_>) -> Option<usize> {
+    let replies = session
+        .get("@/*/*/subscriber/**")
+        .timeout(SUBSCRIBER_QUERY_TIMEOUT)
+        .await
+        .ok()?;
+    let mut subscriptions = BTreeSet::new();
+    while let Ok(reply) = replies.recv_async().await {
+        let Ok(sample) = reply.result() else {
+            continue;
+        };
+        // Admin keys are @/<zid>/<kind>/subscriber/<key expr>
+        let Some(subscription) = sample.key_expr().as_str().splitn(5, '/').nth(4) else {
+            continue;
+        };
+        if KeyExpr::try_from(subscription).is_ok_and(|s| s.intersects(key_expr)) {
+            subscriptions.insert(subscription.to_string());
+        }
+    }
+    (!subscriptions.is_empty()).then_some(subscriptions.len())
+}
+
+/// Blocks until a subscriber matches the publisher, or fails with [`ZspyError::Timeout`].
+async fn wait_for_subscribers(
+    publisher: &Publisher<'_>,
+    key: &str,
+    timeout: Option<Duration>,
+    console: &Console,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Listen before checking so a subscriber appearing in between isn't missed
+    let listener = publisher.matching_listener().await?;
+    if publisher.matching_status().await?.matching() {
+        console.println(format!("Subscribers are matching {}", key.cyan()));
+        return Ok(());
+    }
+    console.println(format!("Waiting for a subscriber on {}", key.cyan()));
+    let matched = async {
+        while let Ok(status) = listener.recv_async().await {
+            if status.matching() {
+                return Ok(());
+            }
+        }
+        Err::<(), Box<dyn Error + Send + Sync>>("Matching listener closed".into())
+    };
+    let timeout_future = async {
+        match timeout {
+            Some(timeout) => time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        result = matched => {
+            result?;
+            console.println("Subscriber found".green());
+            Ok(())
+        }
+        _ = timeout_future => Err(ZspyError::Timeout(format!(
+            "No subscriber matched {} within {}",
+            key,
+            humantime::format_duration(timeout.unwrap_or_default())
+        ))
+        .into()),
+        _ = tokio::signal::ctrl_c() => Err("Interrupted before a subscriber appeared".into()),
+    }
+}
+
+async fn publish_all(
+    registry: &MessageRegistry,
+    args: &PubArgs,
+    sink: &mut Sink<'_>,
+    lines: Option<Box<dyn AsyncBufRead + Unpin>>,
+    count: &mut u64,
+    progress: &Progress,
+    console: &Console,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut pacer = Pacer::new(args.rate, args.batch);
+    let vars = HashMap::new();
+
+    if let Some(reader) = lines {
+        let mut lines = reader.lines();
+        let mut line_no = 0;
+        while let Some(line) = lines.next_line().await? {
+            line_no += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let text = match args.template {
+                true => Template::parse(&line, &[]).map(|t| t.render(*count, &vars)),
+                false => Ok(line),
+            };
+            let payload = text
+                .and_then(|text| encode(registry, args, &text))
+                .map_err(|e| ZspyError::Encode(format!("Line {}: {}", line_no, e)))?;
+            pacer.wait().await;
+            sink.send(&args.key, payload).await?;
+            *count += 1;
+            progress.update(*count);
+        }
+        return Ok(());
+    }
+
+    let raw = raw_payload(args, console).await?;
+    let mut generator = match &args.r#type {
+        Some(msg_type) if args.random => {
+            let descriptor = registry.descriptor(msg_type).ok_or_else(|| {
+                ZspyError::Encode(format!(
+                    "{} has no schema to generate messages from",
+                    msg_type
+                ))
+            })?;
+            Some(Generator::new(descriptor, &args.range, args.seed))
+        }
+        _ => None,
+    };
+    let value = args.value.as_deref().unwrap_or_default();
+    if generator.is_some() {
+        console.println(format!("Value: {}", "random".yellow()));
+    } else if raw.is_none() {
+        console.println(format!("Value: {}", value.yellow()));
+    }
+    let rate = if args.rate > 0.0 {
+        format!("at {} Hz", args.rate)
+    } else {
+        "as fast as possible".to_string()
+    };
+    if args.repeat == 0 {
+        console.println(format!("Publishing continuously {}", rate));
+    } else {
+        console.println(format!("Publishing {} messages {}", args.repeat, rate));
+    }
+
+    // Raw payloads are sent as is, without template expansion
+    let (template, payload) = match raw {
+        Some(bytes) => (None, bytes),
+        None if generator.is_some() => (None, Vec::new()),
+        None => {
+            let template = Template::parse(value, &[])?;
+            // Encode once up front; this also reports bad JSON before anything is published
+            let payload = encode(registry, args, &template.render(0, &vars))?;
+            (Some(template), payload)
+        }
+    };
+
+    loop {
+        pacer.wait().await;
+        let payload = match (&mut generator, &template) {
+            (Some(generator), _) => generator.generate().encode_to_vec(),
+            (None, Some(template)) if !template.is_static() && *count > 0 => {
+                encode(registry, args, &template.render(*count, &vars))?
+            }
+            _ => payload.clone(),
+        };
+        sink.send(&args.key, payload).await?;
+        *count += 1;
+        progress.update(*count);
+        if args.repeat > 0 && *count >= args.repeat {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Random instances of protobuf messages for `zspy pub --random`, generated from their
+//! descriptors.
+//!
+//! Every field is set: numbers uniformly within a range (`-100..100`, or `0..100` for unsigned
+//! types, unless `--range` says otherwise), strings and bytes with 1 to 8 characters, enums to
+//! one of their values, repeated fields and maps with 1 to 3 entries, and one field of each
+//! oneof. `Timestamp`s are set to the current time.
+
+use prost_reflect::{DynamicMessage, Kind, MessageDescriptor, Value};
+use rand::distributions::Alphanumeric;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Messages nested deeper than this are left unset, which also ends recursive types
+const MAX_DEPTH: usize = 3;
+
+/// Length of strings and bytes without a `--range`
+const TEXT_LEN: (f64, f64) = (1.0, 8.0);
+
+/// Messages left unset, as random contents would rarely be meaningful
+const SKIPPED_TYPES: [&str; 5] = [
+    "google.protobuf.Any",
+    "google.protobuf.Struct",
+    "google.protobuf.Value",
+    "google.protobuf.ListValue",
+    "google.protobuf.FieldMask",
+];
+
+/// Parses a `--range` such as `angular_velocity.x=-1..1`.
+pub fn parse_range(s: &str) -> Result<(String, (f64, f64)), String> {
+    let invalid = || format!("'{}' is not FIELD=MIN..MAX, e.g. `imu.x=-1..1`", s);
+    let (field, range) = s.split_once('=').ok_or_else(invalid)?;
+    let (min, max) = range.split_once("..").ok_or_else(invalid)?;
+    let min: f64 = min.trim().parse().map_err(|_| invalid())?;
+    let max: f64 = max.trim().parse().map_err(|_| invalid())?;
+    if field.is_empty() || !min.is_finite() || !max.is_finite() || min > max {
+        return Err(invalid());
+    }
+    Ok((field.to_string(), (min, max)))
+}
+
+pub struct Generator {
+    descriptor: MessageDescriptor,
+    /// Value ranges of numeric fields and length ranges of strings and bytes, by field path
+    ranges: HashMap<String, (f64, f64)>,
+    rng: StdRng,
+}
+
+impl Generator {
+    /// A generator of `descriptor` messages, seeded for reproducible output when `seed` is given.
+    pub fn new(
+        descriptor: MessageDescriptor,
+        ranges: &[(String, (f64, f64))],
+        seed: Option<u64>,
+    ) -> Self {
+        Self {
+            descriptor,
+            ranges: ranges.iter().cloned().collect(),
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+        }
+    }
+
+    pub fn generate(&mut self) -> DynamicMessage {
+        let descriptor = self.descriptor.clone();
+        self.message(&descriptor, "", 0)
+            .unwrap_or_else(|| DynamicMessage::new(descriptor))
+    }
+
+    fn message(
+        &mut self,
+        descriptor: &MessageDescriptor,
+        path: &str,
+        depth: usize,
+    ) -> Option<DynamicMessage> {
+        if depth > MAX_DEPTH || SKIPPED_TYPES.contains(&descriptor.full_name()) {
+            return None;
+        }
+        let mut message = DynamicMessage::new(descriptor.clone());
+        if descriptor.full_name() == "google.protobuf.Timestamp" {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            message.set_field_by_name("seconds", Value::I64(now.as_secs() as i64));
+            message.set_field_by_name("nanos", Value::I32(now.subsec_nanos() as i32));
+            return Some(message);
+        }
+        // Setting a oneof field clears the others, so only set the chosen one
+        let chosen: Vec<u32> = descriptor
+            .oneofs()
+            .filter_map(|oneof| {
+                let fields: Vec<_> = oneof.fields().collect();
+                (!fields.is_empty()).then(|| fields[self.rng.gen_range(0..fields.len())].number())
+            })
+            .collect();
+        for field in descriptor.fields() {
+            if field.containing_oneof().is_some() && !chosen.contains(&field.number()) {
+                continue;
+            }
+            let field_path = if path.is_empty() {
+                field.name().to_string()
+            } else {
+                format!("{}.{}", path, field.name())
+            };
+            let value = if field.is_map() {
+                let Kind::Message(entry) = field.kind() else {
+                    continue;
+                };
+                let (key_kind, value_kind) = (
+                    entry.map_entry_key_field().kind(),
+                    entry.map_entry_value_field().kind(),
+                );
+                let mut map = HashMap::new();
+                for _ in 0..self.rng.gen_range(1..=3) {
+                    let key = self.value(&key_kind, &format!("{}.key", field_path), depth);
+                    let value = self.value(&value_kind, &field_path, depth);
+                    if let (Some(key), Some(value)) = (key.and_then(Value::into_map_key), value) {
+                        map.insert(key, value);
+                    }
+                }
+                Value::Map(map)
+            } else if field.is_list() {
+                let count = self.rng.gen_range(1..=3);
+                let items: Vec<Value> = (0..count)
+                    .filter_map(|_| self.value(&field.kind(), &field_path, depth))
+                    .collect();
+                Value::List(items)
+            } else {
+                match self.value(&field.kind(), &field_path, depth) {
+                    Some(value) => value,
+                    None => continue,
+                }
+            };
+            message.set_field(&field, value);
+        }
+        Some(message)
+    }
+
+    fn value(&mut self, kind: &Kind, path: &str, depth: usize) -> Option<Value> {
+        let range = self.ranges.get(path).copied();
+        let value = match kind {
+            Kind::Double => Value::F64(self.float(range)),
+            Kind::Float => Value::F32(self.float(range) as f32),
+            Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => Value::I32(
+                self.int(range, -100, 100)
+                    .clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+            ),
+            Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => Value::I64(self.int(range, -100, 100)),
+            Kind::Uint32 | Kind::Fixed32 => {
+                Value::U32(self.int(range, 0, 100).clamp(0, u32::MAX as i64) as u32)
+            }
+            Kind::Uint64 | Kind::Fixed64 => Value::U64(self.int(range, 0, 100).max(0) as u64),
+            Kind::Bool => Value::Bool(self.rng.gen()),
+            Kind::String => {
+                let len = self.len(range);
+                Value::String(
+                    (&mut self.rng)
+                        .sample_iter(Alphanumeric)
+                        .take(len)
+                        .map(char::from)
+                        .collect(),
+                )
+            }
+            Kind::Bytes => {
+                let len = self.len(range);
+                Value::Bytes(
+                    (0..len)
+                        .map(|_| self.rng.gen::<u8>())
+                        .collect::<Vec<_>>()
+                        .into(),
+                )
+            }
+            Kind::Enum(descriptor) => {
+                let values: Vec<i32> = descriptor.values().map(|v| v.number()).collect();
+                Value::EnumNumber(values[self.rng.gen_range(0..values.len())])
+            }
+            Kind::Message(descriptor) if descriptor.full_name() == "google.protobuf.Duration" => {
+                let seconds = self.float(range.or(Some((0.0, 60.0))));
+                let mut message = DynamicMessage::new(descriptor.clone());
+                message.set_field_by_name("seconds", Value::I64(seconds.trunc() as i64));
+                message
+                    .set_field_by_name("nanos", Value::I32((seconds.fract() * 1e9).round() as i32));
+                Value::Message(message)
+            }
+            Kind::Message(descriptor) => {
+                Value::Message(self.message(descriptor, path, depth + 1)?)
+            }
+        };
+        Some(value)
+    }
+
+    fn float(&mut self, range: Option<(f64, f64)>) -> f64 {
+        let (min, max) = range.unwrap_or((-100.0, 100.0));
+        self.rng.gen_range(min..=max)
+    }
+
+    /// A random integer within `range`, or `[min, max]` without one.
+    fn int(&mut self, range: Option<(f64, f64)>, min: i64, max: i64) -> i64 {
+        let (min, max) = range.map_or((min, max), |(lo, hi)| (lo.ceil() as i64, hi.floor() as i64));
+        // A range such as 0.2..0.8 holds no integer
+        self.rng.gen_range(min..=max.max(min))
+    }
+
+    fn len(&mut self, range: Option<(f64, f64)>) -> usize {
+        let (min, max) = range.unwrap_or(TEXT_LEN);
+        self.int(Some((min.max(0.0), max.max(0.0))), 0, 0) as usize
+    }
+}
@@ -0,0 +1,807 @@
+//! Capture file format and recorder used by `zspy record`.
+//!
+//! A capture (`.zbag`) file starts with a fixed header followed by a sequence of records and,
+//! once recording completes, an index. All integers are little-endian.
+//!
+//! ```text
+//! header:  magic  b"ZBAG"  (4 bytes)
+//!          version u16     (currently 2; version 1 files have no index)
+//!
+//! record:  timestamp_ns u64   local receive time, nanoseconds since the UNIX epoch
+//!          kind         u8    0 = put, 1 = delete
+//!          key          u32 length + UTF-8 bytes
+//!          encoding     u32 length + UTF-8 bytes (Zenoh encoding string)
+//!          payload      u32 length + bytes
+//!          attachment   u8 flag (0 = absent, 1 = present), then u32 length + bytes if present
+//!
+//! index:   timestamp_ns u64   0
+//!          kind         u8    255, which ends the records
+//!          index        u32 length + JSON [`CaptureIndex`]
+//!
+//! trailer: offset       u64   where the index starts
+//!          magic        b"ZIDX" (4 bytes)
+//! ```
+//!
+//! The index lists every key with its sample count, size and time range, and splits the
+//! records into chunks of about 1 MiB with the keys and time range each holds, so `zspy info`
+//! and `zspy filter` can skip reading most of a large capture. A capture whose recording was
+//! interrupted has no index and is read sample by sample instead.
+//!
+//! Captures can also be written as [MCAP](https://mcap.dev) for Foxglove Studio and other
+//! robotics tools. Each key gets a channel whose metadata holds the Zenoh encoding
+//! (`zenoh.encoding`) and, for deletes, `zenoh.kind = delete`. When the protobuf type of a key
+//! is known, the type's `FileDescriptorSet` is embedded as the channel schema. Attachments are not
+//! stored in MCAP files.
+
+use crate::error::ZspyError;
+use crate::output::SplitOutput;
+use crate::reorder::Reorder;
+use crate::schema;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::ValueEnum;
+use mcap::sans_io::linear_reader::{LinearReadEvent, LinearReader};
+use prost_reflect::MessageDescriptor;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::time;
+use zenoh::{
+    sample::{Sample, SampleKind},
+    Session,
+};
+
+pub const MAGIC: &[u8; 4] = b"ZBAG";
+pub const VERSION: u16 = 2;
+
+const KIND_PUT: u8 = 0;
+const KIND_DELETE: u8 = 1;
+/// Kind of the record holding the index, after the last sample
+const KIND_INDEX: u8 = 255;
+
+const INDEX_MAGIC: &[u8; 4] = b"ZIDX";
+/// A new chunk starts once the current one holds this many bytes of records
+const CHUNK_BYTES: u64 = 1 << 20;
+
+/// MCAP channel metadata keys for what the MCAP message encoding can't express
+const MCAP_ENCODING_KEY: &str = "zenoh.encoding";
+const MCAP_KIND_KEY: &str = "zenoh.kind";
+
+/// On-disk format of a capture file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CaptureFormat {
+    /// zspy's own format, keeping attachments
+    Zbag,
+    /// MCAP, readable by Foxglove Studio and other robotics tools
+    Mcap,
+}
+
+impl CaptureFormat {
+    /// The format implied by `path`'s extension: MCAP for `.mcap`, zbag otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("mcap") => CaptureFormat::Mcap,
+            _ => CaptureFormat::Zbag,
+        }
+    }
+}
+
+/// Destination for recorded samples.
+pub trait SampleWriter {
+    fn write(&mut self, sample: &RecordedSample) -> Result<(), Box<dyn Error + Send + Sync>>;
+    /// Flushes buffered samples and completes the file.
+    fn finish(&mut self) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+/// Source of recorded samples, in capture order.
+pub trait SampleReader {
+    /// Reads the next sample, returning `None` at the end of the capture.
+    fn read(&mut self) -> Result<Option<RecordedSample>, Box<dyn Error + Send + Sync>>;
+}
+
+/// The protobuf type of the samples on a key, if known.
+pub type KeyTypes = Box<dyn Fn(&str) -> Option<MessageDescriptor>>;
+
+/// Creates a capture file at `path`. The types from `key_types` are embedded as the schemas of
+/// MCAP channels and ignored for zbag files.
+pub fn create(
+    path: &Path,
+    format: CaptureFormat,
+    key_types: Option<KeyTypes>,
+) -> Result<Box<dyn SampleWriter>, Box<dyn Error + Send + Sync>> {
+    let file = BufWriter::new(File::create(path)?);
+    Ok(match format {
+        CaptureFormat::Zbag => Box::new(RecordWriter::new(file)?),
+        CaptureFormat::Mcap => Box::new(McapWriter::new(file, key_types)?),
+    })
+}
+
+/// Opens the capture at `path`, detecting its format from the file's magic bytes.
+pub fn open(path: &Path) -> Result<Box<dyn SampleReader>, Box<dyn Error + Send + Sync>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 8];
+    let len = file.read(&mut magic)?;
+    file.rewind()?;
+    if magic[..len] == *mcap::MAGIC {
+        Ok(Box::new(McapReader::new(BufReader::new(file))))
+    } else {
+        Ok(Box::new(RecordReader::new(BufReader::new(file))?))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedSample {
+    pub timestamp_ns: u64,
+    pub kind: SampleKind,
+    pub key: String,
+    pub encoding: String,
+    pub payload: Vec<u8>,
+    pub attachment: Option<Vec<u8>>,
+}
+
+impl RecordedSample {
+    pub fn from_sample(sample: &Sample, timestamp_ns: u64) -> Self {
+        Self {
+            timestamp_ns,
+            kind: sample.kind(),
+            key: sample.key_expr().as_str().to_string(),
+            encoding: sample.encoding().to_string(),
+            payload: sample.payload().to_bytes().into_owned(),
+            attachment: sample.attachment().map(|a| a.to_bytes().into_owned()),
+        }
+    }
+
+    /// The sample as a JSON line for `record --split-output`: payload and attachment as text
+    /// when they are UTF-8, else as `{"base64": ...}`.
+    pub fn to_json(&self) -> Value {
+        let bytes = |bytes: &[u8]| match std::str::from_utf8(bytes) {
+            Ok(text) => json!(text),
+            Err(_) => json!({ "base64": BASE64_STANDARD.encode(bytes) }),
+        };
+        let timestamp = UNIX_EPOCH + Duration::from_nanos(self.timestamp_ns);
+        let mut value = json!({
+            "key": self.key,
+            "timestamp": humantime::format_rfc3339_nanos(timestamp).to_string(),
+            "kind": self.kind.to_string(),
+            "encoding": self.encoding,
+            "payload": bytes(&self.payload),
+        });
+        if let Some(attachment) = &self.attachment {
+            value["attachment"] = bytes(attachment);
+        }
+        value
+    }
+}
+
+impl SampleWriter for SplitOutput {
+    fn write(&mut self, sample: &RecordedSample) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(SplitOutput::write(self, &sample.key, &sample.to_json())?)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(self.flush()?)
+    }
+}
+
+/// Where a key's samples are in a capture, as listed in its [`CaptureIndex`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyIndex {
+    pub key: String,
+    pub samples: u64,
+    /// Total payload size
+    pub bytes: u64,
+    pub first_ns: u64,
+    pub last_ns: u64,
+}
+
+/// A run of consecutive records in a zbag capture.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkIndex {
+    /// File offset of the chunk's first record
+    pub offset: u64,
+    pub samples: u64,
+    pub first_ns: u64,
+    pub last_ns: u64,
+    /// Positions in [`CaptureIndex::keys`] of the keys with samples in the chunk
+    pub keys: Vec<usize>,
+}
+
+/// Summary of a capture's keys and chunks, written at the end of zbag files and otherwise
+/// built by reading the whole capture with [`CaptureIndex::scan`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CaptureIndex {
+    /// Every recorded key, in order of first appearance
+    pub keys: Vec<KeyIndex>,
+    /// Empty for scanned captures, whose records can't be sought to
+    pub chunks: Vec<ChunkIndex>,
+    #[serde(skip)]
+    positions: HashMap<String, usize>,
+    #[serde(skip)]
+    chunk_keys: HashSet<usize>,
+}
+
+impl CaptureIndex {
+    /// Builds the index of a capture by reading all of its samples.
+    pub fn scan(reader: &mut dyn SampleReader) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut index = Self::default();
+        while let Some(sample) = reader.read()? {
+            index.add_key(&sample);
+        }
+        Ok(index)
+    }
+
+    fn add_key(&mut self, sample: &RecordedSample) -> usize {
+        let position = match self.positions.get(&sample.key) {
+            Some(&position) => position,
+            None => {
+                self.positions.insert(sample.key.clone(), self.keys.len());
+                self.keys.push(KeyIndex {
+                    key: sample.key.clone(),
+                    samples: 0,
+                    bytes: 0,
+                    first_ns: sample.timestamp_ns,
+                    last_ns: sample.timestamp_ns,
+                });
+                self.keys.len() - 1
+            }
+        };
+        let key = &mut self.keys[position];
+        key.samples += 1;
+        key.bytes += sample.payload.len() as u64;
+        key.first_ns = key.first_ns.min(sample.timestamp_ns);
+        key.last_ns = key.last_ns.max(sample.timestamp_ns);
+        position
+    }
+
+    /// Adds `sample`, whose record starts at `offset` in the file.
+    fn add(&mut self, sample: &RecordedSample, offset: u64) {
+        let position = self.add_key(sample);
+        let full = self
+            .chunks
+            .last()
+            .is_none_or(|chunk| offset - chunk.offset >= CHUNK_BYTES);
+        if full {
+            self.chunks.push(ChunkIndex {
+                offset,
+                samples: 0,
+                first_ns: sample.timestamp_ns,
+                last_ns: sample.timestamp_ns,
+                keys: Vec::new(),
+            });
+            self.chunk_keys.clear();
+        }
+        let Some(chunk) = self.chunks.last_mut() else {
+            return;
+        };
+        chunk.samples += 1;
+        chunk.first_ns = chunk.first_ns.min(sample.timestamp_ns);
+        chunk.last_ns = chunk.last_ns.max(sample.timestamp_ns);
+        if self.chunk_keys.insert(position) {
+            chunk.keys.push(position);
+        }
+    }
+
+    /// Receive time of the capture's first sample.
+    pub fn start_ns(&self) -> Option<u64> {
+        self.keys.iter().map(|key| key.first_ns).min()
+    }
+
+    /// Receive time of the capture's last sample.
+    pub fn end_ns(&self) -> Option<u64> {
+        self.keys.iter().map(|key| key.last_ns).max()
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.keys.iter().map(|key| key.samples).sum()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.keys.iter().map(|key| key.bytes).sum()
+    }
+}
+
+pub struct RecordWriter<W: Write> {
+    inner: W,
+    /// Bytes written so far, for the offsets in the index
+    offset: u64,
+    index: CaptureIndex,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(mut inner: W) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        inner.write_all(MAGIC)?;
+        inner.write_all(&VERSION.to_le_bytes())?;
+        Ok(Self {
+            inner,
+            offset: (MAGIC.len() + 2) as u64,
+            index: CaptureIndex::default(),
+        })
+    }
+
+    /// Appends a sample, as [`SampleWriter::write`] does, without the trait in scope.
+    pub fn write(&mut self, sample: &RecordedSample) -> Result<(), Box<dyn Error + Send + Sync>> {
+        SampleWriter::write(self, sample)
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.inner.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let len = u32::try_from(bytes.len()).map_err(|_| "Field too large to record")?;
+        self.write_all(&len.to_le_bytes())?;
+        self.write_all(bytes)
+    }
+}
+
+impl<W: Write> SampleWriter for RecordWriter<W> {
+    fn write(&mut self, sample: &RecordedSample) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.index.add(sample, self.offset);
+        self.write_all(&sample.timestamp_ns.to_le_bytes())?;
+        let kind = match sample.kind {
+            SampleKind::Put => KIND_PUT,
+            SampleKind::Delete => KIND_DELETE,
+        };
+        self.write_all(&[kind])?;
+        self.write_bytes(sample.key.as_bytes())?;
+        self.write_bytes(sample.encoding.as_bytes())?;
+        self.write_bytes(&sample.payload)?;
+        match &sample.attachment {
+            Some(attachment) => {
+                self.write_all(&[1])?;
+                self.write_bytes(attachment)?;
+            }
+            None => self.write_all(&[0])?,
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let offset = self.offset;
+        let index = serde_json::to_vec(&self.index)?;
+        self.write_all(&0u64.to_le_bytes())?;
+        self.write_all(&[KIND_INDEX])?;
+        self.write_bytes(&index)?;
+        self.write_all(&offset.to_le_bytes())?;
+        self.write_all(INDEX_MAGIC)?;
+        self.flush()
+    }
+}
+
+/// Reads the index at the end of the zbag capture at `path`, or returns `None` for MCAP files,
+/// version 1 captures and interrupted recordings, which have none.
+pub fn read_index(path: &Path) -> Result<Option<CaptureIndex>, Box<dyn Error + Send + Sync>> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; 6];
+    if file.read_exact(&mut header).is_err()
+        || header[..4] != *MAGIC
+        || u16::from_le_bytes([header[4], header[5]]) < 2
+    {
+        return Ok(None);
+    }
+    let mut trailer = [0u8; 12];
+    if file.seek(SeekFrom::End(-12)).is_err() {
+        return Ok(None);
+    }
+    file.read_exact(&mut trailer)?;
+    if trailer[8..] != *INDEX_MAGIC {
+        return Ok(None);
+    }
+    let offset = u64::from_le_bytes(trailer[..8].try_into().unwrap_or_default());
+    // Skip the index record's timestamp and kind
+    file.seek(SeekFrom::Start(offset.saturating_add(9)))?;
+    let mut reader = RecordReader {
+        inner: BufReader::new(file),
+    };
+    let mut index: CaptureIndex = serde_json::from_slice(&reader.read_bytes()?)
+        .map_err(|e| ZspyError::Decode(format!("Invalid capture index: {}", e)))?;
+    index.positions = index
+        .keys
+        .iter()
+        .enumerate()
+        .map(|(position, key)| (key.key.clone(), position))
+        .collect();
+    Ok(Some(index))
+}
+
+/// Opens the indexed zbag capture at `path` to read only the chunks `select` accepts,
+/// seeking past the others.
+pub fn open_chunks(
+    path: &Path,
+    index: &CaptureIndex,
+    select: impl Fn(&ChunkIndex) -> bool,
+) -> Result<Box<dyn SampleReader>, Box<dyn Error + Send + Sync>> {
+    Ok(Box::new(ChunkReader {
+        reader: RecordReader {
+            inner: BufReader::new(File::open(path)?),
+        },
+        chunks: index
+            .chunks
+            .iter()
+            .filter(|chunk| select(chunk))
+            .map(|chunk| (chunk.offset, chunk.samples))
+            .collect(),
+        remaining: 0,
+    }))
+}
+
+struct ChunkReader {
+    reader: RecordReader<BufReader<File>>,
+    /// Offset and sample count of the chunks still to read
+    chunks: VecDeque<(u64, u64)>,
+    /// Samples left in the current chunk
+    remaining: u64,
+}
+
+impl SampleReader for ChunkReader {
+    fn read(&mut self) -> Result<Option<RecordedSample>, Box<dyn Error + Send + Sync>> {
+        while self.remaining == 0 {
+            let Some((offset, samples)) = self.chunks.pop_front() else {
+                return Ok(None);
+            };
+            self.reader.inner.seek(SeekFrom::Start(offset))?;
+            self.remaining = samples;
+        }
+        self.remaining -= 1;
+        self.reader.read()
+    }
+}
+
+pub struct RecordReader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> RecordReader<R> {
+    pub fn new(mut inner: R) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut magic = [0u8; 4];
+        inner.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ZspyError::Decode("Not a zspy capture file".to_string()).into());
+        }
+        let mut version = [0u8; 2];
+        inner.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        // Version 2 only adds the index, which reading stops at
+        if !(1..=VERSION).contains(&version) {
+            return Err(
+                ZspyError::Decode(format!("Unsupported capture version: {}", version)).into(),
+            );
+        }
+        Ok(Self { inner })
+    }
+
+    /// Reads the next sample, as [`SampleReader::read`] does, without the trait in scope.
+    pub fn read(&mut self) -> Result<Option<RecordedSample>, Box<dyn Error + Send + Sync>> {
+        SampleReader::read(self)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, Box<dyn Error + Send + Sync>> {
+        let mut buf = [0u8; 1];
+        self.inner.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// Reads a length-prefixed field. The buffer grows with the data actually read, so a
+    /// corrupt length fails as a truncated record instead of allocating up to 4 GiB.
+    fn read_bytes(&mut self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let mut len = [0u8; 4];
+        self.inner.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        let mut buf = Vec::new();
+        (&mut self.inner).take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len {
+            return Err(ZspyError::Decode(format!(
+                "Truncated capture record: expected {} bytes, found {}",
+                len,
+                buf.len()
+            ))
+            .into());
+        }
+        Ok(buf)
+    }
+}
+
+impl<R: Read> SampleReader for RecordReader<R> {
+    fn read(&mut self) -> Result<Option<RecordedSample>, Box<dyn Error + Send + Sync>> {
+        let mut timestamp = [0u8; 8];
+        match self.inner.read_exact(&mut timestamp) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let kind = match self.read_u8()? {
+            KIND_PUT => SampleKind::Put,
+            KIND_DELETE => SampleKind::Delete,
+            KIND_INDEX => return Ok(None),
+            other => {
+                return Err(
+                    ZspyError::Decode(format!("Invalid sample kind in capture: {}", other)).into(),
+                )
+            }
+        };
+        let key = String::from_utf8(self.read_bytes()?)?;
+        let encoding = String::from_utf8(self.read_bytes()?)?;
+        let payload = self.read_bytes()?;
+        let attachment = match self.read_u8()? {
+            0 => None,
+            _ => Some(self.read_bytes()?),
+        };
+        Ok(Some(RecordedSample {
+            timestamp_ns: u64::from_le_bytes(timestamp),
+            kind,
+            key,
+            encoding,
+            payload,
+            attachment,
+        }))
+    }
+}
+
+/// MCAP message encoding for a Zenoh encoding, using the names registered with MCAP where
+/// one exists.
+fn mcap_message_encoding(encoding: &str) -> &str {
+    match encoding.split(';').next().unwrap_or_default() {
+        "application/json" | "text/json" => "json",
+        "application/cbor" => "cbor",
+        "application/protobuf" => "protobuf",
+        _ => encoding,
+    }
+}
+
+pub struct McapWriter<W: Write + Seek> {
+    inner: mcap::Writer<W>,
+    key_types: Option<KeyTypes>,
+    /// Schema ids by protobuf type name
+    schemas: HashMap<String, u16>,
+    /// Channel ids by key, encoding and whether the channel carries deletes
+    channels: HashMap<(String, String, bool), u16>,
+    sequence: u32,
+}
+
+impl<W: Write + Seek> McapWriter<W> {
+    pub fn new(
+        inner: W,
+        key_types: Option<KeyTypes>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(Self {
+            inner: mcap::Writer::new(inner)?,
+            key_types,
+            schemas: HashMap::new(),
+            channels: HashMap::new(),
+            sequence: 0,
+        })
+    }
+
+    /// Schema id of the type of the samples on `key`, 0 when it isn't known.
+    fn schema(&mut self, key: &str) -> Result<u16, Box<dyn Error + Send + Sync>> {
+        let Some(descriptor) = self.key_types.as_ref().and_then(|key_types| key_types(key)) else {
+            return Ok(0);
+        };
+        if let Some(id) = self.schemas.get(descriptor.full_name()) {
+            return Ok(*id);
+        }
+        let files = schema::files(std::slice::from_ref(&descriptor));
+        let id = self.inner.add_schema(
+            descriptor.full_name(),
+            "protobuf",
+            &schema::descriptor_set(&files),
+        )?;
+        self.schemas.insert(descriptor.full_name().to_string(), id);
+        Ok(id)
+    }
+
+    fn channel(&mut self, sample: &RecordedSample) -> Result<u16, Box<dyn Error + Send + Sync>> {
+        let id = (
+            sample.key.clone(),
+            sample.encoding.clone(),
+            sample.kind == SampleKind::Delete,
+        );
+        if let Some(channel) = self.channels.get(&id) {
+            return Ok(*channel);
+        }
+        let mut metadata = BTreeMap::new();
+        metadata.insert(MCAP_ENCODING_KEY.to_string(), sample.encoding.clone());
+        let (schema_id, message_encoding) = match sample.kind {
+            SampleKind::Delete => {
+                metadata.insert(MCAP_KIND_KEY.to_string(), "delete".to_string());
+                (0, "")
+            }
+            SampleKind::Put => match self.schema(&sample.key)? {
+                0 => (0, mcap_message_encoding(&sample.encoding)),
+                schema_id => (schema_id, "protobuf"),
+            },
+        };
+        let channel =
+            self.inner
+                .add_channel(schema_id, &sample.key, message_encoding, &metadata)?;
+        self.channels.insert(id, channel);
+        Ok(channel)
+    }
+}
+
+impl<W: Write + Seek> SampleWriter for McapWriter<W> {
+    fn write(&mut self, sample: &RecordedSample) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let channel_id = self.channel(sample)?;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.inner.write_to_known_channel(
+            &mcap::records::MessageHeader {
+                channel_id,
+                sequence: self.sequence,
+                log_time: sample.timestamp_ns,
+                publish_time: sample.timestamp_ns,
+            },
+            &sample.payload,
+        )?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.inner.finish()?;
+        Ok(())
+    }
+}
+
+/// Reads an MCAP file, including ones written by other tools, a record at a time so large
+/// captures aren't loaded into memory. Topics become keys without their leading `/`, and the
+/// Zenoh encoding falls back to the MCAP message encoding.
+pub struct McapReader<R: Read> {
+    inner: R,
+    reader: LinearReader,
+    /// The channels seen so far, by ID
+    channels: HashMap<u16, McapChannel>,
+}
+
+/// What the samples on an MCAP channel are recorded as.
+struct McapChannel {
+    key: String,
+    kind: SampleKind,
+    encoding: String,
+}
+
+impl McapChannel {
+    fn new(channel: mcap::records::Channel) -> Self {
+        let kind = match channel.metadata.get(MCAP_KIND_KEY).map(String::as_str) {
+            Some("delete") => SampleKind::Delete,
+            _ => SampleKind::Put,
+        };
+        let encoding = match channel.metadata.get(MCAP_ENCODING_KEY) {
+            Some(encoding) => encoding.clone(),
+            None => match channel.message_encoding.as_str() {
+                "json" => "application/json".to_string(),
+                "cbor" => "application/cbor".to_string(),
+                "protobuf" => "application/protobuf".to_string(),
+                "" => "zenoh/bytes".to_string(),
+                other => other.to_string(),
+            },
+        };
+        Self {
+            key: channel.topic.trim_start_matches('/').to_string(),
+            kind,
+            encoding,
+        }
+    }
+}
+
+impl<R: Read> McapReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            reader: LinearReader::new(),
+            channels: HashMap::new(),
+        }
+    }
+}
+
+impl<R: Read> SampleReader for McapReader<R> {
+    fn read(&mut self) -> Result<Option<RecordedSample>, Box<dyn Error + Send + Sync>> {
+        while let Some(event) = self.reader.next_event() {
+            match event? {
+                LinearReadEvent::ReadRequest(need) => {
+                    let read = self.inner.read(self.reader.insert(need))?;
+                    self.reader.notify_read(read);
+                }
+                LinearReadEvent::Record { opcode, data } => match mcap::parse_record(opcode, data)?
+                {
+                    mcap::records::Record::Channel(channel) => {
+                        self.channels.insert(channel.id, McapChannel::new(channel));
+                    }
+                    mcap::records::Record::Message { header, data } => {
+                        let channel = self.channels.get(&header.channel_id).ok_or_else(|| {
+                            ZspyError::Decode(format!(
+                                "MCAP message on unknown channel {}",
+                                header.channel_id
+                            ))
+                        })?;
+                        return Ok(Some(RecordedSample {
+                            timestamp_ns: header.log_time,
+                            kind: channel.kind,
+                            key: channel.key.clone(),
+                            encoding: channel.encoding.clone(),
+                            payload: data.into_owned(),
+                            attachment: None,
+                        }));
+                    }
+                    _ => {}
+                },
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Subscribes to `keys` and writes every received sample to `writer`, calling `on_sample`
+/// after each one is written. Runs until the session closes; drop the future to stop.
+///
+/// With `reorder`, samples are written in the order of their Zenoh timestamps, which also
+/// become their capture times. Samples still in `reorder` when the future is dropped are left
+/// for the caller to write.
+pub async fn record<W: SampleWriter + ?Sized>(
+    session: &Session,
+    keys: &[String],
+    writer: &mut W,
+    reorder: Option<&mut Reorder<RecordedSample>>,
+    mut on_sample: impl FnMut(&RecordedSample),
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let mut subscribers = Vec::new();
+    for key in keys {
+        let tx = tx.clone();
+        let subscriber = session
+            .declare_subscriber(key.as_str())
+            .callback(move |sample| {
+                let timestamp_ns = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_nanos() as u64);
+                let recorded = RecordedSample::from_sample(&sample, timestamp_ns);
+                let _ = tx.send((sample.timestamp().copied(), recorded));
+            })
+            .await?;
+        subscribers.push(subscriber);
+    }
+    drop(tx);
+
+    let Some(reorder) = reorder else {
+        while let Some((_, sample)) = rx.recv().await {
+            writer.write(&sample)?;
+            on_sample(&sample);
+        }
+        return Ok(());
+    };
+    loop {
+        let release_at = reorder.next_deadline();
+        tokio::select! {
+            sample = rx.recv() => {
+                let Some((timestamp, mut sample)) = sample else {
+                    break;
+                };
+                if let Some(timestamp) = timestamp {
+                    sample.timestamp_ns = timestamp.get_time().to_duration().as_nanos() as u64;
+                }
+                reorder.push(timestamp, sample, Instant::now());
+            }
+            _ = time::sleep_until(release_at.unwrap_or_else(Instant::now).into()), if release_at.is_some() => {}
+        }
+        for sample in reorder.release(Instant::now()) {
+            writer.write(&sample)?;
+            on_sample(&sample);
+        }
+    }
+    for sample in reorder.drain() {
+        writer.write(&sample)?;
+        on_sample(&sample);
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! `--order-by-timestamp`: hold samples for a short window and release them sorted by their
+//! Zenoh timestamps, so streams from several publishers that reach zspy over different paths
+//! read in causal order.
+//!
+//! Every sample waits at most the window after it arrives. When its time is up, it is released
+//! together with every buffered sample timestamped before it. A sample that arrives after a
+//! later one was already released can't be put back in order; it is released at once and
+//! counted as late. Samples without a timestamp have nothing to order by, so they too are
+//! released at once, and counted separately: publishers need timestamping enabled for them to
+//! be ordered.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+use zenoh::time::Timestamp;
+
+pub struct Reorder<T> {
+    window: Duration,
+    /// Buffered items by timestamp, then arrival number to keep equal timestamps apart
+    buffer: BTreeMap<(Timestamp, u64), T>,
+    /// When each buffered item is due, in arrival order; items released early are skipped
+    deadlines: VecDeque<(Instant, (Timestamp, u64))>,
+    /// Items released as they arrived, because they are late or have no timestamp
+    unordered: VecDeque<T>,
+    /// The newest timestamp released so far
+    released: Option<Timestamp>,
+    arrivals: u64,
+    /// Number of samples released out of order because they arrived too late
+    pub late: u64,
+    /// Number of samples released as they arrived because they had no timestamp
+    pub untimestamped: u64,
+}
+
+impl<T> Reorder<T> {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            buffer: BTreeMap::new(),
+            deadlines: VecDeque::new(),
+            unordered: VecDeque::new(),
+            released: None,
+            arrivals: 0,
+            late: 0,
+            untimestamped: 0,
+        }
+    }
+
+    /// Buffers an item that arrived at `now`.
+    pub fn push(&mut self, timestamp: Option<Timestamp>, item: T, now: Instant) {
+        let Some(timestamp) = timestamp else {
+            self.untimestamped += 1;
+            self.unordered.push_back(item);
+            return;
+        };
+        if self.released.is_some_and(|released| timestamp < released) {
+            self.late += 1;
+            self.unordered.push_back(item);
+            return;
+        }
+        let key = (timestamp, self.arrivals);
+        self.arrivals += 1;
+        self.buffer.insert(key, item);
+        self.deadlines.push_back((now + self.window, key));
+    }
+
+    /// When the next buffered item is due, if any.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.unordered.is_empty() {
+            true => self.deadlines.front().map(|(deadline, _)| *deadline),
+            false => Some(Instant::now()),
+        }
+    }
+
+    /// Takes the items due by `now`, in the order to process them.
+    pub fn release(&mut self, now: Instant) -> Vec<T> {
+        let mut items: Vec<T> = self.unordered.drain(..).collect();
+        while let Some(&(deadline, key)) = self.deadlines.front() {
+            if deadline > now {
+                break;
+            }
+            self.deadlines.pop_front();
+            if !self.buffer.contains_key(&key) {
+                continue;
+            }
+            // Everything timestamped up to the due item goes out with it
+            let rest = self.buffer.split_off(&(key.0, key.1 + 1));
+            let due = std::mem::replace(&mut self.buffer, rest);
+            items.extend(due.into_values());
+            self.released = Some(key.0);
+        }
+        items
+    }
+
+    /// Takes every item, for when no more will arrive.
+    pub fn drain(&mut self) -> Vec<T> {
+        self.deadlines.clear();
+        if let Some(((timestamp, _), _)) = self.buffer.last_key_value() {
+            self.released = Some(*timestamp);
+        }
+        let mut items: Vec<T> = self.unordered.drain(..).collect();
+        items.extend(std::mem::take(&mut self.buffer).into_values());
+        items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty() && self.unordered.is_empty()
+    }
+}
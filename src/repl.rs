@@ -0,0 +1,217 @@
+//! `zspy repl`: an interactive prompt running zspy commands on one shared session.
+//!
+//! Each line is split like a shell command line and parsed as a zspy subcommand, so `echo`,
+//! `pub`, `get`, `list` and the rest take the same options as on the command line (the global
+//! session options excepted). Ctrl-C stops the running command and returns to the prompt.
+//! Keys are completed from the ones the session has seen traffic on.
+
+use crate::{execute, Commands};
+use clap::{CommandFactory, Parser};
+use colored::Colorize;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Context, Editor, Helper};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use zenoh::{Config, Session};
+use zspy::message_registry::MessageRegistry;
+
+/// Keys remembered for completion; beyond this, new keys are ignored
+const MAX_KEYS: usize = 10_000;
+
+/// Words that end the REPL instead of running a command
+const EXIT_WORDS: [&str; 2] = ["exit", "quit"];
+
+#[derive(Parser)]
+#[command(name = "zspy", no_binary_name = true, disable_version_flag = true)]
+struct ReplLine {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+struct ReplHelper {
+    keys: Arc<Mutex<BTreeSet<String>>>,
+    /// Subcommand names with their long options
+    commands: Vec<(String, Vec<String>)>,
+}
+
+impl ReplHelper {
+    fn new(keys: Arc<Mutex<BTreeSet<String>>>) -> Self {
+        let commands = ReplLine::command()
+            .get_subcommands()
+            .map(|sub| {
+                let options = sub
+                    .get_arguments()
+                    .filter_map(|arg| arg.get_long())
+                    .map(|long| format!("--{}", long))
+                    .collect();
+                (sub.get_name().to_string(), options)
+            })
+            .collect();
+        Self { keys, commands }
+    }
+
+    fn candidates(&self, preceding: &str, word: &str) -> Vec<String> {
+        match preceding.split_whitespace().next() {
+            None => self
+                .commands
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .chain(EXIT_WORDS)
+                .filter(|name| name.starts_with(word))
+                .map(str::to_string)
+                .collect(),
+            Some(command) if word.starts_with('-') => self
+                .commands
+                .iter()
+                .filter(|(name, _)| name == command)
+                .flat_map(|(_, options)| options)
+                .filter(|option| option.starts_with(word))
+                .cloned()
+                .collect(),
+            Some(_) => self
+                .keys
+                .lock()
+                .expect("key set lock poisoned")
+                .range(word.to_string()..)
+                .take_while(|key| key.starts_with(word))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let candidates = self
+            .candidates(&line[..start], &line[start..pos])
+            .into_iter()
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Where the prompt history is kept between runs: `$XDG_STATE_HOME/zspy/repl_history`, usually
+/// `~/.local/state/zspy/repl_history`.
+fn history_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("zspy").join("repl_history"))
+}
+
+pub async fn run(
+    session: &Session,
+    registry: &Arc<MessageRegistry>,
+    config: &Config,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let keys = Arc::new(Mutex::new(BTreeSet::new()));
+    let seen = keys.clone();
+    let _subscriber = session
+        .declare_subscriber("**")
+        .callback(move |sample| {
+            let key = sample.key_expr().as_str();
+            let mut keys = seen.lock().expect("key set lock poisoned");
+            if keys.len() < MAX_KEYS && !keys.contains(key) {
+                keys.insert(key.to_string());
+            }
+        })
+        .await?;
+
+    let editor_config = rustyline::Config::builder()
+        .completion_type(CompletionType::List)
+        .auto_add_history(true)
+        .build();
+    let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::with_config(editor_config)?;
+    editor.set_helper(Some(ReplHelper::new(keys)));
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing history file just means a first run
+        let _ = editor.load_history(path);
+    }
+
+    println!(
+        "Connected to Zenoh session with ID: {}",
+        session.zid().to_string().cyan()
+    );
+    println!("Type `help` for commands, Tab to complete, `exit` or Ctrl-D to quit");
+    loop {
+        let line = match tokio::task::block_in_place(|| editor.readline("zspy> ")) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if EXIT_WORDS.contains(&line) {
+            break;
+        }
+        let Some(words) = shlex::split(line) else {
+            eprintln!("{} Unterminated quote", "Error:".red());
+            continue;
+        };
+        let mut command = match ReplLine::try_parse_from(words) {
+            Ok(parsed) => parsed.command,
+            Err(err) => {
+                let _ = err.print();
+                continue;
+            }
+        };
+        if let Err(err) = command.resolve_types(registry) {
+            eprintln!("{} {}", "Error:".red(), err);
+            continue;
+        }
+        // Commands that handle Ctrl-C themselves finish first and print their summaries
+        let result = tokio::select! {
+            biased;
+            result = execute(session, registry, config, &command) => result,
+            _ = tokio::signal::ctrl_c() => {
+                println!("\n{}", "Interrupted".yellow());
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            eprintln!("{} {}", "Error:".red(), err);
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,509 @@
+//! Decoding of ROS 2 messages published through `rmw_zenoh`.
+//!
+//! Message layouts come from ROS `.msg` definitions: a bundled set of common types plus any
+//! found under `--ros2-msg-path` directories (`<package>/msg/<Type>.msg`). Payloads are CDR
+//! (XCDR1) encoded, preceded by the 4-byte encapsulation header.
+//!
+//! `rmw_zenoh` publishes a topic such as `/chatter` on the key expression
+//! `<domain_id>/chatter/<dds type name>/<type hash>`, where the DDS type name of
+//! `std_msgs/msg/String` is `std_msgs::msg::dds_::String_`.
+
+use crate::error::ZspyError;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Definitions of common interface packages, as `(type name, .msg text)`.
+const BUNDLED: &[(&str, &str)] = &[
+    ("builtin_interfaces/msg/Time", "int32 sec\nuint32 nanosec"),
+    (
+        "builtin_interfaces/msg/Duration",
+        "int32 sec\nuint32 nanosec",
+    ),
+    (
+        "std_msgs/msg/Header",
+        "builtin_interfaces/Time stamp\nstring frame_id",
+    ),
+    ("std_msgs/msg/String", "string data"),
+    ("std_msgs/msg/Bool", "bool data"),
+    ("std_msgs/msg/Byte", "byte data"),
+    ("std_msgs/msg/Char", "char data"),
+    ("std_msgs/msg/Empty", ""),
+    ("std_msgs/msg/Int8", "int8 data"),
+    ("std_msgs/msg/Int16", "int16 data"),
+    ("std_msgs/msg/Int32", "int32 data"),
+    ("std_msgs/msg/Int64", "int64 data"),
+    ("std_msgs/msg/UInt8", "uint8 data"),
+    ("std_msgs/msg/UInt16", "uint16 data"),
+    ("std_msgs/msg/UInt32", "uint32 data"),
+    ("std_msgs/msg/UInt64", "uint64 data"),
+    ("std_msgs/msg/Float32", "float32 data"),
+    ("std_msgs/msg/Float64", "float64 data"),
+    (
+        "std_msgs/msg/ColorRGBA",
+        "float32 r\nfloat32 g\nfloat32 b\nfloat32 a",
+    ),
+    (
+        "geometry_msgs/msg/Vector3",
+        "float64 x\nfloat64 y\nfloat64 z",
+    ),
+    ("geometry_msgs/msg/Point", "float64 x\nfloat64 y\nfloat64 z"),
+    (
+        "geometry_msgs/msg/Point32",
+        "float32 x\nfloat32 y\nfloat32 z",
+    ),
+    (
+        "geometry_msgs/msg/Quaternion",
+        "float64 x 0\nfloat64 y 0\nfloat64 z 0\nfloat64 w 1",
+    ),
+    (
+        "geometry_msgs/msg/Pose",
+        "Point position\nQuaternion orientation",
+    ),
+    (
+        "geometry_msgs/msg/PoseStamped",
+        "std_msgs/Header header\nPose pose",
+    ),
+    (
+        "geometry_msgs/msg/PoseWithCovariance",
+        "Pose pose\nfloat64[36] covariance",
+    ),
+    ("geometry_msgs/msg/Twist", "Vector3 linear\nVector3 angular"),
+    (
+        "geometry_msgs/msg/TwistStamped",
+        "std_msgs/Header header\nTwist twist",
+    ),
+    (
+        "geometry_msgs/msg/TwistWithCovariance",
+        "Twist twist\nfloat64[36] covariance",
+    ),
+    ("geometry_msgs/msg/Accel", "Vector3 linear\nVector3 angular"),
+    ("geometry_msgs/msg/Wrench", "Vector3 force\nVector3 torque"),
+    (
+        "geometry_msgs/msg/Transform",
+        "Vector3 translation\nQuaternion rotation",
+    ),
+    (
+        "geometry_msgs/msg/TransformStamped",
+        "std_msgs/Header header\nstring child_frame_id\nTransform transform",
+    ),
+    (
+        "tf2_msgs/msg/TFMessage",
+        "geometry_msgs/TransformStamped[] transforms",
+    ),
+    (
+        "sensor_msgs/msg/Imu",
+        "std_msgs/Header header\n\
+         geometry_msgs/Quaternion orientation\n\
+         float64[9] orientation_covariance\n\
+         geometry_msgs/Vector3 angular_velocity\n\
+         float64[9] angular_velocity_covariance\n\
+         geometry_msgs/Vector3 linear_acceleration\n\
+         float64[9] linear_acceleration_covariance",
+    ),
+    (
+        "sensor_msgs/msg/Temperature",
+        "std_msgs/Header header\nfloat64 temperature\nfloat64 variance",
+    ),
+    (
+        "sensor_msgs/msg/LaserScan",
+        "std_msgs/Header header\n\
+         float32 angle_min\nfloat32 angle_max\nfloat32 angle_increment\n\
+         float32 time_increment\nfloat32 scan_time\n\
+         float32 range_min\nfloat32 range_max\n\
+         float32[] ranges\nfloat32[] intensities",
+    ),
+    (
+        "sensor_msgs/msg/NavSatStatus",
+        "int8 STATUS_NO_FIX = -1\nint8 STATUS_FIX = 0\n\
+         int8 status\nuint16 service",
+    ),
+    (
+        "sensor_msgs/msg/NavSatFix",
+        "std_msgs/Header header\nNavSatStatus status\n\
+         float64 latitude\nfloat64 longitude\nfloat64 altitude\n\
+         float64[9] position_covariance\n\
+         uint8 COVARIANCE_TYPE_UNKNOWN = 0\nuint8 position_covariance_type",
+    ),
+    (
+        "nav_msgs/msg/Odometry",
+        "std_msgs/Header header\nstring child_frame_id\n\
+         geometry_msgs/PoseWithCovariance pose\n\
+         geometry_msgs/TwistWithCovariance twist",
+    ),
+    (
+        "rcl_interfaces/msg/Log",
+        "uint8 DEBUG = 10\nuint8 INFO = 20\n\
+         builtin_interfaces/Time stamp\nuint8 level\nstring name\nstring msg\n\
+         string file\nstring function\nuint32 line",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Primitive {
+    Bool,
+    Byte,
+    Char,
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Int64,
+    Uint64,
+    Float32,
+    Float64,
+    String,
+}
+
+impl Primitive {
+    fn parse(name: &str) -> Option<Self> {
+        // Bounded strings such as `string<=10` decode like plain strings
+        if name == "string" || name.starts_with("string<=") {
+            return Some(Primitive::String);
+        }
+        Some(match name {
+            "bool" => Primitive::Bool,
+            "byte" => Primitive::Byte,
+            "char" => Primitive::Char,
+            "int8" => Primitive::Int8,
+            "uint8" => Primitive::Uint8,
+            "int16" => Primitive::Int16,
+            "uint16" => Primitive::Uint16,
+            "int32" => Primitive::Int32,
+            "uint32" => Primitive::Uint32,
+            "int64" => Primitive::Int64,
+            "uint64" => Primitive::Uint64,
+            "float32" => Primitive::Float32,
+            "float64" => Primitive::Float64,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum BaseType {
+    Primitive(Primitive),
+    /// Fully qualified `package/msg/Type`
+    Message(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Arity {
+    Single,
+    /// `T[N]`
+    Fixed(usize),
+    /// `T[]` or `T[<=N]`
+    Sequence,
+}
+
+#[derive(Debug, Clone)]
+struct Field {
+    name: String,
+    base: BaseType,
+    arity: Arity,
+}
+
+/// Normalizes `pkg/Type`, `pkg/msg/Type` and `pkg::msg::Type` to `pkg/msg/Type`.
+fn normalize_type_name(name: &str) -> Option<String> {
+    let parts: Vec<&str> = name.split(['/', ':']).filter(|s| !s.is_empty()).collect();
+    match parts.as_slice() {
+        [package, ty] | [package, "msg", ty] => Some(format!("{}/msg/{}", package, ty)),
+        _ => None,
+    }
+}
+
+/// Name `rmw_zenoh` uses for a type in key expressions, e.g. `std_msgs::msg::dds_::String_`.
+pub fn dds_type_name(name: &str) -> Option<String> {
+    let normalized = normalize_type_name(name)?;
+    let (package, ty) = normalized.split_once("/msg/")?;
+    Some(format!("{}::msg::dds_::{}_", package, ty))
+}
+
+/// Maps a ROS topic such as `/chatter` to the key expression `rmw_zenoh` publishes it on.
+pub fn topic_key_expr(topic: &str, msg_type: &str, domain_id: u32) -> Option<String> {
+    Some(format!(
+        "{}/{}/{}/*",
+        domain_id,
+        topic.trim_matches('/'),
+        dds_type_name(msg_type)?
+    ))
+}
+
+fn parse_definition(
+    type_name: &str,
+    text: &str,
+) -> Result<Vec<Field>, Box<dyn Error + Send + Sync>> {
+    let package = type_name.split('/').next().unwrap_or_default();
+    let mut fields = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let (Some(ty), Some(name)) = (words.next(), words.next()) else {
+            return Err(format!("{}: invalid line '{}'", type_name, line).into());
+        };
+        // Constants (`int8 FOO = 1`) take no space in the serialized message
+        if name.contains('=') || words.next() == Some("=") {
+            continue;
+        }
+
+        let (base_name, arity) = match ty.split_once('[') {
+            Some((base, rest)) => {
+                let bound = rest.trim_end_matches(']');
+                let arity = if bound.is_empty() || bound.starts_with("<=") {
+                    Arity::Sequence
+                } else {
+                    Arity::Fixed(
+                        bound
+                            .parse()
+                            .map_err(|_| format!("{}: invalid array size '{}'", type_name, ty))?,
+                    )
+                };
+                (base, arity)
+            }
+            None => (ty, Arity::Single),
+        };
+        let base = match Primitive::parse(base_name) {
+            Some(primitive) => BaseType::Primitive(primitive),
+            None if base_name.starts_with("wstring") => {
+                return Err(format!("{}: wstring fields are not supported", type_name).into())
+            }
+            None if base_name == "Header" => BaseType::Message("std_msgs/msg/Header".to_string()),
+            None if base_name.contains('/') => BaseType::Message(
+                normalize_type_name(base_name)
+                    .ok_or_else(|| format!("{}: invalid type '{}'", type_name, base_name))?,
+            ),
+            None => BaseType::Message(format!("{}/msg/{}", package, base_name)),
+        };
+        fields.push(Field {
+            name: name.to_string(),
+            base,
+            arity,
+        });
+    }
+    Ok(fields)
+}
+
+/// Collects `.msg` files laid out as `<package>/msg/<Type>.msg` below `dir`.
+fn find_msg_files(dir: &Path, found: &mut Vec<(String, PathBuf)>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_msg_files(&path, found)?;
+        } else if path.extension().is_some_and(|ext| ext == "msg") {
+            let parent = path.parent();
+            let package = parent.and_then(Path::parent).and_then(Path::file_name);
+            let in_msg_dir = parent.and_then(Path::file_name).is_some_and(|d| d == "msg");
+            if let (true, Some(package), Some(ty)) = (in_msg_dir, package, path.file_stem()) {
+                let name = format!("{}/msg/{}", package.to_string_lossy(), ty.to_string_lossy());
+                found.push((name, path.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decodes CDR payloads of one ROS 2 message type.
+pub struct Decoder {
+    root: String,
+    types: HashMap<String, Vec<Field>>,
+}
+
+impl Decoder {
+    /// Resolves `msg_type` and every type it references from the bundled definitions and the
+    /// `.msg` files under `msg_paths`.
+    pub fn new(
+        msg_type: &str,
+        msg_paths: &[PathBuf],
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut sources: HashMap<String, String> = BUNDLED
+            .iter()
+            .map(|(name, text)| (name.to_string(), text.to_string()))
+            .collect();
+        for dir in msg_paths {
+            let mut found = Vec::new();
+            find_msg_files(dir, &mut found)
+                .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+            for (name, path) in found {
+                sources.insert(name, fs::read_to_string(&path)?);
+            }
+        }
+
+        let root = normalize_type_name(msg_type)
+            .ok_or_else(|| format!("Invalid ROS 2 type name: {}", msg_type))?;
+        let mut types = HashMap::new();
+        let mut pending = vec![root.clone()];
+        while let Some(name) = pending.pop() {
+            if types.contains_key(&name) {
+                continue;
+            }
+            let text = sources
+                .get(&name)
+                .ok_or_else(|| ZspyError::UnknownType(name.clone(), Vec::new()))?;
+            let fields = parse_definition(&name, text)?;
+            for field in &fields {
+                if let BaseType::Message(nested) = &field.base {
+                    pending.push(nested.clone());
+                }
+            }
+            types.insert(name, fields);
+        }
+        Ok(Self { root, types })
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        if bytes.len() < 4 {
+            return Err("Payload too short for a CDR encapsulation header".into());
+        }
+        let little_endian = match (bytes[0], bytes[1]) {
+            (0x00, 0x00) => false,
+            (0x00, 0x01) => true,
+            (a, b) => {
+                return Err(format!("Unsupported CDR encapsulation 0x{:02x}{:02x}", a, b).into())
+            }
+        };
+        let mut reader = CdrReader {
+            data: &bytes[4..],
+            pos: 0,
+            little_endian,
+        };
+        self.read_message(&mut reader, &self.root)
+    }
+
+    fn read_message(
+        &self,
+        reader: &mut CdrReader,
+        name: &str,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let fields = &self.types[name];
+        if fields.is_empty() {
+            // Empty structures are serialized with a single placeholder byte
+            reader.read::<1>()?;
+            return Ok(Value::Object(Map::new()));
+        }
+        let mut object = Map::new();
+        for field in fields {
+            let value = match field.arity {
+                Arity::Single => self.read_value(reader, &field.base)?,
+                Arity::Fixed(len) => self.read_array(reader, &field.base, len)?,
+                Arity::Sequence => {
+                    let len = reader.read_u32()? as usize;
+                    self.read_array(reader, &field.base, len)?
+                }
+            };
+            object.insert(field.name.clone(), value);
+        }
+        Ok(Value::Object(object))
+    }
+
+    fn read_array(
+        &self,
+        reader: &mut CdrReader,
+        base: &BaseType,
+        len: usize,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        if len > reader.remaining() {
+            return Err(format!("Sequence length {} exceeds payload size", len).into());
+        }
+        (0..len)
+            .map(|_| self.read_value(reader, base))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array)
+    }
+
+    fn read_value(
+        &self,
+        reader: &mut CdrReader,
+        base: &BaseType,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let primitive = match base {
+            BaseType::Message(name) => return self.read_message(reader, name),
+            BaseType::Primitive(primitive) => *primitive,
+        };
+        Ok(match primitive {
+            Primitive::Bool => Value::Bool(reader.read::<1>()?[0] != 0),
+            Primitive::Byte | Primitive::Char | Primitive::Uint8 => reader.read::<1>()?[0].into(),
+            Primitive::Int8 => (reader.read::<1>()?[0] as i8).into(),
+            Primitive::Int16 => reader
+                .read_num(i16::from_le_bytes, i16::from_be_bytes)?
+                .into(),
+            Primitive::Uint16 => reader
+                .read_num(u16::from_le_bytes, u16::from_be_bytes)?
+                .into(),
+            Primitive::Int32 => reader
+                .read_num(i32::from_le_bytes, i32::from_be_bytes)?
+                .into(),
+            Primitive::Uint32 => reader.read_u32()?.into(),
+            Primitive::Int64 => reader
+                .read_num(i64::from_le_bytes, i64::from_be_bytes)?
+                .into(),
+            Primitive::Uint64 => reader
+                .read_num(u64::from_le_bytes, u64::from_be_bytes)?
+                .into(),
+            Primitive::Float32 => reader
+                .read_num(f32::from_le_bytes, f32::from_be_bytes)?
+                .into(),
+            Primitive::Float64 => reader
+                .read_num(f64::from_le_bytes, f64::from_be_bytes)?
+                .into(),
+            Primitive::String => {
+                // Length includes the trailing NUL
+                let len = reader.read_u32()? as usize;
+                let bytes = reader.take(len)?;
+                let text = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+                Value::String(String::from_utf8_lossy(text).to_string())
+            }
+        })
+    }
+}
+
+struct CdrReader<'a> {
+    /// Payload after the encapsulation header; alignment is relative to its start
+    data: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+}
+
+impl<'a> CdrReader<'a> {
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.pos)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Box<dyn Error + Send + Sync>> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or("Unexpected end of CDR payload")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Reads `N` bytes after aligning to `N`.
+    fn read<const N: usize>(&mut self) -> Result<[u8; N], Box<dyn Error + Send + Sync>> {
+        self.pos = self.pos.next_multiple_of(N);
+        Ok(self.take(N)?.try_into()?)
+    }
+
+    fn read_num<const N: usize, T>(
+        &mut self,
+        from_le: fn([u8; N]) -> T,
+        from_be: fn([u8; N]) -> T,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let bytes = self.read::<N>()?;
+        Ok(if self.little_endian {
+            from_le(bytes)
+        } else {
+            from_be(bytes)
+        })
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        self.read_num(u32::from_le_bytes, u32::from_be_bytes)
+    }
+}
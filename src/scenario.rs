@@ -0,0 +1,225 @@
+//! `zspy run`: run several publishers described in a YAML scenario file at once, e.g. to
+//! simulate a whole robot's telemetry.
+//!
+//! ```yaml
+//! publishers:
+//!   - key: robot/imu
+//!     type: zspy.ImuMessage
+//!     value: '{"angular_velocity": {"x": {{rand(-1,1)}}, "y": 0, "z": 0}}'
+//!     rate: 50
+//!   - key: robot/status
+//!     value: {state: ready, seq: "{{seq}}"}
+//!     rate: 1
+//!     count: 10
+//!     start: 2s
+//! ```
+//!
+//! All publishers share one timer loop, each keeping its own schedule relative to its start.
+
+use crate::codec::PayloadEncoding;
+use crate::error::ZspyError;
+use crate::message_registry::MessageRegistry;
+use crate::metrics;
+use crate::publish::{self, Progress, PubArgs};
+use crate::stats::format_summary;
+use crate::template::Template;
+use clap::Args;
+use colored::Colorize;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use zenoh::{pubsub::Publisher, Session};
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Scenario file (YAML) listing the publishers to run
+    pub scenario: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Scenario {
+    publishers: Vec<PublisherSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublisherSpec {
+    key: String,
+    /// Protobuf type of the value
+    r#type: Option<String>,
+    /// Schemaless encoding of the value
+    encoding: Option<PayloadEncoding>,
+    /// Message template: a string, or a YAML value published as JSON
+    value: serde_yaml::Value,
+    /// Messages per second
+    #[serde(default = "default_rate")]
+    rate: f64,
+    /// Number of messages to publish (default: until interrupted)
+    count: Option<u64>,
+    /// Delay before the first message, e.g. `2s`
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    start: Duration,
+}
+
+fn default_rate() -> f64 {
+    1.0
+}
+
+/// Scenario durations are written like `--duration` flags, e.g. `500ms` or `2s`.
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    humantime::parse_duration(&text).map_err(serde::de::Error::custom)
+}
+
+/// A running publisher and its schedule.
+struct Stream<'a> {
+    args: PubArgs,
+    template: Template,
+    publisher: Publisher<'a>,
+    start: Instant,
+    period: Duration,
+    count: Option<u64>,
+    sent: u64,
+}
+
+impl Stream<'_> {
+    /// When the next message is due, or `None` once the stream is done. A schedule that runs
+    /// past what an `Instant` can hold ends the stream too.
+    fn next_due(&self) -> Option<Instant> {
+        if self.count.is_some_and(|count| self.sent >= count) {
+            return None;
+        }
+        Duration::try_from_secs_f64(self.period.as_secs_f64() * self.sent as f64)
+            .ok()
+            .and_then(|offset| self.start.checked_add(offset))
+    }
+}
+
+async fn open_stream<'a>(
+    session: &'a Session,
+    registry: &MessageRegistry,
+    spec: PublisherSpec,
+    start: Instant,
+) -> Result<Stream<'a>, Box<dyn Error + Send + Sync>> {
+    // Rates so small the period overflows are as invalid as zero or negative ones
+    let period = match Duration::try_from_secs_f64(1.0 / spec.rate) {
+        Ok(period) if spec.rate > 0.0 && spec.rate.is_finite() => period,
+        _ => {
+            return Err(format!(
+                "Publisher {}: rate must be a positive number of messages per second",
+                spec.key
+            )
+            .into())
+        }
+    };
+    if let Some(type_name) = &spec.r#type {
+        registry.check_type(type_name)?;
+    }
+    let context = |e: Box<dyn Error + Send + Sync>| {
+        ZspyError::Encode(format!("Publisher {}: {}", spec.key, e))
+    };
+    let value = match &spec.value {
+        serde_yaml::Value::String(text) => text.clone(),
+        other => serde_json::to_string(other).map_err(|e| context(e.into()))?,
+    };
+    let args = PubArgs {
+        key: spec.key.clone(),
+        value: Some(value),
+        r#type: spec.r#type.clone(),
+        encoding: spec.encoding,
+        ..PubArgs::default()
+    };
+    let template =
+        Template::parse(args.value.as_deref().unwrap_or_default(), &[]).map_err(context)?;
+    // Encode once up front so a bad value is reported before anything is published
+    publish::encode(registry, &args, &template.render(0, &HashMap::new())).map_err(context)?;
+
+    let mut publisher = session.declare_publisher(spec.key.clone());
+    if let Some(encoding) = spec.encoding {
+        publisher = publisher.encoding(encoding.zenoh_encoding());
+    }
+    Ok(Stream {
+        args,
+        template,
+        publisher: publisher.await?,
+        start: start + spec.start,
+        period,
+        count: spec.count,
+        sent: 0,
+    })
+}
+
+pub async fn run(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &RunArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let text = tokio::fs::read_to_string(&args.scenario).await?;
+    let scenario: Scenario = serde_yaml::from_str(&text)
+        .map_err(|e| format!("Invalid scenario {}: {}", args.scenario.display(), e))?;
+    if scenario.publishers.is_empty() {
+        return Err(format!("Scenario {} has no publishers", args.scenario.display()).into());
+    }
+
+    let start = Instant::now();
+    let mut streams = Vec::new();
+    for spec in scenario.publishers {
+        let count = spec
+            .count
+            .map_or("continuously".to_string(), |n| format!("{} messages", n));
+        let delay = (!spec.start.is_zero())
+            .then(|| format!(" after {}", humantime::format_duration(spec.start)));
+        let summary = format!(
+            "Publishing {} to {} at {} Hz{}",
+            count,
+            spec.key.cyan(),
+            spec.rate,
+            delay.unwrap_or_default()
+        );
+        streams.push(open_stream(session, registry, spec, start).await?);
+        println!("{}", summary);
+    }
+
+    let progress = Progress::new("Published");
+    let mut total = 0;
+    let result = tokio::select! {
+        result = async {
+            // Publish whichever stream is due next until all of them are done
+            while let Some((due, stream)) = streams
+                .iter_mut()
+                .filter_map(|s| Some((s.next_due()?, s)))
+                .min_by_key(|(due, _)| *due)
+            {
+                time::sleep_until(due).await;
+                let payload = publish::encode(
+                    registry,
+                    &stream.args,
+                    &stream.template.render(stream.sent, &HashMap::new()),
+                )?;
+                let len = payload.len();
+                stream.publisher.put(payload).await?;
+                metrics::published(&stream.args.key, len);
+                stream.sent += 1;
+                total += 1;
+                progress.update(total);
+            }
+            Ok::<_, Box<dyn Error + Send + Sync>>(())
+        } => result,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+    progress.finish();
+
+    let elapsed = start.elapsed();
+    println!(
+        "{}",
+        format!("Published {}", format_summary(total, elapsed)).green()
+    );
+    for stream in &streams {
+        println!("  {}: {} messages", stream.args.key.cyan(), stream.sent);
+    }
+    result
+}
@@ -0,0 +1,641 @@
+//! Renders protobuf message descriptors for `zspy types show` and `zspy types export`: as
+//! `.proto`-style text, as a `FileDescriptorSet`, or as JSON Schema. Also compares two
+//! versions of a schema for `zspy types diff`.
+
+use prost_reflect::prost::Message;
+use prost_reflect::prost_types::FileDescriptorSet;
+use prost_reflect::{
+    Cardinality, DescriptorPool, EnumDescriptor, FieldDescriptor, FileDescriptor, Kind,
+    MessageDescriptor, Syntax,
+};
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Formats `descriptor` followed by every message and enum it references that isn't nested
+/// inside it, so the output is self-contained.
+pub fn format_message(descriptor: &MessageDescriptor) -> String {
+    let mut out = String::new();
+    write_message(&mut out, descriptor, descriptor.full_name(), 0);
+
+    let mut seen = HashSet::from([descriptor.full_name().to_string()]);
+    let mut pending = referenced_types(descriptor);
+    while let Some(kind) = pending.pop() {
+        match kind {
+            Kind::Message(message) if seen.insert(message.full_name().to_string()) => {
+                out.push('\n');
+                write_message(&mut out, &message, message.full_name(), 0);
+                pending.extend(referenced_types(&message));
+            }
+            Kind::Enum(enum_type) if seen.insert(enum_type.full_name().to_string()) => {
+                out.push('\n');
+                write_enum(&mut out, &enum_type, enum_type.full_name(), 0);
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Finds fields whose name contains `pattern` (ignoring case) anywhere inside `descriptor`,
+/// returning their field paths and types. Paths use the camelCase JSON names with
+/// `json_names`, and the proto field names otherwise. Recursive types are not descended into
+/// again.
+pub fn find_fields(
+    descriptor: &MessageDescriptor,
+    pattern: &str,
+    json_names: bool,
+) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+    let mut seen = HashSet::new();
+    find_fields_at(
+        descriptor,
+        "",
+        &pattern.to_lowercase(),
+        json_names,
+        &mut seen,
+        &mut found,
+    );
+    found
+}
+
+fn find_fields_at(
+    descriptor: &MessageDescriptor,
+    path: &str,
+    pattern: &str,
+    json_names: bool,
+    seen: &mut HashSet<String>,
+    found: &mut Vec<(String, String)>,
+) {
+    if !seen.insert(descriptor.full_name().to_string()) {
+        return;
+    }
+    for field in descriptor.fields() {
+        let name = if json_names {
+            field.json_name()
+        } else {
+            field.name()
+        };
+        let field_path = if path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", path, name)
+        };
+        if field.name().to_lowercase().contains(pattern) {
+            found.push((field_path.clone(), field_type(&field)));
+        }
+        if let Kind::Message(message) = field.kind() {
+            if !field.is_map() {
+                find_fields_at(&message, &field_path, pattern, json_names, seen, found);
+            }
+        }
+    }
+    seen.remove(descriptor.full_name());
+}
+
+/// Message and enum types used by the fields of `descriptor` that are defined outside it.
+fn referenced_types(descriptor: &MessageDescriptor) -> Vec<Kind> {
+    let mut types = Vec::new();
+    collect_referenced(descriptor, descriptor.full_name(), &mut types);
+    types.reverse();
+    types
+}
+
+fn collect_referenced(descriptor: &MessageDescriptor, root: &str, types: &mut Vec<Kind>) {
+    for field in descriptor.fields() {
+        let kinds = match field.kind() {
+            Kind::Message(entry) if field.is_map() => vec![
+                entry.map_entry_key_field().kind(),
+                entry.map_entry_value_field().kind(),
+            ],
+            kind => vec![kind],
+        };
+        for kind in kinds {
+            let full_name = match &kind {
+                Kind::Message(message) => message.full_name(),
+                Kind::Enum(enum_type) => enum_type.full_name(),
+                _ => continue,
+            };
+            if !full_name.starts_with(&format!("{}.", root)) {
+                types.push(kind.clone());
+            }
+        }
+    }
+    for child in descriptor.child_messages().filter(|m| !m.is_map_entry()) {
+        collect_referenced(&child, root, types);
+    }
+}
+
+fn type_name(kind: &Kind) -> String {
+    match kind {
+        Kind::Double => "double".to_string(),
+        Kind::Float => "float".to_string(),
+        Kind::Int32 => "int32".to_string(),
+        Kind::Int64 => "int64".to_string(),
+        Kind::Uint32 => "uint32".to_string(),
+        Kind::Uint64 => "uint64".to_string(),
+        Kind::Sint32 => "sint32".to_string(),
+        Kind::Sint64 => "sint64".to_string(),
+        Kind::Fixed32 => "fixed32".to_string(),
+        Kind::Fixed64 => "fixed64".to_string(),
+        Kind::Sfixed32 => "sfixed32".to_string(),
+        Kind::Sfixed64 => "sfixed64".to_string(),
+        Kind::Bool => "bool".to_string(),
+        Kind::String => "string".to_string(),
+        Kind::Bytes => "bytes".to_string(),
+        Kind::Message(message) => message.full_name().to_string(),
+        Kind::Enum(enum_type) => enum_type.full_name().to_string(),
+    }
+}
+
+fn field_type(field: &FieldDescriptor) -> String {
+    match field.kind() {
+        Kind::Message(entry) if field.is_map() => format!(
+            "map<{}, {}>",
+            type_name(&entry.map_entry_key_field().kind()),
+            type_name(&entry.map_entry_value_field().kind())
+        ),
+        kind => type_name(&kind),
+    }
+}
+
+fn label(field: &FieldDescriptor, syntax: Syntax) -> &'static str {
+    if field.is_map() {
+        return "";
+    }
+    match (field.cardinality(), syntax) {
+        (Cardinality::Repeated, _) => "repeated ",
+        (Cardinality::Required, _) => "required ",
+        (Cardinality::Optional, Syntax::Proto2) => "optional ",
+        (Cardinality::Optional, Syntax::Proto3)
+            if field.field_descriptor_proto().proto3_optional() =>
+        {
+            "optional "
+        }
+        _ => "",
+    }
+}
+
+fn write_field(out: &mut String, field: &FieldDescriptor, syntax: Syntax, indent: usize) {
+    let _ = writeln!(
+        out,
+        "{:indent$}{}{} {} = {};",
+        "",
+        label(field, syntax),
+        field_type(field),
+        field.name(),
+        field.number(),
+        indent = indent
+    );
+}
+
+fn write_message(out: &mut String, descriptor: &MessageDescriptor, name: &str, indent: usize) {
+    let syntax = descriptor.parent_file().syntax();
+    let _ = writeln!(out, "{:indent$}message {} {{", "", name, indent = indent);
+    let inner = indent + 2;
+
+    for child in descriptor.child_enums() {
+        write_enum(out, &child, child.name(), inner);
+    }
+    for child in descriptor.child_messages().filter(|m| !m.is_map_entry()) {
+        write_message(out, &child, child.name(), inner);
+    }
+
+    // Fields of real oneofs are printed inside their oneof block, in declaration order
+    let mut printed_oneofs = HashSet::new();
+    for field in descriptor.fields() {
+        match field.containing_oneof() {
+            Some(oneof) if !field.field_descriptor_proto().proto3_optional() => {
+                if !printed_oneofs.insert(oneof.name().to_string()) {
+                    continue;
+                }
+                let _ = writeln!(out, "{:inner$}oneof {} {{", "", oneof.name(), inner = inner);
+                for member in oneof.fields() {
+                    write_field(out, &member, syntax, inner + 2);
+                }
+                let _ = writeln!(out, "{:inner$}}}", "", inner = inner);
+            }
+            _ => write_field(out, &field, syntax, inner),
+        }
+    }
+    let _ = writeln!(out, "{:indent$}}}", "", indent = indent);
+}
+
+fn write_enum(out: &mut String, descriptor: &EnumDescriptor, name: &str, indent: usize) {
+    let _ = writeln!(out, "{:indent$}enum {} {{", "", name, indent = indent);
+    for value in descriptor.values() {
+        let _ = writeln!(
+            out,
+            "{:inner$}{} = {};",
+            "",
+            value.name(),
+            value.number(),
+            inner = indent + 2
+        );
+    }
+    let _ = writeln!(out, "{:indent$}}}", "", indent = indent);
+}
+
+/// The files defining `descriptors` and everything they import, dependencies first.
+pub fn files(descriptors: &[MessageDescriptor]) -> Vec<FileDescriptor> {
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    for descriptor in descriptors {
+        add_file(descriptor.parent_file(), &mut seen, &mut files);
+    }
+    files
+}
+
+fn add_file(file: FileDescriptor, seen: &mut HashSet<String>, files: &mut Vec<FileDescriptor>) {
+    if !seen.insert(file.name().to_string()) {
+        return;
+    }
+    for dependency in file.dependencies() {
+        add_file(dependency, seen, files);
+    }
+    files.push(file);
+}
+
+/// Encodes `files` as a binary `FileDescriptorSet`, as written by `protoc --descriptor_set_out`.
+pub fn descriptor_set(files: &[FileDescriptor]) -> Vec<u8> {
+    FileDescriptorSet {
+        file: files
+            .iter()
+            .map(|file| file.file_descriptor_proto().clone())
+            .collect(),
+    }
+    .encode_to_vec()
+}
+
+/// Reconstructs the `.proto` source of `file` from its descriptor. Options and comments are
+/// not preserved.
+pub fn format_file(file: &FileDescriptor) -> String {
+    let mut out = String::new();
+    let syntax = match file.syntax() {
+        Syntax::Proto2 => "proto2",
+        Syntax::Proto3 => "proto3",
+    };
+    let _ = writeln!(out, "syntax = \"{}\";", syntax);
+    if !file.package_name().is_empty() {
+        let _ = writeln!(out, "\npackage {};", file.package_name());
+    }
+    let dependencies: Vec<FileDescriptor> = file.dependencies().collect();
+    if !dependencies.is_empty() {
+        out.push('\n');
+        for dependency in dependencies {
+            let _ = writeln!(out, "import \"{}\";", dependency.name());
+        }
+    }
+    for enum_type in file.enums() {
+        out.push('\n');
+        write_enum(&mut out, &enum_type, enum_type.name(), 0);
+    }
+    for message in file.messages() {
+        out.push('\n');
+        write_message(&mut out, &message, message.name(), 0);
+    }
+    out
+}
+
+/// Builds a JSON Schema (draft 2020-12) describing the JSON form of `descriptor`, with the
+/// messages it references under `$defs`.
+///
+/// With `builtin`, the schema describes the serde derives of the types compiled into zspy, used
+/// with `--json-style serde`: proto field names, every field without presence required and
+/// bytes as arrays of numbers. Otherwise it follows the protobuf JSON mapping: camelCase names,
+/// optional fields, enums by name, and 64-bit integers and bytes as strings.
+pub fn json_schema(descriptor: &MessageDescriptor, builtin: bool) -> Value {
+    let mut builder = JsonSchemaBuilder {
+        root: descriptor.full_name().to_string(),
+        builtin,
+        pending: Vec::new(),
+    };
+    let mut schema = builder.message(descriptor);
+    let mut defs = Map::new();
+    while let Some(message) = builder.pending.pop() {
+        if message.full_name() == builder.root || defs.contains_key(message.full_name()) {
+            continue;
+        }
+        let message_schema = builder.message(&message);
+        defs.insert(message.full_name().to_string(), message_schema);
+    }
+
+    schema["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+    schema["title"] = json!(descriptor.full_name());
+    if !defs.is_empty() {
+        schema["$defs"] = Value::Object(defs);
+    }
+    schema
+}
+
+struct JsonSchemaBuilder {
+    root: String,
+    builtin: bool,
+    /// Referenced messages that still need a `$defs` entry
+    pending: Vec<MessageDescriptor>,
+}
+
+impl JsonSchemaBuilder {
+    fn message(&mut self, descriptor: &MessageDescriptor) -> Value {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for field in descriptor.fields() {
+            let mut schema = match field.kind() {
+                Kind::Message(entry) if field.is_map() => json!({
+                    "type": "object",
+                    "additionalProperties": self.kind(&entry.map_entry_value_field().kind()),
+                }),
+                kind if field.is_list() => json!({ "type": "array", "items": self.kind(&kind) }),
+                kind => self.kind(&kind),
+            };
+            if self.builtin {
+                if field.supports_presence() {
+                    // Option fields, which serialize as null when unset
+                    schema = json!({ "anyOf": [schema, { "type": "null" }] });
+                } else {
+                    required.push(field.name().to_string());
+                }
+            }
+            let name = if self.builtin {
+                field.name()
+            } else {
+                field.json_name()
+            };
+            properties.insert(name.to_string(), schema);
+        }
+
+        let mut schema = json!({ "type": "object", "properties": properties });
+        if !required.is_empty() {
+            schema["required"] = json!(required);
+        }
+        schema
+    }
+
+    fn kind(&mut self, kind: &Kind) -> Value {
+        match kind {
+            Kind::Double | Kind::Float => json!({ "type": "number" }),
+            Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 | Kind::Uint32 | Kind::Fixed32 => {
+                json!({ "type": "integer" })
+            }
+            Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 | Kind::Uint64 | Kind::Fixed64 => {
+                if self.builtin {
+                    json!({ "type": "integer" })
+                } else {
+                    json!({ "type": ["integer", "string"] })
+                }
+            }
+            Kind::Bool => json!({ "type": "boolean" }),
+            Kind::String => json!({ "type": "string" }),
+            Kind::Bytes if self.builtin => json!({
+                "type": "array",
+                "items": { "type": "integer", "minimum": 0, "maximum": 255 },
+            }),
+            Kind::Bytes => json!({ "type": "string", "contentEncoding": "base64" }),
+            Kind::Enum(_) if self.builtin => json!({ "type": "integer" }),
+            Kind::Enum(enum_type) => {
+                let names: Vec<String> = enum_type.values().map(|v| v.name().to_string()).collect();
+                json!({ "anyOf": [{ "enum": names }, { "type": "integer" }] })
+            }
+            Kind::Message(message) => self.message_ref(message),
+        }
+    }
+
+    fn message_ref(&mut self, message: &MessageDescriptor) -> Value {
+        // Well-known types have their own JSON forms in the protobuf JSON mapping
+        if !self.builtin {
+            match message.full_name() {
+                "google.protobuf.Timestamp"
+                | "google.protobuf.Duration"
+                | "google.protobuf.FieldMask" => return json!({ "type": "string" }),
+                "google.protobuf.Struct" | "google.protobuf.Any" | "google.protobuf.Empty" => {
+                    return json!({ "type": "object" })
+                }
+                "google.protobuf.ListValue" => return json!({ "type": "array" }),
+                "google.protobuf.Value" => return json!({}),
+                name if name.starts_with("google.protobuf.") && name.ends_with("Value") => {
+                    if let Some(value) = message.get_field_by_name("value") {
+                        return self.kind(&value.kind());
+                    }
+                }
+                _ => {}
+            }
+        }
+        if message.full_name() == self.root {
+            return json!({ "$ref": "#" });
+        }
+        self.pending.push(message.clone());
+        json!({ "$ref": format!("#/$defs/{}", message.full_name()) })
+    }
+}
+
+/// A difference between two versions of a schema, found by [`diff`].
+pub struct SchemaChange {
+    /// Full name of the message or enum that changed
+    pub type_name: String,
+    pub description: String,
+    /// Whether data written with one version may be misread with the other, on the wire or in
+    /// the JSON and text formats, which identify fields and enum values by name
+    pub breaking: bool,
+}
+
+impl SchemaChange {
+    fn new(type_name: &str, description: String, breaking: bool) -> Self {
+        Self {
+            type_name: type_name.to_string(),
+            description,
+            breaking,
+        }
+    }
+}
+
+/// Compares every message and enum of `old` with its namesake in `new`, reporting types,
+/// fields and enum values that were added, removed, renumbered, renamed or retyped. Removing a
+/// field or enum value isn't breaking when `new` reserves its number.
+pub fn diff(old: &DescriptorPool, new: &DescriptorPool) -> Vec<SchemaChange> {
+    let mut changes = Vec::new();
+    for old_message in old.all_messages().filter(|m| !m.is_map_entry()) {
+        let name = old_message.full_name();
+        match new.get_message_by_name(name) {
+            Some(new_message) => diff_message(&old_message, &new_message, &mut changes),
+            None => changes.push(SchemaChange::new(name, "message removed".to_string(), true)),
+        }
+    }
+    for new_message in new.all_messages().filter(|m| !m.is_map_entry()) {
+        if old.get_message_by_name(new_message.full_name()).is_none() {
+            changes.push(SchemaChange::new(
+                new_message.full_name(),
+                "message added".to_string(),
+                false,
+            ));
+        }
+    }
+    for old_enum in old.all_enums() {
+        let name = old_enum.full_name();
+        match new.get_enum_by_name(name) {
+            Some(new_enum) => diff_enum(&old_enum, &new_enum, &mut changes),
+            None => changes.push(SchemaChange::new(name, "enum removed".to_string(), true)),
+        }
+    }
+    for new_enum in new.all_enums() {
+        if old.get_enum_by_name(new_enum.full_name()).is_none() {
+            changes.push(SchemaChange::new(
+                new_enum.full_name(),
+                "enum added".to_string(),
+                false,
+            ));
+        }
+    }
+    changes
+}
+
+fn diff_message(old: &MessageDescriptor, new: &MessageDescriptor, changes: &mut Vec<SchemaChange>) {
+    let name = old.full_name();
+    for old_field in old.fields() {
+        let number = old_field.number();
+        let new_field = match (
+            new.get_field_by_name(old_field.name()),
+            new.get_field(number),
+        ) {
+            (Some(renumbered), _) if renumbered.number() != number => {
+                changes.push(SchemaChange::new(
+                    name,
+                    format!(
+                        "field {} renumbered from {} to {}",
+                        old_field.name(),
+                        number,
+                        renumbered.number()
+                    ),
+                    true,
+                ));
+                continue;
+            }
+            (Some(new_field), _) | (None, Some(new_field)) => new_field,
+            (None, None) => {
+                let reserved = new.reserved_ranges().any(|range| range.contains(&number));
+                let description = if reserved {
+                    format!(
+                        "field {} = {} removed and reserved",
+                        old_field.name(),
+                        number
+                    )
+                } else {
+                    format!("field {} = {} removed", old_field.name(), number)
+                };
+                changes.push(SchemaChange::new(name, description, !reserved));
+                continue;
+            }
+        };
+        if new_field.name() != old_field.name() {
+            changes.push(SchemaChange::new(
+                name,
+                format!(
+                    "field {} = {} renamed to {}",
+                    old_field.name(),
+                    number,
+                    new_field.name()
+                ),
+                true,
+            ));
+        }
+        let (old_type, new_type) = (field_type(&old_field), field_type(&new_field));
+        if old_type != new_type {
+            changes.push(SchemaChange::new(
+                name,
+                format!(
+                    "field {} = {} changed type from {} to {}",
+                    new_field.name(),
+                    number,
+                    old_type,
+                    new_type
+                ),
+                true,
+            ));
+        } else if old_field.is_list() != new_field.is_list() {
+            let change = if new_field.is_list() {
+                "became repeated"
+            } else {
+                "is no longer repeated"
+            };
+            changes.push(SchemaChange::new(
+                name,
+                format!("field {} = {} {}", new_field.name(), number, change),
+                true,
+            ));
+        }
+    }
+    for new_field in new.fields() {
+        if old.get_field(new_field.number()).is_none()
+            && old.get_field_by_name(new_field.name()).is_none()
+        {
+            changes.push(SchemaChange::new(
+                name,
+                format!(
+                    "field {} {} = {} added",
+                    field_type(&new_field),
+                    new_field.name(),
+                    new_field.number()
+                ),
+                false,
+            ));
+        }
+    }
+}
+
+fn diff_enum(old: &EnumDescriptor, new: &EnumDescriptor, changes: &mut Vec<SchemaChange>) {
+    let name = old.full_name();
+    for old_value in old.values() {
+        let number = old_value.number();
+        match (
+            new.get_value_by_name(old_value.name()),
+            new.get_value(number),
+        ) {
+            (Some(renumbered), _) if renumbered.number() != number => {
+                changes.push(SchemaChange::new(
+                    name,
+                    format!(
+                        "value {} renumbered from {} to {}",
+                        old_value.name(),
+                        number,
+                        renumbered.number()
+                    ),
+                    true,
+                ));
+            }
+            (Some(_), _) => {}
+            (None, Some(new_value)) => changes.push(SchemaChange::new(
+                name,
+                format!(
+                    "value {} = {} renamed to {}",
+                    old_value.name(),
+                    number,
+                    new_value.name()
+                ),
+                true,
+            )),
+            (None, None) => {
+                let reserved = new.reserved_ranges().any(|range| range.contains(&number));
+                let description = if reserved {
+                    format!(
+                        "value {} = {} removed and reserved",
+                        old_value.name(),
+                        number
+                    )
+                } else {
+                    format!("value {} = {} removed", old_value.name(), number)
+                };
+                changes.push(SchemaChange::new(name, description, !reserved));
+            }
+        }
+    }
+    for new_value in new.values() {
+        if old.get_value(new_value.number()).is_none()
+            && old.get_value_by_name(new_value.name()).is_none()
+        {
+            changes.push(SchemaChange::new(
+                name,
+                format!("value {} = {} added", new_value.name(), new_value.number()),
+                false,
+            ));
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Per-message scripts for `zspy echo --script` and `zspy bridge --script`: a Rhai script that
+//! sees every decoded message and decides what becomes of it.
+//!
+//! The script runs once per message with `key`, `timestamp` (RFC 3339) and the decoded `value`
+//! in scope. The value it ends with replaces the message; ending with `()`, e.g. a bare
+//! `return;`, drops it. `publish(key, value)` publishes a derived message as JSON. `state` is an
+//! object map kept from one message to the next, for counters, previous values and the like;
+//! other variables start afresh with every message. `print` writes to stderr so it doesn't mix
+//! with the output:
+//!
+//! ```rhai
+//! state.seen = (state.seen ?? 0) + 1;
+//! if value.battery < 0.2 {
+//!     publish("alerts/battery", #{ key: key, level: value.battery, seen: state.seen });
+//! }
+//! if value.status == "idle" { return; }
+//! value
+//! ```
+
+use crate::codec::PayloadEncoding;
+use crate::metrics;
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use serde_json::Value;
+use std::error::Error;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use zenoh::Session;
+
+/// A loaded `--script`, with the state it keeps between messages.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Mutex<Scope<'static>>,
+    /// Filled by `publish` while the script runs
+    publications: Arc<Mutex<Vec<(String, Dynamic)>>>,
+}
+
+/// What a script made of one message.
+#[derive(Debug, Default, PartialEq)]
+pub struct ScriptOutput {
+    /// Messages to show or forward in place of the input, in order
+    pub messages: Vec<Value>,
+    /// Derived publications: key and JSON value
+    pub publications: Vec<(String, Value)>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Can't read script {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("Invalid script {}: {}", path.display(), e).into())
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let publications = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = Engine::new();
+        engine.on_print(|text| eprintln!("{}", text));
+        let queue = Arc::clone(&publications);
+        engine.register_fn("publish", move |key: &str, value: Dynamic| {
+            queue.lock().unwrap().push((key.to_string(), value));
+        });
+        let ast = engine.compile(text)?;
+        let mut scope = Scope::new();
+        scope.push("state", Map::new());
+        Ok(Self {
+            engine,
+            ast,
+            scope: Mutex::new(scope),
+            publications,
+        })
+    }
+
+    /// Runs the script on the message `value` received on `key` at `timestamp`.
+    pub fn run(
+        &self,
+        key: &str,
+        timestamp: SystemTime,
+        value: Value,
+    ) -> Result<ScriptOutput, String> {
+        let value = rhai::serde::to_dynamic(value).map_err(|e| e.to_string())?;
+        let mut scope = self.scope.lock().unwrap();
+        // Only `state` outlives the message; variables the script declares are dropped after
+        let persistent = scope.len();
+        scope.push("key", key.to_string());
+        scope.push(
+            "timestamp",
+            humantime::format_rfc3339_nanos(timestamp).to_string(),
+        );
+        scope.push("value", value);
+        self.publications.lock().unwrap().clear();
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast);
+        scope.rewind(persistent);
+        let publications = std::mem::take(&mut *self.publications.lock().unwrap());
+        let result = result.map_err(|e| e.to_string())?;
+
+        let to_json =
+            |value: &Dynamic| rhai::serde::from_dynamic::<Value>(value).map_err(|e| e.to_string());
+        let mut output = ScriptOutput::default();
+        if !result.is_unit() {
+            output.messages.push(to_json(&result)?);
+        }
+        for (key, value) in &publications {
+            output.publications.push((key.clone(), to_json(value)?));
+        }
+        Ok(output)
+    }
+}
+
+impl ScriptOutput {
+    /// Publishes the derived publications as JSON.
+    pub async fn publish(&self, session: &Session) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for (key, value) in &self.publications {
+            let payload = value.to_string();
+            let len = payload.len();
+            session
+                .put(key, payload)
+                .encoding(PayloadEncoding::Json.zenoh_encoding())
+                .await?;
+            metrics::published(key, len);
+        }
+        Ok(())
+    }
+}
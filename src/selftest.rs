@@ -0,0 +1,213 @@
+//! `zspy selftest`: publish random messages of a type and subscribe to them from the same
+//! process, checking that each one arrives intact and decodes to what was sent, and measuring
+//! the latency. A one-command smoke test of both the message registry and the Zenoh path.
+
+use crate::error::ZspyError;
+use crate::latency;
+use crate::message_registry::MessageRegistry;
+use crate::random::Generator;
+use crate::stats::format_duration;
+use clap::Args;
+use colored::Colorize;
+use prost::Message;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::time;
+use zenoh::config::Config;
+use zenoh::Session;
+
+#[derive(Args)]
+pub struct SelftestArgs {
+    /// Key to publish and subscribe on, e.g. `zspy/selftest`
+    pub key: String,
+    /// Protobuf type of the test messages
+    #[arg(long, default_value = "zspy.ImuMessage")]
+    pub r#type: String,
+    /// Number of messages to send
+    #[arg(long, default_value = "100", value_parser = clap::value_parser!(u64).range(1..))]
+    pub count: u64,
+    /// Time between messages
+    #[arg(long, default_value = "10ms", value_parser = latency::parse_interval)]
+    pub interval: Duration,
+    /// Subscribe from a second session, so messages go through the transport instead of being
+    /// delivered within the session
+    #[arg(long)]
+    pub two_sessions: bool,
+    /// How long to wait for the subscriber to match and for the last messages to arrive
+    #[arg(long, default_value = "3s", value_parser = humantime::parse_duration)]
+    pub timeout: Duration,
+    /// Seed for the random test messages, for reproducible runs
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+/// A sent message awaiting its echo.
+struct Pending {
+    sent: Instant,
+    payload: Vec<u8>,
+    json: Value,
+}
+
+/// Decodes `payload` as `msg_type` into a JSON value.
+fn decode(
+    registry: &MessageRegistry,
+    msg_type: &str,
+    payload: &[u8],
+) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    Ok(serde_json::from_str(&registry.decode(msg_type, payload)?)?)
+}
+
+pub async fn run(
+    session: &Session,
+    config: &Config,
+    registry: &MessageRegistry,
+    args: &SelftestArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let msg_type = registry.resolve_type(&args.r#type)?;
+    let descriptor = registry.descriptor(&msg_type).ok_or_else(|| {
+        ZspyError::Encode(format!(
+            "{} has no schema to generate messages from",
+            msg_type
+        ))
+    })?;
+    let second = match args.two_sessions {
+        true => Some(zenoh::open(config.clone()).await?),
+        false => None,
+    };
+    println!(
+        "Testing {} on {} ({})",
+        msg_type.cyan(),
+        args.key.cyan(),
+        if second.is_some() {
+            "two sessions"
+        } else {
+            "one session"
+        }
+    );
+
+    let subscriber = second
+        .as_ref()
+        .unwrap_or(session)
+        .declare_subscriber(&args.key)
+        .await?;
+    let publisher = session.declare_publisher(&args.key).await?;
+    let listener = publisher.matching_listener().await?;
+    if !publisher.matching_status().await?.matching() {
+        let matched = async {
+            while let Ok(status) = listener.recv_async().await {
+                if status.matching() {
+                    break;
+                }
+            }
+        };
+        time::timeout(args.timeout, matched).await.map_err(|_| {
+            ZspyError::Timeout(format!("The subscriber never matched {}", args.key))
+        })?;
+    }
+
+    let mut generator = Generator::new(descriptor, &[], args.seed);
+    let mut pending: HashMap<u64, Pending> = HashMap::new();
+    let mut latencies = Vec::new();
+    let (mut codec_failures, mut corrupted, mut seq) = (0u64, 0u64, 0u64);
+    let mut ticker = time::interval(args.interval);
+    let mut deadline = None;
+    loop {
+        tokio::select! {
+            _ = ticker.tick(), if seq < args.count => {
+                let payload = generator.generate().encode_to_vec();
+                // The codec must turn the JSON form back into an equivalent message
+                let json = decode(registry, &msg_type, &payload)?;
+                let reencoded = registry
+                    .encode(&msg_type, &json.to_string())
+                    .and_then(|bytes| decode(registry, &msg_type, &bytes));
+                if reencoded.ok().as_ref() != Some(&json) {
+                    codec_failures += 1;
+                }
+                let sent = Instant::now();
+                pending.insert(seq, Pending { sent, payload: payload.clone(), json });
+                publisher
+                    .put(payload)
+                    .attachment(seq.to_le_bytes().to_vec())
+                    .await?;
+                seq += 1;
+                if seq == args.count {
+                    deadline = Some(Instant::now() + args.timeout);
+                }
+            }
+            sample = subscriber.recv_async() => {
+                let sample = sample?;
+                let received = Instant::now();
+                let Some(seq) = sample
+                    .attachment()
+                    .and_then(|a| a.to_bytes().as_ref().try_into().ok())
+                    .map(u64::from_le_bytes)
+                else {
+                    continue;
+                };
+                let Some(expected) = pending.remove(&seq) else {
+                    continue;
+                };
+                latencies.push(received - expected.sent);
+                let payload = sample.payload().to_bytes();
+                let intact = *payload == expected.payload
+                    && decode(registry, &msg_type, &payload)
+                        .is_ok_and(|json| json == expected.json);
+                if !intact {
+                    corrupted += 1;
+                }
+            }
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => break,
+        }
+        if seq == args.count && pending.is_empty() {
+            break;
+        }
+    }
+    if let Some(second) = second {
+        second.close().await?;
+    }
+
+    let received = latencies.len() as u64;
+    let mark = |ok: bool| if ok { "ok".green() } else { "FAILED".red() };
+    println!(
+        "{:<8} {} {}/{} messages survive a round trip through JSON",
+        "Codec",
+        mark(codec_failures == 0),
+        args.count - codec_failures,
+        args.count
+    );
+    println!(
+        "{:<8} {} {}/{} received, {} corrupted",
+        "Zenoh",
+        mark(received == args.count && corrupted == 0),
+        received,
+        args.count,
+        corrupted
+    );
+    if !latencies.is_empty() {
+        latencies.sort();
+        let avg = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+        println!(
+            "{:<8} min/avg/max/p99 = {}/{}/{}/{}",
+            "Latency",
+            format_duration(latencies[0]),
+            format_duration(avg),
+            format_duration(latencies[latencies.len() - 1]),
+            format_duration(latency::percentile(&latencies, 99.0))
+        );
+    }
+
+    if codec_failures > 0 || corrupted > 0 || received < args.count {
+        return Err(format!(
+            "Self-test failed: {} codec failures, {} of {} messages lost, {} corrupted",
+            codec_failures,
+            args.count - received,
+            args.count,
+            corrupted
+        )
+        .into());
+    }
+    println!("{}", "Self-test passed".green());
+    Ok(())
+}
@@ -0,0 +1,239 @@
+//! Global command-line options controlling how the Zenoh session is configured.
+
+use crate::profile::Profile;
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::time;
+use zenoh::config::{Config, WhatAmI};
+use zenoh::Session;
+
+/// First and longest delay between `--reconnect` attempts; the delay doubles in between.
+const RECONNECT_DELAY: (Duration, Duration) = (Duration::from_millis(500), Duration::from_secs(30));
+
+/// How often `--reconnect` checks whether the session is still connected.
+const CONNECTION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Mode {
+    Client,
+    Peer,
+    Router,
+}
+
+impl Mode {
+    fn as_str(self) -> &'static str {
+        match self {
+            Mode::Client => "client",
+            Mode::Peer => "peer",
+            Mode::Router => "router",
+        }
+    }
+}
+
+impl From<Mode> for WhatAmI {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Client => WhatAmI::Client,
+            Mode::Peer => WhatAmI::Peer,
+            Mode::Router => WhatAmI::Router,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct SessionArgs {
+    /// Zenoh configuration file (JSON5)
+    #[arg(long, global = true, env = "ZENOH_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Endpoint to connect to, e.g. tcp/192.168.1.1:7447 (repeatable; comma-separated in the
+    /// environment variable)
+    #[arg(long, global = true, env = "ZENOH_CONNECT", value_delimiter = ',')]
+    pub connect: Vec<String>,
+    /// Endpoint to listen on, e.g. tcp/0.0.0.0:7447 (repeatable; comma-separated in the
+    /// environment variable)
+    #[arg(long, global = true, env = "ZENOH_LISTEN", value_delimiter = ',')]
+    pub listen: Vec<String>,
+    /// Session mode
+    #[arg(long, value_enum, global = true)]
+    pub mode: Option<Mode>,
+    /// Enable the shared-memory transport, even if the config file disables it (requires a
+    /// build with the `shm` feature)
+    #[arg(long, global = true)]
+    pub shm: bool,
+    /// Keep retrying to connect, at startup and whenever the connection drops (e.g. a router
+    /// restart), with exponential backoff instead of exiting
+    #[arg(long, global = true)]
+    pub reconnect: bool,
+    /// CA certificate (PEM) to verify TLS routers and peers against
+    #[arg(long, global = true, value_name = "FILE")]
+    pub tls_ca: Option<PathBuf>,
+    /// Certificate (PEM) presented over TLS, for routers requiring mutual TLS and for TLS
+    /// listen endpoints
+    #[arg(long, global = true, value_name = "FILE")]
+    pub tls_cert: Option<PathBuf>,
+    /// Private key (PEM) of `--tls-cert`
+    #[arg(long, global = true, value_name = "FILE")]
+    pub tls_key: Option<PathBuf>,
+    /// User to authenticate as to routers with user/password authentication
+    #[arg(long, global = true)]
+    pub user: Option<String>,
+    /// Password of `--user`; prefer the environment variable, which stays out of the shell
+    /// history and process list
+    #[arg(long, global = true, env = "ZENOH_PASSWORD", hide_env_values = true)]
+    pub password: Option<String>,
+}
+
+impl SessionArgs {
+    /// Fills in the options not given on the command line from `profile`.
+    pub fn apply_profile(&mut self, profile: &Profile) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.config.is_none() {
+            self.config = profile.config.clone();
+        }
+        if self.connect.is_empty() {
+            self.connect = profile.connect.clone();
+        }
+        if self.listen.is_empty() {
+            self.listen = profile.listen.clone();
+        }
+        if self.tls_ca.is_none() {
+            self.tls_ca = profile.tls_ca.clone();
+        }
+        // The certificate and its key come together, from the flags or from the profile
+        if self.tls_cert.is_none() && self.tls_key.is_none() {
+            self.tls_cert = profile.tls_cert.clone();
+            self.tls_key = profile.tls_key.clone();
+        }
+        if self.user.is_none() {
+            self.user = profile.user.clone();
+        }
+        if let (None, Some(mode)) = (self.mode, &profile.mode) {
+            self.mode = Some(
+                Mode::from_str(mode, true)
+                    .map_err(|_| format!("Invalid mode '{}' in profile", mode))?,
+            );
+        }
+        Ok(())
+    }
+
+    /// Builds the Zenoh config: the config file (if any) is loaded first, then the individual
+    /// flags override it.
+    pub fn build_config(&self) -> Result<Config, Box<dyn Error + Send + Sync>> {
+        let mut config = match &self.config {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        if let Some(mode) = self.mode {
+            config.insert_json5("mode", &serde_json::to_string(mode.as_str())?)?;
+        }
+        if !self.connect.is_empty() {
+            config.insert_json5("connect/endpoints", &serde_json::to_string(&self.connect)?)?;
+        }
+        if !self.listen.is_empty() {
+            config.insert_json5("listen/endpoints", &serde_json::to_string(&self.listen)?)?;
+        }
+        if self.shm {
+            if !cfg!(feature = "shm") {
+                return Err(
+                    "This zspy was built without shared memory support; rebuild with `--features shm`"
+                        .into(),
+                );
+            }
+            config.insert_json5("transport/shared_memory/enabled", "true")?;
+        }
+        if self.reconnect {
+            // Zenoh redeclares subscribers, publishers and queryables once it reconnects
+            config.insert_json5("connect/timeout_ms", "-1")?;
+            config.insert_json5("connect/exit_on_failure", "false")?;
+            let (initial, max) = RECONNECT_DELAY;
+            let retry = serde_json::json!({
+                "period_init_ms": initial.as_millis() as u64,
+                "period_max_ms": max.as_millis() as u64,
+                "period_increase_factor": 2.0,
+            });
+            config.insert_json5("connect/retry", &retry.to_string())?;
+        }
+        self.apply_tls(&mut config)?;
+        match (&self.user, &self.password) {
+            (Some(user), Some(password)) => {
+                config.insert_json5("transport/auth/usrpwd/user", &serde_json::to_string(user)?)?;
+                config.insert_json5(
+                    "transport/auth/usrpwd/password",
+                    &serde_json::to_string(password)?,
+                )?;
+            }
+            (Some(_), None) => {
+                return Err("--user needs a password: set ZENOH_PASSWORD or --password".into())
+            }
+            (None, Some(_)) => return Err("--password needs --user".into()),
+            (None, None) => {}
+        }
+        Ok(config)
+    }
+
+    /// Points Zenoh's TLS transport at the certificate files, checking they can be read so a
+    /// typo doesn't surface as a failed handshake.
+    fn apply_tls(&self, config: &mut Config) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut set = |keys: &[&str], path: &PathBuf| -> Result<(), Box<dyn Error + Send + Sync>> {
+            std::fs::metadata(path)
+                .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+            let path = serde_json::to_string(&path.to_string_lossy())?;
+            for key in keys {
+                config.insert_json5(&format!("transport/link/tls/{}", key), &path)?;
+            }
+            Ok(())
+        };
+        if let Some(ca) = &self.tls_ca {
+            set(&["root_ca_certificate"], ca)?;
+        }
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => {
+                set(&["connect_certificate", "listen_certificate"], cert)?;
+                set(&["connect_private_key", "listen_private_key"], key)?;
+                config.insert_json5("transport/link/tls/enable_mtls", "true")?;
+            }
+            (Some(_), None) => return Err("--tls-cert needs --tls-key".into()),
+            (None, Some(_)) => return Err("--tls-key needs --tls-cert".into()),
+            (None, None) => {}
+        }
+        Ok(())
+    }
+}
+
+/// Reports on stderr when `session` loses its last router or peer and when it is connected
+/// again, for `--reconnect`. Runs until the session is closed.
+pub async fn watch_connection(session: Session) {
+    let mut lost: Option<Instant> = None;
+    let mut connected_once = false;
+    let mut ticker = time::interval(CONNECTION_CHECK_INTERVAL);
+    while !session.is_closed() {
+        ticker.tick().await;
+        let info = session.info();
+        let connected =
+            info.routers_zid().await.next().is_some() || info.peers_zid().await.next().is_some();
+        match (connected, lost) {
+            (true, Some(since)) => {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Reconnected after {}",
+                        humantime::format_duration(Duration::from_secs(since.elapsed().as_secs()))
+                    )
+                    .green()
+                );
+                lost = None;
+            }
+            (false, None) if connected_once => {
+                eprintln!(
+                    "{}",
+                    "Connection lost; reconnecting with backoff...".yellow()
+                );
+                lost = Some(Instant::now());
+            }
+            _ => {}
+        }
+        connected_once |= connected;
+    }
+}
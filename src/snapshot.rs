@@ -0,0 +1,227 @@
+//! `zspy snapshot`: record which keys are active under a key expression, and optionally their
+//! latest values, then compare a later snapshot against it to see which topics a deployment
+//! change added, removed or changed.
+
+use crate::expr;
+use crate::message_registry::MessageRegistry;
+use crate::terminal;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::Args;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::time;
+use zenoh::{sample::SampleKind, Session};
+
+#[derive(Args)]
+pub struct SnapshotArgs {
+    /// The key expression to snapshot
+    #[arg(default_value = "**")]
+    pub key_expr: String,
+    /// Write the snapshot to this file as JSON
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+    /// Compare the snapshot against one written earlier with `-o`
+    #[arg(long, value_name = "FILE")]
+    pub diff: Option<PathBuf>,
+    /// How long to observe traffic
+    #[arg(long, default_value = "3s", value_parser = humantime::parse_duration)]
+    pub duration: Duration,
+    /// Also record the latest value of each key, and report keys whose value changed
+    #[arg(long)]
+    pub values: bool,
+    /// Protobuf type to decode values as (default: JSON, else text)
+    #[arg(long, requires = "values")]
+    pub r#type: Option<String>,
+    /// Also query the key expression, so keys held by storages and queryables show up without
+    /// traffic
+    #[arg(long)]
+    pub query: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    key_expr: String,
+    /// When the snapshot was taken, in RFC 3339
+    taken: String,
+    #[serde(default)]
+    keys: BTreeMap<String, KeyState>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyState {
+    /// Messages received while observing; 0 for keys only found by `--query`
+    messages: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<Value>,
+}
+
+/// `payload` as JSON: decoded as `msg_type` if given, else parsed as JSON, else as text, and
+/// base64 for binary payloads.
+fn payload_value(
+    registry: &MessageRegistry,
+    msg_type: Option<&str>,
+    payload: &[u8],
+) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    match (msg_type, std::str::from_utf8(payload)) {
+        (None, Err(_)) => Ok(json!({ "base64": BASE64_STANDARD.encode(payload) })),
+        _ => registry.decode_with(msg_type, None, payload),
+    }
+}
+
+async fn take(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &SnapshotArgs,
+) -> Result<Snapshot, Box<dyn Error + Send + Sync>> {
+    let msg_type = args.r#type.as_deref();
+    let mut keys: BTreeMap<String, KeyState> = BTreeMap::new();
+    let record = |keys: &mut BTreeMap<String, KeyState>, key: &str, payload: &[u8], received| {
+        let state = keys.entry(key.to_string()).or_insert(KeyState {
+            messages: 0,
+            value: None,
+        });
+        if received {
+            state.messages += 1;
+        }
+        if args.values {
+            match payload_value(registry, msg_type, payload) {
+                Ok(value) => state.value = Some(value),
+                Err(e) => eprintln!("{}", format!("{} on {}", e, key).yellow()),
+            }
+        }
+    };
+
+    let subscriber = session.declare_subscriber(args.key_expr.as_str()).await?;
+    let _ = time::timeout(args.duration, async {
+        while let Ok(sample) = subscriber.recv_async().await {
+            if sample.kind() == SampleKind::Put {
+                record(
+                    &mut keys,
+                    sample.key_expr().as_str(),
+                    &sample.payload().to_bytes(),
+                    true,
+                );
+            }
+        }
+    })
+    .await;
+    if args.query {
+        let replies = session.get(args.key_expr.as_str()).await?;
+        while let Ok(reply) = replies.recv_async().await {
+            if let Ok(sample) = reply.result() {
+                let key = sample.key_expr().as_str();
+                // What was published while observing is newer than what a storage holds
+                if !keys.contains_key(key) {
+                    record(&mut keys, key, &sample.payload().to_bytes(), false);
+                }
+            }
+        }
+    }
+    Ok(Snapshot {
+        key_expr: args.key_expr.clone(),
+        taken: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+        keys,
+    })
+}
+
+/// Prints the keys added, removed and changed since `before`.
+fn print_diff(before: &Snapshot, after: &Snapshot) {
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for key in before.keys.keys().filter(|k| !after.keys.contains_key(*k)) {
+        println!("{} {}", "-".red(), key.red());
+        removed += 1;
+    }
+    for (key, state) in &after.keys {
+        let Some(old) = before.keys.get(key) else {
+            println!("{} {}", "+".green(), key.green());
+            added += 1;
+            continue;
+        };
+        // Keys only differ in value when both snapshots recorded one
+        let (Some(old), Some(new)) = (&old.value, &state.value) else {
+            continue;
+        };
+        let changes = expr::diff(old, new);
+        if changes.is_empty() {
+            continue;
+        }
+        changed += 1;
+        println!("{} {}", "~".yellow(), key.yellow());
+        for (path, old, new) in changes {
+            let path = if path.is_empty() { "value" } else { &path };
+            println!(
+                "    {}: {} {} {}",
+                path,
+                old,
+                terminal::symbol("→", "->"),
+                new
+            );
+        }
+    }
+    let unchanged = after.keys.len() - added - changed;
+    println!(
+        "\n{} added, {} removed, {} changed, {} unchanged since {}",
+        added, removed, changed, unchanged, before.taken
+    );
+}
+
+pub async fn run(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &SnapshotArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(msg_type) = &args.r#type {
+        registry.check_type(msg_type)?;
+    }
+    // Read the earlier snapshot first, so a bad path fails before observing
+    let before: Option<Snapshot> = match &args.diff {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)?;
+            Some(
+                serde_json::from_str(&text)
+                    .map_err(|e| format!("Invalid snapshot {}: {}", path.display(), e))?,
+            )
+        }
+        None => None,
+    };
+    println!(
+        "Observing {} for {}...",
+        args.key_expr.cyan(),
+        humantime::format_duration(args.duration)
+    );
+    let snapshot = take(session, registry, args).await?;
+
+    match &before {
+        Some(before) => {
+            if before.key_expr != snapshot.key_expr {
+                println!(
+                    "{}",
+                    format!(
+                        "Warning: {} was taken of {}, not {}",
+                        args.diff.as_ref().expect("diffing").display(),
+                        before.key_expr,
+                        snapshot.key_expr
+                    )
+                    .yellow()
+                );
+            }
+            print_diff(before, &snapshot);
+        }
+        None => {
+            for (key, state) in &snapshot.keys {
+                println!("  {} ({} messages)", key.cyan(), state.messages);
+            }
+            println!("{} keys", snapshot.keys.len());
+        }
+    }
+    if let Some(path) = &args.output {
+        std::fs::write(path, serde_json::to_string_pretty(&snapshot)? + "\n")?;
+        println!("Wrote snapshot to {}", path.display());
+    }
+    Ok(())
+}
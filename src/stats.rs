@@ -0,0 +1,247 @@
+//! Rolling per-key traffic statistics shared by `hz` and `monitor`, optionally combined into
+//! groups of keys matching a key expression.
+
+use crate::terminal;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::time::{Duration, Instant};
+use zenoh::key_expr::KeyExpr;
+
+/// Parses a `--window` duration, which must be more than zero for rates to mean anything.
+pub fn parse_window(s: &str) -> Result<Duration, String> {
+    match humantime::parse_duration(s) {
+        Ok(window) if window.is_zero() => Err("the window must be more than 0s".to_string()),
+        Ok(window) => Ok(window),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Statistics over the samples received on one key within a sliding window.
+#[derive(Debug)]
+pub struct KeyStats {
+    window: Duration,
+    arrivals: VecDeque<(Instant, usize)>,
+    pub total_count: u64,
+    pub total_bytes: u64,
+}
+
+/// Summary of the inter-arrival times currently in the window.
+#[derive(Debug, Clone, Copy)]
+pub struct Intervals {
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    /// Standard deviation of the inter-arrival time
+    pub jitter: Duration,
+}
+
+impl KeyStats {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            arrivals: VecDeque::new(),
+            total_count: 0,
+            total_bytes: 0,
+        }
+    }
+
+    pub fn record(&mut self, now: Instant, bytes: usize) {
+        self.arrivals.push_back((now, bytes));
+        self.total_count += 1;
+        self.total_bytes += bytes as u64;
+        self.prune(now);
+    }
+
+    /// Drops arrivals that fell out of the window.
+    pub fn prune(&mut self, now: Instant) {
+        while let Some((t, _)) = self.arrivals.front() {
+            if now.duration_since(*t) > self.window {
+                self.arrivals.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Number of samples currently in the window.
+    pub fn window_count(&self) -> usize {
+        self.arrivals.len()
+    }
+
+    /// Message rate in Hz, derived from the mean inter-arrival time.
+    pub fn rate(&self) -> f64 {
+        match self.intervals() {
+            Some(intervals) if !intervals.mean.is_zero() => 1.0 / intervals.mean.as_secs_f64(),
+            _ => 0.0,
+        }
+    }
+
+    /// Bandwidth in bytes per second over the window.
+    pub fn bandwidth(&self) -> f64 {
+        let bytes: usize = self.arrivals.iter().map(|(_, n)| n).sum();
+        bytes as f64 / self.window.as_secs_f64()
+    }
+
+    pub fn intervals(&self) -> Option<Intervals> {
+        if self.arrivals.len() < 2 {
+            return None;
+        }
+        let deltas: Vec<f64> = self
+            .arrivals
+            .iter()
+            .zip(self.arrivals.iter().skip(1))
+            .map(|((a, _), (b, _))| b.duration_since(*a).as_secs_f64())
+            .collect();
+        let mean = deltas.iter().sum::<f64>() / deltas.len() as f64;
+        let variance = deltas.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / deltas.len() as f64;
+        let min = deltas.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = deltas.iter().cloned().fold(0.0, f64::max);
+        Some(Intervals {
+            min: Duration::from_secs_f64(min),
+            max: Duration::from_secs_f64(max),
+            mean: Duration::from_secs_f64(mean),
+            jitter: Duration::from_secs_f64(variance.sqrt()),
+        })
+    }
+}
+
+/// Combined [`KeyStats`] of every key matching a key expression, for `--group`.
+#[derive(Debug)]
+pub struct GroupStats {
+    pub pattern: KeyExpr<'static>,
+    pub stats: KeyStats,
+    /// Number of distinct keys seen so far that match the pattern
+    pub keys: usize,
+}
+
+impl GroupStats {
+    pub fn new(pattern: &str, window: Duration) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(Self {
+            pattern: KeyExpr::try_from(pattern.to_string())?,
+            stats: KeyStats::new(window),
+            keys: 0,
+        })
+    }
+
+    pub fn matches(&self, key: &str) -> bool {
+        KeyExpr::try_from(key).is_ok_and(|key| self.pattern.intersects(&key))
+    }
+
+    /// Counts a sample on `key` if it matches the pattern; `new_key` tells whether it is the
+    /// first sample seen on `key`.
+    pub fn record(&mut self, key: &str, new_key: bool, now: Instant, bytes: usize) {
+        if !self.matches(key) {
+            return;
+        }
+        if new_key {
+            self.keys += 1;
+        }
+        self.stats.record(now, bytes);
+    }
+}
+
+/// Tracks [`KeyStats`] for every concrete key seen, and [`GroupStats`] for each group added.
+#[derive(Debug)]
+pub struct StatsAggregator {
+    window: Duration,
+    keys: BTreeMap<String, KeyStats>,
+    groups: Vec<GroupStats>,
+}
+
+impl StatsAggregator {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            keys: BTreeMap::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    /// Also combines the statistics of the keys matching `pattern`. A key may belong to
+    /// several groups.
+    pub fn add_group(&mut self, pattern: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.groups.push(GroupStats::new(pattern, self.window)?);
+        Ok(())
+    }
+
+    pub fn record(&mut self, key: &str, now: Instant, bytes: usize) {
+        let new_key = match self.keys.entry(key.to_string()) {
+            Entry::Occupied(mut entry) => {
+                entry.get_mut().record(now, bytes);
+                false
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(KeyStats::new(self.window)).record(now, bytes);
+                true
+            }
+        };
+        for group in &mut self.groups {
+            group.record(key, new_key, now, bytes);
+        }
+    }
+
+    pub fn prune(&mut self, now: Instant) {
+        for stats in self.keys.values_mut() {
+            stats.prune(now);
+        }
+        for group in &mut self.groups {
+            group.stats.prune(now);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &KeyStats)> {
+        self.keys.iter()
+    }
+
+    /// The keys that don't belong to any group.
+    pub fn ungrouped(&self) -> impl Iterator<Item = (&String, &KeyStats)> {
+        self.keys
+            .iter()
+            .filter(|(key, _)| !self.groups.iter().any(|group| group.matches(key)))
+    }
+
+    pub fn groups(&self) -> &[GroupStats] {
+        &self.groups
+    }
+}
+
+pub fn format_bandwidth(bytes_per_sec: f64) -> String {
+    format!("{}/s", format_bytes(bytes_per_sec))
+}
+
+pub fn format_bytes(bytes: f64) -> String {
+    if bytes >= 1024.0 * 1024.0 {
+        format!("{:.1} MiB", bytes / (1024.0 * 1024.0))
+    } else if bytes >= 1024.0 {
+        format!("{:.1} KiB", bytes / 1024.0)
+    } else {
+        format!("{:.0} B", bytes)
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    if secs >= 1.0 {
+        format!("{:.2}s", secs)
+    } else if secs >= 1e-3 {
+        format!("{:.2}ms", secs * 1e3)
+    } else {
+        format!("{:.0}{}", secs * 1e6, terminal::symbol("µs", "us"))
+    }
+}
+
+/// Summarizes `count` messages handled over `elapsed`, e.g. `42 messages in 3.20s (13.1 Hz)`.
+pub fn format_summary(count: u64, elapsed: Duration) -> String {
+    let rate = if elapsed.is_zero() {
+        0.0
+    } else {
+        count as f64 / elapsed.as_secs_f64()
+    };
+    format!(
+        "{} messages in {} ({:.1} Hz)",
+        count,
+        format_duration(elapsed),
+        rate
+    )
+}
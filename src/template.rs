@@ -0,0 +1,140 @@
+//! Payload templates expanded once per message.
+//!
+//! Placeholders are written `{{name}}` or `{{name(arg, ...)}}`:
+//!
+//! | Placeholder        | Expands to                                        |
+//! |--------------------|---------------------------------------------------|
+//! | `{{seq}}`          | message sequence number, starting at 0            |
+//! | `{{now_ms}}`       | current time in milliseconds since the UNIX epoch |
+//! | `{{now_ns}}`       | current time in nanoseconds since the UNIX epoch  |
+//! | `{{now}}`          | current time as an RFC 3339 string                |
+//! | `{{rand(a,b)}}`    | uniform random float in `[a, b)`                  |
+//! | `{{randint(a,b)}}` | uniform random integer in `[a, b]`                |
+//!
+//! Commands may define extra variables, e.g. `serve` provides `{{key}}` and `{{parameters}}`.
+
+use rand::Rng;
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Seq,
+    NowMs,
+    NowNs,
+    Now,
+    Rand(f64, f64),
+    RandInt(i64, i64),
+    Var(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+fn parse_args<T: std::str::FromStr>(
+    name: &str,
+    args: &str,
+) -> Result<(T, T), Box<dyn Error + Send + Sync>> {
+    let parts: Vec<&str> = args.split(',').map(str::trim).collect();
+    match parts.as_slice() {
+        [a, b] => Ok((
+            a.parse()
+                .map_err(|_| format!("Invalid argument '{}' to {}", a, name))?,
+            b.parse()
+                .map_err(|_| format!("Invalid argument '{}' to {}", b, name))?,
+        )),
+        _ => Err(format!("{} expects two arguments", name).into()),
+    }
+}
+
+impl Template {
+    /// Parses `input`, accepting the built-in placeholders plus any name in `vars`.
+    pub fn parse(input: &str, vars: &[&str]) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut segments = Vec::new();
+        let mut rest = input;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find("}}")
+                .ok_or("Unterminated '{{' in template")?
+                + start;
+            let placeholder = rest[start + 2..end].trim();
+            let (name, args) = match placeholder.split_once('(') {
+                Some((name, args)) => (
+                    name.trim(),
+                    Some(
+                        args.strip_suffix(')')
+                            .ok_or_else(|| format!("Missing ')' in {{{{{}}}}}", placeholder))?,
+                    ),
+                ),
+                None => (placeholder, None),
+            };
+            segments.push(match (name, args) {
+                ("seq", None) => Segment::Seq,
+                ("now_ms", None) => Segment::NowMs,
+                ("now_ns", None) => Segment::NowNs,
+                ("now", None) => Segment::Now,
+                ("rand", Some(args)) => {
+                    let (lo, hi) = parse_args::<f64>(name, args)?;
+                    // The span is sampled too, so it has to be finite as well as the bounds
+                    if !(hi - lo).is_finite() {
+                        return Err(format!("Invalid range in {{{{{}}}}}", placeholder).into());
+                    }
+                    if lo >= hi {
+                        return Err(format!("Empty range in {{{{{}}}}}", placeholder).into());
+                    }
+                    Segment::Rand(lo, hi)
+                }
+                ("randint", Some(args)) => {
+                    let (lo, hi) = parse_args::<i64>(name, args)?;
+                    if lo > hi {
+                        return Err(format!("Empty range in {{{{{}}}}}", placeholder).into());
+                    }
+                    Segment::RandInt(lo, hi)
+                }
+                (name, None) if vars.contains(&name) => Segment::Var(name.to_string()),
+                _ => {
+                    return Err(
+                        format!("Unknown template placeholder {{{{{}}}}}", placeholder).into(),
+                    )
+                }
+            });
+            rest = &rest[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        Ok(Self { segments })
+    }
+
+    /// True if the template contains no placeholders, so it renders the same every time.
+    pub fn is_static(&self) -> bool {
+        self.segments.iter().all(|s| matches!(s, Segment::Text(_)))
+    }
+
+    pub fn render(&self, seq: u64, vars: &HashMap<&str, String>) -> String {
+        let now = SystemTime::now();
+        let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut rng = rand::thread_rng();
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Seq => out.push_str(&seq.to_string()),
+                Segment::NowMs => out.push_str(&since_epoch.as_millis().to_string()),
+                Segment::NowNs => out.push_str(&since_epoch.as_nanos().to_string()),
+                Segment::Now => out.push_str(&humantime::format_rfc3339_nanos(now).to_string()),
+                Segment::Rand(lo, hi) => out.push_str(&rng.gen_range(*lo..*hi).to_string()),
+                Segment::RandInt(lo, hi) => out.push_str(&rng.gen_range(*lo..=*hi).to_string()),
+                Segment::Var(name) => out.push_str(vars.get(name.as_str()).map_or("", |v| v)),
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,75 @@
+//! Global command-line options for terminals without ANSI support, CI logs and redirected
+//! output.
+//!
+//! Colors are off when stdout isn't a terminal, with `--no-color` or when `NO_COLOR` is set.
+//! `--plain`, or `TERM=dumb`, additionally drops progress lines that redraw in place and prints
+//! ASCII in place of symbols such as arrows and box-drawing characters.
+
+use clap::Args;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static PLAIN: AtomicBool = AtomicBool::new(false);
+static NO_COLOR: AtomicBool = AtomicBool::new(false);
+
+#[derive(Args)]
+pub struct TerminalArgs {
+    /// Don't color output (also set by the `NO_COLOR` environment variable)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+    /// Plain ASCII output without colors or progress lines, e.g. for CI logs (implied by
+    /// `TERM=dumb`)
+    #[arg(long, global = true)]
+    pub plain: bool,
+}
+
+impl TerminalArgs {
+    /// Applies the options; call before anything is printed.
+    pub fn init(&self) {
+        let dumb = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+        // Older Windows consoles need ANSI support switched on, and may not have it at all
+        #[cfg(windows)]
+        let ansi = colored::control::set_virtual_terminal(true).is_ok();
+        #[cfg(not(windows))]
+        let ansi = true;
+        let plain = self.plain || dumb || !ansi;
+        let no_color = self.no_color
+            || plain
+            || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        if no_color {
+            colored::control::set_override(false);
+        }
+        PLAIN.store(plain, Ordering::Relaxed);
+        NO_COLOR.store(no_color, Ordering::Relaxed);
+    }
+}
+
+/// Whether output should be plain ASCII, see [`TerminalArgs::plain`].
+pub fn plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+/// Whether a status line on stdout may be redrawn in place with `\r`.
+pub fn live_updates() -> bool {
+    !plain() && io::stdout().is_terminal()
+}
+
+/// Whether output on stdout is colored, which `colored` decides from the options above and
+/// whether stdout is a terminal.
+pub fn stdout_colors() -> bool {
+    colored::control::SHOULD_COLORIZE.should_colorize()
+}
+
+/// Whether ANSI colors may be written to stderr.
+pub fn stderr_colors() -> bool {
+    !NO_COLOR.load(Ordering::Relaxed) && io::stderr().is_terminal()
+}
+
+/// `symbol`, or `ascii` in plain mode.
+pub fn symbol(symbol: &'static str, ascii: &'static str) -> &'static str {
+    if plain() {
+        ascii
+    } else {
+        symbol
+    }
+}
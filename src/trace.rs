@@ -0,0 +1,284 @@
+//! `zspy trace`: follow an RPC-over-pub/sub pattern by pairing each request with its response
+//! through a correlation ID field, and print the pairs with the time the service took.
+
+use crate::codec::PayloadEncoding;
+use crate::expr;
+use crate::latency;
+use crate::message_registry::MessageRegistry;
+use crate::stats::format_duration;
+use crate::terminal;
+use clap::Args;
+use colored::Colorize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::time;
+use zenoh::{
+    sample::{Sample, SampleKind},
+    Session,
+};
+
+#[derive(Args)]
+pub struct TraceArgs {
+    /// The key expression requests are published on
+    pub request: String,
+    /// The key expression responses are published on
+    pub response: String,
+    /// Field holding the correlation ID, e.g. `header.request_id`
+    #[arg(long, default_value = "id")]
+    pub id_field: String,
+    /// Field holding the correlation ID in responses (default: --id-field)
+    #[arg(long)]
+    pub response_id_field: Option<String>,
+    /// Protobuf type of the requests
+    #[arg(long)]
+    pub r#type: Option<String>,
+    /// Protobuf type of the responses (default: --type)
+    #[arg(long)]
+    pub response_type: Option<String>,
+    /// Schemaless format of the payloads without a type (default: JSON, else text)
+    #[arg(long, value_enum)]
+    pub encoding: Option<PayloadEncoding>,
+    /// Report a request as unanswered after this long
+    #[arg(long, default_value = "5s", value_parser = humantime::parse_duration)]
+    pub timeout: Duration,
+    /// Stop after this long, e.g. `1m` (default: until Ctrl-C)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
+}
+
+/// A request or response waiting for its counterpart.
+struct Pending {
+    key: String,
+    value: Value,
+    arrived: Instant,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Request,
+    Response,
+}
+
+#[derive(Default)]
+struct Tracer {
+    requests: HashMap<String, Pending>,
+    /// Responses that arrived before their request, which happens when the two are routed
+    /// differently
+    early_responses: HashMap<String, Pending>,
+    latencies: Vec<Duration>,
+    traced: u64,
+    answered: u64,
+    unanswered: u64,
+    orphaned: u64,
+    without_id: u64,
+}
+
+impl Tracer {
+    fn observe(&mut self, side: Side, id: String, message: Pending) {
+        match side {
+            Side::Request => {
+                self.traced += 1;
+                if let Some(response) = self.early_responses.remove(&id) {
+                    self.answered += 1;
+                    print_pair(&id, None, &message, &response);
+                } else if let Some(previous) = self.requests.insert(id.clone(), message) {
+                    self.unanswered += 1;
+                    println!(
+                        "{} id={} on '{}' was sent again before a response",
+                        "Unanswered:".yellow(),
+                        id,
+                        previous.key.cyan()
+                    );
+                }
+            }
+            Side::Response => match self.requests.remove(&id) {
+                Some(request) => {
+                    let latency = message.arrived.duration_since(request.arrived);
+                    self.latencies.push(latency);
+                    self.answered += 1;
+                    print_pair(&id, Some(latency), &request, &message);
+                }
+                None => {
+                    self.early_responses.insert(id, message);
+                }
+            },
+        }
+    }
+
+    /// Reports requests and responses older than `cutoff` that never found a counterpart.
+    fn expire(&mut self, cutoff: Option<Instant>, timeout: Duration) {
+        let expired = |pending: &Pending| cutoff.is_none_or(|cutoff| pending.arrived < cutoff);
+        let mut unanswered: Vec<_> = self
+            .requests
+            .iter()
+            .filter(|(_, request)| expired(request))
+            .map(|(id, request)| (request.arrived, id.clone(), request.key.clone()))
+            .collect();
+        unanswered.sort();
+        for (_, id, key) in unanswered {
+            self.requests.remove(&id);
+            self.unanswered += 1;
+            println!(
+                "{} id={} on '{}' got no response within {}",
+                "Unanswered:".yellow(),
+                id,
+                key.cyan(),
+                humantime::format_duration(timeout)
+            );
+        }
+        let before = self.early_responses.len();
+        self.early_responses.retain(|id, response| {
+            if expired(response) {
+                println!(
+                    "{} id={} on '{}' matches no request",
+                    "Orphaned response:".yellow(),
+                    id,
+                    response.key.cyan()
+                );
+            }
+            !expired(response)
+        });
+        self.orphaned += (before - self.early_responses.len()) as u64;
+    }
+}
+
+fn print_pair(id: &str, latency: Option<Duration>, request: &Pending, response: &Pending) {
+    let latency = match latency {
+        Some(latency) => format_duration(latency),
+        None => "response arrived first".to_string(),
+    };
+    println!("{} {}", format!("[id={}]", id).green(), latency.bold());
+    let (sent, received) = (terminal::symbol("→", "->"), terminal::symbol("←", "<-"));
+    println!("  {} '{}': {}", sent, request.key.cyan(), request.value);
+    println!(
+        "  {} '{}': {}",
+        received,
+        response.key.cyan(),
+        response.value
+    );
+}
+
+impl TraceArgs {
+    fn decode(
+        &self,
+        registry: &MessageRegistry,
+        msg_type: Option<&str>,
+        payload: &[u8],
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        registry.decode_with(msg_type, self.encoding, payload)
+    }
+
+    /// The correlation ID and decoded payload of `sample`, or `None` if it has no ID or fails
+    /// to decode, which is reported.
+    fn message(
+        &self,
+        registry: &MessageRegistry,
+        side: Side,
+        sample: &Sample,
+    ) -> Option<(String, Pending)> {
+        let (msg_type, field) = match side {
+            Side::Request => (self.r#type.as_deref(), self.id_field.as_str()),
+            Side::Response => (
+                self.response_type.as_deref().or(self.r#type.as_deref()),
+                self.response_id_field.as_deref().unwrap_or(&self.id_field),
+            ),
+        };
+        let key = sample.key_expr().as_str();
+        let value = match self.decode(registry, msg_type, &sample.payload().to_bytes()) {
+            Ok(value) => value,
+            Err(err) => {
+                eprintln!("{}", format!("{} on {}", err, key).yellow());
+                return None;
+            }
+        };
+        let id = match expr::get_path(&value, field)? {
+            Value::String(id) => id.clone(),
+            Value::Null => return None,
+            other => other.to_string(),
+        };
+        let pending = Pending {
+            key: key.to_string(),
+            value,
+            arrived: Instant::now(),
+        };
+        Some((id, pending))
+    }
+}
+
+pub async fn run(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &TraceArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for type_name in [&args.r#type, &args.response_type].into_iter().flatten() {
+        registry.check_type(type_name)?;
+    }
+    let requests = session.declare_subscriber(args.request.as_str()).await?;
+    let responses = session.declare_subscriber(args.response.as_str()).await?;
+    println!(
+        "Tracing requests on {} and responses on {}",
+        args.request.cyan(),
+        args.response.cyan()
+    );
+
+    let mut tracer = Tracer::default();
+    let mut ticker = time::interval((args.timeout / 4).max(Duration::from_millis(10)));
+    let deadline = async {
+        match args.duration {
+            Some(duration) => time::sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+    loop {
+        let (side, sample) = tokio::select! {
+            sample = requests.recv_async() => (Side::Request, sample?),
+            sample = responses.recv_async() => (Side::Response, sample?),
+            _ = ticker.tick() => {
+                tracer.expire(Instant::now().checked_sub(args.timeout), args.timeout);
+                continue;
+            }
+            _ = &mut deadline => break,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        if sample.kind() == SampleKind::Delete {
+            continue;
+        }
+        match args.message(registry, side, &sample) {
+            Some((id, message)) => tracer.observe(side, id, message),
+            None => tracer.without_id += 1,
+        }
+    }
+    tracer.expire(None, args.timeout);
+
+    let summary = format!(
+        "Traced {} requests: {} answered, {} unanswered, {} responses without a request",
+        tracer.traced, tracer.answered, tracer.unanswered, tracer.orphaned
+    );
+    println!("\n{}", summary.green());
+    if tracer.without_id > 0 {
+        println!(
+            "{}",
+            format!(
+                "{} messages had no '{}' field to correlate by",
+                tracer.without_id, args.id_field
+            )
+            .yellow()
+        );
+    }
+    let latencies = &mut tracer.latencies;
+    if !latencies.is_empty() {
+        latencies.sort();
+        let avg = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+        println!(
+            "latency min/avg/max/p99 = {}/{}/{}/{}",
+            format_duration(latencies[0]),
+            format_duration(avg),
+            format_duration(latencies[latencies.len() - 1]),
+            format_duration(latency::percentile(latencies, 99.0))
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Payload transform pipelines for `zspy bridge` and `zspy play`: decode each payload to JSON,
+//! rewrite it step by step, and re-encode it, possibly as another type. Useful as a quick
+//! adapter while publishers and subscribers migrate between message versions.
+//!
+//! ```yaml
+//! type: zspy.ImuMessage
+//! output_type: zspy.v2.ImuMessage
+//! steps:
+//!   - rename: {from: angularVelocity, to: gyro}
+//!   - scale: {field: gyro.x, factor: 0.0174533}
+//!   - drop: debug
+//! ```
+
+use crate::codec::PayloadEncoding;
+use crate::expr;
+use crate::message_registry::MessageRegistry;
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+use std::path::Path;
+use zenoh::bytes::Encoding;
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transform {
+    /// Protobuf type of the incoming payloads
+    pub r#type: Option<String>,
+    /// Schemaless format of the incoming payloads (default: JSON)
+    pub encoding: Option<PayloadEncoding>,
+    /// Protobuf type to re-encode as (default: the incoming format)
+    pub output_type: Option<String>,
+    /// Schemaless format to re-encode as
+    pub output_encoding: Option<PayloadEncoding>,
+    /// Written as single-key maps, e.g. `- drop: debug`, rather than YAML tags
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub steps: Vec<Step>,
+}
+
+/// One rewrite of the decoded message. Fields are dotted paths, as for `--field`.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    Rename {
+        from: String,
+        to: String,
+    },
+    Drop(String),
+    /// Replaces a number `x` with `x * factor + offset`, e.g. to convert units
+    Scale {
+        field: String,
+        #[serde(default = "default_factor")]
+        factor: f64,
+        #[serde(default)]
+        offset: f64,
+    },
+}
+
+fn default_factor() -> f64 {
+    1.0
+}
+
+impl Step {
+    fn apply(&self, value: &mut Value) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self {
+            Step::Rename { from, to } => {
+                if let Some(field) = expr::remove_path(value, from) {
+                    expr::set_path(value, to, field);
+                }
+            }
+            Step::Drop(field) => {
+                expr::remove_path(value, field);
+            }
+            Step::Scale {
+                field,
+                factor,
+                offset,
+            } => {
+                // 64-bit integers are strings in JSON
+                let number = match expr::get_path(value, field) {
+                    None | Some(Value::Null) => return Ok(()),
+                    Some(Value::Number(n)) => n.as_f64(),
+                    Some(Value::String(s)) => s.parse().ok(),
+                    Some(_) => None,
+                }
+                .ok_or_else(|| format!("Can't scale {}: not a number", field))?;
+                let scaled = number * factor + offset;
+                // Keep integer fields integers when the result allows it
+                let scaled = if scaled.fract() == 0.0 && scaled.abs() < i64::MAX as f64 {
+                    Value::from(scaled as i64)
+                } else {
+                    Value::from(scaled)
+                };
+                expr::set_path(value, field, scaled);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Transform {
+    /// Loads a pipeline from a YAML file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let text = std::fs::read_to_string(path)?;
+        serde_yaml::from_str(&text)
+            .map_err(|e| format!("Invalid transform {}: {}", path.display(), e).into())
+    }
+
+    /// Whether payloads have to be decoded, or can be forwarded verbatim.
+    pub fn is_identity(&self) -> bool {
+        self.steps.is_empty() && self.output_type.is_none() && self.output_encoding.is_none()
+    }
+
+    /// Fails early when the pipeline names a type that isn't registered.
+    pub fn check_types(
+        &self,
+        registry: &MessageRegistry,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for msg_type in [&self.r#type, &self.output_type].into_iter().flatten() {
+            registry.check_type(msg_type)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes `payload`, applies the steps in order, and encodes the result in the output
+    /// format.
+    pub fn apply(
+        &self,
+        registry: &MessageRegistry,
+        payload: &[u8],
+    ) -> Result<(Vec<u8>, Encoding), Box<dyn Error + Send + Sync>> {
+        let value = self.rewrite(registry, payload)?;
+        self.encode(registry, &value)
+    }
+
+    /// Decodes `payload` and applies the steps in order.
+    pub fn rewrite(
+        &self,
+        registry: &MessageRegistry,
+        payload: &[u8],
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let mut value = registry.decode_with(self.r#type.as_deref(), self.encoding, payload)?;
+        for step in &self.steps {
+            step.apply(&mut value)?;
+        }
+        Ok(value)
+    }
+
+    /// Encodes a rewritten message in the output format.
+    pub fn encode(
+        &self,
+        registry: &MessageRegistry,
+        value: &Value,
+    ) -> Result<(Vec<u8>, Encoding), Box<dyn Error + Send + Sync>> {
+        let json = value.to_string();
+
+        // Without an explicit output format, keep the incoming one
+        let (output_type, output_encoding) =
+            if self.output_type.is_some() || self.output_encoding.is_some() {
+                (self.output_type.as_deref(), self.output_encoding)
+            } else {
+                (self.r#type.as_deref(), self.encoding)
+            };
+        match (output_type, output_encoding) {
+            (Some(msg_type), _) => Ok((registry.encode(msg_type, &json)?, Encoding::default())),
+            (None, Some(encoding)) => {
+                Ok((encoding.codec().encode(&json)?, encoding.zenoh_encoding()))
+            }
+            (None, None) => Ok((json.into_bytes(), PayloadEncoding::Json.zenoh_encoding())),
+        }
+    }
+}
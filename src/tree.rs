@@ -0,0 +1,46 @@
+//! Key space tree rendering for `zspy tree`.
+
+use crate::terminal;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Observed keys split into chunks, with the number of messages seen at or below each node.
+#[derive(Default)]
+pub struct KeyTree {
+    count: u64,
+    children: BTreeMap<String, KeyTree>,
+}
+
+impl KeyTree {
+    /// Adds `count` messages on `key` to every node along its path.
+    pub fn insert(&mut self, key: &str, count: u64) {
+        let mut node = self;
+        node.count += count;
+        for chunk in key.split('/').filter(|c| !c.is_empty()) {
+            node = node.children.entry(chunk.to_string()).or_default();
+            node.count += count;
+        }
+    }
+
+    /// Renders the tree below `root` with box-drawing characters, one node per line.
+    pub fn render(&self, root: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{} ({})", root, self.count);
+        self.render_children(&mut out, "");
+        out
+    }
+
+    fn render_children(&self, out: &mut String, prefix: &str) {
+        let last = self.children.len().saturating_sub(1);
+        for (i, (chunk, child)) in self.children.iter().enumerate() {
+            let (branch, indent) = match (i == last, terminal::plain()) {
+                (true, false) => ("└── ", "    "),
+                (false, false) => ("├── ", "│   "),
+                (true, true) => ("`-- ", "    "),
+                (false, true) => ("|-- ", "|   "),
+            };
+            let _ = writeln!(out, "{}{}{} ({})", prefix, branch, chunk, child.count);
+            child.render_children(out, &format!("{}{}", prefix, indent));
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Message types by key expression, so `echo`, `monitor` and `record` decode keys without
+//! `--type` on every invocation.
+//!
+//! The mapping is read from `--type-map`, or else from `types.toml` next to the config file
+//! (usually `~/.config/zspy/types.toml`):
+//!
+//! ```toml
+//! "robot/*/imu" = "zspy.ImuMessage"
+//! "robot/**" = "my.pkg.Status"
+//! ```
+//!
+//! When several entries match a key, the most specific one wins: the one with the fewest `**`,
+//! then the fewest `*` chunks, then the most literal characters. `--type` overrides the mapping.
+
+use crate::profile;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use zenoh::key_expr::{keyexpr, OwnedKeyExpr};
+
+#[derive(Default)]
+pub struct TypeMap {
+    /// Sorted from the most to the least specific key expression
+    entries: Vec<(OwnedKeyExpr, String)>,
+}
+
+impl TypeMap {
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let table: BTreeMap<String, String> = toml::from_str(text)?;
+        let mut entries = table
+            .into_iter()
+            .map(|(key_expr, msg_type)| {
+                OwnedKeyExpr::autocanonize(key_expr.clone())
+                    .map(|key_expr| (key_expr, msg_type))
+                    .map_err(|e| format!("Invalid key expression '{}': {}", key_expr, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|(key_expr, _)| specificity(key_expr));
+        Ok(Self { entries })
+    }
+
+    /// Loads `path`, or the default `types.toml` if it exists when no path is given.
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let (path, text) = match path {
+            Some(path) => (path.to_path_buf(), std::fs::read_to_string(path)?),
+            None => match default_path()
+                .and_then(|path| Some((path.clone(), std::fs::read_to_string(path).ok()?)))
+            {
+                Some(found) => found,
+                None => return Ok(Self::default()),
+            },
+        };
+        Self::parse(&text).map_err(|e| format!("Invalid type map {}: {}", path.display(), e).into())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The mapped types, most specific key expression first.
+    pub fn types(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(_, msg_type)| msg_type.as_str())
+    }
+
+    /// The type of the most specific entry matching `key`, if any.
+    pub fn type_for(&self, key: &str) -> Option<&str> {
+        let key = keyexpr::new(key).ok()?;
+        self.entries
+            .iter()
+            .find(|(key_expr, _)| key_expr.includes(key))
+            .map(|(_, msg_type)| msg_type.as_str())
+    }
+}
+
+/// `types.toml` in the directory of the user config file.
+pub fn default_path() -> Option<PathBuf> {
+    Some(profile::config_path()?.with_file_name("types.toml"))
+}
+
+/// Sort key putting more specific key expressions first.
+fn specificity(key_expr: &keyexpr) -> (usize, usize, std::cmp::Reverse<usize>) {
+    let chunks = key_expr.as_str().split('/');
+    let (mut double, mut single, mut literal) = (0, 0, 0);
+    for chunk in chunks {
+        match chunk {
+            "**" => double += 1,
+            chunk if chunk.contains('*') => single += 1,
+            chunk => literal += chunk.len(),
+        }
+    }
+    (double, single, std::cmp::Reverse(literal))
+}
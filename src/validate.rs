@@ -0,0 +1,312 @@
+//! `zspy validate`: check every message on a key expression against declarative rules and
+//! count the violations, a lightweight data-quality monitor.
+//!
+//! ```yaml
+//! rules:
+//!   - field: angularVelocity.x
+//!     min: -10
+//!     max: 10
+//!   - field: header.frameId
+//!     required: true
+//!   - field: timestamp
+//!     increasing: true
+//! ```
+//!
+//! `increasing` is checked per key, so a wildcard over several publishers doesn't interleave
+//! their sequences.
+
+use crate::codec::PayloadEncoding;
+use crate::error::ZspyError;
+use crate::expr;
+use crate::message_registry::MessageRegistry;
+use crate::stats::format_summary;
+use clap::Args;
+use colored::Colorize;
+use serde::Deserialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::time;
+use zenoh::{sample::SampleKind, Session};
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// The key expression to validate
+    pub key_expr: String,
+    /// Rules file (YAML) listing the constraints to check
+    #[arg(long)]
+    pub rules: PathBuf,
+    /// Protobuf type of the messages
+    #[arg(long)]
+    pub r#type: Option<String>,
+    /// Schemaless format of the payloads without a type (default: JSON)
+    #[arg(long, value_enum, conflicts_with = "type")]
+    pub encoding: Option<PayloadEncoding>,
+    /// Only print the summary, not each violation
+    #[arg(short, long)]
+    pub quiet: bool,
+    /// Stop after this many messages
+    #[arg(long)]
+    pub count: Option<u64>,
+    /// Stop after this long, e.g. `1m` (default: until Ctrl-C)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    /// Dotted path of the field, as for `--field`
+    field: String,
+    min: Option<f64>,
+    max: Option<f64>,
+    /// The field must be present and not its default (zero, empty or false)
+    #[serde(default)]
+    required: bool,
+    /// Each value must be greater than the previous one on the same key
+    #[serde(default)]
+    increasing: bool,
+}
+
+impl Rule {
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.required {
+            parts.push("required".to_string());
+        }
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => parts.push(format!("in [{}, {}]", min, max)),
+            (Some(min), None) => parts.push(format!(">= {}", min)),
+            (None, Some(max)) => parts.push(format!("<= {}", max)),
+            (None, None) => {}
+        }
+        if self.increasing {
+            parts.push("increasing".to_string());
+        }
+        format!("{} {}", self.field, parts.join(", "))
+    }
+
+    /// Checks `value`, given the value this field had in the previous message on the same key.
+    /// Returns a description of each violation.
+    fn check(&self, value: Option<&Value>, previous: Option<&Value>) -> Vec<String> {
+        let mut violations = Vec::new();
+        let value = match value {
+            Some(value) if !value.is_null() => value,
+            _ => {
+                if self.required {
+                    violations.push("is missing".to_string());
+                }
+                return violations;
+            }
+        };
+        if self.required && is_default(value) {
+            violations.push(format!("has the default value {}", value));
+        }
+        if self.min.is_some() || self.max.is_some() {
+            match as_f64(value) {
+                Some(number) if self.min.is_some_and(|min| number < min) => violations.push(
+                    format!("= {} is below the minimum {}", value, self.min.unwrap()),
+                ),
+                Some(number) if self.max.is_some_and(|max| number > max) => violations.push(
+                    format!("= {} is above the maximum {}", value, self.max.unwrap()),
+                ),
+                Some(_) => {}
+                None => violations.push(format!("= {} is not a number", value)),
+            }
+        }
+        if self.increasing {
+            if let Some(previous) = previous {
+                match compare(value, previous) {
+                    Some(Ordering::Greater) => {}
+                    Some(_) => violations.push(format!(
+                        "= {} does not increase on the previous {}",
+                        value, previous
+                    )),
+                    None => violations.push(format!("= {} can't be ordered", value)),
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// Whether `value` is what protobuf leaves a field that was never set as.
+fn is_default(value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => !b,
+        Value::String(s) => s.is_empty(),
+        Value::Array(items) => items.is_empty(),
+        Value::Object(fields) => fields.is_empty(),
+        _ => as_f64(value) == Some(0.0),
+    }
+}
+
+/// The value of a number, or of a string holding one, as 64-bit integers are encoded in JSON.
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Orders two values of a field: integers exactly, so nanosecond timestamps don't lose their
+/// precision to floats, then any numbers, then RFC 3339 times.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    let as_i128 = |v: &Value| match v {
+        Value::Number(n) => n.as_i64().map(i128::from).or(n.as_u64().map(i128::from)),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    };
+    let as_time =
+        |v: &Value| -> Option<SystemTime> { humantime::parse_rfc3339_weak(v.as_str()?).ok() };
+    if let (Some(a), Some(b)) = (as_i128(a), as_i128(b)) {
+        return Some(a.cmp(&b));
+    }
+    if let (Some(a), Some(b)) = (as_f64(a), as_f64(b)) {
+        return a.partial_cmp(&b);
+    }
+    Some(as_time(a)?.cmp(&as_time(b)?))
+}
+
+impl ValidateArgs {
+    fn decode(
+        &self,
+        registry: &MessageRegistry,
+        payload: &[u8],
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        registry.decode_with(self.r#type.as_deref(), self.encoding, payload)
+    }
+}
+
+pub async fn run(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &ValidateArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let text = tokio::fs::read_to_string(&args.rules).await?;
+    let rules: Rules = serde_yaml::from_str(&text)
+        .map_err(|e| format!("Invalid rules {}: {}", args.rules.display(), e))?;
+    if rules.rules.is_empty() {
+        return Err(format!("Rules file {} has no rules", args.rules.display()).into());
+    }
+    for rule in &rules.rules {
+        if !rule.required && !rule.increasing && rule.min.is_none() && rule.max.is_none() {
+            return Err(format!("Rule for {} checks nothing", rule.field).into());
+        }
+        if let (Some(min), Some(max)) = (rule.min, rule.max) {
+            if min > max {
+                return Err(format!("Rule for {}: min is above max", rule.field).into());
+            }
+        }
+    }
+    if let Some(type_name) = &args.r#type {
+        registry.check_type(type_name)?;
+    }
+    let subscriber = session.declare_subscriber(args.key_expr.as_str()).await?;
+    println!(
+        "Validating {} against {} rules from {}",
+        args.key_expr.cyan(),
+        rules.rules.len(),
+        args.rules.display()
+    );
+
+    let start = Instant::now();
+    let mut messages = 0u64;
+    let mut invalid = 0u64;
+    let mut undecodable = 0u64;
+    let mut violations = vec![0u64; rules.rules.len()];
+    // The previous message on each key, for `increasing`
+    let mut previous: HashMap<String, Value> = HashMap::new();
+    let deadline = async {
+        match args.duration {
+            Some(duration) => time::sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+    while args.count.is_none_or(|count| messages < count) {
+        let sample = tokio::select! {
+            sample = subscriber.recv_async() => sample?,
+            _ = &mut deadline => break,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        if sample.kind() == SampleKind::Delete {
+            continue;
+        }
+        messages += 1;
+        let key = sample.key_expr().as_str();
+        let value = match args.decode(registry, &sample.payload().to_bytes()) {
+            Ok(value) => value,
+            Err(err) => {
+                undecodable += 1;
+                if !args.quiet {
+                    println!("{} '{}': {}", "Undecodable:".red(), key.cyan(), err);
+                }
+                continue;
+            }
+        };
+        let last = previous.get(key);
+        let mut valid = true;
+        for (rule, count) in rules.rules.iter().zip(&mut violations) {
+            let found = rule.check(
+                expr::get_path(&value, &rule.field),
+                last.and_then(|last| expr::get_path(last, &rule.field)),
+            );
+            for violation in &found {
+                if !args.quiet {
+                    println!(
+                        "{} '{}' {} {}",
+                        "Violation:".yellow(),
+                        key.cyan(),
+                        rule.field.bold(),
+                        violation
+                    );
+                }
+            }
+            if !found.is_empty() {
+                *count += 1;
+                valid = false;
+            }
+        }
+        if !valid {
+            invalid += 1;
+        }
+        previous.insert(key.to_string(), value);
+    }
+
+    let summary = format!(
+        "Validated {}: {} with violations, {} undecodable",
+        format_summary(messages, start.elapsed()),
+        invalid,
+        undecodable
+    );
+    println!(
+        "\n{}",
+        if invalid + undecodable > 0 {
+            summary.yellow()
+        } else {
+            summary.green()
+        }
+    );
+    for (rule, count) in rules.rules.iter().zip(&violations) {
+        println!("  {}: {} violations", rule.describe(), count);
+    }
+    if invalid + undecodable > 0 {
+        return Err(ZspyError::Validation(format!(
+            "{} messages broke the rules and {} couldn't be decoded",
+            invalid, undecodable
+        ))
+        .into());
+    }
+    Ok(())
+}
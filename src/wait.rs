@@ -0,0 +1,128 @@
+//! `zspy wait`: block until a matching sample arrives, for readiness checks in scripts and CI.
+
+use crate::codec::PayloadEncoding;
+use crate::error::ZspyError;
+use crate::expr::Filter;
+use crate::message_registry::MessageRegistry;
+use clap::Args;
+use colored::Colorize;
+use serde_json::Value;
+use std::error::Error;
+use std::time::Duration;
+use tokio::time;
+use zenoh::{
+    sample::{Sample, SampleKind},
+    Session,
+};
+
+#[derive(Args)]
+pub struct WaitArgs {
+    /// The key expression to wait on
+    pub key_expr: String,
+    /// Protobuf type of the payloads
+    #[arg(long)]
+    pub r#type: Option<String>,
+    /// Schemaless format of the payloads (default: JSON, else text)
+    #[arg(long, value_enum, conflicts_with = "type")]
+    pub encoding: Option<PayloadEncoding>,
+    /// Only accept a message matching this predicate, e.g. `status == "ready"`
+    #[arg(long)]
+    pub filter: Option<String>,
+    /// Give up after this long, e.g. `30s` (default: wait forever)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub timeout: Option<Duration>,
+    /// Also accept a value already held by a storage or queryable
+    #[arg(long)]
+    pub history: bool,
+}
+
+impl WaitArgs {
+    fn decode(
+        &self,
+        registry: &MessageRegistry,
+        payload: &[u8],
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        registry.decode_with(self.r#type.as_deref(), self.encoding, payload)
+    }
+
+    /// The decoded payload of `sample` if it satisfies the filter.
+    fn accept(
+        &self,
+        registry: &MessageRegistry,
+        filter: Option<&Filter>,
+        sample: &Sample,
+    ) -> Option<Value> {
+        if sample.kind() == SampleKind::Delete {
+            return None;
+        }
+        match self.decode(registry, &sample.payload().to_bytes()) {
+            Ok(value) if filter.is_none_or(|f| f.matches(&value)) => Some(value),
+            Ok(_) => None,
+            Err(err) => {
+                tracing::debug!(
+                    key = sample.key_expr().as_str(),
+                    "Ignoring undecodable sample: {}",
+                    err
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Waits for a matching sample and prints it, or fails with [`ZspyError::Timeout`].
+pub async fn run(
+    session: &Session,
+    registry: &MessageRegistry,
+    args: &WaitArgs,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(msg_type) = &args.r#type {
+        registry.check_type(msg_type)?;
+    }
+    let filter = args.filter.as_deref().map(Filter::parse).transpose()?;
+    // Subscribe before querying so a sample published in between isn't missed
+    let subscriber = session.declare_subscriber(args.key_expr.as_str()).await?;
+
+    let wait = async {
+        if args.history {
+            let replies = session.get(args.key_expr.as_str()).await?;
+            while let Ok(reply) = replies.recv_async().await {
+                if let Ok(sample) = reply.result() {
+                    if let Some(value) = args.accept(registry, filter.as_ref(), sample) {
+                        return Ok((sample.key_expr().to_string(), value));
+                    }
+                }
+            }
+        }
+        loop {
+            let sample = subscriber.recv_async().await?;
+            if let Some(value) = args.accept(registry, filter.as_ref(), &sample) {
+                return Ok::<_, Box<dyn Error + Send + Sync>>((
+                    sample.key_expr().to_string(),
+                    value,
+                ));
+            }
+        }
+    };
+    let timeout = async {
+        match args.timeout {
+            Some(timeout) => time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        result = wait => {
+            let (key, value) = result?;
+            println!("{} '{}': {}", "Matched".green(), key.cyan(), value);
+            Ok(())
+        }
+        _ = timeout => Err(ZspyError::Timeout(format!(
+            "No matching message on {} within {}",
+            args.key_expr,
+            humantime::format_duration(args.timeout.unwrap_or_default())
+        ))
+        .into()),
+        _ = tokio::signal::ctrl_c() => Err("Interrupted before a matching message arrived".into()),
+    }
+}
@@ -0,0 +1,214 @@
+//! Idiomatic JSON for the `google.protobuf` well-known types: RFC 3339 timestamps, humanized
+//! durations such as `1m 30s 500ms`, and `Struct`/`Value`/`ListValue` as plain JSON.
+//!
+//! The built-in types use these through serde attributes set in build.rs. Types loaded at
+//! runtime already get canonical JSON from prost-reflect, whose `"90.500s"` durations are
+//! rewritten by [`humanize_durations`].
+
+use crate::proto::google::protobuf::{Duration, ListValue, Struct, Timestamp, Value as ProtoValue};
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, Kind, MessageDescriptor};
+use serde_json::Value;
+
+const DURATION: &str = "google.protobuf.Duration";
+
+/// The canonical JSON of a well-known type, as prost-reflect renders it.
+fn to_canonical<T: Message>(message: &T, full_name: &str) -> Option<Value> {
+    let descriptor = DescriptorPool::global().get_message_by_name(full_name)?;
+    let dynamic = DynamicMessage::decode(descriptor, message.encode_to_vec().as_slice()).ok()?;
+    serde_json::to_value(&dynamic).ok()
+}
+
+fn from_canonical<T: Message + Default>(value: Value, full_name: &str) -> Result<T, String> {
+    let descriptor = DescriptorPool::global()
+        .get_message_by_name(full_name)
+        .ok_or_else(|| format!("Unknown well-known type {}", full_name))?;
+    DynamicMessage::deserialize(descriptor, value)
+        .map_err(|e| e.to_string())?
+        .transcode_to()
+        .map_err(|e| e.to_string())
+}
+
+/// Formats a duration like `1m 30s 500ms`, with a leading `-` if negative.
+pub fn format_duration(seconds: i64, nanos: i32) -> String {
+    let negative = seconds < 0 || nanos < 0;
+    let duration = std::time::Duration::new(seconds.unsigned_abs(), nanos.unsigned_abs());
+    let sign = if negative { "-" } else { "" };
+    format!("{}{}", sign, humantime::format_duration(duration))
+}
+
+/// Parses a humanized duration such as `1m 30s`, or the canonical `90.5s`.
+pub fn parse_duration(text: &str) -> Result<(i64, i32), String> {
+    let text = text.trim();
+    let (sign, magnitude) = match text.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, text),
+    };
+    let canonical = magnitude.strip_suffix('s').and_then(|number| {
+        let (secs, frac) = number.split_once('.').unwrap_or((number, ""));
+        if frac.len() > 9 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let secs = secs.parse::<u64>().ok()?;
+        let nanos = format!("{:0<9}", frac).parse::<u32>().ok()?;
+        Some(std::time::Duration::new(secs, nanos))
+    });
+    let duration = match canonical {
+        Some(duration) => duration,
+        None => humantime::parse_duration(magnitude)
+            .map_err(|e| format!("Invalid duration '{}': {}", text, e))?,
+    };
+    let seconds = i64::try_from(duration.as_secs())
+        .map_err(|_| format!("Duration '{}' is out of range", text))?;
+    Ok((sign * seconds, sign as i32 * duration.subsec_nanos() as i32))
+}
+
+impl From<Duration> for String {
+    fn from(duration: Duration) -> Self {
+        format_duration(duration.seconds, duration.nanos)
+    }
+}
+
+impl TryFrom<String> for Duration {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let (seconds, nanos) = parse_duration(&text)?;
+        Ok(Self { seconds, nanos })
+    }
+}
+
+impl From<Timestamp> for String {
+    fn from(timestamp: Timestamp) -> Self {
+        // Out-of-range timestamps have no RFC 3339 form
+        match to_canonical(&timestamp, "google.protobuf.Timestamp") {
+            Some(Value::String(text)) => text,
+            _ => format!("{}.{:09}", timestamp.seconds, timestamp.nanos),
+        }
+    }
+}
+
+impl TryFrom<String> for Timestamp {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        from_canonical(Value::String(text), "google.protobuf.Timestamp")
+    }
+}
+
+macro_rules! json_conversions {
+    ($type:ty, $name:literal) => {
+        impl From<$type> for Value {
+            fn from(message: $type) -> Self {
+                to_canonical(&message, $name).unwrap_or(Value::Null)
+            }
+        }
+
+        impl TryFrom<Value> for $type {
+            type Error = String;
+
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                from_canonical(value, $name)
+            }
+        }
+    };
+}
+
+json_conversions!(Struct, "google.protobuf.Struct");
+json_conversions!(ProtoValue, "google.protobuf.Value");
+json_conversions!(ListValue, "google.protobuf.ListValue");
+
+/// Applies `f` to every `google.protobuf.Duration` in `value`, a message of type `descriptor` in
+/// JSON form.
+fn for_each_duration(
+    descriptor: &MessageDescriptor,
+    value: &mut Value,
+    f: &mut dyn FnMut(&mut Value) -> Result<(), String>,
+) -> Result<(), String> {
+    if descriptor.full_name() == DURATION {
+        f(value)
+    } else {
+        visit_durations(descriptor, value, f)
+    }
+}
+
+fn visit_durations(
+    descriptor: &MessageDescriptor,
+    value: &mut Value,
+    f: &mut dyn FnMut(&mut Value) -> Result<(), String>,
+) -> Result<(), String> {
+    let Value::Object(fields) = value else {
+        return Ok(());
+    };
+    for field in descriptor.fields() {
+        let Kind::Message(message) = field.kind() else {
+            continue;
+        };
+        // JSON may use either the camelCase or the original field name
+        let key = if fields.contains_key(field.json_name()) {
+            field.json_name()
+        } else {
+            field.name()
+        };
+        let Some(field_value) = fields.get_mut(key) else {
+            continue;
+        };
+        let (message, values): (MessageDescriptor, Vec<&mut Value>) = if field.is_map() {
+            let Kind::Message(entry) = message.map_entry_value_field().kind() else {
+                continue;
+            };
+            let values = match field_value {
+                Value::Object(entries) => entries.values_mut().collect(),
+                _ => Vec::new(),
+            };
+            (entry, values)
+        } else if field.is_list() {
+            let values = match field_value {
+                Value::Array(items) => items.iter_mut().collect(),
+                _ => Vec::new(),
+            };
+            (message, values)
+        } else {
+            (message, vec![field_value])
+        };
+        for value in values {
+            if message.full_name() == DURATION {
+                f(value)?;
+            } else if message.parent_file().package_name() != "google.protobuf" {
+                visit_durations(&message, value, f)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rewrites the canonical `"90.500s"` durations of a decoded message as `1m 30s 500ms`.
+pub fn humanize_durations(descriptor: &MessageDescriptor, value: &mut Value) {
+    let _ = for_each_duration(descriptor, value, &mut |duration| {
+        if let Some(Ok((seconds, nanos))) = duration.as_str().map(parse_duration) {
+            *duration = Value::String(format_duration(seconds, nanos));
+        }
+        Ok(())
+    });
+}
+
+/// Rewrites humanized durations in a message about to be encoded into the canonical form
+/// prost-reflect expects.
+pub fn canonicalize_durations(
+    descriptor: &MessageDescriptor,
+    value: &mut Value,
+) -> Result<(), String> {
+    for_each_duration(descriptor, value, &mut |duration| {
+        if let Some(text) = duration.as_str() {
+            let (seconds, nanos) = parse_duration(text)?;
+            let sign = if seconds < 0 || nanos < 0 { "-" } else { "" };
+            *duration = Value::String(format!(
+                "{}{}.{:09}s",
+                sign,
+                seconds.unsigned_abs(),
+                nanos.unsigned_abs()
+            ));
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,80 @@
+//! Helpers shared by the integration tests.
+
+// Each test crate uses only some of them
+#![allow(dead_code)]
+
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zenoh::{Config, Session};
+
+/// Opens two peer sessions connected to each other over loopback TCP, with scouting disabled so
+/// the tests neither see nor disturb anything else on the network.
+pub async fn session_pair() -> (Session, Session) {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let endpoint = format!(r#"["tcp/127.0.0.1:{}"]"#, port);
+    let config = |listen: &str, connect: &str| {
+        let mut config = Config::default();
+        config.insert_json5("mode", r#""peer""#).unwrap();
+        config
+            .insert_json5("scouting/multicast/enabled", "false")
+            .unwrap();
+        config.insert_json5("listen/endpoints", listen).unwrap();
+        config.insert_json5("connect/endpoints", connect).unwrap();
+        config
+    };
+    let a = zenoh::open(config(&endpoint, "[]")).await.unwrap();
+    let b = zenoh::open(config("[]", &endpoint)).await.unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !b.info().peers_zid().await.any(|zid| zid == a.zid()) {
+        assert!(Instant::now() < deadline, "sessions did not connect");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    (a, b)
+}
+
+/// A writer whose contents can be read back while it is owned by a printer.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Everything written so far, parsed as one JSON value per line.
+    pub fn json_lines(&self) -> Vec<serde_json::Value> {
+        let bytes = self.0.lock().unwrap();
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    /// Everything written so far, as text.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An empty directory for a test to write files into.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("zspy-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,398 @@
+//! `zspy pub` on one session, `zspy echo` on another, and `zspy selftest`.
+
+mod common;
+
+use common::{session_pair, SharedBuffer};
+use std::io;
+use std::time::{Duration, Instant};
+use zspy::echo::{self, EchoArgs};
+use zspy::message_registry::MessageRegistry;
+use zspy::output::{Console, OutputFormat, RawEscape};
+use zspy::publish::{self, PubArgs, ReliabilityArg, TimestampArg};
+use zspy::selftest::{self, SelftestArgs};
+
+/// Publishes `pub_args` from one session while echoing `echo_args` on the other, and returns
+/// the JSON lines echo printed.
+async fn pub_echo(pub_args: PubArgs, echo_args: EchoArgs) -> Vec<serde_json::Value> {
+    pub_echo_consoles(pub_args, echo_args).await.0.json_lines()
+}
+
+/// Like [`pub_echo`], but returns everything written: echo's output and error stream, then
+/// pub's output.
+async fn pub_echo_consoles(
+    pub_args: PubArgs,
+    echo_args: EchoArgs,
+) -> (SharedBuffer, SharedBuffer, SharedBuffer) {
+    let (a, b) = session_pair().await;
+    let registry = MessageRegistry::with_builtin_types();
+    let (echo_out, echo_err, pub_out) = <(SharedBuffer, SharedBuffer, SharedBuffer)>::default();
+    let echo_console = Console::new(Box::new(echo_out.clone()), Box::new(echo_err.clone()));
+    let pub_console = Console::new(Box::new(pub_out.clone()), Box::new(io::sink()));
+    let echo_args = EchoArgs {
+        output: OutputFormat::Jsonl,
+        duration: Some(Duration::from_secs(10)),
+        ..echo_args
+    };
+    let pub_args = PubArgs {
+        wait_for_subscribers: Some(Some(Duration::from_secs(5))),
+        rate: 50.0,
+        ..pub_args
+    };
+    let (echoed, published) = tokio::join!(
+        echo::run_to(&b, &registry, &echo_args, &echo_console),
+        publish::run_to(&a, &registry, &pub_args, &pub_console),
+    );
+    echoed.unwrap();
+    published.unwrap();
+    (echo_out, echo_err, pub_out)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn echoes_text_payloads() {
+    let lines = pub_echo(
+        PubArgs {
+            key: "test/text".to_string(),
+            value: Some("hello".to_string()),
+            repeat: 3,
+            ..Default::default()
+        },
+        EchoArgs {
+            keys: vec!["test/**".to_string()],
+            count: Some(3),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(lines.len(), 3);
+    for line in &lines {
+        assert_eq!(line["key"], "test/text");
+        assert_eq!(line["kind"], "PUT");
+        assert_eq!(line["payload"], "hello");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writes_status_and_summaries_to_the_console() {
+    let (out, err, published) = pub_echo_consoles(
+        PubArgs {
+            key: "test/console".to_string(),
+            value: Some("hello".to_string()),
+            repeat: 2,
+            ..Default::default()
+        },
+        EchoArgs {
+            keys: vec!["test/console".to_string()],
+            count: Some(2),
+            ..Default::default()
+        },
+    )
+    .await;
+    // Machine-readable output stays clean, with the summary on the error stream
+    assert_eq!(out.json_lines().len(), 2);
+    assert!(err.text().starts_with("Received 2 messages"));
+    let published = published.text();
+    assert!(published.contains("Publishing to key: test/console"));
+    assert!(published.contains("Published 2 messages"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn expands_file_lines_only_with_template() {
+    let dir = common::temp_dir("lines");
+    let file = dir.join("lines.txt");
+    std::fs::write(&file, "line {{seq}}\nline {{seq}}\n").unwrap();
+    for (template, expected) in [
+        (false, ["line {{seq}}", "line {{seq}}"]),
+        (true, ["line 0", "line 1"]),
+    ] {
+        let lines = pub_echo(
+            PubArgs {
+                key: "test/lines".to_string(),
+                from_file: Some(file.clone()),
+                template,
+                ..Default::default()
+            },
+            EchoArgs {
+                keys: vec!["test/lines".to_string()],
+                count: Some(2),
+                ..Default::default()
+            },
+        )
+        .await;
+        let payloads: Vec<_> = lines.iter().map(|line| &line["payload"]).collect();
+        assert_eq!(payloads, expected);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prints_the_key_table_once_without_a_terminal() {
+    let (out, _, _) = pub_echo_consoles(
+        PubArgs {
+            key: "test/table".to_string(),
+            value: Some("reading".to_string()),
+            repeat: 3,
+            ..Default::default()
+        },
+        EchoArgs {
+            keys: vec!["test/table".to_string()],
+            count: Some(3),
+            group_by_key: true,
+            ..Default::default()
+        },
+    )
+    .await;
+    let table = out.text();
+    assert!(!table.contains('\x1b'));
+    assert_eq!(table.matches("LAST VALUE").count(), 1);
+    let row = table
+        .lines()
+        .find(|line| line.starts_with("test/table"))
+        .unwrap();
+    assert!(row.contains("  3  ") && row.ends_with("reading"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn selects_samples_by_reliability() {
+    let lines = pub_echo(
+        PubArgs {
+            key: "test/lossy".to_string(),
+            value: Some("reading".to_string()),
+            reliability: ReliabilityArg::BestEffort,
+            repeat: 2,
+            ..Default::default()
+        },
+        EchoArgs {
+            keys: vec!["test/lossy".to_string()],
+            count: Some(2),
+            reliability: Some(ReliabilityArg::BestEffort),
+            verbose: true,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(lines.len(), 2);
+    for line in &lines {
+        assert_eq!(line["reliability"], "best-effort");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn escapes_binary_payloads() {
+    let modes = [
+        (None, "00ff1b41"),
+        (Some(RawEscape::Escape), r"\u{0}\xff\u{1b}A"),
+        (Some(RawEscape::Base64), "AP8bQQ=="),
+    ];
+    for (raw_escape, expected) in modes {
+        let lines = pub_echo(
+            PubArgs {
+                key: "test/binary".to_string(),
+                hex: Some("00ff1b41".to_string()),
+                ..Default::default()
+            },
+            EchoArgs {
+                keys: vec!["test/binary".to_string()],
+                count: Some(1),
+                raw_escape,
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(lines[0]["payload"], expected);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn round_trips_typed_messages() {
+    let lines = pub_echo(
+        PubArgs {
+            key: "test/imu".to_string(),
+            value: Some(
+                r#"{"angular_velocity": {"x": 1.5, "y": 0, "z": -2}, "timestamp": 42}"#.to_string(),
+            ),
+            r#type: Some("zspy.ImuMessage".to_string()),
+            ..Default::default()
+        },
+        EchoArgs {
+            keys: vec!["test/imu".to_string()],
+            r#type: Some("zspy.ImuMessage".to_string()),
+            count: Some(1),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(lines.len(), 1);
+    let payload = &lines[0]["payload"];
+    assert_eq!(payload["angularVelocity"]["x"], 1.5);
+    assert_eq!(payload["angularVelocity"]["z"], -2.0);
+    assert_eq!(payload["timestamp"], "42");
+    assert!(lines[0].get("decode_error").is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn carries_attachments_and_encoding() {
+    let lines = pub_echo(
+        PubArgs {
+            key: "test/attached".to_string(),
+            value: Some(r#"{"ok": true}"#.to_string()),
+            attach: vec![("source".to_string(), "tests".to_string())],
+            encoding_hint: Some("application/json".to_string()),
+            ..Default::default()
+        },
+        EchoArgs {
+            keys: vec!["test/attached".to_string()],
+            count: Some(1),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["encoding"], "application/json");
+    assert_eq!(lines[0]["attachment"]["source"], "tests");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn advances_a_fixed_timestamp_per_message() {
+    let first = humantime::parse_rfc3339("2024-05-01T12:00:00Z").unwrap();
+    let lines = pub_echo(
+        PubArgs {
+            key: "test/stamped".to_string(),
+            value: Some("tick".to_string()),
+            repeat: 3,
+            timestamp: Some(TimestampArg::At(first)),
+            ..Default::default()
+        },
+        EchoArgs {
+            keys: vec!["test/stamped".to_string()],
+            count: Some(3),
+            ..Default::default()
+        },
+    )
+    .await;
+    let times: Vec<_> = lines
+        .iter()
+        .map(|line| humantime::parse_rfc3339(line["timestamp"].as_str().unwrap()).unwrap())
+        .collect();
+    assert_eq!(times.len(), 3);
+    assert!(times[0].duration_since(first).unwrap() < Duration::from_millis(1));
+    assert!(times.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn runs_scripts_on_messages() {
+    let dir = common::temp_dir("script");
+    let script = dir.join("double.rhai");
+    // Keeps a running total in `state` across messages
+    std::fs::write(
+        &script,
+        "state.total = (state.total ?? 0) + value.n;\n\
+         if value.n <= 1 { return; }\n\
+         #{ n: value.n * 2, key: key, total: state.total }",
+    )
+    .unwrap();
+    let lines = pub_echo(
+        PubArgs {
+            key: "test/script".to_string(),
+            value: Some(r#"{"n": {{seq}}}"#.to_string()),
+            repeat: 4,
+            ..Default::default()
+        },
+        EchoArgs {
+            keys: vec!["test/script".to_string()],
+            count: Some(2),
+            script: Some(script),
+            ..Default::default()
+        },
+    )
+    .await;
+    std::fs::remove_dir_all(dir).unwrap();
+    let payloads: Vec<_> = lines.iter().map(|line| &line["payload"]).collect();
+    assert_eq!(payloads.len(), 2);
+    assert_eq!(payloads[0]["n"], 4);
+    assert_eq!(payloads[1]["n"], 6);
+    assert_eq!(payloads[0]["key"], "test/script");
+    assert_eq!(payloads[0]["total"], 3);
+    assert_eq!(payloads[1]["total"], 6);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn saves_payloads_under_dotted_keys() {
+    let dir = common::temp_dir("save");
+    pub_echo(
+        PubArgs {
+            key: "test/v1.2".to_string(),
+            value: Some(r#"{"ok": true}"#.to_string()),
+            ..Default::default()
+        },
+        EchoArgs {
+            keys: vec!["test/v1.2".to_string()],
+            count: Some(1),
+            save_dir: Some(dir.clone()),
+            save_json: true,
+            ..Default::default()
+        },
+    )
+    .await;
+    let mut names: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    std::fs::remove_dir_all(dir).unwrap();
+    assert_eq!(names.len(), 2);
+    assert!(names[0].starts_with("test_v1.2_") && names[0].ends_with("_000001.bin"));
+    assert!(names[1].starts_with("test_v1.2_") && names[1].ends_with("_000001.json"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn selftest_passes_over_loopback() {
+    let (a, _b) = session_pair().await;
+    let registry = MessageRegistry::with_builtin_types();
+    let args = SelftestArgs {
+        key: "test/selftest".to_string(),
+        r#type: "ImuMessage".to_string(),
+        count: 50,
+        interval: Duration::from_millis(2),
+        two_sessions: false,
+        timeout: Duration::from_secs(5),
+        seed: Some(7),
+    };
+    selftest::run(&a, &zenoh::Config::default(), &registry, &args)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retains_the_last_message_for_late_queries() {
+    let (a, b) = session_pair().await;
+    let registry = MessageRegistry::with_builtin_types();
+    let args = PubArgs {
+        key: "test/retained".to_string(),
+        value: Some("msg {{seq}}".to_string()),
+        repeat: 3,
+        rate: 0.0,
+        retain: true,
+        ..PubArgs::default()
+    };
+    // Nothing subscribes while publishing; a query afterwards still gets the last message
+    let query = async {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let replies = b.get("test/retained").await.unwrap();
+            if let Ok(reply) = replies.recv_async().await {
+                let sample = reply.into_result().unwrap();
+                let payload = sample.payload().to_bytes().to_vec();
+                if payload == b"msg 2" || Instant::now() > deadline {
+                    return payload;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    let quiet = Console::new(Box::new(io::sink()), Box::new(io::sink()));
+    let payload = tokio::select! {
+        published = publish::run_to(&a, &registry, &args, &quiet) => panic!("pub returned {:?}", published.err()),
+        payload = query => payload,
+    };
+    assert_eq!(payload, b"msg 2");
+}
@@ -0,0 +1,172 @@
+//! Writing, indexing and slicing zbag captures, reading MCAP ones, splitting output per key,
+//! and putting samples in timestamp order.
+
+mod common;
+
+use common::temp_dir;
+use std::time::{Duration, Instant};
+use zenoh::sample::SampleKind;
+use zenoh::time::{Timestamp, TimestampId, NTP64};
+use zspy::capture::{self, FilterArgs};
+use zspy::error;
+use zspy::output::{SplitOutput, MAX_OPEN_FILES};
+use zspy::recording::{self, CaptureFormat, RecordedSample};
+use zspy::reorder::Reorder;
+
+fn sample(key: &str, second: u64) -> RecordedSample {
+    RecordedSample {
+        timestamp_ns: 1_000_000_000 * (100 + second),
+        kind: SampleKind::Put,
+        key: key.to_string(),
+        encoding: "zenoh/bytes".to_string(),
+        payload: vec![0; 64 * 1024],
+        attachment: None,
+    }
+}
+
+#[test]
+fn indexes_and_filters_captures() {
+    let dir = temp_dir("capture");
+    let input = dir.join("all.zbag");
+    let mut writer = recording::create(&input, CaptureFormat::Zbag, None).unwrap();
+    for second in 0..60 {
+        for key in ["robot/1/imu", "robot/2/imu", "robot/1/camera"] {
+            writer.write(&sample(key, second)).unwrap();
+        }
+    }
+    writer.finish().unwrap();
+
+    let index = recording::read_index(&input).unwrap().unwrap();
+    assert_eq!(index.keys.len(), 3);
+    assert_eq!(index.samples(), 180);
+    assert!(index.chunks.len() > 1);
+    let mut reader = recording::open(&input).unwrap();
+    let mut read = 0;
+    while reader.read().unwrap().is_some() {
+        read += 1;
+    }
+    assert_eq!(read, 180);
+
+    let output = dir.join("subset.zbag");
+    capture::filter(&FilterArgs {
+        input,
+        keys: vec!["robot/1/**".to_string()],
+        start: Some(std::time::Duration::from_secs(10)),
+        end: Some(std::time::Duration::from_secs(29)),
+        output: output.clone(),
+        format: None,
+    })
+    .unwrap();
+    let index = recording::read_index(&output).unwrap().unwrap();
+    assert_eq!(index.samples(), 40);
+    assert!(index.keys.iter().all(|key| key.key.starts_with("robot/1/")));
+    assert_eq!(index.start_ns(), Some(110_000_000_000));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rejects_corrupt_record_lengths() {
+    let dir = temp_dir("corrupt");
+    let path = dir.join("corrupt.zbag");
+    let mut writer = recording::create(&path, CaptureFormat::Zbag, None).unwrap();
+    writer.write(&sample("robot/1/imu", 0)).unwrap();
+    writer.finish().unwrap();
+    // The first record's key length follows the header, its timestamp and its kind
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[15..19].copy_from_slice(&u32::MAX.to_le_bytes());
+    std::fs::write(&path, bytes).unwrap();
+
+    let err = recording::open(&path).unwrap().read().unwrap_err();
+    assert_eq!(error::classify(&*err).0, "decode");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn streams_mcap_captures() {
+    let dir = temp_dir("mcap");
+    let path = dir.join("all.mcap");
+    let mut writer = recording::create(&path, CaptureFormat::Mcap, None).unwrap();
+    for second in 0..40 {
+        for key in ["robot/1/imu", "robot/2/imu"] {
+            writer.write(&sample(key, second)).unwrap();
+        }
+    }
+    writer.finish().unwrap();
+
+    let mut reader = recording::open(&path).unwrap();
+    let mut read = Vec::new();
+    while let Some(sample) = reader.read().unwrap() {
+        read.push(sample);
+    }
+    assert_eq!(read.len(), 80);
+    assert_eq!(read[0].key, "robot/1/imu");
+    assert_eq!(read[1].key, "robot/2/imu");
+    assert_eq!(read[79].timestamp_ns, 139_000_000_000);
+    assert_eq!(read[79].payload.len(), 64 * 1024);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn splits_output_across_more_keys_than_open_files() {
+    let dir = temp_dir("split");
+    let mut output = SplitOutput::new(&dir).unwrap();
+    let keys: Vec<String> = (0..=MAX_OPEN_FILES)
+        .map(|i| format!("robot/{}", i))
+        .collect();
+    for round in 0..2 {
+        for key in &keys {
+            output
+                .write(key, &serde_json::json!({ "round": round }))
+                .unwrap();
+        }
+    }
+    output.flush().unwrap();
+    drop(output);
+
+    for i in [0, MAX_OPEN_FILES] {
+        let text = std::fs::read_to_string(dir.join(format!("robot/{}.jsonl", i))).unwrap();
+        assert_eq!(text, "{\"round\":0}\n{\"round\":1}\n");
+    }
+}
+
+#[test]
+fn orders_samples_by_timestamp() {
+    let at = |ms: u64| {
+        let time = NTP64::from(Duration::from_millis(1_000_000 + ms));
+        Some(Timestamp::new(time, TimestampId::try_from([1]).unwrap()))
+    };
+    let start = Instant::now();
+    let window = Duration::from_millis(100);
+    let mut reorder = Reorder::new(window);
+    // Published in order 1..=4, received out of order within the window
+    reorder.push(at(2), "b", start);
+    reorder.push(at(1), "a", start + Duration::from_millis(10));
+    reorder.push(None, "untimestamped", start + Duration::from_millis(20));
+    reorder.push(at(4), "d", start + Duration::from_millis(30));
+    assert_eq!(
+        reorder.release(start + Duration::from_millis(50)),
+        ["untimestamped"]
+    );
+    // Due at 100ms: b and everything before it
+    assert_eq!(reorder.release(start + window), ["a", "b"]);
+    reorder.push(at(3), "c", start + Duration::from_millis(120));
+    assert_eq!(
+        reorder.release(start + Duration::from_millis(125)),
+        Vec::<&str>::new()
+    );
+    // d is due first, and takes c along
+    assert_eq!(
+        reorder.next_deadline(),
+        Some(start + Duration::from_millis(130))
+    );
+    assert_eq!(
+        reorder.release(start + Duration::from_millis(130)),
+        ["c", "d"]
+    );
+    // Older than what was already released
+    reorder.push(at(0), "late", start + Duration::from_millis(200));
+    assert_eq!(reorder.drain(), ["late"]);
+    assert_eq!(reorder.late, 1);
+    assert_eq!(reorder.untimestamped, 1);
+    assert!(reorder.is_empty());
+}
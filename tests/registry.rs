@@ -0,0 +1,376 @@
+//! Encoding, decoding and loading schemas with the message registry.
+
+mod common;
+
+use common::temp_dir;
+use serde_json::Value;
+use std::fs;
+use zspy::codec::PayloadEncoding;
+use zspy::error::{self, ZspyError};
+use zspy::message_registry::{JsonStyle, MessageRegistry};
+use zspy::presence::PresenceOptions;
+use zspy::schema;
+use zspy::type_map::TypeMap;
+
+const SENSOR_PROTO: &str = r#"
+syntax = "proto3";
+package test;
+
+message Reading {
+    string sensor = 1;
+    double value = 2;
+}
+"#;
+
+#[test]
+fn round_trips_builtin_types() {
+    let registry = MessageRegistry::with_builtin_types();
+    let json = r#"{"angular_velocity": {"x": 0.25, "y": 1, "z": 0}, "timestamp": 7}"#;
+    let bytes = registry.encode("zspy.ImuMessage", json).unwrap();
+    let decoded: Value =
+        serde_json::from_str(&registry.decode("zspy.ImuMessage", &bytes).unwrap()).unwrap();
+    assert_eq!(decoded["angularVelocity"]["x"], 0.25);
+    assert_eq!(decoded["angularVelocity"]["y"], 1.0);
+    assert_eq!(decoded["timestamp"], "7");
+    assert_eq!(
+        registry
+            .encode("zspy.ImuMessage", &decoded.to_string())
+            .unwrap(),
+        bytes
+    );
+}
+
+#[test]
+fn decodes_with_a_type_an_encoding_or_neither() {
+    let registry = MessageRegistry::with_builtin_types();
+    let bytes = registry
+        .encode("zspy.ImuMessage", r#"{"timestamp": 7}"#)
+        .unwrap();
+    let typed = registry
+        .decode_with(Some("zspy.ImuMessage"), None, &bytes)
+        .unwrap();
+    assert_eq!(typed["timestamp"], "7");
+    let cbor = PayloadEncoding::Cbor
+        .codec()
+        .encode(r#"{"celsius": 21.5}"#)
+        .unwrap();
+    let encoded = registry
+        .decode_with(None, Some(PayloadEncoding::Cbor), &cbor)
+        .unwrap();
+    assert_eq!(encoded["celsius"], 21.5);
+    let json = registry
+        .decode_with(None, None, br#"{"ok": true}"#)
+        .unwrap();
+    assert_eq!(json["ok"], true);
+    let text = registry.decode_with(None, None, b"hello").unwrap();
+    assert_eq!(text, "hello");
+}
+
+#[test]
+fn round_trips_text_format() {
+    let registry = MessageRegistry::with_builtin_types();
+    let bytes = registry
+        .encode_text_format(
+            "zspy.ImuMessage",
+            "angular_velocity { x: 0.25 } timestamp: 7",
+        )
+        .unwrap();
+    let json = r#"{"angular_velocity": {"x": 0.25}, "timestamp": 7}"#;
+    assert_eq!(bytes, registry.encode("zspy.ImuMessage", json).unwrap());
+    let text = registry
+        .decode_text_format("zspy.ImuMessage", &bytes)
+        .unwrap();
+    assert!(text.contains("x: 0.25"), "{}", text);
+    assert_eq!(
+        registry
+            .encode_text_format("zspy.ImuMessage", &text)
+            .unwrap(),
+        bytes
+    );
+
+    let err = registry
+        .encode_text_format("zspy.ImuMessage", "no_such_field: 1")
+        .unwrap_err();
+    assert_eq!(error::classify(err.as_ref()), ("encode", 5));
+}
+
+#[test]
+fn serde_style_keeps_proto_field_names() {
+    let mut registry = MessageRegistry::with_builtin_types();
+    registry.set_json_style(JsonStyle::Serde);
+    let json = r#"{"angular_velocity": {"x": 0.25, "y": 0, "z": 0}, "linear_acceleration": null, "timestamp": 7}"#;
+    let bytes = registry.encode("zspy.ImuMessage", json).unwrap();
+    let decoded: Value =
+        serde_json::from_str(&registry.decode("zspy.ImuMessage", &bytes).unwrap()).unwrap();
+    assert_eq!(decoded["angular_velocity"]["x"], 0.25);
+    assert_eq!(decoded["angular_velocity"]["y"], 0.0);
+    assert_eq!(decoded["timestamp"], 7);
+
+    // The canonical style reads the same messages, with either field name
+    registry.set_json_style(JsonStyle::Canonical);
+    assert_eq!(registry.encode("zspy.ImuMessage", json).unwrap(), bytes);
+}
+
+#[test]
+fn reports_unknown_types_and_bad_payloads() {
+    let registry = MessageRegistry::with_builtin_types();
+    let err = registry.check_type("zspy.NoSuchMessage").unwrap_err();
+    assert!(matches!(err, ZspyError::UnknownType(..)));
+    assert_eq!(error::classify(&err).1, 2);
+
+    let err = registry.encode("zspy.ImuMessage", "not json").unwrap_err();
+    assert_eq!(error::classify(err.as_ref()), ("encode", 5));
+    let err = registry
+        .decode("zspy.ImuMessage", &[0xff, 0xff])
+        .unwrap_err();
+    assert_eq!(error::classify(err.as_ref()), ("decode", 4));
+}
+
+#[test]
+fn loads_proto_files_at_runtime() {
+    let dir = temp_dir("load");
+    fs::write(dir.join("sensor.proto"), SENSOR_PROTO).unwrap();
+    let mut registry = MessageRegistry::with_builtin_types();
+    assert_eq!(registry.load_path(&dir).unwrap(), 1);
+    assert!(registry.list_types().contains(&"test.Reading".to_string()));
+    assert!(registry
+        .list_types()
+        .contains(&"zspy.ImuMessage".to_string()));
+
+    let bytes = registry
+        .encode("test.Reading", r#"{"sensor": "t1", "value": 21.5}"#)
+        .unwrap();
+    let decoded: Value =
+        serde_json::from_str(&registry.decode("test.Reading", &bytes).unwrap()).unwrap();
+    assert_eq!(decoded["sensor"], "t1");
+    assert_eq!(decoded["value"], 21.5);
+    // Defaults are written, as they are for built-in types under the canonical style
+    let bytes = registry
+        .encode("test.Reading", r#"{"sensor": "t1"}"#)
+        .unwrap();
+    let decoded: Value =
+        serde_json::from_str(&registry.decode("test.Reading", &bytes).unwrap()).unwrap();
+    assert_eq!(decoded["value"], 0.0);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reload_replaces_types_and_keeps_them_on_error() {
+    let dir = temp_dir("reload");
+    let proto = dir.join("sensor.proto");
+    fs::write(&proto, SENSOR_PROTO).unwrap();
+    let mut registry = MessageRegistry::with_builtin_types();
+    registry.load_path(&dir).unwrap();
+    let paths = [dir.clone()];
+
+    fs::write(
+        &proto,
+        SENSOR_PROTO.replace(
+            "double value = 2;",
+            "double value = 2;\n    string unit = 3;",
+        ),
+    )
+    .unwrap();
+    assert_eq!(registry.reload(&paths).unwrap(), 1);
+    let bytes = registry
+        .encode("test.Reading", r#"{"value": 1, "unit": "C"}"#)
+        .unwrap();
+    assert!(registry
+        .decode("test.Reading", &bytes)
+        .unwrap()
+        .contains("\"C\""));
+
+    fs::write(&proto, "syntax = \"proto3\"; message {").unwrap();
+    assert!(registry.reload(&paths).is_err());
+    registry.check_type("test.Reading").unwrap();
+    registry.check_type("zspy.ImuMessage").unwrap();
+
+    fs::remove_file(&proto).unwrap();
+    assert_eq!(registry.reload(&paths).unwrap(), 0);
+    assert!(registry.check_type("test.Reading").is_err());
+    registry.check_type("zspy.ImuMessage").unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn auto_decodes_schemaless_payloads() {
+    let registry = MessageRegistry::with_builtin_types();
+    assert_eq!(registry.auto_decode(br#"{"a": 1}"#).format, "json");
+    assert_eq!(registry.auto_decode(b"plain text").format, "text");
+}
+
+#[test]
+fn diffs_schema_versions() {
+    let pool = |name: &str, proto: &str| {
+        let dir = temp_dir(name);
+        fs::write(dir.join("sensor.proto"), proto).unwrap();
+        let mut registry = MessageRegistry::with_builtin_types();
+        registry.load_path(&dir).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        registry
+            .descriptor("test.Reading")
+            .unwrap()
+            .parent_pool()
+            .clone()
+    };
+    let old = pool("diff-old", SENSOR_PROTO);
+    assert!(schema::diff(&old, &old).is_empty());
+
+    let added = pool(
+        "diff-added",
+        &SENSOR_PROTO.replace(
+            "double value = 2;",
+            "double value = 2;\n    string unit = 3;",
+        ),
+    );
+    let changes = schema::diff(&old, &added);
+    assert_eq!(changes.len(), 1);
+    assert!(!changes[0].breaking);
+
+    let retyped = pool(
+        "diff-retyped",
+        &SENSOR_PROTO.replace("double value = 2;", "float reading = 2;"),
+    );
+    let changes: Vec<String> = schema::diff(&old, &retyped)
+        .into_iter()
+        .filter(|c| c.breaking)
+        .map(|c| c.description)
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            "field value = 2 renamed to reading",
+            "field reading = 2 changed type from double to float",
+        ]
+    );
+}
+
+#[test]
+fn shows_field_presence() {
+    let dir = temp_dir("presence");
+    fs::write(
+        dir.join("sensor.proto"),
+        SENSOR_PROTO.replace(
+            "double value = 2;",
+            "double value = 2;\n    optional int32 offset = 3;\n    \
+             oneof source {\n        string device = 4;\n        uint32 channel = 5;\n    }\n    \
+             string origin = 6 [json_name = \"source\"];",
+        ),
+    )
+    .unwrap();
+    let mut registry = MessageRegistry::with_builtin_types();
+    registry.load_path(&dir).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+
+    let bytes = registry
+        .encode(
+            "test.Reading",
+            r#"{"sensor": "t1", "channel": 0, "source": "lab"}"#,
+        )
+        .unwrap();
+    let decode = |mark, skip_defaults| -> Value {
+        let options = PresenceOptions {
+            mark,
+            skip_defaults,
+            ..Default::default()
+        };
+        serde_json::from_str(
+            &registry
+                .decode_presence("test.Reading", &bytes, options)
+                .unwrap(),
+        )
+        .unwrap()
+    };
+    let marked = decode(true, false);
+    assert_eq!(marked["offset"], Value::Null);
+    assert_eq!(marked["value"], 0.0);
+    // The field whose JSON name is `source` keeps it, and the oneof goes by `source_case`
+    assert_eq!(marked["source"], "lab");
+    assert_eq!(marked["source_case"], "channel");
+    assert_eq!(marked["channel"], 0);
+    assert!(marked.get("device").is_none());
+
+    let skipped = decode(false, true);
+    assert!(skipped.get("offset").is_none());
+    assert!(skipped.get("value").is_none());
+    assert_eq!(skipped["channel"], 0);
+
+    // Under the serde style built-in types keep their proto field names
+    registry.set_json_style(JsonStyle::Serde);
+    let bytes = registry
+        .encode(
+            "zspy.ImuMessage",
+            r#"{"angular_velocity": {"x": 1, "y": 0, "z": 0}, "timestamp": 0}"#,
+        )
+        .unwrap();
+    let options = PresenceOptions {
+        mark: true,
+        ..Default::default()
+    };
+    let imu: Value = serde_json::from_str(
+        &registry
+            .decode_presence("zspy.ImuMessage", &bytes, options)
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(imu["angular_velocity"]["x"], 1.0);
+    assert!(imu.get("angularVelocity").is_none());
+}
+
+#[test]
+fn resolves_short_and_misspelled_type_names() {
+    let dir = temp_dir("resolve");
+    fs::write(dir.join("sensor.proto"), SENSOR_PROTO).unwrap();
+    fs::write(
+        dir.join("other.proto"),
+        SENSOR_PROTO.replace("package test;", "package other;"),
+    )
+    .unwrap();
+    let mut registry = MessageRegistry::with_builtin_types();
+    registry.load_path(&dir).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+
+    assert_eq!(
+        registry.resolve_type("ImuMessage").unwrap(),
+        "zspy.ImuMessage"
+    );
+    assert_eq!(
+        registry.resolve_type("zspy.imumessage").unwrap(),
+        "zspy.ImuMessage"
+    );
+    assert!(registry.decode("imumessage", &[]).is_ok());
+
+    match registry.resolve_type("Reading") {
+        Err(ZspyError::AmbiguousType(_, candidates)) => {
+            assert_eq!(candidates, ["other.Reading", "test.Reading"])
+        }
+        other => panic!("expected an ambiguous type, got {:?}", other),
+    }
+    match registry.resolve_type("zspy.ImuMesage") {
+        Err(ZspyError::UnknownType(_, suggestions)) => {
+            assert_eq!(suggestions[0], "zspy.ImuMessage")
+        }
+        other => panic!("expected an unknown type, got {:?}", other),
+    }
+}
+
+#[test]
+fn maps_key_expressions_to_types() {
+    let type_map = TypeMap::parse(
+        r#"
+        "robot/**" = "zspy.Status"
+        "robot/*/imu" = "zspy.ImuMessage"
+        "robot/arm/imu" = "zspy.ArmImu"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(type_map.type_for("robot/arm/imu"), Some("zspy.ArmImu"));
+    assert_eq!(type_map.type_for("robot/leg/imu"), Some("zspy.ImuMessage"));
+    assert_eq!(type_map.type_for("robot/leg/status"), Some("zspy.Status"));
+    assert_eq!(type_map.type_for("fleet/leg/imu"), None);
+    assert!(TypeMap::parse(r#""robot/**/**/" = "x""#).is_err());
+
+    let mut registry = MessageRegistry::with_builtin_types();
+    registry.set_type_map(type_map);
+    assert_eq!(registry.type_for_key("robot/arm/imu"), Some("zspy.ArmImu"));
+}